@@ -2,26 +2,37 @@ use std::{
     collections::HashMap,
     env,
     fmt::Display,
-    hash::Hash,
-    io::{Cursor, Read},
-    path::PathBuf,
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    process::Stdio,
     str::FromStr,
-    sync::{Arc, LazyLock, Mutex, OnceLock},
+    sync::{Arc, LazyLock, Mutex},
+    time::SystemTime,
 };
 
 use anyhow::{Context, bail};
-use clap::{Parser, ValueEnum, builder::ArgPredicate};
-use rand::Rng;
+use clap::{
+    CommandFactory, FromArgMatches, Parser, ValueEnum, parser::ValueSource,
+};
+use regex::{Captures, Regex};
 use spreadsheet_plotter::{
-    AxisOptions, DataFormat, DataSeriesOptions, GnuplotTemplate, PlotType,
+    AxisOptions, Color, DataFormat, DataPoints, DataSeriesOptions,
+    DataSeriesSource, ErrorCategory, ErrorFormat, ExternalOperator,
+    GnuplotTemplate, Length, LogFormat, PipelineConfig, PlotType, Plotter,
+    TempArtifacts, UserConfig, WithCategory, get_matches,
 };
 use strum::Display;
 
+/// Number of datasheets kept in the output directory for reuse
+pub const CACHED_DATASHEETS: usize = 100;
+
 #[derive(Debug, Clone)]
 struct InputDataSeries {
     axis: Field<String>,
     file: Field<usize>,
     ifilter: Field<String>,
+    jitter: Field<String>,
     ofilter: Field<String>,
     opseq: Field<String>,
     plot_type: Field<String>,
@@ -29,6 +40,11 @@ struct InputDataSeries {
     title: Field<String>,
     xexpr: Field<String>,
     yexpr: Field<String>,
+    clause: Field<String>,
+    gpcmd: Field<String>,
+    panel: Field<String>,
+    host: Field<String>,
+    glob: Option<String>,
 }
 
 static DEFAULT_INPUT_DATA_SERIES: LazyLock<Arc<Mutex<InputDataSeries>>> =
@@ -43,7 +59,13 @@ static DEFAULT_INPUT_DATA_SERIES: LazyLock<Arc<Mutex<InputDataSeries>>> =
             axis: Field::Default,
             style: Field::Default,
             ifilter: Field::Default,
+            jitter: Field::Default,
             ofilter: Field::Default,
+            clause: Field::Instant(String::new()),
+            gpcmd: Field::Instant(String::new()),
+            panel: Field::Instant("1".to_string()),
+            host: Field::Default,
+            glob: None,
         }))
     });
 
@@ -54,10 +76,13 @@ impl Default for InputDataSeries {
 }
 
 impl InputDataSeries {
-    const KEYS: [&str; 10] = [
-        "axis", "file", "ifilter", "ofilter", "opseq", "plot", "style",
-        "title", "xexpr", "yexpr",
+    const KEYS: [&str; 15] = [
+        "axis", "clause", "file", "gpcmd", "host", "ifilter", "jitter",
+        "ofilter", "opseq", "panel", "plot", "style", "title", "xexpr",
+        "yexpr",
     ];
+    /// Keys keeping the prefixes they had before ambiguous keys were added
+    const PREFERRED_KEYS: [(&str, &str); 1] = [("p", "plot")];
     fn do_get_matched_key(
         abs: &str,
         match_ref: bool,
@@ -75,7 +100,12 @@ impl InputDataSeries {
             .filter(|k| k.starts_with(abs))
             .map(|k| k.to_string())
             .collect::<Vec<_>>();
-        if matched_keys.is_empty() {
+        if let Some((_, key)) = Self::PREFERRED_KEYS
+            .iter()
+            .find(|(prefix, _)| *prefix == abs)
+        {
+            Ok(key.to_string())
+        } else if matched_keys.is_empty() {
             bail!("Unknown key: {abs}");
         } else if matched_keys.len() == 1 {
             Ok(matched_keys[0].to_string())
@@ -91,6 +121,76 @@ impl InputDataSeries {
     fn get_matched_key(abs: &str) -> anyhow::Result<String> {
         Self::do_get_matched_key(abs, true)
     }
+
+    /// Data series declared as a table of a manifest file, with the same
+    /// keys as the SERIES strings, or the path of a pipeline file (see
+    /// `PipelineConfig`) as key `pipeline` whose keys the others override
+    fn from_table(table: &toml::Table) -> anyhow::Result<Self> {
+        let mut ids = InputDataSeries::default();
+        let pipeline = match table.get("pipeline") {
+            None => toml::Table::new(),
+            Some(toml::Value::String(path)) => {
+                PipelineConfig::load(path)?.to_series_table()?
+            }
+            Some(_) => {
+                bail!("Value of data series key 'pipeline' must be a path")
+            }
+        };
+        let table = table.iter().filter(|(k, _)| k.as_str() != "pipeline");
+        for (k, v) in pipeline.iter().chain(table) {
+            let v = match v {
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Array(_) | toml::Value::Table(_) => {
+                    bail!("Value of data series key '{k}' must be a scalar")
+                }
+                v => v.to_string(),
+            };
+            ids.set(k, &v)?;
+        }
+        Ok(ids)
+    }
+
+    fn set(&mut self, k: &str, v: &str) -> anyhow::Result<()> {
+        let k = InputDataSeries::get_matched_key(k)
+            .context(format!("\nOriginal key-value: {k}={v}"))?;
+
+        match k.as_str() {
+            "file" => match v.strip_prefix("glob:") {
+                Some(pattern) => self.glob = Some(pattern.to_string()),
+                None => self.file = v.parse()?,
+            },
+            "axis" => self.axis = Field::Instant(v.to_string()),
+            "raxis" => self.axis = v.parse()?,
+            "ifilter" => self.ifilter = Field::Instant(v.to_string()),
+            "rifilter" => self.ifilter = v.parse()?,
+            "jitter" => self.jitter = Field::Instant(v.to_string()),
+            "rjitter" => self.jitter = v.parse()?,
+            "ofilter" => self.ofilter = Field::Instant(v.to_string()),
+            "rofilter" => self.ofilter = v.parse()?,
+            "opseq" => self.opseq = Field::Instant(v.to_string()),
+            "ropseq" => self.opseq = v.parse()?,
+            "plot" => self.plot_type = Field::Instant(v.to_string()),
+            "rplot" => self.plot_type = v.parse()?,
+            "style" => self.style = Field::Instant(v.to_string()),
+            "rstyle" => self.style = v.parse()?,
+            "title" => self.title = Field::Instant(v.to_string()),
+            "rtitle" => self.title = v.parse()?,
+            "xexpr" => self.xexpr = Field::Instant(v.to_string()),
+            "rxexpr" => self.xexpr = v.parse()?,
+            "yexpr" => self.yexpr = Field::Instant(v.to_string()),
+            "ryexpr" => self.yexpr = v.parse()?,
+            "clause" => self.clause = Field::Instant(v.to_string()),
+            "rclause" => self.clause = v.parse()?,
+            "gpcmd" => self.gpcmd = Field::Instant(v.to_string()),
+            "rgpcmd" => self.gpcmd = v.parse()?,
+            "panel" => self.panel = Field::Instant(v.to_string()),
+            "rpanel" => self.panel = v.parse()?,
+            "host" => self.host = Field::Instant(v.to_string()),
+            "rhost" => self.host = v.parse()?,
+            _ => bail!("Unknown key: {k}"),
+        }
+        Ok(())
+    }
 }
 
 impl FromStr for InputDataSeries {
@@ -109,32 +209,7 @@ impl FromStr for InputDataSeries {
             if kv.len() != 2 {
                 bail!("Invalid data series part: {part}");
             }
-            let (k, v) = (kv[0], kv[1]);
-            let k = InputDataSeries::get_matched_key(k)
-                .context(format!("\nOriginal key-value: {k}={v}"))?;
-
-            match k.as_str() {
-                "file" => ids.file = v.parse()?,
-                "axis" => ids.axis = Field::Instant(v.to_string()),
-                "raxis" => ids.axis = v.parse()?,
-                "ifilter" => ids.ifilter = Field::Instant(v.to_string()),
-                "rifilter" => ids.ifilter = v.parse()?,
-                "ofilter" => ids.ofilter = Field::Instant(v.to_string()),
-                "rofilter" => ids.ofilter = v.parse()?,
-                "opseq" => ids.opseq = Field::Instant(v.to_string()),
-                "ropseq" => ids.opseq = v.parse()?,
-                "plot" => ids.plot_type = Field::Instant(v.to_string()),
-                "rplot" => ids.plot_type = v.parse()?,
-                "style" => ids.style = Field::Instant(v.to_string()),
-                "rstyle" => ids.style = v.parse()?,
-                "title" => ids.title = Field::Instant(v.to_string()),
-                "rtitle" => ids.title = v.parse()?,
-                "xexpr" => ids.xexpr = Field::Instant(v.to_string()),
-                "rxexpr" => ids.xexpr = v.parse()?,
-                "yexpr" => ids.yexpr = Field::Instant(v.to_string()),
-                "ryexpr" => ids.yexpr = v.parse()?,
-                _ => bail!("Unknown key: {k}"),
-            }
+            ids.set(kv[0], kv[1])?;
         }
 
         Ok(ids)
@@ -155,6 +230,114 @@ pub struct DataSeries {
     axis: String,
     pub use_x2: bool,
     pub use_y2: bool,
+    jitter: String,
+    pub jitter_widths: Option<(f64, f64)>,
+    clause: String,
+    gpcmd: String,
+    panel: String,
+    /// Panel (0-based) of the multiplot layout
+    pub panel_index: usize,
+    /// ssh destination running sp, empty for the local machine
+    pub host: String,
+}
+
+/// Parse jitter widths written as `X:Y`, or a single width for both axes
+fn parse_jitter(s: &str) -> anyhow::Result<Option<(f64, f64)>> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    let mut parts = s.splitn(2, ':');
+    let x =
+        parts.next().unwrap().parse::<f64>().map_err(|e| {
+            anyhow::anyhow!("Failed to parse jitter '{s}': {e}")
+        })?;
+    let y = match parts.next() {
+        Some(y) => y.parse::<f64>().map_err(|e| {
+            anyhow::anyhow!("Failed to parse jitter '{s}': {e}")
+        })?,
+        None => x,
+    };
+    if !x.is_finite() || !y.is_finite() || x < 0.0 || y < 0.0 {
+        bail!("Jitter must be non-negative finite numbers: {s}");
+    }
+    if x == 0.0 && y == 0.0 {
+        return Ok(None);
+    }
+    Ok(Some((x, y)))
+}
+
+/// Check that extra plot-clause text stays within its data series, i.e. it
+/// has no line break, comment, unbalanced quote or bracket, and no comma
+/// outside of them that would start another plot element
+fn check_plot_clause(clause: &str) -> anyhow::Result<()> {
+    let mut quote = None;
+    let mut depth = 0;
+    for c in clause.chars() {
+        match (quote, c) {
+            (_, '\n' | '\r') => {
+                bail!("Plot clause must not contain line breaks: {clause}")
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') if depth > 0 => depth -= 1,
+            (None, ')' | ']' | '}') => {
+                bail!("Unbalanced brackets in plot clause: {clause}")
+            }
+            (None, ',') if depth == 0 => bail!(
+                "Plot clause must not contain ',' outside of quotes or \
+                brackets: {clause}"
+            ),
+            (None, '#') => {
+                bail!("Plot clause must not contain comments: {clause}")
+            }
+            _ => {}
+        }
+    }
+    if quote.is_some() || depth != 0 {
+        bail!("Unbalanced quotes or brackets in plot clause: {clause}");
+    }
+    Ok(())
+}
+
+/// Check that per-series gnuplot commands do not plot on their own, the
+/// plot command is generated from all data series
+fn check_gnuplot_commands(cmds: &str) -> anyhow::Result<()> {
+    let plots = [("plot", 1), ("replot", 3), ("splot", 2)];
+    for stmt in cmds.split(['\n', ';']) {
+        let word = stmt.split_whitespace().next().unwrap_or("");
+        if plots.iter().any(|(cmd, min_len)| {
+            word.len() >= *min_len && cmd.starts_with(word)
+        }) {
+            bail!("Per-series gnuplot commands must not plot: {stmt}");
+        }
+    }
+    Ok(())
+}
+
+impl DataSeries {
+    /// Value of a data series key
+    fn get(&self, key: &str) -> String {
+        match key {
+            "axis" => self.axis.clone(),
+            "clause" => self.clause.clone(),
+            "file" => self.file.to_string(),
+            "gpcmd" => self.gpcmd.clone(),
+            "ifilter" => self.ifilter.clone(),
+            "jitter" => self.jitter.clone(),
+            "ofilter" => self.ofilter.clone(),
+            "opseq" => self.opseq.clone(),
+            "panel" => self.panel.clone(),
+            "plot" => self.plot_type.clone(),
+            "style" => self.style.clone(),
+            "title" => self.title.clone(),
+            "xexpr" => self.xexpr.clone(),
+            "yexpr" => self.yexpr.clone(),
+            "host" => self.host.clone(),
+            _ => unreachable!("unknown data series key {key}"),
+        }
+    }
 }
 
 impl TryFrom<InputDataSeries> for DataSeries {
@@ -169,6 +352,16 @@ impl TryFrom<InputDataSeries> for DataSeries {
             "22" => (true, true),
             _ => bail!("Unknown axis: {axis}"),
         };
+        let jitter: String = ids.jitter.try_into()?;
+        let clause: String = ids.clause.try_into()?;
+        check_plot_clause(&clause)?;
+        let gpcmd: String = ids.gpcmd.try_into()?;
+        check_gnuplot_commands(&gpcmd)?;
+        let panel: String = ids.panel.try_into()?;
+        let panel_index = match panel.parse::<usize>() {
+            Ok(panel) if panel > 0 => panel - 1,
+            _ => bail!("Panel must be a positive integer: {panel}"),
+        };
         Ok(Self {
             file: ids.file.try_into()?,
             ifilter: ids.ifilter.try_into()?,
@@ -182,14 +375,23 @@ impl TryFrom<InputDataSeries> for DataSeries {
             axis,
             use_x2,
             use_y2,
+            jitter_widths: parse_jitter(&jitter)?,
+            jitter,
+            clause,
+            gpcmd,
+            panel,
+            panel_index,
+            host: ids.host.try_into()?,
         })
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct PlotSize {
-    pub width: f32,
-    pub height: f32,
+pub enum PlotSize {
+    /// Scaling factors relative to the default canvas of the terminal
+    Relative { width: f32, height: f32 },
+    /// Physical size of the canvas
+    Physical { width: Length, height: Length },
 }
 
 impl FromStr for PlotSize {
@@ -198,21 +400,64 @@ impl FromStr for PlotSize {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let s = s.chars().filter(|c| !c.is_whitespace()).collect::<String>();
         let mut parts = s.splitn(2, ',');
-        let width =
-            parts.next().unwrap().parse().map_err(|e| {
+        let width = parts.next().unwrap();
+        let height = parts
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Missing plot height in '{s}'"))?;
+        if width.ends_with(char::is_alphabetic)
+            || height.ends_with(char::is_alphabetic)
+        {
+            let width = width.parse().map_err(|e| {
                 anyhow::anyhow!("Failed to parse plot width: {e}")
             })?;
-        let height =
-            parts.next().unwrap().parse().map_err(|e| {
+            let height = height.parse().map_err(|e| {
                 anyhow::anyhow!("Failed to parse plot height: {e}")
             })?;
-        Ok(Self { width, height })
+            return Ok(Self::Physical { width, height });
+        }
+        let width = width
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Failed to parse plot width: {e}"))?;
+        let height = height
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Failed to parse plot height: {e}"))?;
+        Ok(Self::Relative { width, height })
     }
 }
 
 impl Display for PlotSize {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{},{}", self.width, self.height)
+        match self {
+            Self::Relative { width, height } => write!(f, "{width},{height}"),
+            Self::Physical { width, height } => write!(f, "{width},{height}"),
+        }
+    }
+}
+
+/// Multiplot layout written as ROWSxCOLS
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rows, cols) = s
+            .split_once(['x', 'X'])
+            .ok_or_else(|| anyhow::anyhow!("Layout must be ROWSxCOLS: {s}"))?;
+        let parse = |n: &str| match n.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(anyhow::anyhow!(
+                "Rows and columns of layout must be positive integers: {s}"
+            )),
+        };
+        Ok(Self {
+            rows: parse(rows)?,
+            cols: parse(cols)?,
+        })
     }
 }
 
@@ -261,6 +506,106 @@ impl From<Terminal> for spreadsheet_plotter::Terminal {
     }
 }
 
+#[derive(ValueEnum, Display, Clone, Debug)]
+pub enum Preset {
+    /// Compact postscript figures for papers
+    Paper,
+    /// Large fonts and thick lines for slides
+    Slides,
+    /// Light-on-dark x11 window
+    Dark,
+    /// Dense x11 window with small fonts
+    Dashboard,
+}
+
+impl From<Preset> for spreadsheet_plotter::Preset {
+    fn from(value: Preset) -> Self {
+        match value {
+            Preset::Paper => Self::Paper,
+            Preset::Slides => Self::Slides,
+            Preset::Dark => Self::Dark,
+            Preset::Dashboard => Self::Dashboard,
+        }
+    }
+}
+
+#[derive(ValueEnum, Display, Clone, Debug)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Template {
+    /// CDF of each input as lines
+    CdfCompare,
+    /// Time series of the first input on the y axis and of the other inputs
+    /// on the y2 axis
+    TimeseriesDualAxis,
+    /// Latency over throughput of each input as linespoints
+    ThroughputLatency,
+}
+
+impl Template {
+    /// Default values of options, overridden by the configuration file, the
+    /// manifest and the command line
+    fn defaults(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Template::CdfCompare => &[
+                ("plot_type", "lines"),
+                ("opseq", "c"),
+                ("label", "y=CDF"),
+                ("key_position", "bottom right"),
+            ],
+            Template::TimeseriesDualAxis => &[
+                ("plot_type", "lines"),
+                ("label", "x=Time"),
+                ("additional_gnuplot_cmd", "set ytics nomirror; set y2tics"),
+            ],
+            Template::ThroughputLatency => &[
+                ("plot_type", "linespoints"),
+                ("label", "x=Throughput,y=Latency"),
+                ("key_position", "top left"),
+            ],
+        }
+    }
+
+    fn apply_defaults(&self, cmd: clap::Command) -> clap::Command {
+        self.defaults().iter().fold(cmd, |cmd, (id, value)| {
+            cmd.mut_arg(*id, |a| a.default_value(*value))
+        })
+    }
+
+    /// One data series per input file, titled by the file stem
+    fn series(
+        &self,
+        input_paths: &[PathBuf],
+    ) -> anyhow::Result<Vec<InputDataSeries>> {
+        let min_inputs = match self {
+            Template::TimeseriesDualAxis => 2,
+            _ => 1,
+        };
+        if input_paths.len() < min_inputs {
+            bail!(
+                "Template {self} requires at least {min_inputs} input file(s) given with -i"
+            );
+        }
+        Ok(input_paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| {
+                let mut ids = InputDataSeries {
+                    file: Field::Instant(i + 1),
+                    ..Default::default()
+                };
+                if let Some(stem) = path.file_stem() {
+                    ids.title =
+                        Field::Instant(stem.to_string_lossy().into_owned());
+                }
+                if matches!(self, Template::TimeseriesDualAxis) && i > 0 {
+                    ids.axis = Field::Instant("12".to_string());
+                }
+                ids
+            })
+            .collect())
+    }
+}
+
 #[derive(Clone, Debug)]
 enum Field<T: Clone + std::fmt::Debug + std::fmt::Display> {
     PositiveRelative(usize),
@@ -270,6 +615,63 @@ enum Field<T: Clone + std::fmt::Debug + std::fmt::Display> {
     Default,
 }
 
+/// Resolve one field of all data series to values, following references
+/// (rKEY) to earlier or later data series until a value
+fn resolve_field<T>(
+    key: &str,
+    fields: &[Field<T>],
+    default: &Field<T>,
+) -> anyhow::Result<Vec<T>>
+where
+    T: Clone + std::fmt::Debug + std::fmt::Display,
+{
+    let count = fields.len();
+    let mut resolved: Vec<Option<T>> = vec![None; count];
+    for start in 0..count {
+        // data series on the path of references, all taking the same value
+        let mut path = vec![start];
+        let value = loop {
+            let index = *path.last().unwrap();
+            if let Some(value) = &resolved[index] {
+                break value.clone();
+            }
+            let field = match &fields[index] {
+                Field::Default => default,
+                field => field,
+            };
+            let target = match *field {
+                Field::Instant(ref value) => break value.clone(),
+                Field::Default => {
+                    bail!("No value of {key} for data series #{}", index + 1)
+                }
+                Field::Absolute(i) => i.checked_sub(1),
+                Field::PositiveRelative(i) => Some(index + i),
+                Field::NegativeRelative(i) => index.checked_sub(i),
+            };
+            let Some(target) = target.filter(|&target| target < count) else {
+                bail!(
+                    "r{key}={field} of data series #{} is out of range (expected [1, {count}])",
+                    index + 1
+                );
+            };
+            if let Some(pos) = path.iter().position(|&i| i == target) {
+                let cycle = path[pos..]
+                    .iter()
+                    .chain([&target])
+                    .map(|i| format!("#{}", i + 1))
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                bail!("Cyclic references of {key}: {cycle}");
+            }
+            path.push(target);
+        };
+        for index in path {
+            resolved[index] = Some(value.clone());
+        }
+    }
+    Ok(resolved.into_iter().map(Option::unwrap).collect())
+}
+
 macro_rules! impl_try_from_field {
     ($t:ty) => {
         impl TryFrom<Field<$t>> for $t {
@@ -382,10 +784,112 @@ impl FromStr for FileFormat {
     }
 }
 
-static STDIN_CONTENT: OnceLock<String> = OnceLock::new();
+/// Placeholder of the input file in titles, e.g. {file.parent.name}
+static TITLE_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{file((?:\.\w+)*)\}").unwrap());
 
-pub fn get_stdin_reader() -> Cursor<&'static str> {
-    Cursor::new(STDIN_CONTENT.get().unwrap())
+/// Value of the placeholder {file`parts`}, where each part of `parts` (e.g.
+/// ".parent.name") moves to the parent directory or takes the last component
+fn file_placeholder(path: &Path, parts: &str) -> anyhow::Result<String> {
+    let lossy = |s: Option<&std::ffi::OsStr>| {
+        s.map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let mut path = path;
+    let mut parts = parts.split('.').skip(1).peekable();
+    while let Some(part) = parts.next() {
+        let component = match part {
+            "parent" => {
+                path = path.parent().unwrap_or(Path::new(""));
+                continue;
+            }
+            "name" => path.file_name(),
+            "stem" => path.file_stem(),
+            "ext" => path.extension(),
+            _ => bail!(
+                "Unknown part '{part}' of {{file}}, expected parent, name, stem or ext"
+            ),
+        };
+        if parts.peek().is_some() {
+            bail!("'{part}' must be the last part of {{file}}");
+        }
+        return Ok(lossy(component));
+    }
+    Ok(path.display().to_string())
+}
+
+/// Escape a string to be quoted by single quotes in sh
+pub fn escape(s: &str) -> String {
+    s.replace("'", "'\\''")
+}
+
+/// sh command running sp with the arguments and writing the output to
+/// `output_path`, over ssh if a host is given, so that only the output is
+/// copied back
+pub fn sp_command(host: &str, args: &str, output_path: &str) -> String {
+    if host.is_empty() {
+        format!("sp{args} -o '{}'", escape(output_path))
+    } else {
+        format!(
+            "ssh -- '{}' '{}' > '{}'",
+            escape(host),
+            escape(&format!("sp{args}")),
+            escape(output_path)
+        )
+    }
+}
+
+/// Statistic of the values of a datasheet, drawn as a line
+#[derive(Debug, Clone)]
+enum Statistic {
+    Mean,
+    Median,
+    /// Percentile (0-100), by the nearest rank
+    Percentile(f64),
+}
+
+impl FromStr for Statistic {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mean" => Ok(Self::Mean),
+            "median" => Ok(Self::Median),
+            s => match s.strip_prefix('p').map(f64::from_str) {
+                Some(Ok(p)) if (0.0..=100.0).contains(&p) => {
+                    Ok(Self::Percentile(p))
+                }
+                _ => bail!(
+                    "Failed to parse statistic: {s} (expected mean, median or pNN)"
+                ),
+            },
+        }
+    }
+}
+
+impl Display for Statistic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mean => write!(f, "mean"),
+            Self::Median => write!(f, "median"),
+            Self::Percentile(p) => write!(f, "p{p}"),
+        }
+    }
+}
+
+impl Statistic {
+    /// Compute the statistic of sorted values
+    fn compute(&self, sorted: &[f64]) -> f64 {
+        let rank = |p: f64| {
+            let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        match self {
+            Self::Mean => sorted.iter().sum::<f64>() / sorted.len() as f64,
+            Self::Median => rank(50.0),
+            Self::Percentile(p) => rank(*p),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -581,6 +1085,8 @@ pub enum Mode {
     Prepare,
     /// Generate the gnuplot script only
     DryRun,
+    /// Preview the plot on the terminal, and plot it once confirmed
+    Preview,
 }
 
 /// Multi-spreadsheet plotter: sp wrapper for creating complex plots with
@@ -597,16 +1103,28 @@ pub struct Cli {
     ///     ITEM = arbitrary string not containing delimeter
     ///   KEY:
     ///     axis = axis indexes to plot on ("12" for x1y2)
-    ///     file = REF of data source file
+    ///     clause = extra text of the plot clause of the data series
+    ///     file = REF of data source file, or glob:PATTERN for one data
+    ///       series per matching file (appended to the input files), whose
+    ///       title may use {stem}, {name} and {dir} for {file.stem},
+    ///       {file.name} and {file.parent} (title is {stem} by default)
+    ///     gpcmd = gnuplot commands to be used before the 'plot' command
+    ///     host = ssh destination (e.g. user@machine) running sp on its
+    ///       files, only the datasheet is copied back
     ///     ifilter = input filter expression
+    ///     jitter = random offset width (X:Y or both) of points
     ///     ofilter = output filter expression
     ///     opseq = transforms to apply on the data
+    ///     panel = panel (1-based) of --layout holding the data series
     ///     plot-type = plot type of the data series
     ///     style = plotting style of the data series
-    ///     title = title of the data series
+    ///     title = title of the data series, where {file} is the resolved
+    ///       path of the input file, followed by .parent for its directory,
+    ///       and by .name, .stem or .ext for the last component, e.g.
+    ///       {file.stem}-{file.parent.name}
     ///     xexpr = x-axis expression
     ///     yexpr = y-axis expression
-    ///     rKEY = KEY's value of series[REF]
+    ///     rKEY = KEY's value of series[REF], earlier or later
     ///       (rfile is illegal)
     /// REF = (+|-)?[num]
     ///   [num]: Absolute index (1-based),
@@ -614,7 +1132,7 @@ pub struct Cli {
     ///   (+|-)[num]: Relative index
     ///     Current index +/- num when referring fields
     ///     Previous file index +/- num when referring files
-    /// NOTE: prefix of keys is also supported (e.g. a for axis).
+    /// NOTE: prefix of keys is also supported (e.g. a for axis, p for plot).
     /// Example:
     ///   file=0 => delimeter=',' (omitted), read from stdin
     ///   |x=$1|op=c|a=21 => delimeter='|', xexpr="$1", opseq="c", axis="21"
@@ -622,7 +1140,11 @@ pub struct Cli {
     ///     delimeter=',',
     ///     xexpr=series[1].xexpr,
     ///     yexpr=previous_series.yexpr
-    #[arg(verbatim_doc_comment, required = true, value_name = "SERIES")]
+    #[arg(
+        verbatim_doc_comment,
+        required_unless_present_any = ["manifest", "template"],
+        value_name = "SERIES"
+    )]
     input_data_series: Vec<InputDataSeries>,
 
     /// Specify how the plotter should behave
@@ -642,7 +1164,12 @@ pub struct Cli {
     pub format: SeparatedOptions<FileFormat>,
 
     /// Path of the output directory [default: system temporary directory]
-    #[arg(short = 'p', value_name = "PATH")]
+    #[arg(
+        short = 'p',
+        long = "out-path",
+        env = "SP_OUTPUT_DIR",
+        value_name = "PATH"
+    )]
     pub out_path: Option<PathBuf>,
 
     /// Default axis for all data series
@@ -662,12 +1189,21 @@ pub struct Cli {
     #[arg(long = "ifilter", value_name = "FILTER", default_value = "true")]
     ifilter: String,
 
+    /// Default jitter width (X:Y or both) for all data series
+    #[arg(long = "jitter", default_value = "")]
+    jitter: String,
+
+    /// Seed of all randomness: jitter (1 by default) and names of the
+    /// temporary files of sp, so that runs are reproducible
+    #[arg(long, env = "SP_SEED")]
+    pub seed: Option<u32>,
+
     /// Default output filter expression for all data series
     #[arg(long = "ofilter", value_name = "FILTER", default_value = "true")]
     ofilter: String,
 
     /// Default operation sequence for all data series
-    #[arg(long = "opseq", default_value = "")]
+    #[arg(long = "opseq", env = "SP_OPSEQ", default_value = "")]
     opseq: String,
 
     /// Default plot type for all data series
@@ -690,18 +1226,33 @@ pub struct Cli {
     #[arg(long = "yexpr", default_value = "1")]
     yexpr: String,
 
+    /// Default ssh destination (e.g. user@machine) running sp for all data
+    /// series, whose input files are read on that machine
+    #[arg(long = "host", env = "SP_HOST", default_value = "")]
+    host: String,
+
     /// Additional gnuplot commands to be used before the 'plot' command
     #[arg(short = 'g', value_name = "CMD", default_value = "")]
     additional_gnuplot_cmd: String,
 
-    /// Size of the plot (width, height)
-    #[arg(long = "size", default_value = "1,0.75")]
+    /// Size of the plot (width, height), either relative to the default
+    /// canvas (e.g. 1,0.75) or physical (e.g. 3.5in,2.2in or 9cm,6cm)
+    #[arg(long = "size", env = "SP_SIZE", default_value = "1,0.75")]
     plot_size: PlotSize,
 
-    /// Font to be used for all labels (family, size)
+    /// Resolution used to convert physical --size for pixel-based terminals
+    /// (10 to 2400)
     #[arg(
-        long = "font",
-        default_value_if("terminal", ArgPredicate::Equals("postscript".into()), "Helvetica,20"))]
+        long,
+        env = "SP_DPI",
+        default_value = "96",
+        value_parser = clap::value_parser!(u32).range(10..=2400)
+    )]
+    dpi: u32,
+
+    /// Font to be used for all labels (family, size) [default: Helvetica,20
+    /// for postscript]
+    #[arg(long = "font", env = "SP_FONT")]
     font: Option<Font>,
 
     /// Position of legends
@@ -709,19 +1260,17 @@ pub struct Cli {
     key_position: String,
 
     /// Font size to be used for all legends [default: same as --font]
-    #[arg(
-        long = "kfont", 
-        value_name = "FONT", 
-        default_value_if("terminal", ArgPredicate::Equals("postscript".into()), "Helvetica,20"))]
+    #[arg(long = "kfont", env = "SP_KEY_FONT", value_name = "FONT")]
     key_font: Option<Font>,
 
     /// Terminal to be used for plotting
-    #[arg(long = "term", default_value = "x11")]
+    #[arg(long = "term", env = "SP_TERMINAL", default_value = "x11")]
     terminal: Terminal,
 
     /// Gnuplot output destination
     #[arg(
         long = "gpout",
+        env = "SP_GPOUT",
         value_name = "PATH",
         default_value = "./msp_out.pdf"
     )]
@@ -748,10 +1297,139 @@ pub struct Cli {
     #[arg(long, value_name = "LIST<CUSTOM_TICS>")]
     custom_tics: Vec<AxisAssociatedOption<CustomTics>>,
 
+    /// Multiplot layout (ROWSxCOLS) of panels, filled row by row, holding
+    /// the data series by their panel key
+    #[arg(long, value_name = "ROWSxCOLS")]
+    layout: Option<Layout>,
+
+    /// Share the x axis between panels, with the range of the first panel
+    /// (unless --range is given) and tic labels only on the bottom row
+    #[arg(long = "shared-x")]
+    shared_x: bool,
+
+    /// Group the data series into one panel per value of a data series key
+    /// (e.g. file), or split each data series by the values of a column
+    /// expression (e.g. $threads), with the legend in the first panel only
+    #[arg(long = "facet-by", value_name = "KEY|EXPR")]
+    facet_by: Option<String>,
+
     /// Show grid with the default style
     #[arg(long)]
     grid: bool,
 
+    /// List of line colors (#rrggbb or color name) used by data series in
+    /// order, prefix hex colors with the delimiter (e.g. ,#0072b2,#d55e00)
+    #[arg(
+        long,
+        env = "SP_PALETTE",
+        value_name = "LIST<COLOR>",
+        default_value = ""
+    )]
+    palette: SeparatedOptions<Color>,
+
+    /// Downsample datasheets with more than N points before plotting, the
+    /// legend of such data series is annotated
+    #[arg(long = "max-points", env = "SP_MAX_POINTS", value_name = "N")]
+    pub max_points: Option<usize>,
+
+    /// List of statistics (mean|median|pNN, e.g. p99) of the y values of each
+    /// data series, drawn as dashed lines in its color and labeled in the
+    /// right margin
+    #[arg(long = "stat-lines", value_name = "LIST<STAT>", default_value = "")]
+    stat_lines: SeparatedOptions<Statistic>,
+
+    /// Compute the statistics of --stat-lines from the x values instead, drawn
+    /// as vertical lines labeled above the plot (e.g. for CDFs)
+    #[arg(long = "vertical-stat-lines")]
+    vertical_stat_lines: bool,
+
+    /// Write the gnuplot script, all datasheets and a README of the
+    /// invocation into DIR, with paths relative to DIR, so the plot could be
+    /// re-rendered elsewhere; DIR ending with .tar.gz or .tgz is written as a
+    /// tarball instead. With dry-run, datasheets are generated for the bundle
+    /// but not plotted
+    #[arg(long = "emit-bundle", value_name = "DIR")]
+    pub emit_bundle: Option<PathBuf>,
+
+    /// Write a JSON document listing the generated datasheets, logs, gnuplot
+    /// script and output file with their sizes, and timings to PATH
+    #[arg(long = "summary-json", value_name = "PATH")]
+    pub summary_json: Option<PathBuf>,
+
+    /// Keep the temporary files, i.e. the copy of stdin, and the datasheets,
+    /// logs and gnuplot script of plots without --out-path, which are removed
+    /// on exit or interruption otherwise, and print their paths
+    #[arg(long)]
+    pub preserve: bool,
+
+    /// Plot the data series whose datasheets are generated when sp fails for
+    /// others, which are marked as failed in the legend
+    #[arg(long = "keep-going")]
+    pub keep_going: bool,
+
+    /// Regenerate the datasheets of all data series, instead of reusing the
+    /// ones of previous runs for unchanged data series
+    #[arg(long = "no-cache")]
+    no_cache: bool,
+
+    /// Format of the error message printed on failure, the exit code tells
+    /// the category of the error: 2 usage, 3 input parse, 4 expression,
+    /// 5 external tool, 6 plot
+    #[arg(
+        long = "error-format",
+        env = "SP_ERROR_FORMAT",
+        value_name = "FORMAT",
+        default_value = "text"
+    )]
+    #[allow(dead_code)]
+    // read from the raw arguments by ErrorFormat::from_args
+    error_format: ErrorFormat,
+
+    /// Format of the log records printed to stderr, whose verbosity is
+    /// controlled by RUST_LOG (e.g. RUST_LOG=info)
+    #[arg(
+        long = "log-format",
+        env = "SP_LOG_FORMAT",
+        value_name = "FORMAT",
+        default_value = "text"
+    )]
+    #[allow(dead_code)]
+    // read from the raw arguments by LogFormat::from_args
+    log_format: LogFormat,
+
+    /// Appearance preset, provides defaults for --term, --font, --kfont,
+    /// --size, --grid and the line palette
+    #[arg(long, env = "SP_PRESET")]
+    preset: Option<Preset>,
+
+    /// Manifest file (TOML) declaring the data series ([[series]] tables
+    /// with the SERIES keys, or a pipeline file as key `pipeline`) and the
+    /// values of other options, which the command line overrides. Its series
+    /// come before the SERIES arguments
+    #[arg(long, env = "SP_MANIFEST", value_name = "PATH")]
+    #[allow(dead_code)]
+    // read from the raw arguments by UserConfig::load_recipe
+    manifest: Option<PathBuf>,
+
+    /// Plot template, provides the data series (one per input file, titled
+    /// by the file name) when no SERIES is given, and defaults for the plot
+    /// type, opseq, labels and legends
+    #[arg(long, env = "SP_TEMPLATE")]
+    template: Option<Template>,
+
+    #[clap(skip)]
+    explicit_args: Vec<String>,
+
+    #[clap(skip)]
+    config_path: Option<PathBuf>,
+
+    #[clap(skip)]
+    panel_titles: Vec<String>,
+
+    /// Indexes of the data series whose datasheets failed to generate
+    #[clap(skip)]
+    pub failed_series: Vec<usize>,
+
     #[clap(skip)]
     pub output_prefix: String,
 
@@ -760,6 +1438,17 @@ pub struct Cli {
 
     #[clap(skip)]
     pub data_series: Vec<DataSeries>,
+
+    #[clap(skip)]
+    decimation_notes: Vec<Option<String>>,
+
+    /// Values of --stat-lines for each data series, from its datasheet
+    #[arg(skip)]
+    stat_values: Vec<Vec<(String, f64)>>,
+
+    /// Whether the outputs are only needed until they are plotted
+    #[arg(skip)]
+    temporary_outputs: bool,
 }
 
 impl Cli {
@@ -770,18 +1459,6 @@ impl Cli {
             .join(format!("msp-{}-{}", self.output_prefix, suffix))
     }
 
-    fn gen_output_prefix() -> String {
-        let mut rng = rand::rng();
-        const CHARSET: &[u8] =
-            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-        (0..8)
-            .map(|_| {
-                let idx = rng.random_range(0..CHARSET.len());
-                CHARSET[idx] as char
-            })
-            .collect()
-    }
-
     pub fn get_output_path(&self, index: usize) -> PathBuf {
         self.out_path.as_ref().unwrap().join(format!(
             "msp-{}-{}.csv",
@@ -790,6 +1467,250 @@ impl Cli {
         ))
     }
 
+    /// Whether datasheets are generated, which dry-run skips unless they are
+    /// bundled
+    pub fn runs_sp(&self) -> bool {
+        !matches!(self.mode, Mode::DryRun) || self.emit_bundle.is_some()
+    }
+
+    /// Arguments of sp to read the input file of index `file`
+    pub fn get_sp_input_args(&self, file: usize) -> String {
+        let input_str = if file == 0 {
+            "".to_string()
+        } else {
+            format!(
+                " -i '{}'",
+                escape(&self.input_paths[file - 1].display().to_string())
+            )
+        };
+        let header_str = match self
+            .header_presence
+            .as_slice()
+            .iter()
+            .find(|p| p.index == file)
+        {
+            Some(p) if p.presence => " --header true",
+            Some(_) => " --header false",
+            None => "",
+        };
+        let format_str =
+            match self.format.as_slice().iter().find(|p| p.index == file) {
+                Some(p) => format!(" -f {}", p.format),
+                None => "".to_string(),
+            };
+        format!("{input_str}{header_str}{format_str}")
+    }
+
+    /// Distinct values of a column expression in the input files of all data
+    /// series, in the order of the merged datasheets of sp
+    fn facet_values(&self, expr: &str) -> anyhow::Result<Vec<String>> {
+        let mut files = self
+            .data_series
+            .iter()
+            .map(|ds| (ds.file, ds.host.as_str()))
+            .collect::<Vec<_>>();
+        files.dedup();
+        let mut values = Vec::<String>::new();
+        for (i, (file, host)) in files.into_iter().enumerate() {
+            let out_path = env::temp_dir().join(format!(
+                "msp-{}-facet-{}.csv",
+                self.output_prefix,
+                i + 1
+            ));
+            let command = sp_command(
+                host,
+                &format!(
+                    "{} -m dump -x '{}' -y 1 -e m",
+                    self.get_sp_input_args(file),
+                    escape(expr)
+                ),
+                &out_path.display().to_string(),
+            );
+            tracing::info!(command, "Finding facet values");
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .stdin(self.get_sp_stdin(file)?)
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .context("Failed to run sp")
+                .with_category(ErrorCategory::ExternalTool)?
+                .wait_with_output()?;
+            if !output.status.success() {
+                bail!(
+                    "Failed to find values of '{expr}' in input #{file}: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            let content = std::fs::read_to_string(&out_path)?;
+            let _ = std::fs::remove_file(&out_path);
+            for line in content.lines().skip(1).filter(|l| !l.is_empty()) {
+                let value = line
+                    .rsplit_once(',')
+                    .map_or(line, |(x, _)| x)
+                    .trim_matches('"')
+                    .to_string();
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Assign the data series to one panel per group, splitting every data
+    /// series by the values of a column expression if the facet is not a key
+    fn apply_facet(&mut self, facet: &str) -> anyhow::Result<()> {
+        let titles = match InputDataSeries::do_get_matched_key(facet, false) {
+            Ok(key) => {
+                let mut values = Vec::<String>::new();
+                for ds in self.data_series.iter_mut() {
+                    let value = ds.get(&key);
+                    ds.panel_index =
+                        match values.iter().position(|v| *v == value) {
+                            Some(i) => i,
+                            None => {
+                                values.push(value);
+                                values.len() - 1
+                            }
+                        };
+                }
+                values.iter().map(|v| format!("{key}={v}")).collect()
+            }
+            Err(_) => {
+                if which::which("sp").is_err() {
+                    return Err(anyhow::anyhow!("sp is not installed"))
+                        .with_category(ErrorCategory::ExternalTool);
+                }
+                let values = self.facet_values(facet)?;
+                self.data_series = self
+                    .data_series
+                    .iter()
+                    .flat_map(|ds| {
+                        values.iter().enumerate().map(|(i, value)| {
+                            let mut ds = ds.clone();
+                            ds.ifilter = format!(
+                                "({}) AND CAST(({facet}) AS VARCHAR) = '{}'",
+                                ds.ifilter,
+                                value.replace('\'', "''")
+                            );
+                            ds.panel_index = i;
+                            ds
+                        })
+                    })
+                    .collect();
+                values.iter().map(|v| format!("{facet}={v}")).collect()
+            }
+        };
+        self.panel_titles = titles;
+        let panels = self.panel_titles.len();
+        if self.layout.is_none() {
+            let cols = (panels as f64).sqrt().ceil() as usize;
+            self.layout = Some(Layout {
+                rows: panels.div_ceil(cols),
+                cols,
+            });
+        }
+        Ok(())
+    }
+
+    /// Path of the datasheet of a previous run with the same sp arguments,
+    /// `SP_*` variables, input file, user configuration and the other files
+    /// sp reads (operator sequence and recipe files, external operators), if
+    /// caching applies to the series
+    pub fn get_cache_path(
+        &self,
+        index: usize,
+        sp_args: &str,
+    ) -> Option<PathBuf> {
+        let file = self.data_series[index].file;
+        // the content of stdin and remote files is unknown before reading it
+        if self.no_cache
+            || file == 0
+            || !self.data_series[index].host.is_empty()
+        {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        env!("VERSION").hash(&mut hasher);
+        sp_args.hash(&mut hasher);
+        // sp inherits the variables, e.g. SP_RECIPE naming a file
+        let mut vars = env::vars()
+            .filter(|(name, _)| name.starts_with("SP_"))
+            .collect::<Vec<_>>();
+        vars.sort();
+        vars.hash(&mut hasher);
+        let referenced = vars
+            .iter()
+            .map(|(_, value)| value.as_str())
+            .chain(self.data_series[index].opseq.strip_prefix('@'))
+            .map(PathBuf::from)
+            .filter(|path| path.is_file())
+            .chain(ExternalOperator::discover().into_iter().filter_map(
+                |name| {
+                    which::which(format!("{}{name}", ExternalOperator::PREFIX))
+                        .ok()
+                },
+            ));
+        for path in [
+            Some(self.input_paths[file - 1].clone()),
+            self.config_path.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(referenced)
+        {
+            let metadata = std::fs::metadata(&path).ok()?;
+            path.hash(&mut hasher);
+            metadata.len().hash(&mut hasher);
+            metadata.modified().ok()?.hash(&mut hasher);
+        }
+        Some(
+            self.out_path
+                .as_ref()
+                .unwrap()
+                .join(format!("msp-cache-{:016x}.csv", hasher.finish())),
+        )
+    }
+
+    /// Cached datasheets in the output directory, newest first
+    pub fn cached_datasheets(&self) -> Vec<(PathBuf, SystemTime)> {
+        let mut datasheets = std::fs::read_dir(self.out_path.as_ref().unwrap())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                if !name.starts_with("msp-cache-") || !name.ends_with(".csv") {
+                    return None;
+                }
+                Some((entry.path(), entry.metadata().ok()?.modified().ok()?))
+            })
+            .collect::<Vec<_>>();
+        datasheets.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+        datasheets
+    }
+
+    /// Output file written by gnuplot, only postscript terminals write one
+    pub fn get_gnuplot_output(&self) -> Option<PathBuf> {
+        matches!(self.terminal(), spreadsheet_plotter::Terminal::Postscript)
+            .then(|| PathBuf::from(&self.gp_out))
+    }
+
+    /// Track the files only needed until they are plotted, which are removed
+    /// on exit
+    fn track_temp_files(&self) {
+        if !self.temporary_outputs {
+            return;
+        }
+        for i in 0..self.data_series.len() {
+            TempArtifacts::track(self.get_output_path(i));
+            TempArtifacts::track(self.get_log_path(i));
+        }
+        TempArtifacts::track(self.get_temp_file_name(".gp"));
+        TempArtifacts::track(self.get_temp_file_name("preview.gp"));
+    }
+
     pub fn get_log_path(&self, index: usize) -> PathBuf {
         self.out_path.as_ref().unwrap().join(format!(
             "msp-{}-{}.log",
@@ -798,69 +1719,130 @@ impl Cli {
         ))
     }
 
-    fn convert_single_data_series(
-        ds: &mut InputDataSeries,
-        default_series: &InputDataSeries,
-        converted_dss: &mut Vec<DataSeries>,
+    /// Expand data series with a glob pattern as file into one data series
+    /// per matching file, in alphabetical order
+    fn expand_globs(&mut self) -> anyhow::Result<()> {
+        let mut expanded = Vec::with_capacity(self.input_data_series.len());
+        for ids in std::mem::take(&mut self.input_data_series) {
+            let Some(pattern) = &ids.glob else {
+                expanded.push(ids);
+                continue;
+            };
+            let paths = glob::glob(pattern)
+                .map_err(|e| {
+                    anyhow::anyhow!("Invalid glob pattern '{pattern}': {e}")
+                })?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to match glob pattern '{pattern}': {e}"
+                    )
+                })?;
+            if paths.is_empty() {
+                bail!("No file matches glob pattern '{pattern}'");
+            }
+            for path in paths {
+                let mut file_ids = ids.clone();
+                file_ids.glob = None;
+                let title = match &ids.title {
+                    Field::Instant(title) => Some(title.as_str()),
+                    Field::Default => Some("{stem}"),
+                    _ => None,
+                };
+                // shorthands of the {file...} placeholders
+                if let Some(title) = title {
+                    file_ids.title = Field::Instant(
+                        title
+                            .replace("{stem}", "{file.stem}")
+                            .replace("{name}", "{file.name}")
+                            .replace("{dir}", "{file.parent}"),
+                    );
+                }
+                self.input_paths.push(path);
+                file_ids.file = Field::Instant(self.input_paths.len());
+                expanded.push(file_ids);
+            }
+        }
+        self.input_data_series = expanded;
+        Ok(())
+    }
+
+    /// Whether the value of an argument should take precedence over the
+    /// preset, i.e. no preset is used or the argument is given explicitly
+    fn overrides_preset(&self, id: &str) -> bool {
+        self.preset.is_none() || self.explicit_args.iter().any(|a| a == id)
+    }
+
+    fn terminal(&self) -> spreadsheet_plotter::Terminal {
+        match &self.preset {
+            Some(preset) if !self.overrides_preset("terminal") => {
+                spreadsheet_plotter::Preset::from(preset.clone()).terminal()
+            }
+            _ => self.terminal.clone().into(),
+        }
+    }
+
+    /// Resolve the file index of a data series, relative to the file of the
+    /// previous data series
+    fn convert_file(
+        file: &mut Field<usize>,
+        default: &Field<usize>,
+        last_index: usize,
     ) -> anyhow::Result<()> {
-        // use separated logic for input_file
-        if matches!(ds.file, Field::Default) {
-            ds.file = default_series.file.clone();
+        if matches!(file, Field::Default) {
+            *file = default.clone();
         }
-        let last_index = converted_dss.last().map(|ds| ds.file).unwrap_or(0);
-        match ds.file {
+        match *file {
             Field::PositiveRelative(index) => {
-                ds.file = Field::Instant(last_index + index);
+                *file = Field::Instant(last_index + index);
             }
             Field::NegativeRelative(index) => {
                 if index > last_index {
                     bail!(
                         "Referencing minus file index (required {}, base {})",
-                        ds.file,
+                        file,
                         last_index
                     );
                 }
-                ds.file = Field::Instant(last_index - index);
+                *file = Field::Instant(last_index - index);
             }
             Field::Absolute(index) => {
-                ds.file = Field::Instant(index);
+                *file = Field::Instant(index);
             }
             _ => {}
         };
+        Ok(())
+    }
+
+    fn convert_fields(&mut self) -> anyhow::Result<()> {
+        let default_series = InputDataSeries::default();
+        // files are relative to the previous one, hence resolved in order
+        let mut last_index = 0;
+        for ids in self.input_data_series.iter_mut() {
+            Self::convert_file(
+                &mut ids.file,
+                &default_series.file,
+                last_index,
+            )?;
+            last_index = ids.file.clone().try_into()?;
+        }
 
-        let index = converted_dss.len();
         macro_rules! convert_field {
             ($field:ident) => {
-                match ds.$field {
-                    Field::Default => ds.$field = default_series.$field.clone(),
-                    Field::Instant(_) => {}
-                    Field::Absolute(i) => {
-                        if i > index {
-                            bail!(
-                                "Index {} larger then current index {}",
-                                i,
-                                index
-                            );
-                        }
-                        ds.$field =
-                            Field::Instant(converted_dss[i - 1].$field.clone());
-                    }
-                    Field::NegativeRelative(i) => {
-                        if i >= index {
-                            bail!(
-                                "Index -{} is out of range (expected [1, {}])",
-                                i,
-                                index
-                            );
-                        }
-
-                        ds.$field = Field::Instant(
-                            converted_dss[index - i - 1].$field.clone(),
-                        );
-                    }
-                    Field::PositiveRelative(_) => {
-                        bail!("Forward reference is not allowed");
-                    }
+                convert_field!($field, stringify!($field))
+            };
+            ($field:ident, $key:expr) => {
+                let fields = self
+                    .input_data_series
+                    .iter()
+                    .map(|ids| ids.$field.clone())
+                    .collect::<Vec<_>>();
+                let values =
+                    resolve_field($key, &fields, &default_series.$field)?;
+                for (ids, value) in
+                    self.input_data_series.iter_mut().zip(values)
+                {
+                    ids.$field = Field::Instant(value);
                 }
             };
         }
@@ -868,31 +1850,23 @@ impl Cli {
         convert_field!(style);
         convert_field!(title);
         convert_field!(ifilter);
+        convert_field!(jitter);
         convert_field!(ofilter);
         convert_field!(xexpr);
         convert_field!(yexpr);
         convert_field!(opseq);
-        convert_field!(plot_type);
-
-        converted_dss.push(ds.clone().try_into()?);
-
-        Ok(())
-    }
-
-    fn convert_fields(&mut self) -> anyhow::Result<()> {
-        let default_series = InputDataSeries::default();
-        self.data_series = self.input_data_series.iter_mut().try_fold(
-            Vec::<DataSeries>::new(),
-            |mut converted_dss, ds| {
-                Self::convert_single_data_series(
-                    ds,
-                    &default_series,
-                    &mut converted_dss,
-                )?;
-
-                Ok::<_, anyhow::Error>(converted_dss)
-            },
-        )?;
+        convert_field!(plot_type, "plot");
+        convert_field!(clause);
+        convert_field!(gpcmd);
+        convert_field!(panel);
+        convert_field!(host);
+
+        self.data_series = self
+            .input_data_series
+            .iter()
+            .cloned()
+            .map(DataSeries::try_from)
+            .collect::<anyhow::Result<_>>()?;
         Ok(())
     }
 
@@ -912,7 +1886,9 @@ impl Cli {
                         ids.file
                     );
                 }
-                if !matches!(self.mode, Mode::DryRun)
+                // files of remote data series are on their host
+                if self.runs_sp()
+                    && ds.host.is_empty()
                     && !self.input_paths[ds.file - 1].exists()
                 {
                     bail!(
@@ -926,55 +1902,293 @@ impl Cli {
             })
     }
 
-    fn build_stdin_content(&self) -> anyhow::Result<String> {
-        // if nobody references stdin, do not bother reading it
-        if self.data_series.iter().all(|ds| ds.file != 0) {
-            return Ok("".to_string());
+    /// Replace the {file...} placeholders in titles with parts of the input
+    /// file of each data series
+    fn expand_titles(&mut self) -> anyhow::Result<()> {
+        for (index, ds) in self.data_series.iter_mut().enumerate() {
+            if !TITLE_PLACEHOLDER.is_match(&ds.title) {
+                continue;
+            }
+            if ds.file == 0 {
+                bail!(
+                    "Title '{}' of data series #{} refers to the input file, but it reads STDIN",
+                    ds.title,
+                    index + 1
+                );
+            }
+            let path = &self.input_paths[ds.file - 1];
+            // remote files and those of dry-run may not exist
+            let path = std::fs::canonicalize(path).unwrap_or(path.clone());
+            let mut error = None;
+            let title =
+                TITLE_PLACEHOLDER.replace_all(&ds.title, |caps: &Captures| {
+                    file_placeholder(&path, &caps[1]).unwrap_or_else(|e| {
+                        error.get_or_insert(e);
+                        String::new()
+                    })
+                });
+            if let Some(e) = error {
+                return Err(e.context(format!(
+                    "Invalid title '{}' of data series #{}",
+                    ds.title,
+                    index + 1
+                )));
+            }
+            ds.title = title.into_owned();
         }
+        Ok(())
+    }
 
-        let mut stdin_content = String::new();
-        std::io::stdin().read_to_string(&mut stdin_content)?;
-        Ok(stdin_content)
+    pub fn reads_stdin(&self) -> bool {
+        self.data_series.iter().any(|ds| ds.file == 0)
     }
 
-    fn build_gnuplot_cmd(&self) -> anyhow::Result<String> {
-        let data_series_options = self
-            .data_series
-            .iter()
-            .enumerate()
-            .map(|(i, ds)| {
-                let plot_type = if ds.plot_type.is_empty() {
-                    &self.plot_type
-                } else {
-                    &ds.plot_type
-                };
-                let plot_type = match plot_type.to_ascii_lowercase().as_str() {
-                    "points" => PlotType::Points(None),
-                    "lines" => PlotType::Lines(None),
-                    "linespoints" => PlotType::Linespoints(None, None),
-                    _ => bail!("Unknown plot type '{plot_type}'"),
-                };
-                let style = if ds.style.is_empty() {
-                    None
-                } else {
-                    Some(&ds.style)
+    /// Path of the copy of stdin, which every data series reading it replays
+    pub fn get_stdin_path(&self) -> PathBuf {
+        self.get_temp_file_name("stdin")
+    }
+
+    /// Copy stdin to disk, so that inputs of any size are not held in memory
+    fn spill_stdin(&self) -> anyhow::Result<()> {
+        let path = self.get_stdin_path();
+        // the copy of stdin may be large, and is useless once sp exits
+        TempArtifacts::track(&path);
+        let mut file = File::create_new(&path).context(format!(
+            "Failed to create '{}' for stdin",
+            path.display()
+        ))?;
+        std::io::copy(&mut std::io::stdin().lock(), &mut file)
+            .context(format!("Failed to copy stdin to '{}'", path.display()))?;
+        Ok(())
+    }
+
+    /// stdin of sp reading the input file of index `file`
+    pub fn get_sp_stdin(&self, file: usize) -> anyhow::Result<Stdio> {
+        if file != 0 {
+            return Ok(Stdio::null());
+        }
+        let path = self.get_stdin_path();
+        let spilled = File::open(&path)
+            .context(format!("Failed to open '{}'", path.display()))?;
+        Ok(spilled.into())
+    }
+
+    /// Downsample the generated datasheets and regenerate the gnuplot script
+    /// to annotate decimated data series
+    pub fn decimate_datasheets(
+        &mut self,
+        max_points: usize,
+    ) -> anyhow::Result<()> {
+        self.decimation_notes = (0..self.data_series.len())
+            .map(|i| {
+                if self.failed_series.contains(&i) {
+                    return Ok(None);
+                }
+                let path = self.get_output_path(i);
+                let mut points = match DataPoints::from_csv(&path) {
+                    Ok(points) => points,
+                    Err(e) => {
+                        tracing::warn!(
+                            "Series #{} is not decimated: {e}",
+                            i + 1
+                        );
+                        return Ok(None);
+                    }
                 };
-                let title = if ds.title.is_empty() {
-                    None
-                } else {
-                    Some(&ds.title)
+                let note = points.decimate(max_points);
+                if note.is_some() {
+                    DataSeriesSource::Points(points).dump(Some(path))?;
+                }
+                Ok(note)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.update_gnuplot_cmd()
+    }
+
+    /// Compute the statistics of --stat-lines from the datasheets, before
+    /// they are decimated
+    pub fn compute_stat_lines(&mut self) -> anyhow::Result<()> {
+        let stats = self.stat_lines.as_slice();
+        if stats.is_empty() {
+            return Ok(());
+        }
+        self.stat_values = (0..self.data_series.len())
+            .map(|i| {
+                if self.failed_series.contains(&i) {
+                    return Vec::new();
+                }
+                let points = match DataPoints::from_csv(self.get_output_path(i))
+                {
+                    Ok(points) => points,
+                    Err(e) => {
+                        tracing::warn!(
+                            "Statistics of series #{} are not drawn: {e}",
+                            i + 1
+                        );
+                        return Vec::new();
+                    }
                 };
-                let options = DataSeriesOptions::from_datasheet_path(
-                    self.get_output_path(i).display().to_string(),
-                )
-                .with_plot_type(plot_type)
-                .with_additional_option(style)
-                .with_label(title)
-                .with_use_x2(ds.use_x2)
-                .with_use_y2(ds.use_y2);
-                Ok(options)
+                let mut values = points
+                    .points
+                    .iter()
+                    .map(|&(x, y)| if self.vertical_stat_lines { x } else { y })
+                    .filter(|v| !v.is_nan())
+                    .collect::<Vec<_>>();
+                values.sort_by(f64::total_cmp);
+                if values.is_empty() {
+                    return Vec::new();
+                }
+                stats
+                    .iter()
+                    .map(|stat| (stat.to_string(), stat.compute(&values)))
+                    .collect()
             })
-            .collect::<Result<Vec<DataSeriesOptions>, anyhow::Error>>()?;
+            .collect();
+        self.update_gnuplot_cmd()
+    }
+
+    /// Regenerate the gnuplot script, e.g. after datasheets are decimated or
+    /// failed to generate
+    pub fn update_gnuplot_cmd(&mut self) -> anyhow::Result<()> {
+        let datasheet_paths = (0..self.data_series.len())
+            .map(|i| self.get_output_path(i).display().to_string())
+            .collect::<Vec<_>>();
+        self.gpcmd = self.build_gnuplot_cmd(&datasheet_paths)?;
+        Ok(())
+    }
+
+    /// Name of the datasheet of the data series in a bundle directory
+    pub fn get_bundle_datasheet_name(index: usize) -> String {
+        format!("series-{}.csv", index + 1)
+    }
+
+    /// Name of the output file in a bundle directory, with the extension of
+    /// the output file, if the terminal writes one
+    pub fn get_bundle_output_name(&self) -> Option<String> {
+        if !matches!(self.terminal(), spreadsheet_plotter::Terminal::Postscript)
+        {
+            return None;
+        }
+        Some(match Path::new(&self.gp_out).extension() {
+            Some(ext) => format!("plot.{}", ext.to_string_lossy()),
+            None => "plot".to_string(),
+        })
+    }
+
+    /// gnuplot script referring to datasheets and the output relative to the
+    /// directory of the script, executable with a plain `#!/usr/bin/env
+    /// gnuplot` shebang as `env -S` and a persistent window are not expected
+    /// where the bundle is replayed
+    pub fn build_bundle_gnuplot_cmd(&self) -> anyhow::Result<String> {
+        let datasheet_paths = (0..self.data_series.len())
+            .map(Self::get_bundle_datasheet_name)
+            .collect::<Vec<_>>();
+        let script = self
+            .build_gnuplot_template(&datasheet_paths)?
+            .with_output(self.get_bundle_output_name())
+            .with_script_relative_paths(true)
+            .to_string();
+        let body = script
+            .strip_prefix("#!")
+            .and_then(|s| s.split_once('\n'))
+            .map_or(script.as_str(), |(_, body)| body);
+        Ok(format!("#!/usr/bin/env gnuplot\n{body}"))
+    }
+
+    fn build_gnuplot_cmd(
+        &self,
+        datasheet_paths: &[String],
+    ) -> anyhow::Result<String> {
+        Ok(self.build_gnuplot_template(datasheet_paths)?.to_string())
+    }
+
+    /// gnuplot script plotting on the dumb terminal, to preview the plot
+    pub fn build_preview_gnuplot_cmd(&self) -> anyhow::Result<String> {
+        let datasheet_paths = (0..self.data_series.len())
+            .map(|i| self.get_output_path(i).display().to_string())
+            .collect::<Vec<_>>();
+        Ok(self
+            .build_gnuplot_template(&datasheet_paths)?
+            .with_terminal(spreadsheet_plotter::Terminal::Dumb(None, None))
+            .with_output(None::<&str>)
+            .with_font(None::<(&str, usize)>)
+            .with_canvas_size(None)
+            .to_string())
+    }
+
+    /// Replace ranges of axes (e.g. x=0:10,y=1:5) given by --range
+    pub fn set_ranges(&mut self, ranges: &str) -> anyhow::Result<()> {
+        let ranges: SeparatedOptions<AxisAssociatedOption<Range>> =
+            ranges.parse()?;
+        for range in ranges.opts {
+            self.range.opts.retain(|r| r.axis != range.axis);
+            self.range.opts.push(range);
+        }
+        self.update_gnuplot_cmd()
+    }
+
+    fn build_gnuplot_template(
+        &self,
+        datasheet_paths: &[String],
+    ) -> anyhow::Result<GnuplotTemplate> {
+        let data_series_options =
+            self.data_series
+                .iter()
+                .enumerate()
+                .map(|(i, ds)| {
+                    let plot_type = if ds.plot_type.is_empty() {
+                        &self.plot_type
+                    } else {
+                        &ds.plot_type
+                    };
+                    let plot_type =
+                        match plot_type.to_ascii_lowercase().as_str() {
+                            "points" => PlotType::Points(None),
+                            "lines" => PlotType::Lines(None),
+                            "linespoints" => PlotType::Linespoints(None, None),
+                            _ => bail!("Unknown plot type '{plot_type}'"),
+                        };
+                    let style = [ds.style.as_str(), ds.clause.as_str()]
+                        .into_iter()
+                        .filter(|s| !s.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let style = (!style.is_empty()).then_some(style);
+                    // untitled data series use the column header, which already
+                    // carries the decimation note
+                    let title = if ds.title.is_empty() {
+                        None
+                    } else {
+                        match self.decimation_notes.get(i) {
+                            Some(Some(note)) => {
+                                Some(format!("{} {note}", ds.title))
+                            }
+                            _ => Some(ds.title.clone()),
+                        }
+                    };
+                    let failed = self.failed_series.contains(&i);
+                    let title = match title {
+                        None if failed => Some(format!("series #{}", i + 1)),
+                        title => title,
+                    };
+                    let options = DataSeriesOptions::from_datasheet_path(
+                        &datasheet_paths[i],
+                    )
+                    .with_plot_type(plot_type)
+                    .with_additional_option(style)
+                    .with_label(title)
+                    .with_use_x2(ds.use_x2)
+                    .with_use_y2(ds.use_y2)
+                    .with_jitter(ds.jitter_widths)
+                    .with_panel(ds.panel_index)
+                    .with_failed(failed)
+                    .with_stat_lines(
+                        self.stat_values.get(i).cloned().unwrap_or_default(),
+                        self.vertical_stat_lines,
+                    );
+                    Ok(options)
+                })
+                .collect::<Result<Vec<DataSeriesOptions>, anyhow::Error>>()?;
 
         fn build_axis_options(
             opt: AxisOptions,
@@ -1067,25 +2281,73 @@ impl Cli {
             .map(|f| (f.family.as_str(), f.size))
             .or(font);
 
-        let gnuplot_template = GnuplotTemplate::default()
-            .with_additional_command(Some(self.additional_gnuplot_cmd.clone()))
-            .with_data_series_options(data_series_options)
-            .with_xopt(xopt)
-            .with_yopt(yopt)
-            .with_x2opt(x2opt)
-            .with_y2opt(y2opt)
-            .with_terminal(self.terminal.clone().into())
-            .with_font(font)
-            .with_grid(self.grid)
-            .with_key_font(key_font)
-            .with_key_position(self.key_position.clone())
-            .with_output(Some(&self.gp_out))
-            .with_plot_size(
-                self.plot_size.width as f64,
-                self.plot_size.height as f64,
-            );
+        // per-series commands follow the global ones, so they take precedence
+        let additional_command =
+            std::iter::once(self.additional_gnuplot_cmd.clone())
+                .chain(
+                    self.data_series
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, ds)| !ds.gpcmd.is_empty())
+                        .map(|(i, ds)| {
+                            format!("# series {}\n{}", i + 1, ds.gpcmd)
+                        }),
+                )
+                .filter(|cmd| !cmd.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
 
-        Ok(gnuplot_template.to_string())
+        let mut gnuplot_template = match &self.preset {
+            Some(preset) => {
+                GnuplotTemplate::default().with_preset(preset.clone().into())
+            }
+            None => GnuplotTemplate::default(),
+        }
+        .with_additional_command(Some(additional_command))
+        .with_data_series_options(data_series_options)
+        .with_xopt(xopt)
+        .with_yopt(yopt)
+        .with_x2opt(x2opt)
+        .with_y2opt(y2opt)
+        .with_terminal(self.terminal())
+        .with_key_position(self.key_position.clone())
+        .with_output(Some(&self.gp_out))
+        .with_layout(self.layout.as_ref().map(|l| (l.rows, l.cols)))
+        .with_shared_x(self.shared_x)
+        .with_panel_titles(self.panel_titles.clone())
+        .with_shared_key(self.facet_by.is_some())
+        .with_seed(
+            self.data_series
+                .iter()
+                .any(|ds| ds.jitter_widths.is_some())
+                .then_some(self.seed.unwrap_or(1)),
+        );
+        if self.overrides_preset("font") {
+            gnuplot_template = gnuplot_template.with_font(font);
+        }
+        if self.overrides_preset("key_font") || self.overrides_preset("font") {
+            gnuplot_template = gnuplot_template.with_key_font(key_font);
+        }
+        if self.overrides_preset("grid") {
+            gnuplot_template = gnuplot_template.with_grid(self.grid);
+        }
+        if !self.palette.as_slice().is_empty() {
+            gnuplot_template =
+                gnuplot_template.with_palette(self.palette.as_slice().to_vec());
+        }
+        if self.overrides_preset("plot_size") {
+            gnuplot_template = match self.plot_size {
+                PlotSize::Relative { width, height } => gnuplot_template
+                    .with_plot_size(width as f64, height as f64)
+                    .with_canvas_size(None),
+                PlotSize::Physical { width, height } => gnuplot_template
+                    .with_plot_size(1.0, 1.0)
+                    .with_canvas_size(Some((width, height))),
+            };
+        }
+        gnuplot_template = gnuplot_template.with_dpi(self.dpi);
+
+        Ok(gnuplot_template)
     }
 
     /// Set default value of InputDataSeries according to command line options
@@ -1095,6 +2357,7 @@ impl Cli {
 
         ds.file = self.file.clone();
         ds.ifilter = Field::Instant(self.ifilter.clone());
+        ds.jitter = Field::Instant(self.jitter.clone());
         ds.ofilter = Field::Instant(self.ofilter.clone());
         ds.xexpr = Field::Instant(self.xexpr.clone());
         ds.yexpr = Field::Instant(self.yexpr.clone());
@@ -1103,29 +2366,114 @@ impl Cli {
         ds.style = Field::Instant(self.style.clone());
         ds.plot_type = Field::Instant(self.plot_type.clone());
         ds.axis = Field::Instant(self.axis.clone());
+        ds.host = Field::Instant(self.host.clone());
     }
 
     pub fn parse_args() -> anyhow::Result<Self> {
-        let mut cli = Self::parse();
+        let config = UserConfig::load()?;
+        let manifest = UserConfig::load_recipe("--manifest", "SP_MANIFEST")?;
+        let apply_files = |cmd| -> anyhow::Result<clap::Command> {
+            let mut cmd = config.apply_defaults("msp", cmd)?;
+            if let Some(manifest) = &manifest {
+                cmd = manifest.apply_recipe(cmd, &["series"])?;
+            }
+            Ok(cmd)
+        };
+        // the template may come from any source, but its defaults are below
+        // the ones of the configuration file and the manifest
+        let template = apply_files(Self::command())?
+            .ignore_errors(true)
+            .try_get_matches()
+            .ok()
+            .and_then(|m| m.get_one::<Template>("template").cloned());
+        let mut cmd = Self::command();
+        if let Some(template) = &template {
+            cmd = template.apply_defaults(cmd);
+        }
+        let matches = get_matches(apply_files(cmd)?)?;
+        Self::from_matches(&matches, config.path(), manifest.as_ref())
+    }
 
-        if !matches!(cli.mode, Mode::DryRun) && which::which("sp").is_err() {
-            bail!("sp is not installed");
+    /// Options of parsed arguments, with the data series of the manifest
+    fn from_matches(
+        matches: &clap::ArgMatches,
+        config_path: Option<&PathBuf>,
+        manifest: Option<&UserConfig>,
+    ) -> anyhow::Result<Self> {
+        let mut cli = Self::from_arg_matches(matches)?;
+        if let Some(manifest) = manifest {
+            let series = match manifest.get("series") {
+                None => vec![],
+                Some(toml::Value::Array(series)) => series
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| {
+                        s.as_table()
+                            .ok_or_else(|| {
+                                anyhow::anyhow!("series[{i}] must be a table")
+                            })
+                            .and_then(InputDataSeries::from_table)
+                            .context(format!("Invalid series[{i}] in manifest"))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+                Some(_) => {
+                    bail!("series in manifest must be an array of tables")
+                }
+            };
+            cli.input_data_series.splice(0..0, series);
+        }
+        if let Some(template) = &cli.template
+            && cli.input_data_series.is_empty()
+        {
+            cli.input_data_series = template.series(&cli.input_paths)?;
+        }
+        if cli.input_data_series.is_empty() {
+            bail!(
+                "No data series given, neither as SERIES nor in the manifest"
+            );
+        }
+        cli.expand_globs()?;
+        cli.config_path = config_path.cloned();
+        cli.explicit_args =
+            ["terminal", "font", "key_font", "plot_size", "grid"]
+                .into_iter()
+                .filter(|id| {
+                    matches!(
+                        matches.value_source(id),
+                        Some(ValueSource::CommandLine)
+                    )
+                })
+                .map(|id| id.to_string())
+                .collect();
+
+        if cli.runs_sp() && which::which("sp").is_err() {
+            return Err(anyhow::anyhow!("sp is not installed"))
+                .with_category(ErrorCategory::ExternalTool);
         }
 
         cli.fill_defaults();
         cli.convert_fields()?;
         cli.check_file()?;
+        cli.expand_titles()?;
+        if cli.runs_sp()
+            && cli.data_series.iter().any(|ds| !ds.host.is_empty())
+            && which::which("ssh").is_err()
+        {
+            return Err(anyhow::anyhow!("ssh is not installed"))
+                .with_category(ErrorCategory::ExternalTool);
+        }
 
-        cli.output_prefix = Self::gen_output_prefix();
-
-        let stdin_content = cli.build_stdin_content()?;
-        STDIN_CONTENT.get_or_init(|| stdin_content);
+        // msp itself draws names from the unseeded generator, so that its
+        // files are not predictable even with --seed
+        cli.output_prefix = Plotter::random_name(8);
 
+        cli.temporary_outputs = cli.out_path.is_none()
+            && matches!(cli.mode, Mode::Plot | Mode::Preview);
         if cli.out_path.is_none() {
             cli.out_path = Some(env::temp_dir());
         }
 
-        if !matches!(cli.mode, Mode::DryRun)
+        if (cli.runs_sp() || cli.reads_stdin())
             && !cli.out_path.as_ref().unwrap().is_dir()
         {
             std::fs::create_dir_all(cli.out_path.as_ref().unwrap()).context(
@@ -1135,20 +2483,147 @@ impl Cli {
                 ),
             )?;
         }
+        if cli.reads_stdin() {
+            cli.spill_stdin()?;
+        }
 
+        if cli.font.is_none() && matches!(cli.terminal, Terminal::Postscript) {
+            cli.font = Some(Font {
+                family: "Helvetica".to_string(),
+                size: 20,
+            });
+        }
         if cli.key_font.is_none() {
             cli.key_font = cli.font.clone();
         }
 
         if !matches!(cli.mode, Mode::DryRun)
-            && matches!(cli.terminal, Terminal::Postscript)
+            && matches!(
+                cli.terminal(),
+                spreadsheet_plotter::Terminal::Postscript
+            )
             && which::which("ps2pdf").is_err()
         {
-            bail!("ps2pdf is not installed");
+            return Err(anyhow::anyhow!("ps2pdf is not installed"))
+                .with_category(ErrorCategory::ExternalTool);
+        }
+
+        if cli.shared_x && cli.layout.is_none() && cli.facet_by.is_none() {
+            bail!("--shared-x requires --layout or --facet-by");
+        }
+        if let Some(facet) = cli.facet_by.clone() {
+            cli.apply_facet(&facet)?;
+        }
+        let panels = cli.layout.as_ref().map(|l| l.rows * l.cols).unwrap_or(1);
+        if let Some(ds) =
+            cli.data_series.iter().find(|ds| ds.panel_index >= panels)
+        {
+            bail!(
+                "Panel {} is out of the layout ({panels} panels, see --layout)",
+                ds.panel_index + 1
+            );
         }
 
-        cli.gpcmd = cli.build_gnuplot_cmd()?;
+        cli.update_gnuplot_cmd()?;
+        cli.track_temp_files();
 
         Ok(cli)
     }
 }
+
+#[cfg(test)]
+fn parse_test_args(args: &[&str]) -> anyhow::Result<Cli> {
+    let matches = Cli::command().try_get_matches_from(
+        ["msp", "-m", "dry-run", "-i", "Cargo.toml"]
+            .into_iter()
+            .chain(args.iter().copied()),
+    )?;
+    Cli::from_matches(&matches, None, None)
+}
+
+#[test]
+fn test_dpi_bounds() {
+    let series = "file=1,x=$1,y=$2,t=a";
+    assert!(parse_test_args(&["--dpi", "9", series]).is_err());
+    assert!(parse_test_args(&["--dpi", "2401", series]).is_err());
+    assert_eq!(parse_test_args(&["--dpi", "10", series]).unwrap().dpi, 10);
+    assert_eq!(
+        parse_test_args(&["--dpi", "2400", series]).unwrap().dpi,
+        2400
+    );
+}
+
+#[test]
+fn test_preset_expansion() {
+    let series = "file=1,x=$1,y=$2,t=a";
+    let paper = parse_test_args(&["--preset", "paper", series]).unwrap();
+    assert!(paper.gpcmd.contains(
+        "set terminal postscript eps color noenhanced font \"Helvetica,16\"\n"
+    ));
+    assert!(paper.gpcmd.contains("set key font \"Helvetica,14\"\n"));
+    assert!(paper.gpcmd.contains("set size 1,0.6\n"));
+    assert!(paper.gpcmd.contains("set grid\n"));
+    assert!(
+        paper
+            .gpcmd
+            .contains("set linetype 1 lc rgb \"#0072b2\" lw 2\n")
+    );
+    // options given on the command line take precedence over the preset
+    let x11 = parse_test_args(&[
+        "--preset", "paper", "--term", "x11", "--size", "2,1", series,
+    ])
+    .unwrap();
+    assert!(x11.gpcmd.contains("set terminal x11 noenhanced"));
+    assert!(x11.gpcmd.contains("set size 2,1\n"));
+    assert!(
+        x11.gpcmd
+            .contains("set linetype 1 lc rgb \"#0072b2\" lw 2\n")
+    );
+}
+
+#[test]
+fn test_bundle_script() {
+    let cli = parse_test_args(&[
+        "--term",
+        "postscript",
+        "--gpout",
+        "out/x.eps",
+        "file=1,x=$1,y=$2,t=a",
+    ])
+    .unwrap();
+    assert_eq!(cli.get_bundle_output_name().as_deref(), Some("plot.eps"));
+    assert_eq!(
+        cli.build_bundle_gnuplot_cmd().unwrap(),
+        "#!/usr/bin/env gnuplot
+# Preamble
+set encoding utf8
+set datafile separator ','
+set key autotitle columnhead
+sp_dir = ''
+if (exists(\"ARG0\")) { do for [i = strlen(ARG0):1:-1] { if (sp_dir eq '' && ARG0[i:i] eq '/') { sp_dir = ARG0[1:i] } } }
+set terminal postscript eps color noenhanced font \"Helvetica,20\"
+
+# Axes
+
+# Global appearance
+set key font \"Helvetica,20\"
+set size 1,0.75
+set key top right
+
+# Custom commands
+
+
+set output '|ps2pdf -dEPSCrop - \"'.sp_dir.'plot.eps\"'
+plot\\
+\tsp_dir.'series-1.csv' using 1:2 axis x1y1 with points title \"a\"
+"
+    );
+    // the x11 terminal writes no output file
+    let x11 = parse_test_args(&["file=1,x=$1,y=$2,t=a"]).unwrap();
+    assert_eq!(x11.get_bundle_output_name(), None);
+    assert!(
+        !x11.build_bundle_gnuplot_cmd()
+            .unwrap()
+            .contains("set output")
+    );
+}
@@ -1,172 +1,404 @@
 mod cli;
 
 use std::{
-    backtrace::BacktraceStatus,
     fs::File,
-    io::Write,
-    process::{Child, Stdio},
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Child,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::Context;
+use clap::ValueEnum;
+use flate2::{Compression, write::GzEncoder};
+use spreadsheet_plotter::{
+    ErrorCategory, LogFormat, Plotter, TempArtifacts, WithCategory,
+    check_gnuplot, finish_temp_artifacts, finish_temp_artifacts_on_signal,
+    handle_err,
+};
 
-use crate::cli::{Cli, get_stdin_reader};
+use crate::cli::{CACHED_DATASHEETS, Cli, escape, sp_command};
 
-fn handle_err(e: anyhow::Error) {
-    e.chain().for_each(|e| eprintln!("Error: {e}"));
-    let bt = e.backtrace();
-    match bt.status() {
-        BacktraceStatus::Captured => {
-            eprintln!("Backtrace:\n{bt}");
-        }
-        BacktraceStatus::Unsupported => {
-            eprintln!("Backtrace is unsupported.");
-        }
-        BacktraceStatus::Disabled => {
-            eprintln!("Backtrace is disabled.");
-        }
-        _ => {
-            eprintln!("Unknown backtrace status: {:?}", bt.status());
-        }
-    }
+/// sp generating the datasheet of a data series, unless it is reused
+struct SeriesJob {
+    child: Option<Child>,
+    cache_path: Option<PathBuf>,
 }
 
-fn process_data_series(
-    cli: &Cli,
-    index: usize,
-) -> anyhow::Result<(Child, Option<std::thread::JoinHandle<std::io::Result<()>>>)>
-{
+fn process_data_series(cli: &Cli, index: usize) -> anyhow::Result<SeriesJob> {
     let ds = &cli.data_series[index];
-    let file = ds.file;
-
-    fn escape(s: &str) -> String {
-        s.replace("'", "'\\''")
-    }
-
-    let input_str = if file == 0 {
-        "".to_string()
-    } else {
-        format!(
-            " -i '{}'",
-            escape(&cli.input_paths[file - 1].display().to_string())
-        )
-    };
-    let header_str = if let Some(p) = cli
-        .header_presence
-        .as_slice()
-        .iter()
-        .find(|p| p.index == file)
-    {
-        if p.presence {
-            "--header true".to_string()
-        } else {
-            "--header false".to_string()
-        }
-    } else {
-        "".to_string()
-    };
-    let format_str = if let Some(p) =
-        cli.format.as_slice().iter().find(|p| p.index == file)
-    {
-        format!(" --format {}", p.format)
-    } else {
-        "".to_string()
-    };
 
     let output_path = cli.get_output_path(index).display().to_string();
     let log_path = cli.get_log_path(index).display().to_string();
 
-    let command = format!(
-        "sp{}{}{} --mode dump --if '{}' --of '{}' -x '{}' -y '{}' -e '{}' > '{}' 2> '{}'",
-        input_str,
-        header_str,
-        format_str,
+    let args = format!(
+        "{} -m dump --if '{}' --of '{}' -x '{}' -y '{}' -e '{}'",
+        cli.get_sp_input_args(ds.file),
         escape(&ds.ifilter),
         escape(&ds.ofilter),
         escape(&ds.xexpr),
         escape(&ds.yexpr),
         escape(&ds.opseq),
-        escape(&output_path),
+    );
+    // the seed only names temporary files, so it is not part of the cache
+    // key
+    let seed = cli
+        .seed
+        .map(|seed| format!(" --seed {seed}"))
+        .unwrap_or_default();
+    let command = format!(
+        "{} 2> '{}'",
+        sp_command(&ds.host, &format!("{args}{seed}"), &output_path),
         escape(&log_path)
     );
-    log::info!("Command #{}: {}", index + 1, command);
+    let _span = tracing::info_span!("series", index = index + 1).entered();
 
-    let mut child = std::process::Command::new("sh")
+    let cache_path = cli.get_cache_path(index, &args);
+    if let Some(cache) = cache_path.as_ref().filter(|cache| cache.is_file()) {
+        // the datasheet may be decimated in place, so it is a copy
+        std::fs::copy(cache, &output_path).context(format!(
+            "Failed to reuse datasheet '{}'",
+            cache.display()
+        ))?;
+        // keep the reused datasheet from being pruned
+        let _ = File::options()
+            .append(true)
+            .open(cache)
+            .and_then(|f| f.set_modified(SystemTime::now()));
+        tracing::info!(cache = %cache.display(), "Datasheet reused");
+        return Ok(SeriesJob {
+            child: None,
+            cache_path: None,
+        });
+    }
+
+    // sp logs into the log file of the series in the same format
+    let child = std::process::Command::new("sh")
         .arg("-c")
         .arg(&command)
-        .stdin(Stdio::piped())
+        .env("SP_LOG_FORMAT", LogFormat::from_args().name())
+        .stdin(cli.get_sp_stdin(ds.file)?)
         .spawn()?;
-    let stdin_handle = if input_str.is_empty() {
-        let mut stdin = child.stdin.take().unwrap();
-        Some(std::thread::spawn(move || {
-            std::io::copy(&mut get_stdin_reader(), &mut stdin)?;
-            drop(stdin);
-            Ok::<_, std::io::Error>(())
-        }))
-    } else {
-        None
+    tracing::info!(pid = child.id(), command, log = log_path, "sp started");
+
+    Ok(SeriesJob {
+        child: Some(child),
+        cache_path,
+    })
+}
+
+/// Keep the datasheet of a data series for later runs, failures only cost
+/// the reuse
+fn cache_datasheet(cli: &Cli, index: usize, cache: &Path) {
+    let output_path = cli.get_output_path(index);
+    if let Err(e) = std::fs::copy(&output_path, cache) {
+        tracing::warn!("Failed to cache datasheet '{}': {e}", cache.display());
+        return;
+    }
+    for (path, _) in cli.cached_datasheets().iter().skip(CACHED_DATASHEETS) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn call_gnuplot(gpcmd: &str, out_gp_name: PathBuf) -> anyhow::Result<()> {
+    let _span = tracing::info_span!("gnuplot").entered();
+    check_gnuplot(Plotter::plot_with_script_path(gpcmd, out_gp_name))
+}
+
+/// Write the bundle into `path`, or into a tarball if it is named like one
+fn emit_bundle(cli: &Cli, path: &Path) -> anyhow::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let Some(stem) = name
+        .strip_suffix(".tar.gz")
+        .or_else(|| name.strip_suffix(".tgz"))
+    else {
+        return write_bundle(cli, path);
     };
 
-    Ok((child, stdin_handle))
+    let dir = cli.get_temp_file_name("bundle");
+    write_bundle(cli, &dir)?;
+    let file = File::create(path)
+        .context(format!("Failed to create tarball '{}'", path.display()))?;
+    let mut tarball =
+        tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    tarball
+        .append_dir_all(stem, &dir)
+        .and_then(|_| tarball.into_inner()?.finish())
+        .context(format!("Failed to write tarball '{}'", path.display()))?;
+    std::fs::remove_dir_all(&dir)?;
+    tracing::info!("Bundle packed into {}", path.display());
+    Ok(())
 }
 
-fn call_gnuplot(cli: &Cli) -> anyhow::Result<()> {
-    let gpcmd = &cli.gpcmd;
-    let out_gp_name = cli.get_temp_file_name(".gp");
-    let mut out_gp = File::create(out_gp_name.clone())?;
-
-    log::info!("gnuplot file: {}", out_gp_name.display());
-    writeln!(out_gp, "{gpcmd}")?;
-    drop(out_gp);
-    let mut child = std::process::Command::new("gnuplot")
-        .arg("-p")
-        .arg(out_gp_name)
-        .spawn()?;
-    let result = child.wait().context("gnuplot failed")?;
-    if result.success() {
-        Ok(())
-    } else {
-        Err(anyhow::anyhow!(
-            "gnuplot failed (exit code: {:?})",
-            result.code()
-        ))
+fn write_bundle(cli: &Cli, dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir).context(format!(
+        "Failed to create bundle directory '{}'",
+        dir.display()
+    ))?;
+    for index in 0..cli.data_series.len() {
+        if cli.failed_series.contains(&index) {
+            continue;
+        }
+        let src = cli.get_output_path(index);
+        let dst = dir.join(Cli::get_bundle_datasheet_name(index));
+        std::fs::copy(&src, &dst).context(format!(
+            "Failed to copy '{}' to '{}'",
+            src.display(),
+            dst.display()
+        ))?;
     }
+
+    let gp_path = dir.join("plot.gp");
+    let mut gp = File::create(&gp_path)?;
+    writeln!(gp, "{}", cli.build_bundle_gnuplot_cmd()?)?;
+    gp.set_permissions(std::fs::Permissions::from_mode(0o755))?;
+    std::fs::write(dir.join("README.md"), bundle_readme(cli))?;
+    tracing::info!("Bundle written to {}", dir.display());
+    Ok(())
+}
+
+/// How the bundle was generated and how to re-render it
+fn bundle_readme(cli: &Cli) -> String {
+    let invocation = std::env::args()
+        .map(|arg| format!("'{}'", escape(&arg)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut readme = format!(
+        "# msp plot bundle\n\n\
+         Generated by msp {} with:\n\n    {invocation}\n\n\
+         Run `gnuplot plot.gp` to render the plot{}, and edit `plot.gp` to \
+         restyle it. The datasheets are:\n\n",
+        env!("VERSION"),
+        cli.get_bundle_output_name()
+            .map(|name| format!(" into `{name}` next to it"))
+            .unwrap_or_default()
+    );
+    for (index, ds) in cli.data_series.iter().enumerate() {
+        if cli.failed_series.contains(&index) {
+            continue;
+        }
+        let input = match ds.file {
+            0 => "STDIN".to_string(),
+            file => cli.input_paths[file - 1].display().to_string(),
+        };
+        readme += &format!(
+            "- `{}`: \"{}\", from {input}\n",
+            Cli::get_bundle_datasheet_name(index),
+            ds.title
+        );
+    }
+    readme
+}
+
+/// Show the plot on the dumb terminal until the user accepts it, possibly
+/// after changing the ranges of axes, or cancels it
+fn preview(cli: &mut Cli) -> anyhow::Result<bool> {
+    // stdin may be the data of a data series
+    let tty = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("Preview requires a terminal")
+        .with_category(ErrorCategory::Usage)?;
+    let mut answers = BufReader::new(tty.try_clone()?);
+    let mut tty = tty;
+    loop {
+        call_gnuplot(
+            &cli.build_preview_gnuplot_cmd()?,
+            cli.get_temp_file_name("preview.gp"),
+        )?;
+        write!(
+            tty,
+            "Plot? [Y]es, [n]o, or ranges to change (e.g. x=0:10,y=1:5): "
+        )?;
+        let mut answer = String::new();
+        if answers.read_line(&mut answer)? == 0 {
+            return Ok(false);
+        }
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "" | "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            ranges => {
+                if let Err(e) = cli.set_ranges(ranges) {
+                    writeln!(tty, "Invalid ranges: {e}")?;
+                }
+            }
+        }
+    }
+}
+
+/// What a run did, for --summary-json
+#[derive(Default)]
+struct Summary {
+    plotted: bool,
+    reused: Vec<bool>,
+    timings: Vec<(&'static str, Duration)>,
+}
+
+fn write_summary(
+    cli: &Cli,
+    path: &Path,
+    summary: &Summary,
+    result: &anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    fn artifact(path: &Path) -> serde_json::Value {
+        serde_json::json!({
+            "path": path.display().to_string(),
+            "bytes": std::fs::metadata(path).ok().map(|m| m.len()),
+        })
+    }
+    let plotted = summary.plotted;
+    let series = cli
+        .data_series
+        .iter()
+        .enumerate()
+        .map(|(i, ds)| {
+            serde_json::json!({
+                "index": i + 1,
+                "input": (ds.file > 0)
+                    .then(|| cli.input_paths[ds.file - 1].display().to_string()),
+                "datasheet": artifact(&cli.get_output_path(i)),
+                "log": artifact(&cli.get_log_path(i)),
+                "reused": summary.reused.get(i).copied().unwrap_or(false),
+                "failed": cli.failed_series.contains(&i),
+            })
+        })
+        .collect::<Vec<_>>();
+    let timings = summary
+        .timings
+        .iter()
+        .map(|(stage, duration)| {
+            (stage.to_string(), duration.as_secs_f64().into())
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let mut report = serde_json::json!({
+        "status": if result.is_ok() { "ok" } else { "error" },
+        "mode": cli.mode.to_possible_value().unwrap().get_name(),
+        "series": series,
+        "script": plotted.then(|| artifact(&cli.get_temp_file_name(".gp"))),
+        "output": cli
+            .get_gnuplot_output()
+            .filter(|_| plotted)
+            .map(|output| artifact(&output)),
+        "bundle": cli.emit_bundle.as_ref().map(|dir| dir.display().to_string()),
+        "timings": timings,
+    });
+    if let Err(e) = result {
+        report["error"] = serde_json::json!({
+            "category": ErrorCategory::of(e).map(|c| c.name()),
+            "message": format!("{e:#}"),
+        });
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&report)? + "\n")
+        .context(format!("Failed to write summary '{}'", path.display()))?;
+    Ok(())
 }
 
 fn try_main() -> anyhow::Result<()> {
-    env_logger::init();
-    let cli = cli::Cli::parse_args()?;
+    LogFormat::from_args().init();
+    let mut cli = cli::Cli::parse_args().with_category(ErrorCategory::Usage)?;
+    finish_temp_artifacts_on_signal(cli.preserve);
+
+    let start = Instant::now();
+    let mut summary = Summary::default();
+    let result = run(&mut cli, &mut summary);
+    summary.timings.push(("total", start.elapsed()));
+    let summary_result = match &cli.summary_json {
+        Some(path) => write_summary(&cli, path, &summary, &result),
+        None => Ok(()),
+    };
+    finish_temp_artifacts(cli.preserve);
+    summary_result?;
+    result
+}
 
-    if matches!(cli.mode, cli::Mode::DryRun) {
+fn run(cli: &mut Cli, summary: &mut Summary) -> anyhow::Result<()> {
+    if !cli.runs_sp() {
         println!("{}", cli.gpcmd);
         return Ok(());
     }
 
-    let children = (0..cli.data_series.len())
-        .map(|i| process_data_series(&cli, i))
+    let start = Instant::now();
+    let jobs = (0..cli.data_series.len())
+        .map(|i| process_data_series(cli, i))
         .collect::<Result<Vec<_>, _>>()?;
+    summary.reused = jobs.iter().map(|job| job.child.is_none()).collect();
 
-    for (index, (mut child, stdin_handle)) in children.into_iter().enumerate() {
-        if let Some(handle) = stdin_handle {
-            handle.join().map_err(|e| anyhow::anyhow!("{e:?}"))??;
-        }
-        let result = child.wait().context(format!(
-            "sp failed (log in {})",
-            cli.get_log_path(index).display(),
-        ))?;
+    let mut first_failure = None;
+    for (index, job) in jobs.into_iter().enumerate() {
+        let Some(mut child) = job.child else {
+            continue;
+        };
+        let _span = tracing::info_span!("series", index = index + 1).entered();
+        let result = child
+            .wait()
+            .context(format!(
+                "sp failed (log in {})",
+                cli.get_log_path(index).display(),
+            ))
+            .with_category(ErrorCategory::ExternalTool)?;
+        tracing::info!(pid = child.id(), %result, "sp exited");
         if !result.success() {
-            return Err(anyhow::anyhow!(
-                "sp failed (exit code: {:?}, log in {})",
+            // the log is referred to by the error
+            TempArtifacts::untrack(cli.get_log_path(index));
+            // keep the category of the failure reported by sp
+            let category = result
+                .code()
+                .and_then(ErrorCategory::from_exit_code)
+                .unwrap_or(ErrorCategory::ExternalTool);
+            let e = anyhow::anyhow!(
+                "sp failed for data series #{} ({category}, exit code: {:?}, log in {})",
+                index + 1,
                 result.code(),
                 cli.get_log_path(index).display()
-            ));
+            );
+            if !cli.keep_going {
+                return Err(e).with_category(category);
+            }
+            // only a warning, as the run still succeeds
+            tracing::warn!("{e}");
+            cli.failed_series.push(index);
+            first_failure.get_or_insert((e, category));
+            continue;
+        }
+        if let Some(cache) = &job.cache_path {
+            cache_datasheet(cli, index, cache);
         }
     }
-    log::info!("Datasheet generated");
+    if let Some((e, category)) = first_failure {
+        if cli.failed_series.len() == cli.data_series.len() {
+            return Err(e.context("sp failed for all data series"))
+                .with_category(category);
+        }
+        cli.update_gnuplot_cmd()?;
+    }
+    tracing::info!("Datasheet generated");
+    summary.timings.push(("datasheets", start.elapsed()));
+
+    cli.compute_stat_lines()?;
+    if let Some(max_points) = cli.max_points {
+        let start = Instant::now();
+        cli.decimate_datasheets(max_points)?;
+        summary.timings.push(("decimate", start.elapsed()));
+    }
 
-    if matches!(cli.mode, cli::Mode::Prepare) {
+    if matches!(cli.mode, cli::Mode::Preview) && !preview(cli)? {
+        tracing::info!("Plot cancelled");
+        return Ok(());
+    }
+
+    if let Some(dir) = &cli.emit_bundle {
+        emit_bundle(cli, dir)?;
+    }
+
+    if matches!(cli.mode, cli::Mode::Prepare | cli::Mode::DryRun) {
         println!("{}", cli.gpcmd);
     } else {
-        call_gnuplot(&cli)?;
+        let start = Instant::now();
+        call_gnuplot(&cli.gpcmd, cli.get_temp_file_name(".gp"))?;
+        summary.timings.push(("gnuplot", start.elapsed()));
+        summary.plotted = true;
     }
 
     Ok(())
@@ -175,9 +407,6 @@ fn try_main() -> anyhow::Result<()> {
 fn main() -> anyhow::Result<()> {
     match try_main() {
         Ok(()) => Ok(()),
-        Err(e) => {
-            handle_err(e);
-            std::process::exit(1)
-        }
+        Err(e) => std::process::exit(handle_err(e)),
     }
 }
@@ -1,9 +1,16 @@
-use std::path::PathBuf;
+use std::{
+    io::{BufRead, Read},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
-use clap::{Parser, ValueEnum};
+use anyhow::bail;
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
 use spreadsheet_plotter::{
-    DataFormat, DataInput, DataSeriesOptions, Expr, GnuplotTemplate, OpSeq,
-    PlainSelector,
+    Column, CsvDialect, DataFormat, DataInput, DataSeriesOptions,
+    ErrorCategory, ErrorFormat, Expr, ExternalOperator, GnuplotTemplate,
+    LogFormat, MemorySize, MissingValues, OpSeq, PlainSelector, Plotter,
+    TempArtifacts, TimeFormat, UserConfig, WithCategory, get_matches,
 };
 
 /// Specify whether the input file has header row
@@ -23,8 +30,28 @@ pub enum Mode {
     Plot,
     /// Dump the processed data to stdout
     Dump,
+    /// Print statistics (count, min, max, mean, stddev, percentiles) of the
+    /// processed data to stdout
+    Stats,
     /// Print the SQL query to stdout
     DryRun,
+    /// Print each operator of the opseq with its arguments and the columns
+    /// it produces to stdout, without reading the data
+    Explain,
+}
+
+/// Format of the output of dump/stats mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// CSV with a header row
+    #[default]
+    Csv,
+    /// Array of objects, one per row
+    Json,
+    /// Object of arrays, one per column
+    JsonColumns,
+    /// Parquet compressed with zstd, written by duckdb to -o PATH
+    Parquet,
 }
 
 impl Default for Mode {
@@ -41,21 +68,108 @@ impl Default for Mode {
 pub struct Cli {
     /// OPSEQ = {[operator](arg)}+
     ///   operator =
-    ///     a(range): moving average
+    ///     a(range): moving average of y over x in [x - left, x + right],
+    ///       range = left,right (in x units) or a single value for both
+    ///     b(range): moving minimum of y, same range as a
     ///     c: cdf
     ///     d(range): derivation over a smooth window
+    ///     D(n): downsample to at most n points (every k-th point)
+    ///     e(base,axis): base (10 by default) to the power of y, or of x if
+    ///       axis = 1
+    ///     F: amplitude spectrum of y (x = frequency), assuming uniformly
+    ///       spaced x
+    ///     g(mode): gap (difference of the consecutive x values) as y, x
+    ///       kept (mode = 0, default) or replaced with the index (1)
     ///     i: integral
+    ///     k(k,order): the k records with the largest y (order = 0, default)
+    ///       or the smallest y (1)
+    ///     l(base,axis): logarithm of y (or x if axis = 1) in base (10 by
+    ///       default), NaN for non-positive values
+    ///     L(id): restore the records saved by S(id) (id = 0 by default)
+    ///     M(range): moving median of y, same range as a
     ///     m: merge (sum of y values with the same x value)
-    ///     o: sort by x axis
+    ///     n(mode): normalize y, mode = 0 to [0,1] (default), 1 z-score
+    ///     o(by,order,stable): sort by x (by = 0, default) or y (1), in
+    ///       ascending (order = 0, default) or descending (1) order, ties
+    ///       keeping their order in SQL as well if stable = 1
+    ///     q(p1,p2,...): percentiles of y (x = percentile, y = value)
+    ///     r(start,step): resample y onto x = start + k * step by linear
+    ///       interpolation
+    ///     R(mode): linear regression y = a * x + b, y replaced with the fit
+    ///       (mode = 0, default) or the residuals (1), or table (0, a),
+    ///       (1, b), (2, R^2) (2)
     ///     s: step (difference of the consecutive y values)
-    ///     u: unique (preserve the first occurrence of each x value)
-    #[arg(short = 'e', verbatim_doc_comment)]
-    pub opseq: Option<OpSeq>,
+    ///     S(id): checkpoint, save the records under id (0 by default)
+    ///     t(range): moving maximum of y, same range as a
+    ///     u(mode): unique (one record for each x value), keeping y of the
+    ///       first (mode = 0, default), the last (1) or the mean (2)
+    ///     x(scale,offset): x * scale + offset, scale = 1 and offset = 0
+    ///       by default
+    ///     y(scale,offset): y * scale + offset
+    ///   arguments could also be written as (arg,...) with named ones, e.g.
+    ///     d(window=0.5) or k(20,order=smallest), see the README
+    ///   {name:args}: external operator, runs the executable sp-op-<name>
+    ///     on PATH with the comma-separated args as arguments, which reads
+    ///     the (x, y) table as CSV on stdin and writes it to stdout
+    ///   @name: alias defined in the config file, which may refer to other
+    ///     aliases
+    ///   OPSEQ/OPSEQ/...: branches, the operators before the first / are
+    ///     applied once, then each branch separately to their output, each
+    ///     producing a data series, e.g. o/c/d0.1, and may restore the
+    ///     checkpoints saved before the first /, e.g. oS1m/c/L1d0.1
+    /// @path: read OPSEQ from the file at path
+    #[arg(short = 'e', env = "SP_OPSEQ", verbatim_doc_comment)]
+    pub opseq: Option<String>,
 
-    /// Input file format
-    #[arg(short = 'f')]
+    /// Input file format (e.g. csv, tsv, dat, fwf, prom, influx, json,
+    /// parquet, arrow or xlsx),
+    /// detected by duckdb (or by the extension of the file) if unset
+    #[arg(short = 'f', env = "SP_FORMAT")]
     input_format: Option<DataFormat>,
 
+    /// Delimiter of the CSV input (e.g. ';' or '|'), detected if unset
+    #[arg(long, value_name = "CHAR")]
+    delimiter: Option<char>,
+
+    /// Character quoting the values of the CSV input (e.g. "'"), `"` by
+    /// default
+    #[arg(long, value_name = "CHAR")]
+    quote: Option<char>,
+
+    /// Character escaping the quote within quoted values of the CSV input,
+    /// the quote itself by default
+    #[arg(long, value_name = "CHAR")]
+    escape: Option<char>,
+
+    /// Prefix of the comment lines of the CSV input, which are skipped
+    #[arg(long, value_name = "CHAR")]
+    comment: Option<char>,
+
+    /// Pad rows of the CSV input with missing columns, and read malformed
+    /// quoting as is instead of failing
+    #[arg(long)]
+    flexible: bool,
+
+    /// Offsets (in characters from 0) at which the columns of fixed-width
+    /// input start, inferred from the blank positions if unset
+    #[arg(
+        long = "column-offsets",
+        value_name = "OFFSETS",
+        value_delimiter = ','
+    )]
+    column_offsets: Option<Vec<usize>>,
+
+    /// Sheet of the spreadsheet input, by name or position from 1 (the
+    /// first one by default)
+    #[arg(long, value_name = "NAME")]
+    sheet: Option<String>,
+
+    /// Samples of the Prometheus input to read, selected by metric name
+    /// and/or label matchers as in PromQL (e.g.
+    /// 'node_cpu_seconds_total{mode="idle"}'), all of them if unset
+    #[arg(long, value_name = "SELECTOR")]
+    metric: Option<String>,
+
     /// Filter to apply on the input data (SQL expression)
     #[arg(long = "if")]
     input_filter: Option<String>,
@@ -68,89 +182,872 @@ pub struct Cli {
     #[arg(short = 'g')]
     gnuplot_snippet: Option<String>,
 
-    /// Specify whether the input file has header row
-    #[arg(long, default_value = "auto")]
+    /// Plot the x values as category labels (e.g. host names), placed at
+    /// evenly spaced positions in the order of their first appearance, with
+    /// the labels as tics
+    #[arg(long)]
+    categorical_x: bool,
+
+    /// Read the x values as timestamps, ISO-8601 / RFC-3339 (e.g.
+    /// 2024-05-01T12:00:00Z) or of the strptime FORMAT (e.g. "%d/%b/%Y:%H:%M:%S
+    /// %z"), and plot them on a time axis. The operators compute on seconds
+    /// since the Unix epoch, which are also the x values of the datasheet.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "",
+        conflicts_with = "categorical_x"
+    )]
+    time_x: Option<String>,
+
+    /// Unit of the x values (e.g. s), labeling the x axis with it as
+    /// composed by the operators (e.g. 1/s after F)
+    #[arg(long, value_name = "UNIT")]
+    x_unit: Option<String>,
+
+    /// Unit of the y values (e.g. ms), labeling the y axis with it as
+    /// composed by the operators (e.g. ms/s after d)
+    #[arg(long, value_name = "UNIT")]
+    y_unit: Option<String>,
+
+    /// What becomes of the selected records missing x or y (e.g. of empty
+    /// cells): drop them, propagate them as empty cells of the datasheet,
+    /// interpolate y linearly in x, or treat y as zero
+    #[arg(long, value_name = "POLICY", default_value = "propagate")]
+    missing: MissingValues,
+
+    /// Specify whether the input file has header row, which is guessed from
+    /// the first lines of text input if auto (logged with RUST_LOG=info)
+    #[arg(long, env = "SP_HEADER", default_value = "auto")]
     header: HeaderPresence,
 
-    /// Input file (stdin if empty)
-    #[arg(short, default_value = "/dev/stdin")]
-    input_path: PathBuf,
+    /// Skip the first N rows of the input data
+    #[arg(long, value_name = "N", default_value = "0")]
+    skip: usize,
+
+    /// Skip the first N lines of the input file (e.g. a preamble banner),
+    /// before its header row, or the first N rows of a spreadsheet
+    #[arg(long = "skip-rows", value_name = "N", default_value = "0")]
+    skip_rows: usize,
+
+    /// Skip the last N rows of the input data (e.g. summary lines), before
+    /// --skip, --head and --tail
+    #[arg(long = "skip-footer", value_name = "N", default_value = "0")]
+    skip_footer: usize,
+
+    /// Only read the first N rows of the input data (after --skip)
+    #[arg(long, value_name = "N")]
+    head: Option<usize>,
+
+    /// Only read the last N rows of the input data (after --skip and --head)
+    #[arg(long, value_name = "N")]
+    tail: Option<usize>,
+
+    /// Input file (stdin if empty), repeated or given as a glob pattern
+    /// (e.g. 'results/*.csv') to concatenate the rows of several files
+    #[arg(
+        short = 'i',
+        value_name = "INPUT_PATH",
+        default_value = "/dev/stdin"
+    )]
+    input_paths: Vec<PathBuf>,
+
+    /// Add the path of the input file of each row as a last column named
+    /// filename
+    #[arg(long = "source-column")]
+    source_column: bool,
+
+    /// Run the same expressions and opseq on two input files, the results
+    /// are overlaid in plot mode and joined by x in dump mode, with the
+    /// absolute and relative change of y
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["OLD", "NEW"],
+        conflicts_with_all = ["input_paths", "stream", "follow"]
+    )]
+    compare: Vec<PathBuf>,
 
     /// Mark character that indicates a column index
-    #[arg(long = "index-mark", default_value("$"))]
+    #[arg(long = "index-mark", env = "SP_INDEX_MARK", default_value("$"))]
     index_mark: char,
 
     /// Specify how the plotter should behave
     #[arg(short, default_value = "plot")]
     mode: Mode,
 
+    /// Write the output of dump/stats mode to PATH instead of stdout, the
+    /// file is replaced atomically once the output is complete
+    #[arg(short = 'o', value_name = "PATH")]
+    output_path: Option<PathBuf>,
+
+    /// Format of the output of dump/stats mode, numbers being written as
+    /// JSON numbers and empty cells as null in JSON, parquet needing -o
+    #[arg(short = 'F', long = "output-format", default_value = "csv")]
+    output_format: OutputFormat,
+
+    /// Refuse to overwrite an existing file given by -o
+    #[arg(long = "no-clobber", requires = "output_path")]
+    no_clobber: bool,
+
+    /// Keep the temporary files (gnuplot scripts, outputs of external
+    /// operators), which are removed on exit or interruption otherwise, and
+    /// print their paths
+    #[arg(long)]
+    preserve: bool,
+
+    /// Save the gnuplot script used for plotting to PATH
+    #[arg(short = 'S', value_name = "PATH")]
+    script_path: Option<PathBuf>,
+
+    /// Downsample datasheets with more than N points before plotting
+    #[arg(long = "max-points", env = "SP_MAX_POINTS", value_name = "N")]
+    max_points: Option<usize>,
+
+    /// Memory budget of duckdb (e.g. 512M or 2G), beyond which it spills
+    /// intermediate data to a temporary directory, reported as a warning.
+    /// The input file is then scanned in chunks instead of being loaded,
+    /// only its referenced columns being read.
+    #[arg(long = "max-memory", env = "SP_MAX_MEMORY", value_name = "SIZE")]
+    max_memory: Option<MemorySize>,
+
+    /// Replot the datasheet at PATH (e.g. one listed by --list-datasheets)
+    /// instead of the one of the previous plot
+    #[arg(long, value_name = "PATH", conflicts_with = "mode")]
+    replot: Option<PathBuf>,
+
+    /// List the datasheets kept from recent plots and exit
+    #[arg(long = "list-datasheets")]
+    list_datasheets: bool,
+
+    /// List the sheets of the spreadsheet input (by position and name, for
+    /// --sheet) and exit
+    #[arg(long = "list-sheets")]
+    list_sheets: bool,
+
+    /// After plotting, open the gnuplot script in $EDITOR and replot it
+    /// each time it is saved, until the editor exits
+    #[arg(long)]
+    edit: bool,
+
+    /// Read stdin incrementally and refresh the plot periodically, until
+    /// stdin is closed. Only the new rows are processed if the operators
+    /// are row-local, the whole pipeline is rerun otherwise.
+    #[arg(long)]
+    stream: bool,
+
+    /// Follow the input file as it grows, like tail -f, and refresh the plot
+    /// periodically with its new rows until interrupted (the same as
+    /// --stream with stdin, new rows included)
+    #[arg(long, conflicts_with = "stream")]
+    follow: bool,
+
+    /// Interval in seconds between plot refreshes of --stream and --follow
+    #[arg(long, value_name = "SECS", default_value = "1")]
+    refresh: f64,
+
+    /// Only keep the last N rows of the input for --stream and --follow
+    #[arg(long = "stream-window", value_name = "N")]
+    stream_window: Option<usize>,
+
+    /// Format of the error message printed on failure, the exit code tells
+    /// the category of the error: 2 usage, 3 input parse, 4 expression,
+    /// 5 external tool, 6 plot
+    #[arg(
+        long = "error-format",
+        env = "SP_ERROR_FORMAT",
+        value_name = "FORMAT",
+        default_value = "text"
+    )]
+    #[allow(dead_code)]
+    // read from the raw arguments by ErrorFormat::from_args
+    error_format: ErrorFormat,
+
+    /// Format of the log records printed to stderr, whose verbosity is
+    /// controlled by RUST_LOG (e.g. RUST_LOG=info)
+    #[arg(
+        long = "log-format",
+        env = "SP_LOG_FORMAT",
+        value_name = "FORMAT",
+        default_value = "text"
+    )]
+    #[allow(dead_code)]
+    // read from the raw arguments by LogFormat::from_args
+    log_format: LogFormat,
+
+    /// List the external operators (sp-op-<name>) found on PATH and the
+    /// opseq aliases of the config file, then exit
+    #[arg(long = "list-ops")]
+    list_ops: bool,
+
+    /// Recipe file (TOML) providing the values of other options, e.g. input,
+    /// expressions, filters and opseq, which the command line overrides
+    #[arg(long, env = "SP_RECIPE", value_name = "PATH")]
+    #[allow(dead_code)]
+    // read from the raw arguments by UserConfig::load_recipe
+    recipe: Option<PathBuf>,
+
+    /// Pipeline file (TOML, or JSON if named *.json) as read by the library
+    /// (input, expressions, filters, operators, outputs), which the recipe
+    /// and the command line override
+    #[arg(long, env = "SP_PIPELINE", value_name = "PATH")]
+    #[allow(dead_code)]
+    // read from the raw arguments by UserConfig::load_pipeline
+    pipeline: Option<PathBuf>,
+
+    /// Seed of all randomness (e.g. names of the temporary files), so that
+    /// runs are reproducible
+    #[arg(long, env = "SP_SEED")]
+    seed: Option<u32>,
+
+    /// Print wall-clock time spent in each stage (ingest, expressions, each
+    /// operator, output...) to stderr at the end, materializing the result
+    /// of each operator to time it
+    #[arg(long)]
+    timing: bool,
+
+    /// Run the pipeline N more times after a cold run, discarding the output,
+    /// and report the median and minimum time spent in each stage
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["timing", "report_json"]
+    )]
+    bench: Option<usize>,
+
+    /// Write a JSON document describing the run (inputs, expressions, row
+    /// counts of each stage and operator, output files and timings) to PATH
+    #[arg(long = "report-json", value_name = "PATH")]
+    report_json: Option<PathBuf>,
+
     /// Initial X axis expression (SQL expression)
     #[arg(short, default_value("1"))]
     xexpr: String,
 
-    /// Initial Y axis expression (SQL expression)
+    /// Initial Y axis expression (SQL expression), specify multiple times
+    /// for multiple data series sharing the same pass over the input
     #[arg(short, default_value("1"))]
-    yexpr: String,
+    yexpr: Vec<String>,
+
+    /// DuckDB query over the input table `input` producing columns x and y,
+    /// used instead of -x, -y and --if
+    #[arg(long, conflicts_with_all = ["xexpr", "yexpr", "input_filter"])]
+    sql: Option<String>,
+}
+
+// name of the column of an expression, e.g. `latency` for `$latency`, none
+// with --sql
+fn column_name(expr: &str, index_mark: char, sql: bool) -> String {
+    if sql {
+        return String::new();
+    }
+    match expr.strip_prefix(index_mark) {
+        Some(name)
+            if name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+        {
+            name.to_string()
+        }
+        _ => expr.to_string(),
+    }
+}
+
+// labels of the axes with the units given by --x-unit and --y-unit as
+// composed by the operators, an axis being unlabeled if its data series
+// disagree (e.g. branches computing different quantities)
+fn axis_labels(
+    x: Column,
+    ys: Vec<Column>,
+    opseq: Option<&OpSeq>,
+    branches: &[OpSeq],
+) -> (Option<String>, Option<String>) {
+    let columns = |y: Column| {
+        let (x, y) = match opseq {
+            Some(opseq) => opseq.columns(x.clone(), y),
+            None => (x.clone(), y),
+        };
+        if branches.is_empty() {
+            vec![(x, y)]
+        } else {
+            branches
+                .iter()
+                .map(|branch| branch.columns(x.clone(), y.clone()))
+                .collect()
+        }
+    };
+    let series = ys.into_iter().flat_map(columns).collect::<Vec<_>>();
+    let label = |axis: fn(&(Column, Column)) -> &Column| {
+        let first = axis(&series[0]);
+        series
+            .iter()
+            .all(|s| axis(s).unit == first.unit)
+            .then(|| {
+                let same_name =
+                    series.iter().all(|s| axis(s).name == first.name);
+                Column {
+                    name: if same_name {
+                        first.name.clone()
+                    } else {
+                        String::new()
+                    },
+                    unit: first.unit.clone(),
+                }
+                .to_string()
+            })
+            .filter(|label| !label.is_empty())
+    };
+    (label(|(x, _)| x), label(|(_, y)| y))
+}
+
+pub struct StreamOptions {
+    /// File followed as it grows, stdin if unset
+    pub followed: Option<PathBuf>,
+    /// The input is copied to this file before each refresh that reruns the
+    /// whole pipeline
+    pub spool_path: PathBuf,
+    /// Keep the first line across refreshes as it may be the header
+    pub keep_header: bool,
+    pub window: Option<usize>,
+    pub refresh: Duration,
 }
 
 pub struct ParsedCli {
-    pub gnuplot_cmd: String,
+    /// Template of the gnuplot script, completed with the categories of the
+    /// datasheet if `categorical_x`
+    pub gnuplot_template: GnuplotTemplate,
+    pub categorical_x: bool,
     pub tmp_datasheet_path: PathBuf,
     pub data_input: DataInput,
+    /// The new input of --compare, `data_input` being the old one
+    pub compare_input: Option<DataInput>,
     pub selector: PlainSelector,
+    /// Query producing the initial (x, y) table instead of the selector
+    pub raw_sql: Option<String>,
     pub opseq: Option<OpSeq>,
+    /// External operators, each followed by the operators applied to its
+    /// output
+    pub external_ops: Vec<(ExternalOperator, OpSeq)>,
+    /// Operators applied separately to the output of `opseq`, each branch
+    /// producing a data series
+    pub branches: Vec<OpSeq>,
     pub mode: Mode,
+    pub script_path: Option<PathBuf>,
+    pub edit: bool,
+    pub max_points: Option<usize>,
+    pub max_memory: Option<MemorySize>,
+    /// Where duckdb spills under --max-memory, created only if it does
+    pub spill_dir: PathBuf,
+    pub stream: Option<StreamOptions>,
+    pub timing: bool,
+    pub bench: Option<usize>,
+    pub report_path: Option<PathBuf>,
+    pub output_path: Option<PathBuf>,
+    pub output_format: OutputFormat,
+    pub no_clobber: bool,
+    pub preserve: bool,
+    /// Description of the run for --report-json, completed after the run
+    pub report: serde_json::Value,
+}
+
+/// Number of datasheets of recent plots kept for --replot
+pub const KEPT_DATASHEETS: usize = 10;
+
+/// Datasheets kept from recent plots with their modification time, the
+/// most recent first
+pub fn kept_datasheets() -> Vec<(PathBuf, SystemTime)> {
+    let mut datasheets = std::fs::read_dir(std::env::temp_dir())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with("sp-") || !name.ends_with(".spdata") {
+                return None;
+            }
+            Some((entry.path(), entry.metadata().ok()?.modified().ok()?))
+        })
+        .collect::<Vec<_>>();
+    datasheets.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    datasheets
+}
+
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        s if s < 60 => format!("{s}s ago"),
+        s if s < 3600 => format!("{}m ago", s / 60),
+        s if s < 86400 => format!("{}h ago", s / 3600),
+        s => format!("{}d ago", s / 86400),
+    }
+}
+
+/// Input paths with the glob patterns (that are not existing paths)
+/// expanded into the matching files, in alphabetical order
+fn expand_input_paths(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut expanded = Vec::with_capacity(paths.len());
+    for path in paths {
+        let pattern = path.display().to_string();
+        if path.exists() || !pattern.contains(['*', '?', '[']) {
+            expanded.push(path.clone());
+            continue;
+        }
+        let matches = glob::glob(&pattern)
+            .map_err(|e| {
+                anyhow::anyhow!("Invalid glob pattern '{pattern}': {e}")
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to match glob pattern '{pattern}': {e}")
+            })?;
+        if matches.is_empty() {
+            bail!("No file matches glob pattern '{pattern}'");
+        }
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// Temporary path of stdin whose extension tells duckdb its compression,
+/// detected by the magic bytes of gzip and zstd, so that compressed input is
+/// decompressed on the fly as well. A regular file (e.g. the copy of stdin
+/// made by msp) is linked rather than copied, while a pipe is spooled, as
+/// it is read more than once (sniffed, then read by duckdb).
+fn spool_stdin() -> anyhow::Result<PathBuf> {
+    let regular = std::fs::canonicalize("/dev/stdin")
+        .ok()
+        .filter(|path| path.is_file());
+    let mut stdin = std::io::stdin().lock();
+    let mut head = Vec::new();
+    let magic = match &regular {
+        Some(path) => {
+            std::fs::File::open(path)?.take(4).read_to_end(&mut head)?;
+            &head
+        }
+        None => stdin.fill_buf()?,
+    };
+    let extension = if magic.starts_with(&[0x1f, 0x8b]) {
+        "csv.gz"
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        "csv.zst"
+    } else {
+        "csv"
+    };
+    let mut spool = None;
+    let path =
+        TempArtifacts::create("sp-", extension, |path| match &regular {
+            Some(file) => std::os::unix::fs::symlink(file, path),
+            None => std::fs::File::create_new(path).map(|f| spool = Some(f)),
+        })?;
+    if let Some(mut spool) = spool {
+        std::io::copy(&mut stdin, &mut spool)?;
+    }
+    Ok(path)
 }
 
 impl Cli {
     pub fn parse_args() -> anyhow::Result<ParsedCli> {
-        let cli = Self::parse();
-        let data_input = DataInput::new(
-            cli.input_format.unwrap_or_else(|| {
-                if cli.input_path == PathBuf::from("/dev/stdin") {
-                    DataFormat::Explicit("csv".to_string())
+        let config = UserConfig::load()?;
+        let mut cmd = config.apply_defaults("sp", Self::command())?;
+        if let Some(pipeline) =
+            UserConfig::load_pipeline("--pipeline", "SP_PIPELINE")?
+        {
+            cmd = pipeline.apply_recipe(cmd, &[])?;
+        }
+        if let Some(recipe) = UserConfig::load_recipe("--recipe", "SP_RECIPE")?
+        {
+            cmd = recipe.apply_recipe(cmd, &[])?;
+        }
+        let matches = get_matches(cmd)?;
+        let mut cli = Self::from_arg_matches(&matches)?;
+        cli.input_paths = expand_input_paths(&cli.input_paths)?;
+        let stdin = [PathBuf::from("/dev/stdin")];
+
+        if let Some(seed) = cli.seed {
+            Plotter::set_seed(seed.into());
+        }
+
+        if cli.list_datasheets {
+            let now = SystemTime::now();
+            for (path, modified) in kept_datasheets() {
+                let age = now.duration_since(modified).unwrap_or_default();
+                println!("{}\t{}", path.display(), format_age(age));
+            }
+            std::process::exit(0);
+        }
+        if cli.list_sheets {
+            let input = DataInput::new(
+                cli.input_format.clone().unwrap_or_default(),
+                cli.input_paths[0].display().to_string(),
+                None,
+            )?;
+            for (i, name) in input.sheet_names()?.iter().enumerate() {
+                println!("{}\t{name}", i + 1);
+            }
+            std::process::exit(0);
+        }
+        if let Some(path) = &cli.replot {
+            if !path.is_file() {
+                bail!("Datasheet '{}' does not exist", path.display());
+            }
+            cli.mode = Mode::Replot;
+        }
+
+        if cli.list_ops {
+            for name in ExternalOperator::discover() {
+                println!("{{{name}}}");
+            }
+            for (name, alias) in config.aliases() {
+                println!("@{name} = {alias}");
+            }
+            std::process::exit(0);
+        }
+
+        if cli.output_path.is_some()
+            && !matches!(cli.mode, Mode::Dump | Mode::Stats)
+        {
+            bail!("-o must be used with --mode dump or --mode stats");
+        }
+        if cli.output_format != OutputFormat::Csv
+            && !matches!(cli.mode, Mode::Dump | Mode::Stats)
+        {
+            bail!("-F must be used with --mode dump or --mode stats");
+        }
+        if cli.output_format == OutputFormat::Parquet
+            && cli.output_path.is_none()
+        {
+            bail!("-F parquet must be used with -o");
+        }
+        if cli.no_clobber
+            && let Some(path) = &cli.output_path
+            && path.exists()
+        {
+            bail!("Output '{}' already exists", path.display());
+        }
+
+        if let Some(n) = cli.bench {
+            if n == 0 {
+                bail!("--bench needs at least one run");
+            }
+            if !matches!(cli.mode, Mode::Dump | Mode::Stats) {
+                bail!("--bench must be used with --mode dump or --mode stats");
+            }
+        }
+
+        let stream = if cli.stream || cli.follow {
+            let option = if cli.stream { "--stream" } else { "--follow" };
+            if cli.stream && cli.input_paths != stdin {
+                bail!("--stream only reads from stdin");
+            }
+            if cli.input_paths.len() > 1 {
+                bail!("--follow only reads a single input");
+            }
+            if !matches!(cli.mode, Mode::Plot) {
+                bail!("{option} must be used with --mode plot");
+            }
+            if !cli.refresh.is_finite() || cli.refresh <= 0.0 {
+                bail!("--refresh must be positive");
+            }
+            let followed =
+                (cli.input_paths != stdin).then(|| cli.input_paths[0].clone());
+            if let Some(path) = &followed
+                && !path.is_file()
+            {
+                bail!("Input '{}' does not exist", path.display());
+            }
+            let spool_path = TempArtifacts::create("sp-", "csv", |path| {
+                std::fs::File::create_new(path).map(drop)
+            })?;
+            cli.input_paths = vec![spool_path.clone()];
+            cli.input_format
+                .get_or_insert(DataFormat::Explicit("csv".to_string()));
+            Some(StreamOptions {
+                followed,
+                spool_path,
+                keep_header: !matches!(cli.header, HeaderPresence::False),
+                window: cli.stream_window,
+                refresh: Duration::from_secs_f64(cli.refresh),
+            })
+        } else {
+            None
+        };
+
+        if !cli.compare.is_empty() {
+            if cli.yexpr.len() > 1 {
+                bail!("--compare cannot be used with multiple -y");
+            }
+            cli.input_paths = vec![cli.compare[0].clone()];
+        }
+        // duckdb reads stdin as is, so it is only spooled (to detect its
+        // compression) if the data is read
+        let stdin_path = if cli.input_paths == stdin
+            && matches!(cli.mode, Mode::Plot | Mode::Dump | Mode::Stats)
+        {
+            Some(spool_stdin()?)
+        } else {
+            None
+        };
+        let new_data_input = |paths: &[PathBuf]| {
+            let (path, more_paths) = paths.split_first().unwrap();
+            let is_stdin = path.as_path() == Path::new("/dev/stdin");
+            DataInput::new(
+                cli.input_format.clone().unwrap_or_else(|| {
+                    if is_stdin {
+                        DataFormat::Explicit("csv".to_string())
+                    } else {
+                        DataFormat::Auto
+                    }
+                }),
+                match &stdin_path {
+                    Some(stdin_path) if is_stdin => stdin_path,
+                    _ => path,
+                }
+                .display()
+                .to_string(),
+                match cli.header {
+                    HeaderPresence::Auto => None,
+                    HeaderPresence::True => Some(true),
+                    HeaderPresence::False => Some(false),
+                },
+            )
+            .and_then(|input| input.with_delimiter(cli.delimiter))
+            .and_then(|input| {
+                input.with_dialect(
+                    CsvDialect::default()
+                        .with_quote(cli.quote)
+                        .with_escape(cli.escape)
+                        .with_comment(cli.comment)
+                        .with_flexible(cli.flexible),
+                )
+            })
+            .and_then(|input| {
+                input.with_column_offsets(cli.column_offsets.clone())
+            })
+            .and_then(|input| input.with_sheet(cli.sheet.clone()))
+            .and_then(|input| input.with_metric(cli.metric.clone()))
+            .and_then(|input| {
+                input.with_skipped_lines(cli.skip_rows, cli.skip_footer)
+            })
+            .and_then(|input| {
+                input.with_more_inputs(
+                    more_paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect(),
+                )
+            })
+            .and_then(|input| input.with_source_column(cli.source_column))
+            .and_then(|input| {
+                // spreadsheets, dat and fwf files are only converted if the
+                // data is read, and the spool of --stream and --follow is
+                // still empty, so it is not sniffed either
+                if matches!(cli.mode, Mode::Replot | Mode::Explain)
+                    || stream.is_some()
+                {
+                    Ok(input)
                 } else {
-                    DataFormat::Auto
+                    input.read_natively()
                 }
-            }),
-            cli.input_path.display().to_string(),
-            match cli.header {
-                HeaderPresence::Auto => None,
-                HeaderPresence::True => Some(true),
-                HeaderPresence::False => Some(false),
+            })
+            .map(|input| {
+                input
+                    .with_row_limits(cli.skip, cli.head, cli.tail)
+                    .with_streaming(cli.max_memory.is_some())
+            })
+        };
+        let data_input = new_data_input(&cli.input_paths)?;
+        let compare_input = cli
+            .compare
+            .get(1)
+            .map(|path| new_data_input(std::slice::from_ref(path)))
+            .transpose()?;
+        let tmp_datasheet_path = cli.replot.clone().unwrap_or_else(|| {
+            std::env::temp_dir().join(format!("{}.spdata", env!("VERSION")))
+        });
+        let spill_dir = tmp_datasheet_path.with_extension("spspill");
+        TempArtifacts::track(&spill_dir);
+
+        let opseq = cli
+            .opseq
+            .map(|s| config.expand_aliases(&s))
+            .transpose()
+            .with_category(ErrorCategory::Expression)?;
+        let report = serde_json::json!({
+            "version": env!("VERSION"),
+            "mode": cli.mode.to_possible_value().unwrap().get_name(),
+            "inputs": std::iter::once(&data_input)
+                .chain(&compare_input)
+                .map(|input| serde_json::json!({
+                    "path": input.input(),
+                    "format": input.format().to_string(),
+                    "header": format!("{:?}", cli.header).to_lowercase(),
+                    "skip": cli.skip,
+                    "head": cli.head,
+                    "tail": cli.tail,
+                }))
+                .collect::<Vec<_>>(),
+            "expressions": {
+                "x": cli.xexpr,
+                "y": cli.yexpr,
+                "input_filter": cli.input_filter,
+                "output_filter": cli.output_filter,
+                "sql": cli.sql,
             },
-        )?;
-        let tmp_datasheet_path =
-            std::env::temp_dir().join(format!("{}.spdata", env!("VERSION")));
+            "opseq": opseq,
+        });
+
+        let (opseq, external_ops, branches) = match &opseq {
+            Some(s) if s.contains('{') => {
+                let (head, external_ops) = OpSeq::parse_with_external(s)
+                    .with_category(ErrorCategory::Expression)?;
+                (Some(head), external_ops, vec![])
+            }
+            Some(s) => {
+                let (head, branches) = OpSeq::parse_with_branches(s)
+                    .with_category(ErrorCategory::Expression)?;
+                (Some(head), vec![], branches)
+            }
+            None => (None, vec![], vec![]),
+        };
+        for (op, _) in &external_ops {
+            if which::which(op.program()).is_err() {
+                return Err(anyhow::anyhow!(
+                    "External operator {op} not found: '{}' is not on PATH",
+                    op.program()
+                ))
+                .with_category(ErrorCategory::Expression);
+            }
+        }
+        if !external_ops.is_empty() && cli.yexpr.len() > 1 {
+            bail!("External operators cannot be used with multiple -y");
+        }
+        if !external_ops.is_empty() && compare_input.is_some() {
+            bail!("External operators cannot be used with --compare");
+        }
+        if !branches.is_empty() && cli.yexpr.len() > 1 {
+            bail!("Branches cannot be used with multiple -y");
+        }
+        if !branches.is_empty() && compare_input.is_some() {
+            bail!("Branches cannot be used with --compare");
+        }
 
-        let ds = DataSeriesOptions::from_datasheet_path(
-            tmp_datasheet_path.display().to_string(),
-        );
+        // multiple series are dumped side by side as x_1,y_1,x_2,y_2,...
+        let dss = if !cli.compare.is_empty() {
+            cli.compare
+                .iter()
+                .enumerate()
+                .map(|(i, path)| {
+                    DataSeriesOptions::from_datasheet_path(
+                        tmp_datasheet_path.display().to_string(),
+                    )
+                    .with_columns(2 * i + 1, 2 * i + 2)
+                    .with_label(Some(&path.display().to_string()))
+                })
+                .collect()
+        } else if cli.yexpr.len() == 1 && branches.is_empty() {
+            vec![DataSeriesOptions::from_datasheet_path(
+                tmp_datasheet_path.display().to_string(),
+            )]
+        } else {
+            // branches are labeled with their whole operation sequence
+            let labels = if branches.is_empty() {
+                cli.yexpr.clone()
+            } else {
+                let head = opseq.as_ref().map(|o| o.to_string());
+                branches
+                    .iter()
+                    .map(|b| format!("{}{b}", head.as_deref().unwrap_or("")))
+                    .collect()
+            };
+            labels
+                .iter()
+                .enumerate()
+                .map(|(i, label)| {
+                    DataSeriesOptions::from_datasheet_path(
+                        tmp_datasheet_path.display().to_string(),
+                    )
+                    .with_columns(2 * i + 1, 2 * i + 2)
+                    .with_label(Some(label))
+                })
+                .collect()
+        };
 
+        let (xlabel, ylabel) = if cli.x_unit.is_some() || cli.y_unit.is_some() {
+            let column = |expr: &str, unit: Option<&String>| {
+                Column::new(
+                    column_name(expr, cli.index_mark, cli.sql.is_some()),
+                    unit,
+                )
+            };
+            axis_labels(
+                column(&cli.xexpr, cli.x_unit.as_ref()),
+                cli.yexpr
+                    .iter()
+                    .map(|expr| column(expr, cli.y_unit.as_ref()))
+                    .collect(),
+                opseq.as_ref(),
+                &branches,
+            )
+        } else {
+            (None, None)
+        };
         let gnuplot_template = GnuplotTemplate::default()
             .with_terminal(spreadsheet_plotter::Terminal::Dumb(None, None))
-            .with_data_series_options(vec![ds])
-            .with_additional_command(cli.gnuplot_snippet);
+            .with_data_series_options(
+                dss.into_iter()
+                    .map(|ds| ds.with_categorical_x(cli.categorical_x))
+                    .collect(),
+            )
+            .with_additional_command(cli.gnuplot_snippet)
+            .with_default_labels(xlabel, ylabel);
+        let time_x = cli.time_x.as_ref().map(TimeFormat::new);
+        let gnuplot_template = match time_x {
+            Some(_) => gnuplot_template.with_time_x(),
+            None => gnuplot_template,
+        };
 
         let xexpr = Expr::new(&cli.xexpr, cli.index_mark);
-        let yexpr = Expr::new(&cli.yexpr, cli.index_mark);
+        let mut yexprs = cli.yexpr.iter().map(|s| Expr::new(s, cli.index_mark));
+        let yexpr = yexprs.next().unwrap();
         let input_filter =
             cli.input_filter.map(|s| Expr::new(&s, cli.index_mark));
         let output_filter =
             cli.output_filter.map(|s| Expr::new(&s, cli.index_mark));
 
         Ok(ParsedCli {
-            gnuplot_cmd: gnuplot_template.to_string(),
+            gnuplot_template,
+            categorical_x: cli.categorical_x,
             tmp_datasheet_path,
             data_input,
+            compare_input,
             selector: PlainSelector::new(
                 xexpr,
                 yexpr,
                 input_filter,
                 output_filter,
-            )?,
-            opseq: cli.opseq,
+            )
+            .and_then(|s| s.with_extra_yexprs(yexprs.collect()))
+            .with_category(ErrorCategory::Expression)?
+            .with_time_x(time_x)
+            .with_missing(cli.missing),
+            raw_sql: cli.sql,
+            opseq,
+            external_ops,
+            branches,
             mode: cli.mode,
+            script_path: cli.script_path,
+            edit: cli.edit,
+            max_points: cli.max_points,
+            max_memory: cli.max_memory,
+            spill_dir,
+            stream,
+            timing: cli.timing,
+            bench: cli.bench,
+            report_path: cli.report_json,
+            output_path: cli.output_path,
+            output_format: cli.output_format,
+            no_clobber: cli.no_clobber,
+            preserve: cli.preserve,
+            report,
         })
     }
 }
@@ -1,65 +1,1294 @@
 use std::{
-    backtrace::BacktraceStatus,
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, IsTerminal, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio, exit},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant},
 };
 
-use anyhow::bail;
-use spreadsheet_plotter::{DataSeriesSource, Plotter};
+use anyhow::Context;
+use spreadsheet_plotter::{
+    DataInput, DataPoints, DataSeriesSource, ErrorCategory, LogFormat, OpSeq,
+    Plotter, Table, TempArtifacts, Value, WithCategory, check_gnuplot,
+    csv_to_json, finish_temp_artifacts, finish_temp_artifacts_on_signal,
+    handle_err, run_duckdb,
+};
 use sqlformat::{FormatOptions, QueryParams};
 
-use crate::cli::{Cli, Mode};
+use crate::cli::{
+    Cli, KEPT_DATASHEETS, Mode, OutputFormat, ParsedCli, StreamOptions,
+    kept_datasheets,
+};
 
 mod cli;
 
-fn handle_err(e: anyhow::Error) {
-    e.chain().for_each(|e| eprintln!("Error: {e}"));
-    let bt = e.backtrace();
-    match bt.status() {
-        BacktraceStatus::Captured => {
-            eprintln!("Backtrace:\n{bt}");
+fn check_dependencies() -> anyhow::Result<()> {
+    Ok(())
+}
+
+fn decimate_datasheet(path: &Path, max_points: usize) -> anyhow::Result<()> {
+    let _span = tracing::info_span!("decimate", max_points).entered();
+    let mut points = match DataPoints::from_csv(path) {
+        Ok(points) => points,
+        Err(e) => {
+            tracing::warn!("Datasheet is not decimated: {e}");
+            return Ok(());
         }
-        BacktraceStatus::Unsupported => {
-            eprintln!("Backtrace is unsupported.");
+    };
+    if let Some(note) = points.decimate(max_points) {
+        tracing::info!("Datasheet decimated {note}");
+        DataSeriesSource::Points(points).dump(Some(path.to_path_buf()))?;
+    }
+    Ok(())
+}
+
+/// Temporary path in the same directory as `path`, so that renaming it to
+/// `path` is atomic
+fn partial_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.{}.partial", std::process::id()))
+}
+
+/// Move the complete output at `tmp` to `path`
+fn persist_output(
+    tmp: &Path,
+    path: &Path,
+    no_clobber: bool,
+) -> anyhow::Result<()> {
+    let result = if no_clobber {
+        // unlike rename, hard_link fails if the destination exists
+        std::fs::hard_link(tmp, path).and_then(|_| std::fs::remove_file(tmp))
+    } else {
+        std::fs::rename(tmp, path)
+    };
+    result.map_err(|e| {
+        let _ = std::fs::remove_file(tmp);
+        anyhow::anyhow!("Failed to write output '{}': {e}", path.display())
+    })
+}
+
+/// Keep the datasheet of this plot under a random name for --replot, and
+/// remove the oldest kept ones
+fn keep_datasheet(cli: &ParsedCli) -> anyhow::Result<()> {
+    // the next plot replaces the datasheet instead of writing into it, so a
+    // hard link is as good as a copy
+    let kept =
+        TempArtifacts::create(
+            "sp-",
+            "spdata",
+            |kept| match std::fs::hard_link(&cli.tmp_datasheet_path, kept) {
+                Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+                    let mut copy = File::create_new(kept)?;
+                    std::io::copy(
+                        &mut File::open(&cli.tmp_datasheet_path)?,
+                        &mut copy,
+                    )
+                    .map(drop)
+                }
+                result => result,
+            },
+        )
+        .context("Failed to keep datasheet")?;
+    TempArtifacts::untrack(&kept);
+    tracing::info!("Datasheet kept as {}", kept.display());
+    for (path, _) in kept_datasheets().iter().skip(KEPT_DATASHEETS) {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+/// Where the marks of the stages of the duckdb run are saved, see
+/// `StageMarks`
+fn marks_path(cli: &ParsedCli) -> PathBuf {
+    let path = cli.tmp_datasheet_path.with_extension("spmarks");
+    TempArtifacts::track(&path);
+    path
+}
+
+/// Statements recording in SQL variables when each stage of the duckdb run
+/// starts, for --timing, --bench and --report-json. The marks are saved to
+/// `marks_path` after the last stage, along with the number of records of
+/// each stage (with --report-json), so that counting them is not timed.
+struct StageMarks {
+    enabled: bool,
+    count: bool,
+    // name of each stage and the SQL expression counting its records
+    stages: Vec<(String, Option<String>)>,
+}
+
+impl StageMarks {
+    fn new(cli: &ParsedCli) -> Self {
+        Self {
+            enabled: (cli.timing
+                || cli.bench.is_some()
+                || cli.report_path.is_some())
+                && !matches!(cli.mode, Mode::DryRun),
+            count: cli.report_path.is_some(),
+            stages: Vec::new(),
         }
-        BacktraceStatus::Disabled => {
-            eprintln!("Backtrace is disabled.");
+    }
+
+    /// Statement starting `stage`, whose records are counted by the SQL
+    /// expression `rows`
+    fn start(
+        &mut self,
+        stage: impl Into<String>,
+        rows: Option<&str>,
+    ) -> String {
+        if !self.enabled {
+            return String::new();
         }
-        _ => {
-            eprintln!("Unknown backtrace status: {:?}", bt.status());
+        self.stages.push((stage.into(), rows.map(str::to_string)));
+        format!(
+            "SET VARIABLE sp_mark{} = get_current_timestamp();\n",
+            self.stages.len() - 1
+        )
+    }
+
+    /// Statements ending the last stage and saving the marks to `path`
+    fn finish(mut self, path: &Path) -> String {
+        if !self.enabled {
+            return String::new();
         }
+        let mut sql = self.start("end", None);
+        let values = self
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(i, (stage, rows))| {
+                format!(
+                    "({i}, epoch_us(getvariable('sp_mark{i}')), {}, '{}')",
+                    rows.as_deref()
+                        .filter(|_| self.count)
+                        .unwrap_or("NULL::BIGINT"),
+                    stage.replace('\'', "''")
+                )
+            })
+            .collect::<Vec<_>>();
+        // COPY prints nothing, so the marks do not mix into the output
+        sql.push_str(&format!(
+            "COPY (SELECT us, rows, stage FROM (VALUES {}) AS marks(i, us, rows, stage) ORDER BY i) TO '{}' (FORMAT csv, HEADER);\n",
+            values.join(", "),
+            path.display().to_string().replace('\'', "''")
+        ));
+        sql
     }
 }
 
-fn check_dependencies() -> anyhow::Result<()> {
+/// Start of a stage of the duckdb run, with the number of records it
+/// produced if counted
+struct StageMark {
+    stage: String,
+    us: i64,
+    rows: Option<usize>,
+}
+
+/// Marks saved by the last duckdb run (see `StageMarks`), which are removed
+/// so that they are not taken for those of a later run
+fn read_marks(cli: &ParsedCli) -> Vec<StageMark> {
+    let path = marks_path(cli);
+    let content = std::fs::read_to_string(&path).unwrap_or_default();
+    let _ = std::fs::remove_file(&path);
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let (us, rest) = line.split_once(',')?;
+            let (rows, stage) = rest.split_once(',')?;
+            // quoted by duckdb if it contains a comma or a quote
+            let stage = match stage.strip_prefix('"') {
+                Some(quoted) => quoted.strip_suffix('"')?.replace("\"\"", "\""),
+                None => stage.to_string(),
+            };
+            Some(StageMark {
+                stage,
+                us: us.parse().ok()?,
+                rows: rows.parse().ok(),
+            })
+        })
+        .collect()
+}
+
+/// Number of data rows (excluding the header) of a CSV file
+fn count_rows(path: &Path) -> Option<usize> {
+    let file = File::open(path).ok()?;
+    Some(BufReader::new(file).lines().count().saturating_sub(1))
+}
+
+fn write_report(
+    cli: &ParsedCli,
+    path: &Path,
+    timing: &Timing,
+    result: &anyhow::Result<Option<usize>>,
+) -> anyhow::Result<()> {
+    let mut report = cli.report.clone();
+    let rows_of = |stage: &str| {
+        timing
+            .rows
+            .iter()
+            .find(|(s, _)| s == stage)
+            .and_then(|(_, rows)| *rows)
+    };
+    let operators = timing
+        .rows
+        .iter()
+        .filter_map(|(stage, rows)| {
+            Some(serde_json::json!({
+                "operator": stage.strip_prefix("operator ")?,
+                "rows": rows,
+            }))
+        })
+        .collect::<Vec<_>>();
+    report["rows"] = serde_json::json!({
+        "input": rows_of("ingest"),
+        "selected": rows_of("expressions"),
+        "operators": operators,
+        "output": result.as_ref().ok().copied().flatten(),
+    });
+    report["outputs"] = serde_json::json!({
+        "datasheet": matches!(cli.mode, Mode::Plot | Mode::Replot)
+            .then(|| cli.tmp_datasheet_path.display().to_string()),
+        "script": cli.script_path.as_ref().map(|p| p.display().to_string()),
+        "dump": cli.output_path.as_ref().map(|p| p.display().to_string()),
+    });
+    report["timings"] = timing.to_json();
+    report["status"] = if result.is_ok() { "ok" } else { "error" }.into();
+    if let Err(e) = result {
+        report["error"] = serde_json::json!({
+            "category": ErrorCategory::of(e).map(|c| c.name()),
+            "message": format!("{e:#}"),
+        });
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&report)? + "\n")
+        .context(format!("Failed to write report '{}'", path.display()))?;
     Ok(())
 }
 
-fn try_main() -> anyhow::Result<()> {
-    env_logger::init();
-    let cli = Cli::parse_args()?;
-    check_dependencies()?;
+/// SQL of the pipeline up to the first external operator, or of the whole
+/// pipeline if there is none
+fn build_sql(cli: &ParsedCli) -> String {
+    let _span = tracing::info_span!("build_sql").entered();
+    let opseq = cli.opseq.as_ref();
+    let last = cli.external_ops.is_empty();
+    let opseq_sql = |y_name: &str| match opseq {
+        Some(opseq) => opseq.to_sql("t0", "x", y_name),
+        None => "".to_string(),
+    };
+    let final_table = match opseq {
+        Some(opseq) => opseq.get_tmp_table_name(),
+        None => "t0".to_string(),
+    };
+    if let Some(compare_input) = &cli.compare_input {
+        let mut sql = memory_sql(cli);
+        let src_tables = [&cli.data_input, compare_input]
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let table = format!("s{}", i + 1);
+                sql.push_str(&input.to_sql("src_tbl"));
+                sql.push_str(&select_sql(cli, input));
+                sql.push_str(&format!(
+                    "CREATE TABLE {table} AS {}SELECT x, y FROM {final_table};\nDROP TABLE t0;\n",
+                    opseq_sql("y")
+                ));
+                table
+            })
+            .collect::<Vec<_>>();
+        sql.push_str(&match cli.mode {
+            Mode::Dump => output_sql(
+                cli,
+                cli.selector.to_diff_sql(&src_tables[0], &src_tables[1]),
+            ),
+            _ => finish_sql(cli, src_tables, true),
+        });
+        return sql;
+    }
 
-    if matches!(cli.mode, Mode::Replot) {
-        if which::which("gnuplot").is_err() {
-            bail!("gnuplot is not installed");
+    let mut marks = StageMarks::new(cli);
+    let mut sql = memory_sql(cli);
+    sql.push_str(&marks.start("ingest", Some("getvariable('sp_rows_input')")));
+    sql.push_str(&cli.data_input.to_sql("src_tbl"));
+    if cli.report_path.is_some() {
+        sql.push_str(
+            "SET VARIABLE sp_rows_input = (SELECT count(*) FROM src_tbl);\n",
+        );
+    }
+    sql.push_str(
+        &marks.start("expressions", Some("(SELECT count(*) FROM t0)")),
+    );
+    sql.push_str(&select_sql(cli, &cli.data_input));
+
+    let y_columns = cli.selector.y_columns();
+    if !cli.branches.is_empty() || y_columns.len() > 1 {
+        sql.push_str(&marks.start("operators", None));
+    }
+    let src_tables = if !cli.branches.is_empty() {
+        // the shared operators run only once, each branch then starts from
+        // their output or restores one of their checkpoints
+        sql.push_str(&match opseq {
+            Some(opseq) => opseq.to_sql_tables("t0", "shared"),
+            None => "CREATE TABLE shared AS SELECT x, y FROM t0;\n".to_string(),
+        });
+        cli.branches
+            .iter()
+            .enumerate()
+            .map(|(i, branch)| {
+                let table = format!("s{}", i + 1);
+                sql.push_str(&format!(
+                    "CREATE TABLE {table} AS {}SELECT x, y FROM {};\n",
+                    branch.to_sql("shared", "x", "y"),
+                    if branch.ops.is_empty() {
+                        "shared".to_string()
+                    } else {
+                        branch.get_tmp_table_name()
+                    },
+                ));
+                table
+            })
+            .collect()
+    } else if y_columns.len() == 1 {
+        // each operator is materialized to be timed and counted on its own
+        match opseq {
+            Some(opseq) if marks.enabled => {
+                sql.push_str(&opseq.to_sql_steps(
+                    "t0",
+                    "x",
+                    "y",
+                    |op, table| {
+                        marks.start(
+                            format!("operator {op}"),
+                            Some(&format!("(SELECT count(*) FROM {table})")),
+                        )
+                    },
+                ));
+            }
+            _ => sql.push_str(&opseq_sql("y")),
         }
-        Plotter::plot(&cli.gnuplot_cmd)?;
+        vec![final_table]
     } else {
-        let complete_sql = format!(
-            "{}{}{}{}",
-            cli.data_input.to_sql("src_tbl"),
-            cli.selector.to_preprocess_sql("src_tbl", "t0"),
-            match &cli.opseq {
-                Some(opseq) => opseq.to_sql("t0", "x", "y"),
-                None => "".to_string(),
-            },
-            cli.selector.to_postprocess_sql(&match &cli.opseq {
-                Some(opseq) => opseq.get_tmp_table_name(),
-                None => "t0".to_string(),
-            }),
+        // the input is read only once, each y column then goes through the
+        // operator sequence separately
+        y_columns
+            .iter()
+            .enumerate()
+            .map(|(i, y_name)| {
+                let table = format!("s{}", i + 1);
+                sql.push_str(&format!(
+                    "CREATE TABLE {table} AS {}SELECT x, \"{}\" AS y FROM {final_table};\n",
+                    opseq_sql(y_name),
+                    if opseq.is_some() { "y" } else { y_name },
+                ));
+                table
+            })
+            .collect()
+    };
+
+    sql.push_str(&marks.start(
+        match cli.mode {
+            Mode::Dump | Mode::Stats => "output",
+            _ => "dump",
+        },
+        None,
+    ));
+    sql.push_str(&finish_sql(cli, src_tables, last));
+    sql.push_str(&marks.finish(&marks_path(cli)));
+    sql
+}
+
+/// Settings of duckdb bounding its memory by --max-memory
+fn memory_sql(cli: &ParsedCli) -> String {
+    cli.max_memory
+        .map(|max_memory| max_memory.to_duckdb_sql(&cli.spill_dir))
+        .unwrap_or_default()
+}
+
+/// duckdb only creates the spill directory once it exceeds --max-memory
+fn report_spill(cli: &ParsedCli) {
+    if let Some(max_memory) = cli.max_memory
+        && cli.spill_dir.exists()
+    {
+        tracing::warn!(
+            "duckdb exceeded --max-memory {max_memory} and spilled to disk"
+        );
+    }
+}
+
+/// SQL turning the table `src_tbl` of `input` into the initial table `t0`
+fn select_sql(cli: &ParsedCli, input: &DataInput) -> String {
+    let mut sql = match &cli.raw_sql {
+        Some(query) => format!(
+            "CREATE VIEW input AS SELECT * FROM src_tbl;\nCREATE TABLE t0 AS SELECT x, y FROM ({}) AS sp_sql;\nDROP VIEW input;\n",
+            query.trim().trim_end_matches(';')
+        ),
+        None => cli.selector.to_preprocess_sql("src_tbl", "t0"),
+    };
+    sql.push_str(&input.drop_sql("src_tbl"));
+    sql
+}
+
+/// Where the (x, y) table is saved before and after the `i`th external
+/// operator, removed on exit unless --preserve
+fn stage_path(cli: &ParsedCli, i: usize) -> PathBuf {
+    let path = cli
+        .tmp_datasheet_path
+        .with_extension(format!("spstage{i}.csv"));
+    TempArtifacts::track(&path);
+    path
+}
+
+/// SQL reading the output of an external operator at `path` and applying
+/// `opseq` to it, the output is post-processed if `last`
+fn build_stage_sql(
+    cli: &ParsedCli,
+    path: &Path,
+    opseq: &OpSeq,
+    last: bool,
+) -> String {
+    let mut sql = memory_sql(cli);
+    sql.push_str(&format!(
+        "CREATE TABLE t0 AS SELECT * FROM read_csv('{}', header = true, names = ['x', 'y']);\n",
+        path.display()
+    ));
+    sql.push_str(&opseq.to_sql("t0", "x", "y"));
+    sql.push_str(&finish_sql(cli, vec![opseq.get_tmp_table_name()], last));
+    sql
+}
+
+/// Post-process `src_tables`, or pass the single table on to the next
+/// external operator unless `last`
+fn finish_sql(cli: &ParsedCli, src_tables: Vec<String>, last: bool) -> String {
+    if !last {
+        return format!("SELECT x, y FROM {};\n", src_tables[0]);
+    }
+    let sql = match cli.mode {
+        Mode::Stats => cli.selector.to_stats_sql(&src_tables),
+        _ if src_tables.len() == 1 => {
+            cli.selector.to_postprocess_sql(&src_tables[0])
+        }
+        _ => cli.selector.to_multi_postprocess_sql(&src_tables),
+    };
+    output_sql(cli, sql)
+}
+
+/// With -F parquet, the final query of dump/stats mode is copied by duckdb
+/// to the partial output, then its number of rows is printed
+fn output_sql(cli: &ParsedCli, sql: String) -> String {
+    match &cli.output_path {
+        Some(path) if cli.output_format == OutputFormat::Parquet => {
+            let tmp = partial_path(path).display().to_string();
+            let tmp = tmp.replace('\'', "''");
+            format!(
+                "COPY ({}) TO '{tmp}' (FORMAT parquet, COMPRESSION zstd);\nSELECT count(*) AS rows FROM read_parquet('{tmp}');\n",
+                sql.trim_end().trim_end_matches(';')
+            )
+        }
+        _ => sql,
+    }
+}
+
+/// Run the pipeline up to the last external operator (if any), returns the
+/// SQL of the remaining pipeline
+fn run_external_ops(cli: &ParsedCli) -> anyhow::Result<String> {
+    if cli.external_ops.is_empty() {
+        return Ok(build_sql(cli));
+    }
+    let mut input = stage_path(cli, 0);
+    run_duckdb(&build_sql(cli), Stdio::from(File::create(&input)?), |_| {
+        Ok(())
+    })?;
+    for (i, (op, opseq)) in cli.external_ops.iter().enumerate() {
+        let _span =
+            tracing::info_span!("external_op", operator = %op).entered();
+        let output = stage_path(cli, 2 * i + 1);
+        tracing::debug!(input = %input.display(), output = %output.display());
+        let status = Command::new(op.program())
+            .args(&op.args)
+            .stdin(File::open(&input)?)
+            .stdout(File::create(&output)?)
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to run {}: {e}", op.program()))
+            .with_category(ErrorCategory::ExternalTool)?;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "External operator {op} failed with {status}"
+            ))
+            .with_category(ErrorCategory::ExternalTool);
+        }
+        if i + 1 == cli.external_ops.len() {
+            return Ok(build_stage_sql(cli, &output, opseq, true));
+        }
+        input = stage_path(cli, 2 * i + 2);
+        run_duckdb(
+            &build_stage_sql(cli, &output, opseq, false),
+            Stdio::from(File::create(&input)?),
+            |_| Ok(()),
+        )?;
+    }
+    unreachable!("external_ops is not empty")
+}
+
+/// SQL printed by the dry-run mode, external operators are only noted as
+/// they cannot run without data
+fn dry_run_sql(cli: &ParsedCli) -> String {
+    let mut sql = build_sql(cli);
+    for (i, (op, opseq)) in cli.external_ops.iter().enumerate() {
+        let output = stage_path(cli, 2 * i + 1);
+        sql.push_str(&format!(
+            "-- {} {} < {} > {}\n",
+            op.program(),
+            op.args.join(" "),
+            stage_path(cli, 2 * i).display(),
+            output.display()
+        ));
+        sql.push_str(&build_stage_sql(
+            cli,
+            &output,
+            opseq,
+            i + 1 == cli.external_ops.len(),
+        ));
+    }
+    sql
+}
+
+/// Operators of the pipeline printed by the explain mode, one stage (the
+/// operators after an external operator or a branch) after another
+fn explain(cli: &ParsedCli) -> String {
+    let head = cli.opseq.clone().unwrap_or(OpSeq { ops: vec![] });
+    // a branch is explained after the shared operators, which it continues
+    let stages = std::iter::once(("opseq".to_string(), head.clone(), 0))
+        .chain(cli.external_ops.iter().map(|(op, opseq)| {
+            (format!("after external operator {op}"), opseq.clone(), 0)
+        }))
+        .chain(cli.branches.iter().enumerate().map(|(i, branch)| {
+            let ops = head.ops.iter().chain(&branch.ops).cloned().collect();
+            (format!("branch {}", i + 1), OpSeq { ops }, head.ops.len())
+        }));
+    let mut out = String::new();
+    for (title, opseq, skipped) in stages {
+        let steps = opseq
+            .explain("x", "y")
+            .into_iter()
+            .skip(skipped)
+            .collect::<Vec<_>>();
+        let rows = std::iter::once(
+            ["#", "operator", "x", "y", "description"].map(String::from),
+        )
+        .chain(steps.iter().enumerate().map(|(i, step)| {
+            [
+                (i + 1).to_string(),
+                step.operator.clone(),
+                step.x_name.clone(),
+                step.y_name.clone(),
+                step.description.to_string(),
+            ]
+        }))
+        .collect::<Vec<_>>();
+        let widths = (0..5)
+            .map(|c| rows.iter().map(|row| row[c].len()).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        out.push_str(&format!("{title}:\n"));
+        if steps.is_empty() {
+            out.push_str("  (no operator)\n");
+            continue;
+        }
+        for row in &rows {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            out.push_str(&format!("  {}\n", line.trim_end()));
+        }
+        for (i, step) in steps.iter().enumerate() {
+            if let Some(warning) = &step.warning {
+                out.push_str(&format!("  warning: #{}: {warning}\n", i + 1));
+            }
+        }
+    }
+    out
+}
+
+fn generate_datasheet(cli: &ParsedCli, sql: &str) -> anyhow::Result<()> {
+    // the previous datasheet may be linked by a kept one, which must not be
+    // overwritten
+    let _ = std::fs::remove_file(&cli.tmp_datasheet_path);
+    run_duckdb(sql, Stdio::piped(), |stdout| {
+        let dss = DataSeriesSource::Child(stdout.unwrap());
+        dss.dump(Some(cli.tmp_datasheet_path.clone()))?;
+        Ok(())
+    })
+}
+
+/// Whether the pipeline runs without duckdb (see `native_table`), as it is
+/// not installed or sp is built without the `duckdb` feature
+fn runs_natively() -> bool {
+    !cfg!(feature = "duckdb") || which::which("duckdb").is_err()
+}
+
+/// The output of the pipeline computed without duckdb: the input read by
+/// `DataInput::read_table`, the expressions and filters evaluated by the
+/// selector and the operators by `OpSeq::eval`. The tables of several data
+/// series are placed side by side as by `to_multi_postprocess_sql`. What
+/// only duckdb runs is an error.
+fn native_table(cli: &ParsedCli) -> anyhow::Result<Table> {
+    let registered = cli
+        .opseq
+        .iter()
+        .chain(&cli.branches)
+        .any(|opseq| !opseq.is_native());
+    let unsupported = [
+        (cli.raw_sql.is_some(), "--sql"),
+        (cli.compare_input.is_some(), "--compare"),
+        (cli.stream.is_some(), "--stream and --follow"),
+        (!cli.external_ops.is_empty(), "external operators"),
+        (registered, "registered operators"),
+        (matches!(cli.mode, Mode::Stats), "--mode stats"),
+        (cli.output_format == OutputFormat::Parquet, "-F parquet"),
+    ];
+    if let Some((_, what)) = unsupported.iter().find(|(used, _)| *used) {
+        return Err(anyhow::anyhow!(
+            "{what} needs duckdb, {}",
+            match cfg!(feature = "duckdb") {
+                true => "which is not installed",
+                false => "which this build of sp does not run",
+            }
+        ))
+        .with_category(ErrorCategory::ExternalTool);
+    }
+
+    let input = cli
+        .data_input
+        .read_table()
+        .with_category(ErrorCategory::InputParse)?;
+    let selected = cli
+        .selector
+        .select_table(&input)
+        .with_category(ErrorCategory::Expression)?;
+    let points = |column: usize| {
+        selected
+            .rows
+            .iter()
+            .map(|row| Ok((row[0].to_f64()?, row[column].to_f64()?)))
+            .collect::<anyhow::Result<Vec<_>>>()
+    };
+    let opseq = cli
+        .opseq
+        .clone()
+        .map_or_else(|| OpSeq::builder().build(), Ok)?;
+    let series = if !cli.branches.is_empty() {
+        opseq.eval_branches(&cli.branches, points(1)?)
+    } else {
+        (1..selected.columns.len())
+            .map(|column| opseq.eval(points(column)?))
+            .collect()
+    }
+    .with_category(ErrorCategory::Expression)?;
+    let mut tables = series
+        .into_iter()
+        .map(|points| {
+            cli.selector.postprocess_table(Table {
+                columns: vec!["x".to_string(), "y".to_string()],
+                rows: points
+                    .into_iter()
+                    .map(|(x, y)| vec![Value::from_f64(x), Value::from_f64(y)])
+                    .collect(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .with_category(ErrorCategory::Expression)?;
+    if tables.len() == 1 {
+        return Ok(tables.remove(0));
+    }
+    let len = tables.iter().map(|t| t.rows.len()).max().unwrap_or(0);
+    Ok(Table {
+        columns: (1..=tables.len())
+            .flat_map(|n| [format!("x_{n}"), format!("y_{n}")])
+            .collect(),
+        rows: (0..len)
+            .map(|i| {
+                tables
+                    .iter()
+                    .flat_map(|t| match t.rows.get(i) {
+                        Some(row) => row.clone(),
+                        None => vec![Value::Null; 2],
+                    })
+                    .collect()
+            })
+            .collect(),
+    })
+}
+
+/// Same as `run`, but the pipeline is computed by `native_table`
+fn run_natively(
+    cli: &ParsedCli,
+    timing: &mut Timing,
+) -> anyhow::Result<Option<usize>> {
+    tracing::info!("duckdb is not available, running the pipeline natively");
+    let table = native_table(cli)?;
+    timing.lap("native (ingest, exprs, ops)");
+    let mut csv = Vec::new();
+    table.write_csv(&mut csv)?;
+    if !matches!(cli.mode, Mode::Dump) {
+        // the previous datasheet may be linked by a kept one
+        let _ = std::fs::remove_file(&cli.tmp_datasheet_path);
+        std::fs::write(&cli.tmp_datasheet_path, csv)?;
+        timing.lap("dump");
+        return plot_datasheet(cli, timing);
+    }
+
+    let tmp_output = cli.output_path.as_deref().map(partial_path);
+    let mut writer: Box<dyn Write> = match &tmp_output {
+        Some(tmp) => Box::new(std::io::BufWriter::new(
+            File::create(tmp)
+                .context(format!("Failed to create '{}'", tmp.display()))?,
+        )),
+        None if cli.bench.is_some() => Box::new(std::io::sink()),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let result = match cli.output_format {
+        OutputFormat::Csv => writer.write_all(&csv).map_err(Into::into),
+        format => csv_to_json(
+            &csv[..],
+            &mut writer,
+            format == OutputFormat::JsonColumns,
+        )
+        .map(drop),
+    }
+    .and_then(|_| writer.flush().map_err(Into::into));
+    drop(writer);
+    if let Some(tmp) = &tmp_output {
+        match (result, &cli.output_path) {
+            (Ok(()), Some(path)) => persist_output(tmp, path, cli.no_clobber)?,
+            (result, _) => {
+                let _ = std::fs::remove_file(tmp);
+                result?;
+            }
+        }
+    } else {
+        result?;
+    }
+    timing.lap("output");
+    Ok(Some(table.rows.len()))
+}
+
+/// Interval between the reads of a followed file at its end
+const FOLLOW_POLL: Duration = Duration::from_millis(100);
+
+/// Interval between the checks of the script edited with --edit
+const EDIT_POLL: Duration = Duration::from_millis(200);
+
+/// Send the lines of the append-only file at `path` as it grows, like
+/// `tail -f`, until the receiver is dropped
+fn follow(path: &Path, tx: &mpsc::Sender<String>) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut line = String::new();
+    loop {
+        // a partial last line is completed by the next reads
+        if reader.read_line(&mut line)? == 0 || !line.ends_with('\n') {
+            std::thread::sleep(FOLLOW_POLL);
+            continue;
+        }
+        let complete = line.trim_end_matches(['\n', '\r']).to_string();
+        if tx.send(complete).is_err() {
+            return Ok(());
+        }
+        line.clear();
+    }
+}
+
+/// Refreshes of --stream and --follow that only process the rows received
+/// since the previous one: duckdb selects the (x, y) points of the new rows,
+/// which then go through the operators natively (see `OpSeq::eval_chunk`)
+/// and are appended to the datasheet. This needs every step to look at a
+/// single row (and the previous one), i.e. no whole-table operator, row
+/// limits, --stream-window, --sql, --of, multiple -y, branches or external
+/// operators.
+struct AppendedRows {
+    input: DataInput,
+    opseq: OpSeq,
+    lasts: Vec<Option<(f64, f64)>>,
+    // whether the datasheet of the rows so far is written
+    started: bool,
+}
+
+impl AppendedRows {
+    fn new(
+        cli: &ParsedCli,
+        opts: &StreamOptions,
+    ) -> anyhow::Result<Option<Self>> {
+        let opseq = cli.opseq.clone().unwrap_or(OpSeq { ops: vec![] });
+        let row_local = opts.window.is_none()
+            && opseq.is_native()
+            && opseq.is_streaming()
+            // categories are not parsed as points by the operators
+            && (!cli.categorical_x || opseq.ops.is_empty())
+            && cli.raw_sql.is_none()
+            && cli.compare_input.is_none()
+            && !cli.selector.filters_output()
+            && cli.selector.y_columns().len() == 1
+            && cli.branches.is_empty()
+            && cli.external_ops.is_empty();
+        if !row_local {
+            return Ok(None);
+        }
+        let batch_path = TempArtifacts::create("sp-", "csv", |path| {
+            File::create_new(path).map(drop)
+        })?;
+        Ok(cli
+            .data_input
+            .with_appended_rows(&batch_path.display().to_string())
+            .map(|input| Self {
+                input,
+                opseq,
+                lasts: vec![],
+                started: false,
+            }))
+    }
+
+    /// Append the points of `rows` (after the `header` row, if any) to the
+    /// datasheet, returns whether the datasheet is written
+    fn append(
+        &mut self,
+        cli: &ParsedCli,
+        header: Option<&String>,
+        rows: impl Iterator<Item = String>,
+    ) -> anyhow::Result<bool> {
+        let mut batch = File::create(self.input.input())?;
+        for line in header.into_iter().cloned().chain(rows) {
+            writeln!(batch, "{line}")?;
+        }
+        drop(batch);
+        let mut sql = memory_sql(cli);
+        sql.push_str(&self.input.to_sql("src_tbl"));
+        sql.push_str(&select_sql(cli, &self.input));
+        sql.push_str("SELECT x, y FROM t0;\n");
+        let selected = run_duckdb(&sql, Stdio::piped(), |stdout| {
+            let mut lines = BufReader::new(stdout.unwrap()).lines();
+            let header = lines.next().transpose()?.unwrap_or_default();
+            Ok((header, lines.collect::<std::io::Result<Vec<_>>>()?))
+        })?;
+        let (header, lines) = selected;
+        if !self.started && lines.is_empty() {
+            return Ok(false);
+        }
+
+        let mut datasheet = if self.started {
+            std::fs::OpenOptions::new()
+                .append(true)
+                .open(&cli.tmp_datasheet_path)?
+        } else {
+            let mut datasheet = File::create(&cli.tmp_datasheet_path)?;
+            writeln!(datasheet, "{header}")?;
+            self.started = true;
+            datasheet
+        };
+        if self.opseq.ops.is_empty() {
+            for line in lines {
+                writeln!(datasheet, "{line}")?;
+            }
+            return Ok(true);
+        }
+        let points = DataPoints::from_reader(BufReader::new(
+            std::iter::once(header)
+                .chain(lines)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .as_bytes(),
+        ))?
+        .points;
+        // missing values are empty, as in the datasheets of duckdb
+        let cell = |v: f64| {
+            if v.is_nan() {
+                String::new()
+            } else {
+                v.to_string()
+            }
+        };
+        for (x, y) in self.opseq.eval_chunk(points, &mut self.lasts)? {
+            writeln!(datasheet, "{},{}", cell(x), cell(y))?;
+        }
+        Ok(true)
+    }
+}
+
+/// Spool stdin (or the lines appended to the followed file) into a file
+/// and rerun the whole pipeline on it every `refresh`, so every operator
+/// sees a consistent snapshot of the input, unless only the new rows need
+/// to be processed (see `AppendedRows`)
+fn stream(cli: &ParsedCli, opts: &StreamOptions) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    match opts.followed.clone() {
+        Some(path) => std::thread::spawn(move || {
+            if let Err(e) = follow(&path, &tx) {
+                tracing::warn!("Stopped following '{}': {e}", path.display());
+            }
+        }),
+        None => std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        }),
+    };
+
+    // the dumb terminal output is redrawn in place, unless it goes to a
+    // file or a terminal without escape sequences
+    let redraw = std::io::stdout().is_terminal()
+        && std::env::var("TERM").is_ok_and(|term| term != "dumb");
+    let mut appended = AppendedRows::new(cli, opts)?;
+    tracing::info!(incremental = appended.is_some(), "Refreshing on new rows");
+    let mut header = None;
+    let mut rows = VecDeque::new();
+    loop {
+        let deadline = Instant::now() + opts.refresh;
+        let mut changed = false;
+        let mut eof = false;
+        loop {
+            match rx.recv_timeout(
+                deadline.saturating_duration_since(Instant::now()),
+            ) {
+                Ok(line) => {
+                    changed = true;
+                    if opts.keep_header && header.is_none() {
+                        header = Some(line);
+                        continue;
+                    }
+                    rows.push_back(line);
+                    if opts.window.is_some_and(|w| rows.len() > w) {
+                        rows.pop_front();
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    eof = true;
+                    break;
+                }
+            }
+        }
+
+        if changed && !rows.is_empty() {
+            if let Some(appended) = &mut appended {
+                if !appended.append(cli, header.as_ref(), rows.drain(..))? {
+                    continue;
+                }
+            } else {
+                let mut spool = File::create(&opts.spool_path)?;
+                for line in header.iter().chain(rows.iter()) {
+                    writeln!(spool, "{line}")?;
+                }
+                drop(spool);
+                generate_datasheet(cli, &run_external_ops(cli)?)?;
+            }
+            if redraw {
+                print!("\x1b[H\x1b[2J");
+                std::io::stdout().flush()?;
+            }
+            plot(cli)?;
+        }
+        if eof {
+            return Ok(());
+        }
+    }
+}
+
+/// gnuplot script of the datasheet, whose x columns (1, 3, ...) hold the
+/// category labels with --categorical-x
+fn gnuplot_cmd(cli: &ParsedCli) -> anyhow::Result<String> {
+    if !cli.categorical_x {
+        return Ok(cli.gnuplot_template.to_string());
+    }
+    let series = cli.selector.y_columns().len().max(cli.branches.len());
+    let columns = (0..series.max(1)).map(|i| 2 * i + 1).collect::<Vec<_>>();
+    let categories =
+        DataPoints::read_categories(&cli.tmp_datasheet_path, &columns)
+            .context(format!(
+                "Failed to read the categories of datasheet '{}'",
+                cli.tmp_datasheet_path.display()
+            ))?;
+    Ok(cli
+        .gnuplot_template
+        .clone()
+        .with_x_categories(categories)
+        .to_string())
+}
+
+fn plot(cli: &ParsedCli) -> anyhow::Result<()> {
+    let _span = tracing::info_span!("gnuplot").entered();
+    if which::which("gnuplot").is_err() {
+        // the script of --edit is only run by gnuplot
+        if cli.edit {
+            return Err(anyhow::anyhow!("gnuplot is not installed"))
+                .with_category(ErrorCategory::ExternalTool);
+        }
+        return plot_natively(cli);
+    }
+    let gnuplot_cmd = gnuplot_cmd(cli)?;
+    if !cli.edit {
+        return check_gnuplot(match &cli.script_path {
+            Some(path) => Plotter::plot_with_script_path(&gnuplot_cmd, path),
+            None => Plotter::plot(&gnuplot_cmd),
+        });
+    }
+
+    // the script must stay at a known place to be edited
+    let path = cli
+        .script_path
+        .clone()
+        .unwrap_or_else(|| cli.tmp_datasheet_path.with_extension("gp"));
+    check_gnuplot(Plotter::plot_with_script_path(&gnuplot_cmd, &path))?;
+    let editor = std::env::var("EDITOR").unwrap_or("vi".to_string());
+    let mut editor_process = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(&path)
+        .spawn()?;
+    // the script is replotted each time it is saved, until the editor exits
+    let mut plotted = std::fs::read(&path)?;
+    loop {
+        let status = editor_process.try_wait()?;
+        // an editor may replace the script rather than write into it, so it
+        // is briefly missing
+        if let Ok(script) = std::fs::read(&path)
+            && script != plotted
+        {
+            // a mistake in the edited script is reported, and fixed by the
+            // next save
+            if let Err(e) = check_gnuplot(Plotter::plot_script(&path)) {
+                tracing::error!("Failed to replot '{}': {e}", path.display());
+            }
+            plotted = script;
+        }
+        match status {
+            Some(status) if !status.success() => {
+                return Err(anyhow::anyhow!(
+                    "Editor '{editor}' failed with {status}"
+                ))
+                .with_category(ErrorCategory::ExternalTool);
+            }
+            Some(_) => return Ok(()),
+            None => std::thread::sleep(EDIT_POLL),
+        }
+    }
+}
+
+/// Draw the plot in text without gnuplot, sized like the dumb terminal of
+/// gnuplot after `tput cols` and `tput lines`, read from the environment
+fn plot_natively(cli: &ParsedCli) -> anyhow::Result<()> {
+    tracing::info!("gnuplot is not installed, plotting natively");
+    if let Some(path) = &cli.script_path {
+        tracing::warn!("No gnuplot script is saved to '{}'", path.display());
+    }
+    let plot = cli
+        .gnuplot_template
+        .to_native_plot()
+        .with_category(ErrorCategory::Plot)?;
+    let size = |variable: &str, default: usize| {
+        std::env::var(variable)
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(default)
+    };
+    print!(
+        "{}",
+        plot.to_text(size("COLUMNS", 80), size("LINES", 25).saturating_sub(1))
+    );
+    Ok(())
+}
+
+/// Wall-clock time spent in each stage of a run, reported by --timing
+struct Timing {
+    last: Instant,
+    stages: Vec<(String, Duration)>,
+    /// Number of records produced by each stage of the duckdb run, if
+    /// counted for --report-json
+    rows: Vec<(String, Option<usize>)>,
+}
+
+impl Timing {
+    fn new() -> Self {
+        Self {
+            last: Instant::now(),
+            stages: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// End the current stage, which started when the previous stage ended
+    fn lap(&mut self, stage: impl Into<String>) {
+        let now = Instant::now();
+        self.stages.push((stage.into(), now - self.last));
+        self.last = now;
+    }
+
+    /// End the current stage, a duckdb run, which is split into the stages
+    /// between its `marks` if there are any, the rest of it being the
+    /// startup and exit of duckdb
+    fn lap_duckdb(&mut self, stage: &str, marks: Vec<StageMark>) {
+        if marks.len() < 2 {
+            self.lap(stage);
+            return;
+        }
+        let now = Instant::now();
+        let stages = marks
+            .windows(2)
+            .map(|pair| {
+                let us = (pair[1].us - pair[0].us).max(0) as u64;
+                (pair[0].stage.clone(), Duration::from_micros(us))
+            })
+            .collect::<Vec<_>>();
+        let marked = stages.iter().map(|(_, d)| *d).sum();
+        self.stages.push((
+            "duckdb startup and exit".to_string(),
+            (now - self.last).saturating_sub(marked),
+        ));
+        self.stages.extend(stages);
+        self.count(marks);
+        self.last = now;
+    }
+
+    /// Keep the number of records of the stages of a duckdb run
+    fn count(&mut self, marks: Vec<StageMark>) {
+        self.rows.extend(
+            marks
+                .into_iter()
+                .filter(|mark| mark.stage != "end")
+                .map(|mark| (mark.stage, mark.rows)),
         );
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        self.stages
+            .iter()
+            .map(|(stage, duration)| {
+                serde_json::json!({
+                    "stage": stage,
+                    "ms": duration.as_secs_f64() * 1000.0,
+                })
+            })
+            .collect()
+    }
+
+    fn report(&self) {
+        let total: Duration = self.stages.iter().map(|(_, d)| *d).sum();
+        eprintln!("Timing:");
+        let stages = self.stages.iter().map(|(s, d)| (s.as_str(), *d));
+        for (stage, duration) in stages.chain(std::iter::once(("total", total)))
+        {
+            eprintln!(
+                "  {stage:<36} {:>10.3} ms {:>6.1}%",
+                duration.as_secs_f64() * 1000.0,
+                duration.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON)
+                    * 100.0
+            );
+        }
+    }
+}
 
+/// Report the timing of each stage of a cold run, and the minimum and
+/// median over the warm runs after it
+fn report_bench(runs: &[Timing]) {
+    let Some((cold, warm)) = runs.split_first() else {
+        return;
+    };
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    eprintln!("Benchmark ({} warm runs after a cold run):", warm.len());
+    eprintln!(
+        "  {:<36} {:>12} {:>12} {:>12}",
+        "stage", "cold (ms)", "min (ms)", "median (ms)"
+    );
+    let total = |t: &Timing| t.stages.iter().map(|(_, d)| *d).sum();
+    let rows = cold
+        .stages
+        .iter()
+        .enumerate()
+        .map(|(i, (stage, duration))| {
+            let durations = warm
+                .iter()
+                .filter_map(|t| t.stages.get(i))
+                .filter(|(s, _)| s == stage)
+                .map(|(_, d)| *d)
+                .collect::<Vec<_>>();
+            (stage.as_str(), *duration, durations)
+        })
+        .chain(std::iter::once((
+            "total",
+            total(cold),
+            warm.iter().map(total).collect(),
+        )));
+    for (stage, cold, mut durations) in rows {
+        durations.sort();
+        eprintln!(
+            "  {stage:<36} {:>12.3} {:>12.3} {:>12.3}",
+            ms(cold),
+            durations.first().copied().map(ms).unwrap_or(f64::NAN),
+            durations
+                .get(durations.len() / 2)
+                .copied()
+                .map(ms)
+                .unwrap_or(f64::NAN)
+        );
+    }
+}
+
+fn try_main() -> anyhow::Result<()> {
+    LogFormat::from_args().init();
+    let mut timing = Timing::new();
+    let cli = tracing::info_span!("parse_args")
+        .in_scope(Cli::parse_args)
+        .with_category(ErrorCategory::Usage)?;
+    check_dependencies()?;
+    finish_temp_artifacts_on_signal(cli.preserve);
+    timing.lap("parse arguments");
+
+    if let Some(n) = cli.bench {
+        let runs = (0..=n)
+            .map(|_| {
+                let mut timing = Timing::new();
+                run(&cli, &mut timing).map(|_| timing)
+            })
+            .collect::<anyhow::Result<Vec<_>>>();
+        report_spill(&cli);
+        finish_temp_artifacts(cli.preserve);
+        report_bench(&runs?);
+        return Ok(());
+    }
+
+    let result = run(&cli, &mut timing);
+    report_spill(&cli);
+    finish_temp_artifacts(cli.preserve);
+    if cli.timing {
+        timing.report();
+    }
+    if let Some(path) = &cli.report_path {
+        write_report(&cli, path, &timing, &result)?;
+    }
+    result.map(|_| ())
+}
+
+/// Returns the number of output rows if known
+fn run(cli: &ParsedCli, timing: &mut Timing) -> anyhow::Result<Option<usize>> {
+    if matches!(cli.mode, Mode::Replot) {
+        plot(cli)?;
+        timing.lap("gnuplot");
+        Ok(count_rows(&cli.tmp_datasheet_path))
+    } else {
+        let _ = std::fs::remove_file(marks_path(cli));
+        if matches!(cli.mode, Mode::Explain) {
+            print!("{}", explain(cli));
+            return Ok(None);
+        }
         if matches!(cli.mode, Mode::DryRun) {
+            let complete_sql = dry_run_sql(cli);
+            timing.lap("build SQL");
             let options = FormatOptions {
                 indent: sqlformat::Indent::Spaces(4),
                 uppercase: Some(true),
@@ -73,60 +1302,138 @@ fn try_main() -> anyhow::Result<()> {
             let formatted_sql =
                 sqlformat::format(&complete_sql, &QueryParams::None, &options);
             println!("{formatted_sql}");
-            return Ok(());
+            timing.lap("format SQL");
+            return Ok(None);
         }
 
-        if which::which("duckdb").is_err() {
-            bail!("duckdb is not installed");
+        if runs_natively() {
+            return run_natively(cli, timing);
         }
 
-        if matches!(cli.mode, Mode::Dump) {
-            let status = Command::new("duckdb")
-                .arg("-csv")
-                .arg("-bail")
-                .arg("-c")
-                .arg(complete_sql.clone())
-                .stdout(Stdio::inherit())
-                .spawn()?
-                .wait()?;
-            if !status.success() {
-                bail!(
-                    "duckdb failed with {status}\nOriginal SQL:\n{complete_sql}"
-                );
-            }
-            return Ok(());
+        if let Some(stream_opts) = &cli.stream {
+            stream(cli, stream_opts)?;
+            keep_datasheet(cli)?;
+            timing.lap("stream");
+            return Ok(count_rows(&cli.tmp_datasheet_path));
         }
 
-        let mut child = Command::new("duckdb")
-            .arg("-csv")
-            .arg("-bail")
-            .arg("-c")
-            .arg(complete_sql)
-            .stdout(Stdio::piped())
-            .spawn()?;
-        let stdout = child.stdout.take().unwrap();
-        let dss = DataSeriesSource::Child(stdout);
-        dss.dump(Some(cli.tmp_datasheet_path))?;
-        let status = child.wait()?;
-        if !status.success() {
-            bail!("duckdb failed with {status}");
+        let complete_sql = run_external_ops(cli)?;
+        if cli.external_ops.is_empty() {
+            timing.lap("build SQL");
+        } else {
+            // the marks are those of the run before the external operators
+            timing.lap("build SQL, duckdb and external operators");
+            timing.count(read_marks(cli));
         }
 
-        if which::which("gnuplot").is_err() {
-            bail!("gnuplot is not installed");
+        if matches!(cli.mode, Mode::Dump | Mode::Stats) {
+            // written next to the destination, then renamed into place
+            let tmp_output = cli.output_path.as_deref().map(partial_path);
+            let create = |tmp: &Path| {
+                File::create(tmp)
+                    .context(format!("Failed to create '{}'", tmp.display()))
+            };
+            let stdout = match &tmp_output {
+                _ if cli.output_format != OutputFormat::Csv => Stdio::piped(),
+                Some(tmp) => Stdio::from(create(tmp)?),
+                None if cli.bench.is_some() => Stdio::null(),
+                None if cli.report_path.is_some() => Stdio::piped(),
+                None => Stdio::inherit(),
+            };
+            // output is only relayed when rows have to be counted or the
+            // CSV of duckdb converted to JSON, while duckdb writes Parquet
+            // itself and only prints its number of rows
+            let result = run_duckdb(&complete_sql, stdout, |output| {
+                let Some(output) = output else {
+                    return Ok(None);
+                };
+                if cli.output_format == OutputFormat::Parquet {
+                    let count = BufReader::new(output).lines().nth(1);
+                    return Ok(count.transpose()?.and_then(|c| c.parse().ok()));
+                }
+                if cli.output_format != OutputFormat::Csv {
+                    let columnar =
+                        cli.output_format == OutputFormat::JsonColumns;
+                    let output = BufReader::new(output);
+                    let rows = match &tmp_output {
+                        Some(tmp) => csv_to_json(
+                            output,
+                            &mut std::io::BufWriter::new(create(tmp)?),
+                            columnar,
+                        ),
+                        None if cli.bench.is_some() => {
+                            csv_to_json(output, &mut std::io::sink(), columnar)
+                        }
+                        None => csv_to_json(
+                            output,
+                            &mut std::io::stdout().lock(),
+                            columnar,
+                        ),
+                    }?;
+                    return Ok(Some(rows));
+                }
+                let mut stdout = std::io::stdout().lock();
+                let mut lines = 0usize;
+                for line in BufReader::new(output).lines() {
+                    writeln!(stdout, "{}", line?)?;
+                    lines += 1;
+                }
+                Ok(Some(lines.saturating_sub(1)))
+            });
+            if result.is_err()
+                && let Some(tmp) = &tmp_output
+            {
+                let _ = std::fs::remove_file(tmp);
+            }
+            let output_rows = result?;
+            let output_rows = match (&tmp_output, &cli.output_path) {
+                (Some(tmp), Some(path)) => {
+                    let rows = output_rows.or_else(|| count_rows(tmp));
+                    persist_output(tmp, path, cli.no_clobber)?;
+                    rows
+                }
+                _ => output_rows,
+            };
+            timing.lap_duckdb(
+                "duckdb (ingest, exprs, ops, output)",
+                read_marks(cli),
+            );
+            return Ok(output_rows);
         }
-        Plotter::plot(&cli.gnuplot_cmd)?;
+
+        generate_datasheet(cli, &complete_sql)?;
+        timing.lap_duckdb("duckdb (ingest, exprs, ops, dump)", read_marks(cli));
+        plot_datasheet(cli, timing)
     }
+}
 
-    Ok(())
+/// Decimate and keep the generated datasheet, then plot it
+fn plot_datasheet(
+    cli: &ParsedCli,
+    timing: &mut Timing,
+) -> anyhow::Result<Option<usize>> {
+    if cli.max_points.is_some()
+        && (cli.selector.y_columns().len() > 1
+            || cli.compare_input.is_some()
+            || !cli.branches.is_empty())
+    {
+        tracing::warn!(
+            "--max-points is ignored with multiple -y, --compare or branches"
+        );
+    } else if let Some(max_points) = cli.max_points {
+        decimate_datasheet(&cli.tmp_datasheet_path, max_points)?;
+        timing.lap("decimate");
+    }
+    keep_datasheet(cli)?;
+
+    plot(cli)?;
+    timing.lap("gnuplot");
+    Ok(count_rows(&cli.tmp_datasheet_path))
 }
 
 fn main() -> anyhow::Result<()> {
     match try_main() {
         Ok(()) => Ok(()),
-        Err(e) => {
-            handle_err(e);
-            exit(1)
-        }
+        Err(e) => exit(handle_err(e)),
     }
 }
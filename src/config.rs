@@ -0,0 +1,309 @@
+// User configuration file that provides defaults of command line options
+
+use std::path::PathBuf;
+
+use anyhow::{Context, anyhow, bail};
+use regex::Regex;
+
+use crate::{PipelineConfig, error::raw_arg};
+
+#[derive(Debug, Clone, Default)]
+pub struct UserConfig {
+    path: Option<PathBuf>,
+    table: toml::Table,
+}
+
+impl UserConfig {
+    /// `$XDG_CONFIG_HOME/spreadsheet-plotter/config.toml`, falls back to
+    /// `~/.config` if `$XDG_CONFIG_HOME` is unset
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(".config"))
+            })
+            .map(|dir| dir.join("spreadsheet-plotter").join("config.toml"))
+    }
+
+    /// Load the configuration file from the default path, a missing file is
+    /// treated as an empty configuration
+    pub fn load() -> anyhow::Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load_from(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: PathBuf) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(&path).context(format!(
+            "Failed to read config file '{}'",
+            path.display()
+        ))?;
+        let table = content.parse::<toml::Table>().context(format!(
+            "Failed to parse config file '{}'",
+            path.display()
+        ))?;
+        tracing::info!("Config file loaded: {}", path.display());
+        Ok(Self {
+            path: Some(path),
+            table,
+        })
+    }
+
+    fn display_path(&self) -> String {
+        self.path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or("<none>".to_string())
+    }
+
+    /// Use values in the `[<section>]` table as default values of the
+    /// options of `cmd`. Keys are long names (or ids) of the options, and
+    /// options given on the command line still take precedence.
+    pub fn apply_defaults(
+        &self,
+        section: &str,
+        cmd: clap::Command,
+    ) -> anyhow::Result<clap::Command> {
+        let Some(table) = self.table.get(section) else {
+            return Ok(cmd);
+        };
+        let table = table.as_table().ok_or_else(|| {
+            anyhow!("[{section}] in '{}' is not a table", self.display_path())
+        })?;
+        self.apply_table(table, &format!("[{section}] of "), cmd)
+    }
+
+    /// Load the recipe file given by option `long` (or environment variable
+    /// `env`), which is looked up in the raw arguments as it provides
+    /// defaults of the others
+    pub fn load_recipe(long: &str, env: &str) -> anyhow::Result<Option<Self>> {
+        raw_arg(long, env)
+            .map(|path| Self::load_from(PathBuf::from(path)))
+            .transpose()
+    }
+
+    /// Use the top-level values of a recipe file as default values of the
+    /// options of `cmd`, like `apply_defaults`, except the `reserved` keys
+    /// handled by the caller
+    pub fn apply_recipe(
+        &self,
+        cmd: clap::Command,
+        reserved: &[&str],
+    ) -> anyhow::Result<clap::Command> {
+        let table = self
+            .table
+            .iter()
+            .filter(|(key, _)| !reserved.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        self.apply_table(&table, "", cmd)
+    }
+
+    /// Load the pipeline file (see `PipelineConfig`) given by option `long`
+    /// (or environment variable `env`) as a recipe of sp
+    pub fn load_pipeline(
+        long: &str,
+        env: &str,
+    ) -> anyhow::Result<Option<Self>> {
+        raw_arg(long, env)
+            .map(|path| {
+                let path = PathBuf::from(path);
+                Ok(Self {
+                    table: PipelineConfig::load(&path)?.to_recipe()?,
+                    path: Some(path),
+                })
+            })
+            .transpose()
+    }
+
+    /// Top-level value of a recipe file
+    pub fn get(&self, key: &str) -> Option<&toml::Value> {
+        self.table.get(key)
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    /// Keys are long names, short names or ids of the options, arrays give
+    /// multiple values
+    fn apply_table(
+        &self,
+        table: &toml::Table,
+        location: &str,
+        mut cmd: clap::Command,
+    ) -> anyhow::Result<clap::Command> {
+        for (key, value) in table {
+            let id = cmd
+                .get_arguments()
+                .filter(|a| !a.is_positional())
+                .find(|a| {
+                    a.get_long() == Some(key)
+                        || a.get_id() == key
+                        || a.get_short().is_some_and(|c| key == &c.to_string())
+                })
+                .map(|a| a.get_id().clone())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unknown option '{key}' in {location}'{}'",
+                        self.display_path()
+                    )
+                })?;
+            let scalar = |value: &toml::Value| match value {
+                toml::Value::String(s) => Ok(s.clone()),
+                toml::Value::Array(_) | toml::Value::Table(_) => Err(anyhow!(
+                    "Option '{key}' in {location}'{}' must be a scalar or an array of scalars",
+                    self.display_path()
+                )),
+                v => Ok(v.to_string()),
+            };
+            let values = match value {
+                toml::Value::Array(values) => {
+                    values.iter().map(scalar).collect::<Result<Vec<_>, _>>()?
+                }
+                value => vec![scalar(value)?],
+            };
+            // clap only takes static strings as default values, the config
+            // lives as long as the program anyway
+            let values: Vec<&'static str> = values
+                .into_iter()
+                .map(|v| &*Box::leak(v.into_boxed_str()))
+                .collect();
+            cmd = cmd.mut_arg(id, |a| a.default_values(values));
+        }
+        Ok(cmd)
+    }
+
+    /// Replace `@name` in an operator sequence with the alias defined in the
+    /// `[aliases]` table, which may refer to other aliases. An operator
+    /// sequence that is only `@path` of an existing file is read from the
+    /// file, where `#` starts a comment and whitespace is ignored. Aliases
+    /// take precedence over files of the same name, which may be given as
+    /// `@./name` instead.
+    pub fn expand_aliases(&self, opseq: &str) -> anyhow::Result<String> {
+        let opseq = match opseq.strip_prefix('@') {
+            Some(name) if self.aliases().iter().any(|(n, _)| n == name) => {
+                if std::path::Path::new(name).is_file() {
+                    tracing::warn!(
+                        "'@{name}' is the opseq alias, not the file '{name}' (use '@./{name}' for the file)"
+                    );
+                }
+                opseq.to_string()
+            }
+            Some(path) if std::path::Path::new(path).is_file() => {
+                std::fs::read_to_string(path)
+                    .context(format!("Failed to read opseq file '{path}'"))?
+                    .lines()
+                    .map(|line| line.split('#').next().unwrap_or(""))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            _ => opseq.to_string(),
+        };
+        // whitespace separates an alias from what follows it, and is then
+        // ignored, except in the arguments of external operators
+        let mut depth = 0usize;
+        Ok(self
+            .expand_with(&opseq, &mut vec![])?
+            .chars()
+            .filter(|&c| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                depth > 0 || !c.is_whitespace()
+            })
+            .collect())
+    }
+
+    /// Aliases defined in the `[aliases]` table
+    pub fn aliases(&self) -> Vec<(String, String)> {
+        self.table
+            .get("aliases")
+            .and_then(|t| t.as_table())
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, value)| {
+                Some((name.clone(), value.as_str()?.trim().to_string()))
+            })
+            .collect()
+    }
+
+    // `@name` replaced with its alias, which may refer to other aliases in
+    // turn, `stack` being the aliases being expanded
+    fn expand_with(
+        &self,
+        opseq: &str,
+        stack: &mut Vec<String>,
+    ) -> anyhow::Result<String> {
+        let pattern = Regex::new(r"@([A-Za-z0-9_-]+)").unwrap();
+        let aliases = self.aliases();
+        let mut expanded = String::new();
+        let mut last = 0;
+        for caps in pattern.captures_iter(opseq) {
+            let range = caps.get(0).unwrap().range();
+            expanded.push_str(&opseq[last..range.start]);
+            last = range.end;
+            let name = &caps[1];
+            if stack.iter().any(|n| n == name) {
+                bail!(
+                    "Opseq alias '@{name}' refers to itself (@{} -> @{name})",
+                    stack.join(" -> @")
+                );
+            }
+            let Some((_, alias)) = aliases.iter().find(|(n, _)| n == name)
+            else {
+                bail!(
+                    "Unknown opseq alias '@{name}' (config file: '{}', defined aliases: {})",
+                    self.display_path(),
+                    if aliases.is_empty() {
+                        "none".to_string()
+                    } else {
+                        aliases
+                            .iter()
+                            .map(|(n, _)| format!("@{n}"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    }
+                );
+            };
+            stack.push(name.to_string());
+            expanded.push_str(&self.expand_with(alias, stack)?);
+            stack.pop();
+        }
+        expanded.push_str(&opseq[last..]);
+        Ok(expanded)
+    }
+}
+
+#[test]
+fn test_expand_aliases() {
+    let config = UserConfig {
+        path: None,
+        table: "[aliases]\n\
+                smooth = \"oa5\"\n\
+                tail = \"@smooth q99, 99.9\"\n\
+                LICENSE = \"o\"\n\
+                loop = \"@loop\"\n"
+            .parse()
+            .unwrap(),
+    };
+    let expand = |opseq: &str| config.expand_aliases(opseq);
+    assert_eq!(expand("@tail c").unwrap(), "oa5q99,99.9c");
+    // spaces are kept in the arguments of external operators
+    assert_eq!(expand("@smooth {fit:a b} x").unwrap(), "oa5{fit:a b}x");
+    // aliases take precedence over files
+    assert_eq!(expand("@LICENSE").unwrap(), "o");
+    let path = std::env::temp_dir()
+        .join(format!("sp-test-{}.opseq", std::process::id()));
+    std::fs::write(&path, "# comment\n@smooth # smoothed\n{fit: a}\n").unwrap();
+    let from_file = expand(&format!("@{}", path.display()));
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(from_file.unwrap(), "oa5{fit: a}");
+    assert!(expand("@loop").is_err());
+    assert!(expand("@missing").is_err());
+}
@@ -2,6 +2,21 @@ use std::{fmt::Display, str::FromStr};
 
 use anyhow::bail;
 
+use crate::{Table, Value};
+
+/// Formats of spreadsheets, read natively with the `xlsx` feature
+const SPREADSHEET_FORMATS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+/// Extensions of compressed files, which duckdb decompresses on the fly
+const COMPRESSED_EXTENSIONS: [&str; 2] = ["gz", "zst"];
+
+/// Extensions of the text formats detected by duckdb under compression
+const TEXT_EXTENSIONS: [&str; 5] = ["csv", "tsv", "json", "jsonl", "ndjson"];
+
+/// Formats (and extensions) of Apache Arrow IPC files, e.g. written by
+/// polars or pandas, which are all read by `read_arrow`
+const ARROW_FORMATS: [&str; 4] = ["arrow", "arrows", "feather", "ipc"];
+
 #[derive(Debug, Clone)]
 pub enum DataFormat {
     /// translates into `select * from '<input>'`
@@ -36,11 +51,100 @@ impl FromStr for DataFormat {
     }
 }
 
+/// Quoting, escaping and comments of CSV input, detected by duckdb (or left
+/// to its defaults) if unset, e.g. `'` quotes of some European exports
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CsvDialect {
+    /// Character quoting values, `"` by default
+    pub quote: Option<char>,
+    /// Character escaping the quote within quoted values, the quote itself
+    /// by default
+    pub escape: Option<char>,
+    /// Prefix of comment lines, which are skipped
+    pub comment: Option<char>,
+    /// Rows with missing columns are padded with NULL, and malformed quoting
+    /// is read as is instead of failing
+    pub flexible: bool,
+}
+
+impl CsvDialect {
+    pub fn with_quote(mut self, quote: Option<char>) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    pub fn with_escape(mut self, escape: Option<char>) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    pub fn with_comment(mut self, comment: Option<char>) -> Self {
+        self.comment = comment;
+        self
+    }
+
+    pub fn with_flexible(mut self, flexible: bool) -> Self {
+        self.flexible = flexible;
+        self
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // options of read_csv
+    fn to_sql(&self) -> String {
+        let mut sql = String::new();
+        for (name, c) in [
+            ("quote", self.quote),
+            ("escape", self.escape),
+            ("comment", self.comment),
+        ] {
+            if let Some(c) = c {
+                sql.push_str(&format!(", {name}={}", sql_char(c)));
+            }
+        }
+        if self.flexible {
+            sql.push_str(", null_padding=true, strict_mode=false");
+        }
+        sql
+    }
+}
+
+// SQL string of a path
+fn sql_path(path: &str) -> String {
+    format!("'{}'", path.replace('\'', "''"))
+}
+
+// SQL string of a single character
+fn sql_char(c: char) -> String {
+    match c {
+        '\t' => "'\\t'".to_string(),
+        '\'' => "''''".to_string(),
+        c => format!("'{c}'"),
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct DataInput {
     format: DataFormat,
     input: String,
     header: Option<bool>,
+    skip: usize,
+    head: Option<usize>,
+    tail: Option<usize>,
+    streamed: bool,
+    delimiter: Option<char>,
+    sheet: Option<String>,
+    column_offsets: Option<Vec<usize>>,
+    dialect: CsvDialect,
+    skip_lines: usize,
+    skip_footer: usize,
+    more_inputs: Vec<String>,
+    source_column: bool,
+    metric: Option<String>,
+    // paths of the inputs converted to CSV by `read_natively`, in order
+    origins: Vec<String>,
 }
 
 impl DataInput {
@@ -50,7 +154,8 @@ impl DataInput {
     ) -> anyhow::Result<()> {
         if header.is_some() {
             if let DataFormat::Explicit(fmt) = format {
-                if fmt == "csv" || fmt == "xlsx" {
+                if ["csv", "tsv", "dat", "fwf", "xlsx"].contains(&fmt.as_str())
+                {
                     return Ok(());
                 }
             }
@@ -60,37 +165,951 @@ impl DataInput {
         Ok(())
     }
 
+    /// Format `tsv` is read as `csv` delimited by tabs, `dat` (gnuplot-style
+    /// data separated by whitespace), `prom` (the text exposition format of
+    /// Prometheus) and `influx` (the line protocol of InfluxDB, with the
+    /// extension `lp`), also detected by their extension, are converted to
+    /// CSV by `read_natively`, and Arrow IPC files (format or extension
+    /// `arrow`, `arrows`, `feather` or `ipc`) with the `arrow` extension of
+    /// duckdb, which scans their record batches as they are. Compressed
+    /// files (`.gz` or `.zst`) are decompressed on the fly, and read as CSV
+    /// unless the extension before the compression one is of another text
+    /// format (e.g. `events.json.gz`).
     pub fn new(
         format: DataFormat,
         input: String,
         header: Option<bool>,
     ) -> anyhow::Result<Self> {
         Self::format_check(&format, header)?;
+        let path = std::path::Path::new(&input);
+        let extension_of = |path: &std::path::Path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| ext.to_ascii_lowercase())
+        };
+        let extension = extension_of(path);
+        let compressed = extension
+            .as_ref()
+            .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.as_str()));
+        let inner_extension = path
+            .file_stem()
+            .and_then(|stem| extension_of(std::path::Path::new(stem)));
+        let (format, delimiter) = match format {
+            DataFormat::Explicit(fmt) if fmt == "tsv" => {
+                (DataFormat::Explicit("csv".to_string()), Some('\t'))
+            }
+            DataFormat::Explicit(fmt)
+                if ARROW_FORMATS.contains(&fmt.as_str()) =>
+            {
+                (DataFormat::Explicit("arrow".to_string()), None)
+            }
+            DataFormat::Auto
+                if extension.as_ref().is_some_and(|ext| {
+                    ARROW_FORMATS.contains(&ext.as_str())
+                }) =>
+            {
+                (DataFormat::Explicit("arrow".to_string()), None)
+            }
+            DataFormat::Auto
+                if compressed
+                    && !inner_extension.as_ref().is_some_and(|ext| {
+                        TEXT_EXTENSIONS.contains(&ext.as_str())
+                    }) =>
+            {
+                (DataFormat::Explicit("csv".to_string()), None)
+            }
+            DataFormat::Auto
+                if matches!(extension.as_deref(), Some("dat" | "prom")) =>
+            {
+                (DataFormat::Explicit(extension.unwrap()), None)
+            }
+            DataFormat::Auto if extension.as_deref() == Some("lp") => {
+                (DataFormat::Explicit("influx".to_string()), None)
+            }
+            format => (format, None),
+        };
         Ok(Self {
             format,
             input,
             header,
+            delimiter,
+            ..Default::default()
         })
     }
 
-    pub fn to_sql(&self, table_name: &str) -> String {
-        match self.format {
-            DataFormat::Auto => format!(
-                "CREATE TABLE {} AS SELECT * FROM '{}';\n",
-                table_name, self.input
+    /// Read the input as CSV delimited by `delimiter` (e.g. `;` or `|`)
+    /// instead of letting duckdb detect it, the format must then be `auto`,
+    /// `csv` or `tsv`
+    pub fn with_delimiter(
+        mut self,
+        delimiter: Option<char>,
+    ) -> anyhow::Result<Self> {
+        if delimiter.is_none() {
+            return Ok(self);
+        }
+        if let DataFormat::Explicit(fmt) = &self.format
+            && fmt != "csv"
+        {
+            bail!("--delimiter must be used with --format csv or --format tsv");
+        }
+        self.format = DataFormat::Explicit("csv".to_string());
+        self.delimiter = delimiter;
+        Ok(self)
+    }
+
+    /// Read the input as CSV of `dialect` (see `with_delimiter`), the format
+    /// must then be `auto`, `csv` or `tsv`
+    pub fn with_dialect(mut self, dialect: CsvDialect) -> anyhow::Result<Self> {
+        if dialect.is_default() {
+            return Ok(self);
+        }
+        if let DataFormat::Explicit(fmt) = &self.format
+            && fmt != "csv"
+        {
+            bail!(
+                "--quote, --escape, --comment and --flexible must be used with --format csv or --format tsv"
+            );
+        }
+        self.format = DataFormat::Explicit("csv".to_string());
+        self.dialect = dialect;
+        Ok(self)
+    }
+
+    /// Read the input as fixed-width columns starting at `column_offsets`
+    /// (in characters from 0, e.g. `[0, 12, 20]`) instead of inferring them,
+    /// the format must then be `auto` or `fwf`
+    pub fn with_column_offsets(
+        mut self,
+        column_offsets: Option<Vec<usize>>,
+    ) -> anyhow::Result<Self> {
+        let Some(offsets) = column_offsets else {
+            return Ok(self);
+        };
+        if let DataFormat::Explicit(fmt) = &self.format
+            && fmt != "fwf"
+        {
+            bail!("--column-offsets must be used with --format fwf");
+        }
+        if offsets.is_empty() || offsets.windows(2).any(|w| w[0] >= w[1]) {
+            bail!("Column offsets must be increasing, got {offsets:?}");
+        }
+        self.format = DataFormat::Explicit("fwf".to_string());
+        self.column_offsets = Some(offsets);
+        Ok(self)
+    }
+
+    /// Whether the input is a spreadsheet, by its format or the extension of
+    /// its path if the format is `auto`
+    pub fn is_spreadsheet(&self) -> bool {
+        let format = match &self.format {
+            DataFormat::Explicit(fmt) => Some(fmt.as_str()),
+            DataFormat::Auto => std::path::Path::new(&self.input)
+                .extension()
+                .and_then(|ext| ext.to_str()),
+        };
+        format.is_some_and(|fmt| {
+            SPREADSHEET_FORMATS.contains(&fmt.to_ascii_lowercase().as_str())
+        })
+    }
+
+    /// Names of the sheets of spreadsheet input, in order, for `with_sheet`
+    #[cfg(feature = "xlsx")]
+    pub fn sheet_names(&self) -> anyhow::Result<Vec<String>> {
+        if !self.is_spreadsheet() {
+            bail!(
+                "'{}' is not a spreadsheet ({})",
+                self.input,
+                SPREADSHEET_FORMATS.join(", ")
+            );
+        }
+        crate::spreadsheet::sheet_names(&self.input)
+    }
+
+    /// Read the sheet of spreadsheet input given by its name or by its
+    /// position (from 1) instead of the first one
+    pub fn with_sheet(mut self, sheet: Option<String>) -> anyhow::Result<Self> {
+        if sheet.is_some() && !self.is_spreadsheet() {
+            bail!(
+                "--sheet must be used with a spreadsheet ({})",
+                SPREADSHEET_FORMATS.join(", ")
+            );
+        }
+        self.sheet = sheet;
+        Ok(self)
+    }
+
+    /// Only read the samples of Prometheus input matching `metric`, a metric
+    /// name and/or label matchers as in PromQL, e.g.
+    /// `node_cpu_seconds_total{mode="idle"}` or `{job=~"api|web"}`, the
+    /// format must then be `auto` or `prom`
+    pub fn with_metric(
+        mut self,
+        metric: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let Some(metric) = metric else {
+            return Ok(self);
+        };
+        if !matches!(&self.format, DataFormat::Explicit(fmt) if fmt == "prom") {
+            if let DataFormat::Explicit(_) = &self.format {
+                bail!("--metric must be used with --format prom");
+            }
+            self.format = DataFormat::Explicit("prom".to_string());
+        }
+        #[cfg(feature = "gnuplot")]
+        crate::prometheus::MetricSelector::parse(&metric)?;
+        self.metric = Some(metric);
+        Ok(self)
+    }
+
+    /// Concatenate the rows of `inputs` after those of the input, all files
+    /// being read with the same format and options, and each one with its
+    /// own header row. The delimiter and the header row are detected in the
+    /// first file only.
+    pub fn with_more_inputs(
+        mut self,
+        inputs: Vec<String>,
+    ) -> anyhow::Result<Self> {
+        if !inputs.is_empty() {
+            self.multi_file_check("Several inputs")?;
+        }
+        self.more_inputs = inputs;
+        Ok(self)
+    }
+
+    /// Add the path of the file each row comes from as a last column named
+    /// `filename`, e.g. to tell apart the rows of several inputs
+    pub fn with_source_column(
+        mut self,
+        source_column: bool,
+    ) -> anyhow::Result<Self> {
+        if source_column {
+            self.multi_file_check("--source-column")?;
+        }
+        self.source_column = source_column;
+        Ok(self)
+    }
+
+    // formats read by the multi-file readers of duckdb (or converted to CSV)
+    fn multi_file_check(&self, what: &str) -> anyhow::Result<()> {
+        let arrow =
+            matches!(&self.format, DataFormat::Explicit(fmt) if fmt == "arrow");
+        if arrow || (self.is_spreadsheet() && !cfg!(feature = "xlsx")) {
+            bail!(
+                "{what} must be used with text, CSV, JSON or Parquet input, or spreadsheets with the xlsx feature"
+            );
+        }
+        Ok(())
+    }
+
+    /// Read the inputs that duckdb does not read by itself into temporary
+    /// CSV files, which become the inputs: `dat`, `fwf`, `prom` and `influx`
+    /// files, and spreadsheets (with the `xlsx` feature). Other inputs are
+    /// returned as is, except that the delimiter of uncompressed CSV (or
+    /// text) input without one is sniffed from its first lines, input
+    /// separated by runs of spaces being read as `dat`.
+    #[cfg(feature = "gnuplot")]
+    pub fn read_natively(mut self) -> anyhow::Result<Self> {
+        if self.delimiter.is_none() && self.is_plain_text() {
+            match self.sniff_delimiter()? {
+                Some(' ') => {
+                    self.format = DataFormat::Explicit("dat".to_string())
+                }
+                Some(delimiter) => {
+                    tracing::debug!(?delimiter, "Sniffed the delimiter");
+                    self.format = DataFormat::Explicit("csv".to_string());
+                    self.delimiter = Some(delimiter);
+                }
+                None => {}
+            }
+        }
+        let convert = match &self.format {
+            DataFormat::Explicit(fmt) if fmt == "dat" => Self::dat_to_csv,
+            DataFormat::Explicit(fmt) if fmt == "fwf" => Self::fwf_to_csv,
+            DataFormat::Explicit(fmt) if fmt == "prom" => Self::prom_to_csv,
+            DataFormat::Explicit(fmt) if fmt == "influx" => Self::influx_to_csv,
+            #[cfg(feature = "xlsx")]
+            _ if self.is_spreadsheet() => Self::spreadsheet_to_csv,
+            _ => return self.with_detected_header(),
+        };
+        let origins = std::iter::once(&self.input)
+            .chain(&self.more_inputs)
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut csv_paths = origins
+            .iter()
+            .map(|src| {
+                let csv_path = crate::plotter::temp_filename("sp-", "csv")?;
+                convert(&self, src, &csv_path)?;
+                Ok(csv_path.display().to_string())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let input = csv_paths.remove(0);
+        // samples and points are written with a header row
+        let header = match &self.format {
+            DataFormat::Explicit(fmt) if fmt == "prom" || fmt == "influx" => {
+                Some(true)
+            }
+            _ => self.header,
+        };
+        // the converted files start after the skipped lines
+        Self {
+            format: DataFormat::Explicit("csv".to_string()),
+            input,
+            more_inputs: csv_paths,
+            origins,
+            header,
+            sheet: None,
+            skip_lines: 0,
+            ..self
+        }
+        .with_detected_header()
+    }
+
+    // whether plain text input without `header` has a header row, which is
+    // guessed from its first lines:
+    // - a column typed (numbers or ISO-8601 timestamps) below the first row
+    //   but not in it gives a header
+    // - a first row typed in every column, or with duplicate values, gives
+    //   none
+    // - otherwise, a first row none of whose values appear again in its
+    //   column gives a header
+    #[cfg(feature = "gnuplot")]
+    fn with_detected_header(mut self) -> anyhow::Result<Self> {
+        if self.header.is_some() || !self.is_plain_text() {
+            return Ok(self);
+        }
+        let delimiter = self.delimiter.unwrap_or(',');
+        let rows = self
+            .head_lines()?
+            .iter()
+            .map(|line| split_row(line, delimiter))
+            .collect::<Vec<_>>();
+        let Some((first, rest)) = rows.split_first() else {
+            return Ok(self);
+        };
+        let typed = |cell: &str| {
+            cell.parse::<f64>().is_ok()
+                || matches!(crate::TimeFormat::Iso8601.parse(cell), Ok(Some(_)))
+        };
+        let column = |i: usize| {
+            rest.iter()
+                .filter_map(move |row| row.get(i))
+                .filter(|cell| !cell.is_empty())
+        };
+        let typed_below = (0..first.len()).find(|&i| {
+            !typed(&first[i])
+                && column(i).count() > 0
+                && column(i).all(|c| typed(c))
+        });
+        let distinct =
+            first.iter().collect::<std::collections::HashSet<_>>().len()
+                == first.len();
+        let (header, reason) = if let Some(i) = typed_below {
+            (
+                true,
+                format!("column {} is only typed below the first row", i + 1),
+            )
+        } else if first.iter().all(|cell| typed(cell)) {
+            (false, "the first row is typed".to_string())
+        } else if !distinct {
+            (false, "the first row has duplicate values".to_string())
+        } else if let Some(i) =
+            (0..first.len()).find(|&i| column(i).any(|c| *c == first[i]))
+        {
+            (false, format!("column {} repeats its first value", i + 1))
+        } else {
+            (
+                true,
+                "the first row has distinct values of its own".to_string(),
+            )
+        };
+        tracing::debug!(
+            input = self.input,
+            header,
+            "Header row detected: {reason}"
+        );
+        self.format = DataFormat::Explicit("csv".to_string());
+        self.header = Some(header);
+        Ok(self)
+    }
+
+    // complete, non-blank and non-comment lines of the first 8 KiB
+    #[cfg(feature = "gnuplot")]
+    fn head_lines(&self) -> anyhow::Result<Vec<String>> {
+        use std::io::Read;
+
+        let mut head = Vec::new();
+        std::fs::File::open(&self.input)?
+            .take(8 * 1024)
+            .read_to_end(&mut head)?;
+        let cut = head.len() == 8 * 1024;
+        let head = String::from_utf8_lossy(&head);
+        let mut lines = head.lines().skip(self.skip_lines).collect::<Vec<_>>();
+        // the last line may be cut
+        if cut && lines.len() > 1 {
+            lines.pop();
+        }
+        let comment = self.dialect.comment.unwrap_or('#');
+        Ok(lines
+            .into_iter()
+            .filter(|line| {
+                !line.trim().is_empty() && !line.starts_with(comment)
+            })
+            .map(str::to_string)
+            .collect())
+    }
+
+    // uncompressed CSV or text file (but stdin, which can only be read
+    // once), whose format may still be `auto`
+    #[cfg(feature = "gnuplot")]
+    fn is_plain_text(&self) -> bool {
+        if self.input == "/dev/stdin" {
+            return false;
+        }
+        let extension = std::path::Path::new(&self.input)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match (&self.format, extension.as_deref()) {
+            (_, Some(ext)) if COMPRESSED_EXTENSIONS.contains(&ext) => false,
+            (DataFormat::Explicit(fmt), _) => fmt == "csv",
+            (DataFormat::Auto, ext) => {
+                matches!(ext, None | Some("csv" | "txt"))
+            }
+        }
+    }
+
+    // the delimiter that splits all the complete lines of the first 8 KiB
+    // into the same number of columns, the most frequent one if several do;
+    // ' ' stands for runs of whitespace, only if no other delimiter does
+    #[cfg(feature = "gnuplot")]
+    fn sniff_delimiter(&self) -> anyhow::Result<Option<char>> {
+        let lines = self.head_lines()?;
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        // occurrences of `delimiter` outside of double quotes
+        let count = |line: &str, delimiter: char| {
+            let mut quoted = false;
+            line.chars()
+                .filter(|&c| {
+                    if c == '"' {
+                        quoted = !quoted;
+                    }
+                    c == delimiter && !quoted
+                })
+                .count()
+        };
+        let consistent = |counts: Vec<usize>| {
+            (counts[0] > 0 && counts.iter().all(|&n| n == counts[0]))
+                .then_some(counts[0])
+        };
+        // the first of the most frequent ones is the last maximum
+        let delimiter = [',', '\t', ';', '|']
+            .into_iter()
+            .rev()
+            .filter_map(|delimiter| {
+                let counts = lines.iter().map(|line| count(line, delimiter));
+                Some((consistent(counts.collect())?, delimiter))
+            })
+            .max_by_key(|(n, _)| *n)
+            .map(|(_, delimiter)| delimiter);
+        Ok(delimiter.or_else(|| {
+            let counts =
+                lines.iter().map(|line| line.split_whitespace().count() - 1);
+            consistent(counts.collect()).map(|_| ' ')
+        }))
+    }
+
+    #[cfg(feature = "xlsx")]
+    fn spreadsheet_to_csv(
+        &self,
+        src: &str,
+        dst: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        crate::spreadsheet::sheet_to_csv(
+            src,
+            self.sheet.as_deref(),
+            self.skip_lines,
+            dst,
+        )
+    }
+
+    #[cfg(feature = "gnuplot")]
+    fn prom_to_csv(
+        &self,
+        src: &str,
+        dst: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        let selector = self
+            .metric
+            .as_deref()
+            .map(crate::prometheus::MetricSelector::parse)
+            .transpose()?;
+        crate::prometheus::samples_to_csv(src, selector.as_ref(), dst)
+    }
+
+    #[cfg(feature = "gnuplot")]
+    fn influx_to_csv(
+        &self,
+        src: &str,
+        dst: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        crate::influx::points_to_csv(src, dst)
+    }
+
+    // gnuplot-style data, whose columns are separated by runs of whitespace
+    // and may be quoted, `#` starting a comment; blank lines (separating
+    // the data blocks of gnuplot) are skipped
+    #[cfg(feature = "gnuplot")]
+    fn dat_to_csv(
+        &self,
+        src: &str,
+        dst: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        use anyhow::Context;
+        use std::io::{BufRead, Write};
+
+        let file = std::fs::File::open(src)
+            .context(format!("Failed to open '{src}'"))?;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(dst)?);
+        let lines = std::io::BufReader::new(file).lines();
+        for line in lines.skip(self.skip_lines) {
+            let cells = split_dat_row(&line?);
+            if cells.is_empty() {
+                continue;
+            }
+            let cells = cells
+                .iter()
+                .map(|cell| crate::plotter::to_rfc4180_csv_cell(cell))
+                .collect::<Vec<_>>();
+            writeln!(writer, "{}", cells.join(","))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // text of fixed-width columns, e.g. outputs of `sar` or `iostat`, which
+    // start at `column_offsets`, or otherwise at each run of positions that
+    // are not blank in every line; blank lines are skipped
+    #[cfg(feature = "gnuplot")]
+    fn fwf_to_csv(
+        &self,
+        src: &str,
+        dst: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        use anyhow::Context;
+        use std::io::{BufRead, Write};
+
+        let lines = || -> anyhow::Result<_> {
+            let file = std::fs::File::open(src)
+                .context(format!("Failed to open '{src}'"))?;
+            let lines = std::io::BufReader::new(file).lines();
+            Ok(lines.skip(self.skip_lines).filter(|line| {
+                line.as_ref().is_ok_and(|l| !l.trim().is_empty())
+            }))
+        };
+        let offsets = match &self.column_offsets {
+            Some(offsets) => offsets.clone(),
+            None => {
+                // positions beyond the end of a line are blank in it
+                let mut blank = Vec::new();
+                for line in lines()? {
+                    for (i, c) in line?.chars().enumerate() {
+                        if i >= blank.len() {
+                            blank.resize(i + 1, true);
+                        }
+                        blank[i] &= c.is_whitespace();
+                    }
+                }
+                (0..blank.len())
+                    .filter(|&i| !blank[i] && (i == 0 || blank[i - 1]))
+                    .collect()
+            }
+        };
+        tracing::debug!(?offsets, "Reading fixed-width columns");
+
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(dst)?);
+        for line in lines()? {
+            let chars = line?.chars().collect::<Vec<_>>();
+            let cells = offsets
+                .iter()
+                .enumerate()
+                .map(|(i, &start)| {
+                    let end = offsets.get(i + 1).copied().unwrap_or(usize::MAX);
+                    let cell = chars
+                        [start.min(chars.len())..end.min(chars.len())]
+                        .iter()
+                        .collect::<String>();
+                    crate::plotter::to_rfc4180_csv_cell(cell.trim()).to_string()
+                })
+                .collect::<Vec<_>>();
+            writeln!(writer, "{}", cells.join(","))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn format(&self) -> &DataFormat {
+        &self.format
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Only ingest rows after skipping the first `skip` rows, then only the
+    /// first `head` rows and/or the last `tail` rows of the remaining ones
+    pub fn with_row_limits(
+        mut self,
+        skip: usize,
+        head: Option<usize>,
+        tail: Option<usize>,
+    ) -> Self {
+        self.skip = skip;
+        self.head = head;
+        self.tail = tail;
+        self
+    }
+
+    /// Skip the first `skip_lines` lines of the file (e.g. a preamble banner,
+    /// before the header row), and the last `skip_footer` rows (e.g.
+    /// summary lines), of each file, before the row limits apply. Lines can
+    /// only be skipped in text and spreadsheet input, where they are rows of
+    /// the sheet.
+    pub fn with_skipped_lines(
+        mut self,
+        skip_lines: usize,
+        skip_footer: usize,
+    ) -> anyhow::Result<Self> {
+        if skip_lines > 0 {
+            // spreadsheets are only read natively with the `xlsx` feature
+            let text = match &self.format {
+                _ if self.is_spreadsheet() => cfg!(feature = "xlsx"),
+                DataFormat::Auto => {
+                    let extension = std::path::Path::new(&self.input)
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .map(|ext| ext.to_ascii_lowercase());
+                    let text = match extension.as_deref() {
+                        None => true,
+                        Some(ext) => {
+                            ["csv", "tsv", "txt", "log"].contains(&ext)
+                                || COMPRESSED_EXTENSIONS.contains(&ext)
+                        }
+                    };
+                    if text {
+                        self.format = DataFormat::Explicit("csv".to_string());
+                    }
+                    text
+                }
+                DataFormat::Explicit(fmt) => {
+                    ["csv", "dat", "fwf"].contains(&fmt.as_str())
+                }
+            };
+            if !text {
+                bail!(
+                    "--skip-rows must be used with text or spreadsheet input"
+                );
+            }
+        }
+        self.skip_lines = skip_lines;
+        self.skip_footer = skip_footer;
+        Ok(self)
+    }
+
+    /// Same input, read from `path` holding the rows appended to it (after
+    /// the header row, if any), e.g. by `sp --follow`. None if rows are
+    /// skipped or limited, which only apply to the whole input.
+    pub fn with_appended_rows(&self, path: &str) -> Option<Self> {
+        let whole = self.skip > 0
+            || self.head.is_some()
+            || self.tail.is_some()
+            || self.skip_lines > 0
+            || self.skip_footer > 0
+            || !self.more_inputs.is_empty()
+            || self.source_column;
+        (!whole).then(|| Self {
+            input: path.to_string(),
+            ..self.clone()
+        })
+    }
+
+    /// Read plain CSV (or text) input natively into a `Table`, the way
+    /// `to_sql` has duckdb read it: the rows of all inputs (matched by
+    /// column name), after the skipped lines and footers, and within the
+    /// row limits. Other formats (or compressed input) are left to duckdb,
+    /// and are errors, so the input should have gone through
+    /// `read_natively` first, which also detects its delimiter and header.
+    pub fn read_table(&self) -> anyhow::Result<Table> {
+        let extension = std::path::Path::new(&self.input)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        let csv = match &self.format {
+            DataFormat::Explicit(fmt) => fmt == "csv",
+            DataFormat::Auto => matches!(
+                extension.as_deref(),
+                None | Some("csv" | "tsv" | "txt" | "log")
             ),
-            DataFormat::Explicit(ref fmt) => {
+        };
+        let compressed = extension
+            .as_deref()
+            .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext));
+        if !csv || compressed {
+            bail!(
+                "Input '{}' ({} format) is only read by duckdb, only plain CSV is read natively",
+                self.input,
+                self.format
+            );
+        }
+        let delimiter = match (self.delimiter, extension.as_deref()) {
+            (Some(delimiter), _) => delimiter,
+            (None, Some("tsv")) => '\t',
+            (None, _) => ',',
+        };
+        let mut table = Table::default();
+        let inputs = std::iter::once(&self.input).chain(&self.more_inputs);
+        for (i, path) in inputs.enumerate() {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read '{path}': {e}"))?;
+            let text = text
+                .split_inclusive('\n')
+                .skip(self.skip_lines)
+                .collect::<String>();
+            let mut file = Table::from_csv(
+                &text,
+                delimiter,
+                self.header.unwrap_or(true),
+                &self.dialect,
+            )?;
+            file.rows
+                .truncate(file.rows.len().saturating_sub(self.skip_footer));
+            if self.source_column {
+                let origin = self.origins.get(i).unwrap_or(path);
+                file.columns.push("filename".to_string());
+                for row in &mut file.rows {
+                    row.push(Value::Text(origin.clone()));
+                }
+            }
+            match i {
+                0 => table = file,
+                _ => table.union_by_name(file),
+            }
+        }
+        let rows = std::mem::take(&mut table.rows).into_iter().skip(self.skip);
+        table.rows = match self.head {
+            Some(head) => rows.take(head).collect(),
+            None => rows.collect(),
+        };
+        if let Some(tail) = self.tail {
+            table.rows.drain(..table.rows.len().saturating_sub(tail));
+        }
+        Ok(table)
+    }
+
+    /// Scan the input through a view instead of loading it into a table, so
+    /// that duckdb reads it in chunks as it is selected, and only the
+    /// columns referenced by the expressions. Inputs larger than the memory
+    /// (see `MemorySize`) are then only bounded by the selected (x, y)
+    /// table, at the cost of reading the input again for each query of it.
+    /// Ignored for stdin, which can only be read once.
+    pub fn with_streaming(mut self, streamed: bool) -> Self {
+        self.streamed = streamed && self.input != "/dev/stdin";
+        self
+    }
+
+    /// SQL dropping `table_name` once the input has been selected from it
+    pub fn drop_sql(&self, table_name: &str) -> String {
+        let kind = if self.streamed { "VIEW" } else { "TABLE" };
+        format!("DROP {kind} {table_name};\n")
+    }
+
+    // reader of auto input by its extension (before the compression one),
+    // which takes several files and the `filename` option
+    fn auto_reader(&self) -> &'static str {
+        let extension_of = |path: &std::path::Path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| ext.to_ascii_lowercase())
+        };
+        let path = std::path::Path::new(&self.input);
+        let extension = match extension_of(path) {
+            Some(ext) if COMPRESSED_EXTENSIONS.contains(&ext.as_str()) => path
+                .file_stem()
+                .and_then(|stem| extension_of(std::path::Path::new(stem))),
+            extension => extension,
+        };
+        match extension.as_deref() {
+            Some("parquet") => "parquet",
+            Some("json" | "jsonl" | "ndjson") => "json",
+            Some(ext) if SPREADSHEET_FORMATS.contains(&ext) => "xlsx",
+            _ => "csv",
+        }
+    }
+
+    pub fn to_sql(&self, table_name: &str) -> String {
+        let paths = if self.more_inputs.is_empty() {
+            sql_path(&self.input)
+        } else {
+            let paths = std::iter::once(&self.input)
+                .chain(&self.more_inputs)
+                .map(|path| sql_path(path))
+                .collect::<Vec<_>>();
+            format!("[{}]", paths.join(", "))
+        };
+        // the footer of each file is found by its `filename`
+        let filename = self.source_column
+            || (!self.more_inputs.is_empty() && self.skip_footer > 0);
+        let format = match &self.format {
+            DataFormat::Auto if filename || !self.more_inputs.is_empty() => {
+                Some(self.auto_reader())
+            }
+            DataFormat::Auto => None,
+            DataFormat::Explicit(fmt) => Some(fmt.as_str()),
+        };
+        let source = match format {
+            None => paths,
+            Some(fmt) => {
                 let header_opt = match self.header {
                     Some(true) => ", header=true",
                     Some(false) => ", header=false",
                     None => "",
                 };
 
+                // without the `xlsx` feature, spreadsheets are read by duckdb
+                let sheet_opt = match &self.sheet {
+                    Some(sheet) => {
+                        format!(", sheet='{}'", sheet.replace('\'', "''"))
+                    }
+                    None => "".to_string(),
+                };
+                let skip_opt = match self.skip_lines {
+                    0 => "".to_string(),
+                    lines => format!(", skip={lines}"),
+                };
+                let csv_opts = match self.delimiter {
+                    Some(delim) => format!(", delim={}", sql_char(delim)),
+                    None => "".to_string(),
+                } + &self.dialect.to_sql();
+                let filename_opt =
+                    if filename { ", filename=true" } else { "" };
+                // the files may have different columns, e.g. the labels of
+                // converted Prometheus samples
+                let union_opt = if !self.more_inputs.is_empty()
+                    && matches!(fmt, "csv" | "json" | "parquet")
+                {
+                    ", union_by_name=true"
+                } else {
+                    ""
+                };
+
                 format!(
-                    "CREATE TABLE {} AS SELECT * FROM read_{}('{}'{});\n",
-                    table_name, fmt, self.input, header_opt
+                    "read_{}({}{}{}{}{}{}{})",
+                    fmt,
+                    paths,
+                    header_opt,
+                    csv_opts,
+                    skip_opt,
+                    sheet_opt,
+                    filename_opt,
+                    union_opt
                 )
             }
+        };
+
+        tracing::debug!(%source, source_column = self.source_column, skip_lines = self.skip_lines, skip_footer = self.skip_footer, skip = self.skip, head = ?self.head, tail = ?self.tail, streamed = self.streamed, "Ingesting input");
+
+        // LIMIT/OFFSET are pushed down into the scan, so --head and --skip
+        // stop reading early; --tail has to count all rows
+        let mut query = format!("SELECT * FROM {source}");
+        // converted inputs are named after their original files
+        if self.source_column && !self.origins.is_empty() {
+            let cases = std::iter::once(&self.input)
+                .chain(&self.more_inputs)
+                .zip(&self.origins)
+                .map(|(path, origin)| {
+                    format!("WHEN {} THEN {}", sql_path(path), sql_path(origin))
+                })
+                .collect::<Vec<_>>();
+            query = format!(
+                "SELECT * REPLACE (CASE filename {} END AS filename) FROM {source}",
+                cases.join(" ")
+            );
+        }
+        if self.skip_footer > 0 {
+            let (partition, exclude) = match self.more_inputs.is_empty() {
+                true => ("", ""),
+                false if self.source_column => ("PARTITION BY filename", ""),
+                false => ("PARTITION BY filename", ", filename"),
+            };
+            query = format!(
+                "SELECT * EXCLUDE (sp_row, sp_rows{exclude}) FROM (SELECT *, row_number() OVER ({partition}) AS sp_row, count(*) OVER ({partition}) AS sp_rows FROM ({query})) WHERE sp_row <= sp_rows - {}",
+                self.skip_footer
+            );
+        }
+        if let Some(head) = self.head {
+            query.push_str(&format!(" LIMIT {head}"));
+        }
+        if self.skip > 0 {
+            query.push_str(&format!(" OFFSET {}", self.skip));
+        }
+        if let Some(tail) = self.tail {
+            query = format!(
+                "SELECT * EXCLUDE (sp_row, sp_rows) FROM (SELECT *, row_number() OVER () AS sp_row, count(*) OVER () AS sp_rows FROM ({query})) WHERE sp_row > sp_rows - {tail}"
+            );
+        }
+
+        // read_arrow is provided by a community extension, installed once
+        let extension = match &self.format {
+            DataFormat::Explicit(fmt) if fmt == "arrow" => {
+                "INSTALL arrow FROM community;\nLOAD arrow;\n"
+            }
+            _ => "",
+        };
+        let kind = if self.streamed { "VIEW" } else { "TABLE" };
+        format!("{extension}CREATE {kind} {table_name} AS {query};\n")
+    }
+}
+
+// cells of a row of a `dat` file, none for blank and comment lines
+#[cfg(feature = "gnuplot")]
+fn split_dat_row(line: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                cell.get_or_insert_with(String::new);
+            }
+            '#' if !quoted => break,
+            c if c.is_whitespace() && !quoted => cells.extend(cell.take()),
+            c => cell.get_or_insert_with(String::new).push(c),
+        }
+    }
+    cells.extend(cell);
+    cells
+}
+
+// trimmed cells of a CSV row, where double-quoted cells may contain the
+// delimiter and `""`
+#[cfg(feature = "gnuplot")]
+fn split_row(line: &str, delimiter: char) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cells.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
         }
     }
+    cells.iter().map(|cell| cell.trim().to_string()).collect()
 }
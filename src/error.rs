@@ -0,0 +1,247 @@
+// Error categories shared by sp and msp, each mapped to a distinct exit code
+
+use std::{backtrace::BacktraceStatus, fmt::Display, process::ExitStatus};
+
+use clap::{ArgMatches, ValueEnum};
+
+/// Category of a failure, attached to an `anyhow::Error` as its context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Invalid command line options or configuration
+    Usage,
+    /// The input data could not be read or parsed
+    InputParse,
+    /// Invalid expression or operator sequence
+    Expression,
+    /// An external tool (duckdb, sp, ...) is missing or failed
+    ExternalTool,
+    /// gnuplot failed to render the plot
+    Plot,
+    /// The run was cancelled before completion
+    Cancelled,
+}
+
+impl ErrorCategory {
+    pub const ALL: [Self; 6] = [
+        Self::Usage,
+        Self::InputParse,
+        Self::Expression,
+        Self::ExternalTool,
+        Self::Plot,
+        Self::Cancelled,
+    ];
+
+    /// Exit code of the process, 1 is left for uncategorized errors
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Usage => 2,
+            Self::InputParse => 3,
+            Self::Expression => 4,
+            Self::ExternalTool => 5,
+            Self::Plot => 6,
+            // conventional code of processes terminated by SIGINT
+            Self::Cancelled => 130,
+        }
+    }
+
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.exit_code() == code)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Usage => "usage",
+            Self::InputParse => "input-parse",
+            Self::Expression => "expression",
+            Self::ExternalTool => "external-tool",
+            Self::Plot => "plot",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// The outermost category attached to `e`, if any
+    pub fn of(e: &anyhow::Error) -> Option<Self> {
+        e.downcast_ref::<Self>().copied()
+    }
+}
+
+impl Display for ErrorCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Usage => write!(f, "usage error"),
+            Self::InputParse => write!(f, "input parse error"),
+            Self::Expression => write!(f, "expression error"),
+            Self::ExternalTool => write!(f, "external tool failure"),
+            Self::Plot => write!(f, "plot failure"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+pub trait WithCategory {
+    /// Attach `category` to the error unless it is already categorized
+    fn with_category(self, category: ErrorCategory) -> Self;
+}
+
+impl<T> WithCategory for anyhow::Result<T> {
+    fn with_category(self, category: ErrorCategory) -> Self {
+        self.map_err(|e| match ErrorCategory::of(&e) {
+            Some(_) => e,
+            None => e.context(category),
+        })
+    }
+}
+
+/// Format of the error message printed before exiting
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl ErrorFormat {
+    /// Look for `--error-format` in the raw arguments (or `SP_ERROR_FORMAT`),
+    /// since errors must be reported in the right format even if the
+    /// arguments fail to parse
+    pub fn from_args() -> Self {
+        raw_arg("--error-format", "SP_ERROR_FORMAT")
+            .and_then(|value| Self::from_str(&value, true).ok())
+            .unwrap_or_default()
+    }
+
+    /// Print `e` to stderr and return the exit code, the category only shows
+    /// in the exit code (and in the JSON report)
+    pub fn report(self, e: &anyhow::Error) -> i32 {
+        let category = ErrorCategory::of(e);
+        let exit_code = category.map(|c| c.exit_code()).unwrap_or(1);
+        let messages = Self::messages(e);
+        match self {
+            Self::Text => eprintln!("Error: {}", messages.join(": ")),
+            Self::Json => {
+                let report = serde_json::json!({
+                    "category": category.map(|c| c.name()),
+                    "exit_code": exit_code,
+                    "messages": messages,
+                });
+                eprintln!("{report}");
+            }
+        }
+        exit_code
+    }
+
+    // messages of the chain of `e` from the outermost one, but its category
+    fn messages(e: &anyhow::Error) -> Vec<String> {
+        let category = ErrorCategory::of(e);
+        e.chain()
+            .map(|e| e.to_string())
+            .filter(|m| category.is_none_or(|c| *m != c.to_string()))
+            .collect()
+    }
+}
+
+/// Report the error (with its backtrace if captured) and return the exit code
+pub fn handle_err(e: anyhow::Error) -> i32 {
+    let format = ErrorFormat::from_args();
+    let exit_code = format.report(&e);
+    if matches!(format, ErrorFormat::Json) {
+        return exit_code;
+    }
+    let bt = e.backtrace();
+    match bt.status() {
+        BacktraceStatus::Captured => {
+            eprintln!("Backtrace:\n{bt}");
+        }
+        BacktraceStatus::Unsupported => {
+            tracing::debug!("Backtrace is unsupported.");
+        }
+        BacktraceStatus::Disabled => {
+            tracing::debug!("Backtrace is disabled.");
+        }
+        _ => {
+            tracing::debug!("Unknown backtrace status: {:?}", bt.status());
+        }
+    }
+    exit_code
+}
+
+/// Categorize the result of running gnuplot
+pub fn check_gnuplot(
+    result: std::io::Result<ExitStatus>,
+) -> anyhow::Result<()> {
+    let status = result
+        .map_err(|e| anyhow::anyhow!("Failed to run gnuplot: {e}"))
+        .with_category(ErrorCategory::ExternalTool)?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("gnuplot failed with {status}"))
+            .with_category(ErrorCategory::Plot);
+    }
+    Ok(())
+}
+
+/// Value of option `long` in the raw arguments, or of the environment
+/// variable `env`, for options needed before the arguments are parsed
+pub(crate) fn raw_arg(long: &str, env: &str) -> Option<String> {
+    let args = std::env::args().collect::<Vec<_>>();
+    args.iter()
+        .enumerate()
+        .find_map(|(i, arg)| match arg.strip_prefix(long) {
+            Some("") => args.get(i + 1).map(String::as_str),
+            Some(value) => value.strip_prefix('='),
+            None => None,
+        })
+        .map(str::to_string)
+        .or_else(|| std::env::var(env).ok())
+}
+
+/// `Command::get_matches`, but usage errors are returned as categorized
+/// errors when they have to be reported as JSON
+pub fn get_matches(cmd: clap::Command) -> anyhow::Result<ArgMatches> {
+    if matches!(ErrorFormat::from_args(), ErrorFormat::Text) {
+        return Ok(cmd.get_matches());
+    }
+    match cmd.try_get_matches() {
+        Ok(matches) => Ok(matches),
+        Err(e) if e.use_stderr() => {
+            Err(anyhow::anyhow!("{}", e.to_string().trim_end()))
+                .with_category(ErrorCategory::Usage)
+        }
+        Err(e) => e.exit(),
+    }
+}
+
+#[test]
+fn test_error_categories() {
+    let codes = ErrorCategory::ALL.map(|c| (c.name(), c.exit_code()));
+    assert_eq!(
+        codes,
+        [
+            ("usage", 2),
+            ("input-parse", 3),
+            ("expression", 4),
+            ("external-tool", 5),
+            ("plot", 6),
+            ("cancelled", 130),
+        ]
+    );
+    for category in ErrorCategory::ALL {
+        assert_eq!(
+            ErrorCategory::from_exit_code(category.exit_code()),
+            Some(category)
+        );
+        let e = Err::<(), _>(anyhow::anyhow!("cause"))
+            .with_category(category)
+            .map_err(|e| e.context("Failed to run"))
+            .unwrap_err();
+        assert_eq!(ErrorCategory::of(&e), Some(category));
+        assert_eq!(ErrorFormat::messages(&e), ["Failed to run", "cause"]);
+    }
+    assert_eq!(ErrorCategory::from_exit_code(1), None);
+
+    // the innermost category is kept
+    let e = Err::<(), _>(anyhow::anyhow!("cause"))
+        .with_category(ErrorCategory::Plot)
+        .with_category(ErrorCategory::Usage)
+        .unwrap_err();
+    assert_eq!(ErrorCategory::of(&e), Some(ErrorCategory::Plot));
+}
@@ -0,0 +1,456 @@
+// Bounded-memory evaluation of operation sequences: points beyond a memory
+// budget are sorted in runs spilled to disk and merged back
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    fmt::Display,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, bail};
+
+use crate::{
+    GenericOperator, OpSeq, TempArtifacts,
+    opeseq::{Checkpoints, Operator, sort_points_by},
+    plotter::temp_filename,
+};
+
+/// Amount of memory, e.g. `--max-memory` of sp, parsed from a number of
+/// bytes with an optional binary unit (`K`, `M`, `G`, `T`, also written as
+/// `KB` or `KiB`), e.g. `512M` or `1.5GiB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemorySize(pub usize);
+
+impl FromStr for MemorySize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number = number
+            .parse::<f64>()
+            .map_err(|_| anyhow::anyhow!("Invalid memory size '{s}'"))?;
+        // bytes, or a binary prefix with an optional `iB` or `B`
+        let unit = unit.trim().to_ascii_lowercase();
+        let mut chars = unit.chars();
+        let exponent = match (chars.next(), chars.as_str()) {
+            (None | Some('b'), "") => 0,
+            (Some('k'), "" | "b" | "ib") => 1,
+            (Some('m'), "" | "b" | "ib") => 2,
+            (Some('g'), "" | "b" | "ib") => 3,
+            (Some('t'), "" | "b" | "ib") => 4,
+            _ => bail!("Invalid unit of memory size '{s}'"),
+        };
+        Ok(Self((number * 1024f64.powi(exponent)) as usize))
+    }
+}
+
+impl Display for MemorySize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match [
+            ("TiB", 1 << 40),
+            ("GiB", 1 << 30),
+            ("MiB", 1 << 20),
+            ("KiB", 1 << 10),
+        ]
+        .into_iter()
+        .find(|(_, size)| self.0 >= *size && self.0.is_multiple_of(*size))
+        {
+            Some((unit, size)) => write!(f, "{}{unit}", self.0 / size),
+            None => write!(f, "{}B", self.0),
+        }
+    }
+}
+
+impl MemorySize {
+    /// Settings of duckdb limiting its memory to this size, beyond which it
+    /// spills to `spill_dir` (created by duckdb only when needed)
+    pub fn to_duckdb_sql(&self, spill_dir: &Path) -> String {
+        format!(
+            "SET memory_limit = '{self}';\nSET temp_directory = '{}';\n",
+            spill_dir.display().to_string().replace('\'', "''")
+        )
+    }
+
+    // number of points fitting in this size, at least a few pages
+    fn points(&self) -> usize {
+        (self.0 / size_of::<(f64, f64)>()).max(1024)
+    }
+}
+
+type Points<'a> = Box<dyn Iterator<Item = anyhow::Result<(f64, f64)>> + 'a>;
+
+// a run of points sorted by key, written to a temporary file and removed
+// once read
+struct Run {
+    path: PathBuf,
+    reader: BufReader<File>,
+}
+
+impl Run {
+    fn write(points: &[(f64, f64)]) -> anyhow::Result<Self> {
+        let path = temp_filename("sp-", "sprun")?;
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for (x, y) in points {
+            writer.write_all(&x.to_le_bytes())?;
+            writer.write_all(&y.to_le_bytes())?;
+        }
+        writer.flush()?;
+        let reader = BufReader::new(File::open(&path)?);
+        Ok(Self { path, reader })
+    }
+
+    fn next(&mut self) -> anyhow::Result<Option<(f64, f64)>> {
+        let mut bytes = [0u8; 16];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let (x, y) = bytes.split_at(8);
+        Ok(Some((
+            f64::from_le_bytes(x.try_into().unwrap()),
+            f64::from_le_bytes(y.try_into().unwrap()),
+        )))
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        TempArtifacts::untrack(&self.path);
+    }
+}
+
+// head of a run in the merge, the earlier runs come first among equal keys
+// so that the sort is stable
+struct Head {
+    key: f64,
+    run: usize,
+    point: (f64, f64),
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, as BinaryHeap pops the greatest
+        other
+            .key
+            .total_cmp(&self.key)
+            .then(other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+/// Stable sort of `points` by `key` keeping at most `capacity` points in
+/// memory, returns the number of points and the sorted points
+fn external_sort<'a>(
+    points: Points<'a>,
+    key: fn(&(f64, f64)) -> f64,
+    capacity: usize,
+    op: &GenericOperator,
+) -> anyhow::Result<(usize, Points<'a>)> {
+    let mut buffer = Vec::new();
+    let mut runs = Vec::new();
+    let mut len = 0;
+    for point in points {
+        buffer.push(point?);
+        len += 1;
+        if buffer.len() >= capacity {
+            sort_points_by(&mut buffer, key);
+            runs.push(Run::write(&buffer).context("Failed to spill points")?);
+            buffer.clear();
+        }
+    }
+    sort_points_by(&mut buffer, key);
+    if runs.is_empty() {
+        return Ok((len, Box::new(buffer.into_iter().map(Ok))));
+    }
+    tracing::warn!(
+        "Operator {op} spilled {len} points to disk in {} sorted runs as they exceed the memory budget",
+        runs.len()
+    );
+
+    let mut heap = BinaryHeap::new();
+    for (run, points) in runs.iter_mut().enumerate() {
+        if let Some(point) = points.next()? {
+            heap.push(Head {
+                key: key(&point),
+                run,
+                point,
+            });
+        }
+    }
+    // the points left in memory are the last run
+    let mut buffer = buffer.into_iter();
+    let buffer_run = runs.len();
+    if let Some(point) = buffer.next() {
+        heap.push(Head {
+            key: key(&point),
+            run: buffer_run,
+            point,
+        });
+    }
+    let merged = std::iter::from_fn(move || {
+        let head = heap.pop()?;
+        let next = if head.run == buffer_run {
+            Ok(buffer.next())
+        } else {
+            runs[head.run].next()
+        };
+        match next {
+            Ok(Some(point)) => heap.push(Head {
+                key: key(&point),
+                run: head.run,
+                point,
+            }),
+            Ok(None) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        Some(Ok(head.point))
+    });
+    Ok((len, Box::new(merged)))
+}
+
+// consecutive points of sorted `points` with the same key
+fn groups<'a>(
+    points: Points<'a>,
+    same: fn(&(f64, f64), &(f64, f64)) -> bool,
+) -> impl Iterator<Item = anyhow::Result<Vec<(f64, f64)>>> + 'a {
+    let mut points = points.peekable();
+    std::iter::from_fn(move || {
+        let mut group = vec![match points.next()? {
+            Ok(point) => point,
+            Err(e) => return Some(Err(e)),
+        }];
+        while let Some(Ok(point)) = points.peek() {
+            if !same(&group[0], point) {
+                break;
+            }
+            group.push(*point);
+            points.next();
+        }
+        Some(Ok(group))
+    })
+}
+
+fn eval_operator<'a>(
+    op: &'a GenericOperator,
+    points: Points<'a>,
+    capacity: usize,
+) -> anyhow::Result<Points<'a>> {
+    let by_x = |p: &(f64, f64)| p.0;
+    let same_x = |a: &(f64, f64), b: &(f64, f64)| a.0 == b.0;
+    Ok(match op {
+        _ if op.is_streaming() => {
+            let mut points = points.peekable();
+            let mut last = None;
+            Box::new(
+                std::iter::from_fn(move || {
+                    points.peek()?;
+                    let chunk = points
+                        .by_ref()
+                        .take(OpSeq::STREAM_CHUNK_POINTS)
+                        .collect::<anyhow::Result<Vec<_>>>()
+                        .and_then(|chunk| {
+                            let next_last = chunk.last().copied();
+                            let chunk = op.eval_chunk(chunk, last)?;
+                            last = next_last;
+                            Ok(chunk)
+                        });
+                    Some(chunk)
+                })
+                .flat_map(|chunk| match chunk {
+                    Ok(chunk) => chunk.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                }),
+            )
+        }
+        GenericOperator::Order(order) => {
+            external_sort(points, order.key(), capacity, op)?.1
+        }
+        GenericOperator::Merge(_) => {
+            let (_, sorted) = external_sort(points, by_x, capacity, op)?;
+            Box::new(groups(sorted, same_x).map(|group| {
+                let group = group?;
+                Ok((group[0].0, group.iter().map(|(_, y)| y).sum()))
+            }))
+        }
+        GenericOperator::Integral(_) => {
+            let (_, sorted) = external_sort(points, by_x, capacity, op)?;
+            let mut sum = 0.0;
+            // the peers (equal x values) share the sum of the last one
+            Box::new(
+                groups(sorted, same_x)
+                    .map(move |group| {
+                        let group = group?;
+                        sum += group.iter().map(|(_, y)| y).sum::<f64>();
+                        Ok(group.into_iter().map(|(x, _)| (x, sum)).collect())
+                    })
+                    .flat_map(flatten_group),
+            )
+        }
+        GenericOperator::Cdf(_) => {
+            let (len, sorted) = external_sort(points, |p| p.1, capacity, op)?;
+            let mut rank = 0;
+            // the peers (equal y values) share the fraction of the last one
+            Box::new(
+                groups(sorted, |a, b| a.1.total_cmp(&b.1).is_eq())
+                    .map(move |group| {
+                        let group = group?;
+                        rank += group.len();
+                        let fraction = rank as f64 / len as f64;
+                        Ok(group
+                            .into_iter()
+                            .map(|(_, y)| (y, fraction))
+                            .collect())
+                    })
+                    .flat_map(flatten_group),
+            )
+        }
+        GenericOperator::TopK(top_k) => {
+            // the top k of the kept points and the following ones are the
+            // top k of all of them, as the order of the points is preserved
+            if top_k.k > capacity {
+                tracing::warn!(
+                    "Operator {op} holds {} points in memory despite the memory budget",
+                    top_k.k
+                );
+            }
+            let mut kept = Vec::new();
+            for point in points {
+                kept.push(point?);
+                if kept.len() >= top_k.k + OpSeq::STREAM_CHUNK_POINTS {
+                    kept = top_k.eval(kept)?;
+                }
+            }
+            Box::new(top_k.eval(kept)?.into_iter().map(Ok))
+        }
+        GenericOperator::Quantile(quantile) => {
+            // missing values are no values, as in quantile_cont
+            let points = Box::new(
+                points.filter(|p| !p.as_ref().is_ok_and(|p| p.1.is_nan())),
+            );
+            let (len, sorted) = external_sort(points, |p| p.1, capacity, op)?;
+            let ranks = quantile.ranks(len);
+            let mut values = HashMap::new();
+            for (rank, point) in sorted.enumerate() {
+                let point = point?;
+                if ranks.contains(&rank) {
+                    values.insert(rank, point.1);
+                }
+            }
+            Box::new(
+                quantile
+                    .eval_sorted(len, |rank| values[&rank])
+                    .into_iter()
+                    .map(Ok),
+            )
+        }
+        _ => {
+            let points = points.collect::<anyhow::Result<Vec<_>>>()?;
+            if points.len() > capacity {
+                tracing::warn!(
+                    "Operator {op} holds all the {} points in memory despite the memory budget",
+                    points.len()
+                );
+            }
+            Box::new(op.eval(points)?.into_iter().map(Ok))
+        }
+    })
+}
+
+fn flatten_group(
+    group: anyhow::Result<Vec<(f64, f64)>>,
+) -> Vec<anyhow::Result<(f64, f64)>> {
+    match group {
+        Ok(group) => group.into_iter().map(Ok).collect(),
+        Err(e) => vec![Err(e)],
+    }
+}
+
+impl OpSeq {
+    /// Same as `eval_chunks`, but the blocking operators keep about
+    /// `max_memory` of points in memory: `o`, `m`, `i`, `c` and `q` sort the
+    /// rest in runs spilled to temporary files, which are merged back, and
+    /// `k` keeps only the top k points, while `a`, `b`, `d`, `D`, `F`, `g1`,
+    /// `M`, `n`, `r`, `R`, `t` and `u` still collect all the points (both
+    /// cases are reported as warnings). `S` and `L` collect all the points
+    /// as well, the former keeping a copy of them until the end
+    pub fn eval_chunks_bounded(
+        &self,
+        chunks: impl IntoIterator<Item = anyhow::Result<Vec<(f64, f64)>>>,
+        max_memory: MemorySize,
+        mut sink: impl FnMut(Vec<(f64, f64)>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let capacity = max_memory.points();
+        let points: Points =
+            Box::new(chunks.into_iter().flat_map(flatten_group));
+        let mut checkpoints = Checkpoints::new();
+        let mut points = self
+            .ops
+            .iter()
+            .try_fold(points, |points, op| match op {
+                GenericOperator::Checkpoint(_)
+                | GenericOperator::Restore(_) => {
+                    let points = points.collect::<anyhow::Result<Vec<_>>>()?;
+                    let points = op.eval_in_seq(points, &mut checkpoints)?;
+                    Ok(Box::new(points.into_iter().map(Ok)) as Points)
+                }
+                _ => eval_operator(op, points, capacity),
+            })?
+            .peekable();
+        while points.peek().is_some() {
+            sink(
+                points
+                    .by_ref()
+                    .take(Self::STREAM_CHUNK_POINTS)
+                    .collect::<anyhow::Result<_>>()?,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_memory_size() {
+    let size = |s: &str| s.parse::<MemorySize>().map(|size| size.0);
+    assert_eq!(size("512").unwrap(), 512);
+    assert_eq!(size("512B").unwrap(), 512);
+    assert_eq!(size("4k").unwrap(), 4 << 10);
+    assert_eq!(size("1.5 MiB").unwrap(), 3 << 19);
+    assert_eq!(size("2GB").unwrap(), 2 << 30);
+    assert_eq!(size("1TiB").unwrap(), 1 << 40);
+    for invalid in
+        ["", "5ib", "5iB", "5bb", "5kk", "5kibb", "5x", "G", "1.2.3M"]
+    {
+        assert!(size(invalid).is_err(), "{invalid}");
+    }
+    let size = MemorySize(3 << 29);
+    assert_eq!(size.to_string().parse::<MemorySize>().unwrap().0, size.0);
+    assert_eq!(
+        size.to_duckdb_sql(Path::new("/tmp/it's")),
+        "SET memory_limit = '1536MiB';\nSET temp_directory = '/tmp/it''s';\n"
+    );
+}
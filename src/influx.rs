@@ -0,0 +1,279 @@
+// InfluxDB line protocol (e.g. archived telemetry) read natively and
+// converted to CSV, one row per point with its tags and fields as columns
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, anyhow, bail};
+
+use crate::plotter::to_rfc4180_csv_cell;
+
+struct Point {
+    measurement: String,
+    tags: Vec<(String, String)>,
+    fields: Vec<(String, String)>,
+    // nanoseconds since the Unix epoch
+    timestamp: Option<i64>,
+}
+
+// parts of `s` between the occurrences of `sep` that are neither escaped
+// by a backslash nor, if `quotes`, within double quotes, escapes being kept
+fn split_unescaped(s: &str, sep: char, quotes: bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    let mut quoted = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' if quotes => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// `key=value` split at the first unescaped `=`
+fn split_pair(pair: &str) -> anyhow::Result<(String, &str)> {
+    match split_unescaped(pair, '=', false).as_slice() {
+        [key, ..] if key.len() < pair.len() && !key.is_empty() => {
+            Ok((unescape(key), &pair[key.len() + 1..]))
+        }
+        _ => bail!("Invalid key-value pair '{pair}'"),
+    }
+}
+
+// field value as written to CSV: strings unquoted, integers without their
+// `i` or `u` suffix, and booleans as `true` or `false`
+fn field_value(value: &str) -> anyhow::Result<String> {
+    if let Some(s) = value.strip_prefix('"') {
+        let s = s
+            .strip_suffix('"')
+            .ok_or_else(|| anyhow!("Unterminated string '{value}'"))?;
+        return Ok(unescape(s));
+    }
+    Ok(match value {
+        "t" | "T" | "true" | "True" | "TRUE" => "true".to_string(),
+        "f" | "F" | "false" | "False" | "FALSE" => "false".to_string(),
+        v if v.ends_with(['i', 'u'])
+            && v[..v.len() - 1].parse::<i128>().is_ok() =>
+        {
+            v[..v.len() - 1].to_string()
+        }
+        v if v.parse::<f64>().is_ok() => v.to_string(),
+        v => bail!("Invalid field value '{v}'"),
+    })
+}
+
+// point of a line, none for blank lines and comments
+fn parse_point(line: &str) -> anyhow::Result<Option<Point>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    // the measurement and the tags are unquoted
+    let (key, rest) = match split_unescaped(line, ' ', false).as_slice() {
+        [key, ..] if key.len() < line.len() => (*key, &line[key.len() + 1..]),
+        _ => bail!("Missing fields"),
+    };
+    let mut key = split_unescaped(key, ',', false).into_iter();
+    let measurement = unescape(key.next().unwrap_or_default());
+    if measurement.is_empty() {
+        bail!("Missing measurement");
+    }
+    let tags = key
+        .map(|tag| {
+            let (name, value) = split_pair(tag)?;
+            Ok((name, unescape(value)))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let rest = split_unescaped(rest.trim(), ' ', true);
+    let (fields, timestamp) = match rest.as_slice() {
+        [fields] => (*fields, None),
+        [fields, timestamp] => (*fields, Some(timestamp.trim())),
+        _ => bail!("Unexpected text after the timestamp"),
+    };
+    let fields = split_unescaped(fields, ',', true)
+        .into_iter()
+        .map(|field| {
+            let (name, value) = split_pair(field)?;
+            Ok((name, field_value(value)?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if fields.is_empty() {
+        bail!("Missing fields");
+    }
+    let timestamp = timestamp
+        .map(|ts| {
+            ts.parse::<i64>()
+                .map_err(|_| anyhow!("Invalid timestamp '{ts}'"))
+        })
+        .transpose()?;
+    Ok(Some(Point {
+        measurement,
+        tags,
+        fields,
+        timestamp,
+    }))
+}
+
+/// Write the points of the line protocol at `path` to `dst` as CSV, with
+/// the columns `measurement`, one per tag key, one per field key (both in
+/// alphabetical order, empty where a point lacks them) and `timestamp` (in
+/// seconds since the Unix epoch, from nanoseconds, empty if the point has
+/// none). Keys named `measurement` or `timestamp`, or both a tag and a field
+/// key, are prefixed with `tag_` or `field_`.
+pub(crate) fn points_to_csv(
+    path: &str,
+    dst: &std::path::Path,
+) -> anyhow::Result<()> {
+    let file = std::fs::File::open(path)
+        .context(format!("Failed to open '{path}'"))?;
+    let mut points = Vec::new();
+    for (i, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let point = parse_point(&line?)
+            .context(format!("Invalid point at line {} of '{path}'", i + 1))?;
+        points.extend(point);
+    }
+    if points.is_empty() {
+        bail!("No point in '{path}'");
+    }
+    let names = |pairs: fn(&Point) -> &Vec<(String, String)>| {
+        points
+            .iter()
+            .flat_map(|p| pairs(p).iter().map(|(name, _)| name.clone()))
+            .collect::<BTreeSet<_>>()
+    };
+    let tag_names = names(|p| &p.tags);
+    let field_names = names(|p| &p.fields);
+    tracing::debug!(
+        path,
+        points = points.len(),
+        ?tag_names,
+        ?field_names,
+        "Reading line protocol"
+    );
+    let column = |name: &String, prefix: &str, others: &BTreeSet<String>| {
+        if others.contains(name)
+            || matches!(name.as_str(), "measurement" | "timestamp")
+        {
+            format!("{prefix}{name}")
+        } else {
+            name.clone()
+        }
+    };
+    let columns = std::iter::once("measurement".to_string())
+        .chain(
+            tag_names
+                .iter()
+                .map(|name| column(name, "tag_", &field_names)),
+        )
+        .chain(
+            field_names
+                .iter()
+                .map(|name| column(name, "field_", &tag_names)),
+        )
+        .chain(["timestamp".to_string()])
+        .collect::<Vec<_>>();
+    if let Some(duplicate) = columns
+        .iter()
+        .enumerate()
+        .find(|&(i, name)| columns[..i].contains(name))
+        .map(|(_, name)| name)
+    {
+        bail!("Column '{duplicate}' of '{path}' comes from several keys");
+    }
+
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(dst)?);
+    let header = columns
+        .iter()
+        .map(|name| to_rfc4180_csv_cell(name).to_string())
+        .collect::<Vec<_>>();
+    writeln!(writer, "{}", header.join(","))?;
+    for point in &points {
+        let cell = |pairs: &Vec<(String, String)>, name: &String| {
+            pairs
+                .iter()
+                .find(|(key, _)| key == name)
+                .map_or("".to_string(), |(_, value)| {
+                    to_rfc4180_csv_cell(value).to_string()
+                })
+        };
+        let cells = std::iter::once(
+            to_rfc4180_csv_cell(&point.measurement).to_string(),
+        )
+        .chain(tag_names.iter().map(|name| cell(&point.tags, name)))
+        .chain(field_names.iter().map(|name| cell(&point.fields, name)))
+        .chain(std::iter::once(match point.timestamp {
+            Some(ns) => (ns as f64 / 1e9).to_string(),
+            None => "".to_string(),
+        }))
+        .collect::<Vec<_>>();
+        writeln!(writer, "{}", cells.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test_points_to_csv() {
+    let dir = std::env::temp_dir();
+    let src = dir.join(format!("sp-test-{}.lp", std::process::id()));
+    let dst = dir.join(format!("sp-test-{}.csv", std::process::id()));
+    let convert = |lines: &str| {
+        std::fs::write(&src, lines).unwrap();
+        let result = points_to_csv(src.to_str().unwrap(), &dst)
+            .map(|()| std::fs::read_to_string(&dst).unwrap());
+        let _ = std::fs::remove_file(&src);
+        let _ = std::fs::remove_file(&dst);
+        result
+    };
+
+    // escaped spaces and commas, quoted strings, integers and booleans,
+    // points without timestamps
+    let csv = convert(
+        "# comment\n\
+         cpu\\ load,host=a\\,b,region=eu\\ west usage=0.5,n=3i,ok=t 1700000000000000000\n\
+         cpu\\ load,host=c msg=\"hello, \\\"world\\\"\",n=-2u\n",
+    )
+    .unwrap();
+    assert_eq!(
+        csv,
+        "measurement,host,region,msg,n,ok,usage,timestamp\n\
+         \"cpu load\",\"a,b\",\"eu west\",,3,true,0.5,1700000000\n\
+         \"cpu load\",c,,\"hello, \"\"world\"\"\",-2,,,\n"
+    );
+
+    // keys of several columns are prefixed
+    let csv = convert("m,host=a,timestamp=x host=1,measurement=2 5\n").unwrap();
+    assert_eq!(
+        csv,
+        "measurement,tag_host,tag_timestamp,field_host,field_measurement,timestamp\n\
+         m,a,x,1,2,0.000000005\n"
+    );
+    assert!(convert("m,tag_x=a,x=b x=1\n").is_err());
+
+    assert!(convert("").is_err());
+    assert!(convert("# only comments\n\n").is_err());
+    assert!(convert("m x=1i2\n").is_err());
+    assert!(convert("m x=\"unterminated\n").is_err());
+}
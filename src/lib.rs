@@ -1,25 +1,158 @@
+//! Library core of `sp` and `msp`.
+//!
+//! The following items are the stable API, whose breaking changes only come
+//! with a new minor version (while the major version is 0):
+//!
+//! - data: `DataPoints` (an in-memory datasheet) with `PointStream` (one
+//!   read row by row), `DataSeriesSource`,
+//!   `DataInput`, `DataFormat`, `CsvDialect`, `TimeFormat` and
+//!   `MissingValues`, and `Table` of `Value` (input read without duckdb)
+//! - transforms: `Expr`, `PlainSelector`, `OpSeq` (parsed or built by
+//!   `OpSeqBuilder`, with `OpSeq::eval` and `OpSeq::apply` on `DataPoints`,
+//!   and their streaming variants `OpSeq::eval_chunks` and `OpSeq::apply_csv`,
+//!   also bounded by a `MemorySize`),
+//!   `GenericOperator`, `ExternalOperator`, and `CustomOperator` with
+//!   `RegisteredOperator`, and `Column` (the name and unit of an axis)
+//! - plotting: `GnuplotTemplate`, `AxisOptions`, `DataSeriesOptions` and the
+//!   types of their options, `Plotter` and `TempArtifacts`, and
+//!   `NativePlot` of `NativeSeries` (drawn without gnuplot)
+//! - pipeline: `PlotterBuilder`, `PipelineConfig` (its serializable
+//!   definition) with the types of its sections, `Artifacts`,
+//!   `PipelineEvent`, `ErrorCategory` and `WithCategory`
+//!
+//! Hidden items only serve the binaries and may change at any time.
+
+#[cfg(feature = "cli")]
+mod config;
 #[cfg(feature = "preprocess")]
 mod datainput;
+#[cfg(feature = "cli")]
+mod error;
+#[cfg(feature = "cli")]
+mod extsort;
+#[cfg(all(feature = "gnuplot", feature = "preprocess"))]
+mod influx;
+#[cfg(feature = "cli")]
+mod logging;
+#[cfg(any(feature = "gnuplot", feature = "preprocess"))]
+mod missing;
+#[cfg(any(feature = "gnuplot", feature = "preprocess"))]
+mod nativeplot;
 #[cfg(feature = "preprocess")]
 mod opeseq;
+#[cfg(feature = "cli")]
+mod pipeconfig;
+#[cfg(feature = "cli")]
+mod pipeline;
 #[cfg(feature = "preprocess")]
 mod plainselect;
+#[cfg(all(feature = "gnuplot", feature = "preprocess"))]
+mod prometheus;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "preprocess")]
+mod spectrum;
+#[cfg(feature = "xlsx")]
+mod spreadsheet;
+#[cfg(feature = "preprocess")]
+mod sqlexpr;
+#[cfg(feature = "preprocess")]
+mod table;
+#[cfg(feature = "wasm")]
+mod wasm;
 
 #[cfg(feature = "gnuplot")]
 mod plotscript;
 #[cfg(feature = "gnuplot")]
 mod plotter;
+#[cfg(feature = "gnuplot")]
+mod temp;
+#[cfg(any(feature = "gnuplot", feature = "preprocess"))]
+mod timestamp;
 
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use config::UserConfig;
+#[cfg(feature = "preprocess")]
+pub use datainput::CsvDialect;
 #[cfg(feature = "preprocess")]
 pub use datainput::DataFormat;
 #[cfg(feature = "preprocess")]
 pub use datainput::DataInput;
+#[cfg(feature = "cli")]
+pub use error::ErrorCategory;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use error::ErrorFormat;
+#[cfg(feature = "cli")]
+pub use error::WithCategory;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use error::check_gnuplot;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use error::get_matches;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use error::handle_err;
+#[cfg(feature = "cli")]
+pub use extsort::MemorySize;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use logging::LogFormat;
+#[cfg(any(feature = "gnuplot", feature = "preprocess"))]
+pub use missing::MissingValues;
+#[cfg(any(feature = "gnuplot", feature = "preprocess"))]
+pub use nativeplot::NativePlot;
+#[cfg(any(feature = "gnuplot", feature = "preprocess"))]
+pub use nativeplot::NativeSeries;
+#[cfg(any(feature = "gnuplot", feature = "preprocess"))]
+pub use nativeplot::SeriesStyle;
+#[cfg(feature = "preprocess")]
+pub use opeseq::Column;
+#[cfg(feature = "preprocess")]
+pub use opeseq::CustomOperator;
+#[cfg(feature = "preprocess")]
+pub use opeseq::ExplainedStep;
+#[cfg(feature = "preprocess")]
+pub use opeseq::ExternalOperator;
+#[cfg(feature = "preprocess")]
+pub use opeseq::GenericOperator;
 #[cfg(feature = "preprocess")]
 pub use opeseq::OpSeq;
 #[cfg(feature = "preprocess")]
+pub use opeseq::OpSeqBuilder;
+#[cfg(feature = "preprocess")]
+pub use opeseq::RegisteredOperator;
+#[cfg(feature = "cli")]
+pub use pipeconfig::InputConfig;
+#[cfg(feature = "cli")]
+pub use pipeconfig::OperatorConfig;
+#[cfg(feature = "cli")]
+pub use pipeconfig::OutputConfig;
+#[cfg(feature = "cli")]
+pub use pipeconfig::PipelineConfig;
+#[cfg(feature = "cli")]
+pub use pipeconfig::PlotConfig;
+#[cfg(feature = "cli")]
+pub use pipeline::Artifacts;
+#[cfg(feature = "cli")]
+pub use pipeline::CancellationToken;
+#[cfg(feature = "cli")]
+pub use pipeline::PipelineEvent;
+#[cfg(feature = "cli")]
+pub use pipeline::PlotterBuilder;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use pipeline::run_duckdb;
+#[cfg(feature = "preprocess")]
 pub use plainselect::Expr;
 #[cfg(feature = "preprocess")]
 pub use plainselect::PlainSelector;
+#[cfg(feature = "preprocess")]
+pub use table::Table;
+#[cfg(feature = "preprocess")]
+pub use table::Value;
 
 #[cfg(feature = "gnuplot")]
 pub use plotscript::AxisOptions;
@@ -30,12 +163,16 @@ pub use plotscript::DataSeriesOptions;
 #[cfg(feature = "gnuplot")]
 pub use plotscript::GnuplotTemplate;
 #[cfg(feature = "gnuplot")]
+pub use plotscript::Length;
+#[cfg(feature = "gnuplot")]
 pub use plotscript::LineStyle;
 #[cfg(feature = "gnuplot")]
 pub use plotscript::PlotType;
 #[cfg(feature = "gnuplot")]
 pub use plotscript::PointStyle;
 #[cfg(feature = "gnuplot")]
+pub use plotscript::Preset;
+#[cfg(feature = "gnuplot")]
 pub use plotscript::StandardTics;
 #[cfg(feature = "gnuplot")]
 pub use plotscript::Terminal;
@@ -45,3 +182,18 @@ pub use plotter::DataPoints;
 pub use plotter::DataSeriesSource;
 #[cfg(feature = "gnuplot")]
 pub use plotter::Plotter;
+#[cfg(feature = "gnuplot")]
+pub use plotter::PointStream;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use plotter::csv_to_json;
+#[cfg(feature = "gnuplot")]
+pub use temp::TempArtifacts;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use temp::finish_temp_artifacts;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use temp::finish_temp_artifacts_on_signal;
+#[cfg(any(feature = "gnuplot", feature = "preprocess"))]
+pub use timestamp::TimeFormat;
@@ -0,0 +1,48 @@
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
+
+use crate::error::raw_arg;
+
+/// Format of the log records printed to stderr
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl LogFormat {
+    /// Look for `--log-format` in the raw arguments (or `SP_LOG_FORMAT`),
+    /// since logging starts before the arguments are parsed
+    pub fn from_args() -> Self {
+        raw_arg("--log-format", "SP_LOG_FORMAT")
+            .and_then(|value| Self::from_str(&value, true).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+
+    /// Install the global subscriber, records are filtered by `RUST_LOG`
+    /// (only errors by default), and spans are logged with their duration
+    /// when they close
+    pub fn init(self) {
+        let filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::ERROR.into())
+            .from_env_lossy();
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(std::io::stderr);
+        match self {
+            Self::Text => builder.init(),
+            Self::Json => builder.json().with_current_span(true).init(),
+        }
+    }
+}
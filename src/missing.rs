@@ -0,0 +1,262 @@
+// Missing values, i.e. empty cells of the input (NULL in duckdb) and of
+// datasheets (NaN in `DataPoints`)
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::bail;
+
+#[cfg(feature = "preprocess")]
+use crate::Value;
+
+/// What becomes of the records with missing values before the operators
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingValues {
+    /// Records missing x or y are removed
+    Drop,
+    /// Missing values are kept, and written as empty cells of the datasheet
+    #[default]
+    Propagate,
+    /// Missing y values are interpolated linearly in x between the nearest
+    /// records with y, the records before the first or after the last such
+    /// record and those missing x being removed
+    Interpolate,
+    /// Missing y values are replaced with 0, and records missing x are
+    /// removed
+    Zero,
+}
+
+impl Display for MissingValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Drop => write!(f, "drop"),
+            Self::Propagate => write!(f, "propagate"),
+            Self::Interpolate => write!(f, "interpolate"),
+            Self::Zero => write!(f, "zero"),
+        }
+    }
+}
+
+impl FromStr for MissingValues {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "drop" => Self::Drop,
+            "propagate" => Self::Propagate,
+            "interpolate" => Self::Interpolate,
+            "zero" => Self::Zero,
+            _ => bail!(
+                "Invalid missing value policy '{s}' (expected drop, propagate, interpolate or zero)"
+            ),
+        })
+    }
+}
+
+impl MissingValues {
+    /// Apply the policy to points whose missing values are NaN
+    pub fn apply(self, mut points: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+        match self {
+            Self::Propagate => {}
+            Self::Drop => points.retain(|(x, y)| !x.is_nan() && !y.is_nan()),
+            Self::Zero => {
+                points.retain(|(x, _)| !x.is_nan());
+                for (_, y) in &mut points {
+                    if y.is_nan() {
+                        *y = 0.0;
+                    }
+                }
+            }
+            Self::Interpolate => {
+                points.retain(|(x, _)| !x.is_nan());
+                interpolate(&mut points);
+                points.retain(|(_, y)| !y.is_nan());
+            }
+        }
+        points
+    }
+
+    /// Same as `apply`, but to the rows `(x, y_columns...)` of a `Table`,
+    /// records being removed if any of their y values stays missing, as by
+    /// `to_sql`
+    #[cfg(feature = "preprocess")]
+    pub(crate) fn apply_rows(
+        self,
+        mut rows: Vec<Vec<Value>>,
+    ) -> anyhow::Result<Vec<Vec<Value>>> {
+        if self == Self::Propagate {
+            return Ok(rows);
+        }
+        rows.retain(|row| !row[0].is_null());
+        match self {
+            Self::Zero => {
+                for value in rows.iter_mut().flat_map(|row| &mut row[1..]) {
+                    if value.is_null() {
+                        *value = Value::Number(0.0);
+                    }
+                }
+            }
+            Self::Interpolate => {
+                let xs = rows
+                    .iter()
+                    .map(|row| row[0].to_f64())
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                for column in 1..rows.first().map_or(0, Vec::len) {
+                    let mut points = rows
+                        .iter()
+                        .zip(&xs)
+                        .map(|(row, &x)| Ok((x, row[column].to_f64()?)))
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    interpolate(&mut points);
+                    for (row, (_, y)) in rows.iter_mut().zip(points) {
+                        row[column] = Value::from_f64(y);
+                    }
+                }
+            }
+            _ => {}
+        }
+        rows.retain(|row| !row[1..].iter().any(Value::is_null));
+        Ok(rows)
+    }
+
+    /// SQL of duckdb applying the policy to table `(x, y_columns...)` in
+    /// place, records being removed if any of their y values stays missing
+    #[cfg(feature = "preprocess")]
+    pub(crate) fn to_sql(self, table: &str, y_columns: &[String]) -> String {
+        let delete = |columns: &[String]| {
+            format!(
+                "DELETE FROM {table} WHERE {};\n",
+                std::iter::once("x")
+                    .chain(columns.iter().map(String::as_str))
+                    .map(|column| format!("{column} IS NULL"))
+                    .collect::<Vec<_>>()
+                    .join(" OR ")
+            )
+        };
+        match self {
+            Self::Propagate => "".to_string(),
+            Self::Drop => delete(y_columns),
+            Self::Zero => format!(
+                "{}UPDATE {table} SET {};\n",
+                delete(&[]),
+                y_columns
+                    .iter()
+                    .map(|y| format!("{y} = coalesce({y}, 0)"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Interpolate => {
+                // nearest (x, y) with y before and after each record
+                let interpolated = |y: &String| {
+                    let before = "ORDER BY rowid ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING";
+                    let after = "ORDER BY rowid ROWS BETWEEN 1 FOLLOWING AND UNBOUNDED FOLLOWING";
+                    let x_of = format!("CASE WHEN {y} IS NOT NULL THEN x END");
+                    format!(
+                        "coalesce({y}, last_value({y} IGNORE NULLS) OVER ({before}) + (first_value({y} IGNORE NULLS) OVER ({after}) - last_value({y} IGNORE NULLS) OVER ({before})) * (x - last_value({x_of} IGNORE NULLS) OVER ({before})) / (first_value({x_of} IGNORE NULLS) OVER ({after}) - last_value({x_of} IGNORE NULLS) OVER ({before}))) AS {y}"
+                    )
+                };
+                format!(
+                    "{}CREATE OR REPLACE TABLE {table} AS SELECT x, {} FROM {table} ORDER BY rowid;\n{}",
+                    delete(&[]),
+                    y_columns
+                        .iter()
+                        .map(interpolated)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    delete(y_columns)
+                )
+            }
+        }
+    }
+}
+
+// y values of `points` (without missing x) interpolated linearly in x
+// between the nearest points with y
+fn interpolate(points: &mut [(f64, f64)]) {
+    let mut last = None;
+    for i in 0..points.len() {
+        if points[i].1.is_nan() {
+            continue;
+        }
+        if let Some(j) = last
+            && i > j + 1
+        {
+            let (x0, y0) = points[j];
+            let (x1, y1) = points[i];
+            // neighbors of the same x give no line, and the values stay
+            // missing as with the NULL of SQL
+            if x1 != x0 {
+                for point in &mut points[j + 1..i] {
+                    point.1 = y0 + (y1 - y0) * (point.0 - x0) / (x1 - x0);
+                }
+            }
+        }
+        last = Some(i);
+    }
+}
+
+#[test]
+fn test_missing_values() {
+    let nan = f64::NAN;
+    let points =
+        vec![(0.0, nan), (1.0, 1.0), (2.0, nan), (3.0, 3.0), (nan, 4.0)];
+    assert_eq!(
+        MissingValues::Drop.apply(points.clone()),
+        [(1.0, 1.0), (3.0, 3.0)]
+    );
+    assert_eq!(
+        MissingValues::Zero.apply(points.clone()),
+        [(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 3.0)]
+    );
+    assert_eq!(
+        MissingValues::Interpolate.apply(points),
+        [(1.0, 1.0), (2.0, 2.0), (3.0, 3.0)]
+    );
+
+    // no line goes through neighbors of the same x
+    let points =
+        vec![(1.0, 1.0), (2.0, nan), (1.0, 3.0), (4.0, nan), (5.0, 5.0)];
+    assert_eq!(
+        MissingValues::Interpolate.apply(points),
+        [(1.0, 1.0), (1.0, 3.0), (4.0, 4.5), (5.0, 5.0)]
+    );
+}
+
+#[cfg(feature = "preprocess")]
+#[test]
+fn test_missing_rows() {
+    let row = |x: Option<f64>, y0: Option<f64>, y1: Option<f64>| {
+        [x, y0, y1]
+            .map(|v| v.map_or(Value::Null, Value::Number))
+            .to_vec()
+    };
+    let rows = vec![
+        row(Some(0.0), Some(0.0), None),
+        row(Some(1.0), None, Some(1.0)),
+        row(Some(2.0), Some(4.0), Some(2.0)),
+        row(None, Some(1.0), Some(1.0)),
+    ];
+    assert_eq!(
+        MissingValues::Propagate.apply_rows(rows.clone()).unwrap(),
+        rows
+    );
+    assert_eq!(
+        MissingValues::Drop.apply_rows(rows.clone()).unwrap(),
+        [row(Some(2.0), Some(4.0), Some(2.0))]
+    );
+    assert_eq!(
+        MissingValues::Zero.apply_rows(rows.clone()).unwrap(),
+        [
+            row(Some(0.0), Some(0.0), Some(0.0)),
+            row(Some(1.0), Some(0.0), Some(1.0)),
+            row(Some(2.0), Some(4.0), Some(2.0)),
+        ]
+    );
+    // each y column is interpolated on its own
+    assert_eq!(
+        MissingValues::Interpolate.apply_rows(rows).unwrap(),
+        [
+            row(Some(1.0), Some(2.0), Some(1.0)),
+            row(Some(2.0), Some(4.0), Some(2.0)),
+        ]
+    );
+}
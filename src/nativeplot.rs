@@ -0,0 +1,713 @@
+// Plots drawn natively rather than by gnuplot, e.g. as SVG in a browser
+// (see the `wasm` feature): the frame with the tics and labels of the
+// axes, the data series and their key
+
+use std::ops::Range;
+
+/// How the points of a data series are drawn
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SeriesStyle {
+    #[default]
+    Points,
+    Lines,
+    LinesPoints,
+}
+
+/// Data series of a `NativePlot`, missing values (NaN) being left out and
+/// breaking lines
+#[derive(Debug, Clone, Default)]
+pub struct NativeSeries {
+    pub label: Option<String>,
+    pub points: Vec<(f64, f64)>,
+    pub style: SeriesStyle,
+}
+
+/// Default colors of the data series, those of the line types of gnuplot
+const PALETTE: [&str; 8] = [
+    "#9400d3", "#009e73", "#56b4e9", "#e69f00", "#f0e442", "#0072b2",
+    "#e51e10", "#000000",
+];
+
+/// Plot of data series drawn without gnuplot, see `to_svg`
+#[derive(Debug, Clone, Default)]
+pub struct NativePlot {
+    series: Vec<NativeSeries>,
+    xlabel: Option<String>,
+    ylabel: Option<String>,
+    xrange: Option<Range<f64>>,
+    yrange: Option<Range<f64>>,
+    xlog: Option<f64>,
+    ylog: Option<f64>,
+    grid: bool,
+}
+
+// axis of a plot, whose values are transformed (by the logarithm of a log
+// scale) before they are placed linearly between `min` and `max`
+#[derive(Debug, Clone)]
+struct Axis {
+    min: f64,
+    max: f64,
+    log: Option<f64>,
+    tics: Vec<(f64, String)>,
+}
+
+impl Axis {
+    // `values` transformed, within `range` if given and otherwise spanning
+    // whole tics, with at most about `max_tics` tics
+    fn new(
+        values: impl Iterator<Item = f64>,
+        range: Option<&Range<f64>>,
+        log: Option<f64>,
+        max_tics: usize,
+    ) -> Self {
+        let transform = |v: f64| match log {
+            Some(base) if v > 0.0 => v.log(base),
+            Some(_) => f64::NAN,
+            None => v,
+        };
+        let (mut min, mut max) = match range {
+            Some(range) => (transform(range.start), transform(range.end)),
+            None => values
+                .map(transform)
+                .filter(|v| v.is_finite())
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                    (lo.min(v), hi.max(v))
+                }),
+        };
+        if !min.is_finite() || !max.is_finite() {
+            (min, max) = (0.0, 1.0);
+        }
+        if min == max {
+            let pad = if min == 0.0 { 1.0 } else { min.abs() / 10.0 };
+            (min, max) = (min - pad, max + pad);
+        }
+        let mut step = tic_step((max - min) / max_tics.max(1) as f64);
+        // whole decades of log scales
+        if log.is_some() {
+            step = step.max(1.0).round();
+        }
+        if range.is_none() {
+            min = (min / step).floor() * step;
+            max = (max / step).ceil() * step;
+        }
+        let tics = (0..)
+            .map(|i| ((min / step).ceil() + i as f64) * step)
+            .take_while(|t| *t <= max + step * 1e-9)
+            .map(|t| {
+                let label = match log {
+                    Some(base) => tic_label(base.powf(t), base.powf(t)),
+                    None => tic_label(t, step),
+                };
+                (t, label)
+            })
+            .collect();
+        Self {
+            min,
+            max,
+            log,
+            tics,
+        }
+    }
+
+    // position of `v` from 0 (min) to 1 (max), NaN if it has none
+    fn position(&self, v: f64) -> f64 {
+        let v = match self.log {
+            Some(base) if v > 0.0 => v.log(base),
+            Some(_) => f64::NAN,
+            None => v,
+        };
+        (v - self.min) / (self.max - self.min)
+    }
+}
+
+// 1, 2 or 5 times a power of 10, the smallest at least `raw`
+fn tic_step(raw: f64) -> f64 {
+    let magnitude = 10f64.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= raw * (1.0 - 1e-9))
+        .unwrap_or(10.0 * magnitude)
+}
+
+// label of tic `t` of tics `step` apart, with as many decimals as needed
+fn tic_label(t: f64, step: f64) -> String {
+    let t = if t.abs() < step * 1e-9 { 0.0 } else { t };
+    if t != 0.0 && !(1e-4..1e6).contains(&t.abs()) {
+        return format!("{t:e}");
+    }
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    format!("{t:.decimals$}")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Anchor {
+    Middle,
+    End,
+}
+
+// elements of a drawn plot, placed in the units of the canvas from its top
+// left corner
+#[derive(Debug, Clone)]
+enum Shape {
+    // line through points, of the frame or of data series `series`
+    Line {
+        points: Vec<(f64, f64)>,
+        series: Option<usize>,
+        dashed: bool,
+    },
+    // marks of the points of data series `series`
+    Marks {
+        points: Vec<(f64, f64)>,
+        series: usize,
+    },
+    // text centered vertically on `at`
+    Text {
+        at: (f64, f64),
+        text: String,
+        anchor: Anchor,
+        vertical: bool,
+    },
+}
+
+// area of the data series on the canvas
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    left: f64,
+    top: f64,
+    right: f64,
+    bottom: f64,
+}
+
+impl Area {
+    fn contains(&self, (x, y): (f64, f64)) -> bool {
+        let margin = 1e-6;
+        x >= self.left - margin
+            && x <= self.right + margin
+            && y >= self.top - margin
+            && y <= self.bottom + margin
+    }
+}
+
+impl NativePlot {
+    pub fn new(series: Vec<NativeSeries>) -> Self {
+        Self {
+            series,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_xlabel(mut self, label: Option<impl AsRef<str>>) -> Self {
+        self.xlabel = label.map(|l| l.as_ref().to_string());
+        self
+    }
+
+    pub fn with_ylabel(mut self, label: Option<impl AsRef<str>>) -> Self {
+        self.ylabel = label.map(|l| l.as_ref().to_string());
+        self
+    }
+
+    /// Range of the x axis, otherwise spanning the points up to whole tics
+    pub fn with_xrange(mut self, range: Option<Range<f64>>) -> Self {
+        self.xrange = range;
+        self
+    }
+
+    pub fn with_yrange(mut self, range: Option<Range<f64>>) -> Self {
+        self.yrange = range;
+        self
+    }
+
+    /// Logarithmic x axis of `base`, where values that are not positive are
+    /// left out
+    pub fn with_xlogscale(mut self, base: Option<f64>) -> Self {
+        self.xlog = base;
+        self
+    }
+
+    pub fn with_ylogscale(mut self, base: Option<f64>) -> Self {
+        self.ylog = base;
+        self
+    }
+
+    pub fn with_grid(mut self, grid: bool) -> Self {
+        self.grid = grid;
+        self
+    }
+
+    // shapes of the plot on a canvas of `width` by `height`, where a
+    // character takes `char_width` by `line_height`
+    fn layout(
+        &self,
+        width: f64,
+        height: f64,
+        (char_width, line_height): (f64, f64),
+    ) -> (Vec<Shape>, Area) {
+        let points = || self.series.iter().flat_map(|s| &s.points);
+        let tics = |length: f64, spacing: f64| {
+            ((length / spacing) as usize).clamp(2, 10)
+        };
+        let yaxis = Axis::new(
+            points().map(|(_, y)| *y),
+            self.yrange.as_ref(),
+            self.ylog,
+            tics(height, 3.0 * line_height),
+        );
+        let ylabels_width = yaxis
+            .tics
+            .iter()
+            .map(|(_, label)| label.chars().count())
+            .max()
+            .unwrap_or(0) as f64
+            * char_width;
+        let left = ylabels_width
+            + char_width
+            + if self.ylabel.is_some() {
+                2.0 * line_height
+            } else {
+                0.0
+            };
+        let bottom = height
+            - line_height * if self.xlabel.is_some() { 2.5 } else { 1.5 };
+        let area = Area {
+            left,
+            top: line_height / 2.0,
+            right: width - 2.0 * char_width,
+            bottom,
+        };
+        let xaxis = Axis::new(
+            points().map(|(x, _)| *x),
+            self.xrange.as_ref(),
+            self.xlog,
+            tics(area.right - area.left, 12.0 * char_width),
+        );
+        let place = |(x, y): (f64, f64)| {
+            (
+                area.left + xaxis.position(x) * (area.right - area.left),
+                area.bottom - yaxis.position(y) * (area.bottom - area.top),
+            )
+        };
+
+        let mut shapes = Vec::new();
+        let frame = |points: Vec<(f64, f64)>, dashed: bool| Shape::Line {
+            points,
+            series: None,
+            dashed,
+        };
+        let text = |at: (f64, f64), text: &str, anchor: Anchor| Shape::Text {
+            at,
+            text: text.to_string(),
+            anchor,
+            vertical: false,
+        };
+        let tic_length = char_width / 2.0;
+        for (t, label) in &xaxis.tics {
+            let x = area.left
+                + (t - xaxis.min) / (xaxis.max - xaxis.min)
+                    * (area.right - area.left);
+            if self.grid {
+                shapes.push(frame(vec![(x, area.top), (x, area.bottom)], true));
+            }
+            shapes.push(frame(
+                vec![(x, area.bottom), (x, area.bottom - tic_length)],
+                false,
+            ));
+            shapes.push(text(
+                (x, area.bottom + line_height),
+                label,
+                Anchor::Middle,
+            ));
+        }
+        for (t, label) in &yaxis.tics {
+            let y = area.bottom
+                - (t - yaxis.min) / (yaxis.max - yaxis.min)
+                    * (area.bottom - area.top);
+            if self.grid {
+                shapes.push(frame(vec![(area.left, y), (area.right, y)], true));
+            }
+            shapes.push(frame(
+                vec![(area.left, y), (area.left + tic_length, y)],
+                false,
+            ));
+            shapes.push(text((area.left - char_width, y), label, Anchor::End));
+        }
+        shapes.push(frame(
+            vec![
+                (area.left, area.top),
+                (area.right, area.top),
+                (area.right, area.bottom),
+                (area.left, area.bottom),
+                (area.left, area.top),
+            ],
+            false,
+        ));
+        if let Some(label) = &self.xlabel {
+            shapes.push(text(
+                ((area.left + area.right) / 2.0, height - line_height / 2.0),
+                label,
+                Anchor::Middle,
+            ));
+        }
+        if let Some(label) = &self.ylabel {
+            shapes.push(Shape::Text {
+                at: (line_height / 2.0, (area.top + area.bottom) / 2.0),
+                text: label.clone(),
+                anchor: Anchor::Middle,
+                vertical: true,
+            });
+        }
+
+        for (i, series) in self.series.iter().enumerate() {
+            let placed = series
+                .points
+                .iter()
+                .map(|&point| place(point))
+                .collect::<Vec<_>>();
+            if series.style != SeriesStyle::Points {
+                // missing values break the line
+                for run in
+                    placed.split(|(x, y)| !x.is_finite() || !y.is_finite())
+                {
+                    if run.len() > 1 {
+                        shapes.push(Shape::Line {
+                            points: run.to_vec(),
+                            series: Some(i),
+                            dashed: false,
+                        });
+                    }
+                }
+            }
+            if series.style != SeriesStyle::Lines {
+                shapes.push(Shape::Marks {
+                    points: placed
+                        .into_iter()
+                        .filter(|&point| area.contains(point))
+                        .collect(),
+                    series: i,
+                });
+            }
+
+            // key of the series, at the top right corner of the plot
+            let Some(label) = &series.label else {
+                continue;
+            };
+            let y = area.top + line_height * (i as f64 + 1.0);
+            let sample =
+                (area.right - 5.0 * char_width, area.right - char_width);
+            shapes.push(text((sample.0 - char_width, y), label, Anchor::End));
+            if series.style != SeriesStyle::Points {
+                shapes.push(Shape::Line {
+                    points: vec![(sample.0, y), (sample.1, y)],
+                    series: Some(i),
+                    dashed: false,
+                });
+            }
+            if series.style != SeriesStyle::Lines {
+                shapes.push(Shape::Marks {
+                    points: vec![((sample.0 + sample.1) / 2.0, y)],
+                    series: i,
+                });
+            }
+        }
+        (shapes, area)
+    }
+
+    /// SVG document of the plot, `width` by `height` pixels
+    pub fn to_svg(&self, width: u32, height: u32) -> String {
+        let (width, height) = (width as f64, height as f64);
+        let (shapes, area) = self.layout(width, height, (7.0, 16.0));
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+             <clipPath id=\"sp-area\"><rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\"/></clipPath>\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n",
+            area.left,
+            area.top,
+            area.right - area.left,
+            area.bottom - area.top
+        );
+        let coordinates = |points: &[(f64, f64)]| {
+            points
+                .iter()
+                .map(|(x, y)| format!("{x:.1},{y:.1}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        for shape in shapes {
+            let element = match shape {
+                Shape::Line {
+                    points,
+                    series: None,
+                    dashed,
+                } => format!(
+                    "<polyline fill=\"none\" stroke=\"{}\" points=\"{}\"{}/>",
+                    if dashed { "#c0c0c0" } else { "black" },
+                    coordinates(&points),
+                    if dashed { " stroke-dasharray=\"2,3\"" } else { "" },
+                ),
+                Shape::Line {
+                    points,
+                    series: Some(i),
+                    ..
+                } => format!(
+                    "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\" clip-path=\"url(#sp-area)\"/>",
+                    PALETTE[i % PALETTE.len()],
+                    coordinates(&points)
+                ),
+                Shape::Marks { points, series } => format!(
+                    "<path fill=\"none\" stroke=\"{}\" d=\"{}\"/>",
+                    PALETTE[series % PALETTE.len()],
+                    points
+                        .iter()
+                        .map(|(x, y)| format!(
+                            "M{:.1} {y:.1}a2.5 2.5 0 1 0 5 0a2.5 2.5 0 1 0 -5 0",
+                            x - 2.5
+                        ))
+                        .collect::<String>()
+                ),
+                Shape::Text {
+                    at: (x, y),
+                    text,
+                    anchor,
+                    vertical,
+                } => format!(
+                    "<text x=\"{x:.1}\" y=\"{y:.1}\" dominant-baseline=\"central\" text-anchor=\"{}\"{}>{}</text>",
+                    match anchor {
+                        Anchor::Middle => "middle",
+                        Anchor::End => "end",
+                    },
+                    if vertical {
+                        format!(" transform=\"rotate(-90 {x:.1} {y:.1})\"")
+                    } else {
+                        "".to_string()
+                    },
+                    xml_escape(&text)
+                ),
+            };
+            svg.push_str(&element);
+            svg.push('\n');
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Text of the plot, `width` by `height` characters, like the dumb
+    /// terminal of gnuplot: each data series is drawn with its own
+    /// character, see `MARKS`
+    pub fn to_text(&self, width: usize, height: usize) -> String {
+        let (shapes, area) =
+            self.layout(width as f64, height as f64, (1.0, 1.0));
+        let mut grid = vec![vec![' '; width]; height];
+        // cell of a point, if it is on the grid
+        let cell = |(x, y): (f64, f64)| {
+            let (col, row) = (x.floor(), y.floor());
+            (col >= 0.0
+                && row >= 0.0
+                && col < width as f64
+                && row < height as f64)
+                .then_some((col as usize, row as usize))
+        };
+        for shape in shapes {
+            match shape {
+                Shape::Line {
+                    points,
+                    series,
+                    dashed,
+                } => {
+                    for segment in points.windows(2) {
+                        let (Some(from), Some(to)) =
+                            (cell(segment[0]), cell(segment[1]))
+                        else {
+                            continue;
+                        };
+                        let cells = raster(from, to);
+                        for (i, &(col, row)) in cells.iter().enumerate() {
+                            let c = &mut grid[row][col];
+                            *c = match series {
+                                Some(series) => {
+                                    if !area.contains((col as f64, row as f64))
+                                    {
+                                        continue;
+                                    }
+                                    MARKS[series % MARKS.len()]
+                                }
+                                None if dashed => {
+                                    if i % 2 == 1 || *c != ' ' {
+                                        continue;
+                                    }
+                                    '.'
+                                }
+                                // tics and the corners of the border
+                                None if cells.len() == 1 => '+',
+                                None if from.1 == to.1 => match *c {
+                                    '|' | '+' => '+',
+                                    _ => '-',
+                                },
+                                None => match *c {
+                                    '-' | '+' => '+',
+                                    _ => '|',
+                                },
+                            };
+                        }
+                    }
+                }
+                Shape::Marks { points, series } => {
+                    for (col, row) in points.into_iter().filter_map(cell) {
+                        grid[row][col] = MARKS[series % MARKS.len()];
+                    }
+                }
+                Shape::Text {
+                    at: (x, y),
+                    text,
+                    anchor,
+                    vertical,
+                } => {
+                    let len = text.chars().count() as f64;
+                    // characters are centered on their positions
+                    let start = match anchor {
+                        Anchor::Middle => (1.0 - len) / 2.0,
+                        Anchor::End => 0.5 - len,
+                    };
+                    for (i, c) in text.chars().enumerate() {
+                        let at = match vertical {
+                            true => (x, y + start + i as f64),
+                            false => (x + start + i as f64, y),
+                        };
+                        if let Some((col, row)) = cell(at) {
+                            grid[row][col] = c;
+                        }
+                    }
+                }
+            }
+        }
+        grid.into_iter()
+            .map(|row| {
+                row.into_iter().collect::<String>().trim_end().to_string()
+                    + "\n"
+            })
+            .collect()
+    }
+}
+
+/// Characters drawing the data series in `NativePlot::to_text`
+const MARKS: [char; 8] = ['*', '#', '$', '%', '@', '&', '=', 'o'];
+
+// cells on the line from `from` to `to`, by Bresenham's algorithm
+fn raster(from: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    let (x0, y0) = (from.0 as i64, from.1 as i64);
+    let (x1, y1) = (to.0 as i64, to.1 as i64);
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+    let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+    let mut cells = vec![(x as usize, y as usize)];
+    while (x, y) != (x1, y1) {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += sx;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += sy;
+        }
+        cells.push((x as usize, y as usize));
+    }
+    cells
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn test_tics() {
+    let axis = Axis::new([0.3, 9.2].into_iter(), None, None, 5);
+    assert_eq!((axis.min, axis.max), (0.0, 10.0));
+    let labels = axis
+        .tics
+        .iter()
+        .map(|(_, l)| l.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, ["0", "2", "4", "6", "8", "10"]);
+
+    let axis = Axis::new([0.01, 0.02].into_iter(), Some(&(0.0..0.05)), None, 5);
+    let labels = axis
+        .tics
+        .iter()
+        .map(|(_, l)| l.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, ["0.00", "0.01", "0.02", "0.03", "0.04", "0.05"]);
+
+    // decades of log scales, non-positive values being left out
+    let axis = Axis::new([-1.0, 2.0, 3000.0].into_iter(), None, Some(10.0), 10);
+    let labels = axis
+        .tics
+        .iter()
+        .map(|(_, l)| l.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, ["1", "10", "100", "1000", "10000"]);
+    assert_eq!(axis.position(100.0), 0.5);
+    assert!(axis.position(0.0).is_nan());
+}
+
+#[test]
+fn test_svg() {
+    let plot = NativePlot::new(vec![
+        NativeSeries {
+            label: Some("a<b".to_string()),
+            points: vec![(0.0, 1.0), (1.0, f64::NAN), (2.0, 3.0), (3.0, 2.0)],
+            style: SeriesStyle::Lines,
+        },
+        NativeSeries {
+            label: None,
+            points: vec![(1.0, 2.0)],
+            style: SeriesStyle::Points,
+        },
+    ])
+    .with_xlabel(Some("time"));
+    let svg = plot.to_svg(640, 480);
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.ends_with("</svg>\n"));
+    assert!(svg.contains(">a&lt;b</text>"));
+    assert!(svg.contains(">time</text>"));
+    // the missing value breaks the line, whose second run has 2 points, and
+    // the key has a sample line
+    let lines = svg.matches(&format!("stroke=\"{}\"", PALETTE[0])).count();
+    assert_eq!(lines, 2);
+    assert_eq!(svg.matches("a2.5 2.5").count(), 2);
+}
+
+#[test]
+fn test_text() {
+    let plot = NativePlot::new(vec![
+        NativeSeries {
+            label: Some("y".to_string()),
+            points: (0..=10).map(|i| (i as f64, (i * i) as f64)).collect(),
+            style: SeriesStyle::Lines,
+        },
+        NativeSeries {
+            label: Some("z".to_string()),
+            points: vec![(5.0, 50.0)],
+            style: SeriesStyle::Points,
+        },
+    ])
+    .with_xlabel(Some("x"))
+    .with_ylabel(Some("x^2"));
+    let text = plot.to_text(60, 20);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 20);
+    assert!(lines.iter().all(|line| line.chars().count() <= 60));
+    assert_eq!(lines[0], format!("  100 +{}+", "-".repeat(51)));
+    assert!(lines[1].ends_with("y *****|"));
+    // the key of points shows the mark of the series, also drawn at (5, 50)
+    assert!(lines[2].ends_with("#  |"));
+    assert_eq!(lines[9].matches('#').count(), 1);
+    assert!(lines[17].starts_with("    0 ***"));
+    assert_eq!(
+        lines[18].trim(),
+        "0                         5                        10"
+    );
+    assert_eq!(lines[19].trim(), "x");
+}
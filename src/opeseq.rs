@@ -1,14 +1,23 @@
 // Implementation of operators and the interpretation of operation sequence
 
-use std::{fmt::Display, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, LazyLock, RwLock},
+};
 
 use anyhow::{Result, anyhow, bail};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use strum::Display;
 
 // Internal representation of operators, no associated functionalities
 #[derive(Debug)]
 pub struct Op {
+    // `[` for registered operators written by their names
     op: char,
+    name: Option<String>,
     arg: Vec<f64>,
 }
 
@@ -16,21 +25,35 @@ impl Op {
     // try to retrieve operator and its argument from the beginning of the
     // string, returns the operator and number of characters consumed
     fn from_str(s: &str) -> Result<(Self, usize)> {
-        // operators are alphabets
-        let op = match s.chars().nth(0) {
-            Some(c @ 'a'..='z') => c,
-            Some(c @ 'A'..='Z') => c,
+        // operators are alphabets, or names of registered operators in
+        // brackets
+        let (op, name, head_len) = match s.chars().nth(0) {
+            Some('[') => {
+                let close = s
+                    .find(']')
+                    .ok_or_else(|| anyhow!("Unclosed '[' in '{s}'"))?;
+                ('[', Some(s[1..close].to_string()), close + 1)
+            }
+            Some(c @ 'a'..='z') => (c, None, 1),
+            Some(c @ 'A'..='Z') => (c, None, 1),
             Some(c) => bail!("Non-alphabetic operator '{c}'"),
             None => bail!("Empty string"),
         };
+        let label = name.clone().unwrap_or_else(|| op.to_string());
+        let s = &s[head_len..];
+        // arguments in parentheses could also be named
+        if s.starts_with('(') {
+            let close = s.find(')').ok_or_else(|| {
+                anyhow!("Unclosed parenthesis after '{label}'")
+            })?;
+            let arg = Self::parse_args(op, &label, &s[1..close])?;
+            return Ok((Self { op, name, arg }, head_len + close + 1));
+        }
         // arguments are comma-separated numbers that follows operators
-        let (arg, argstr_len) = match s[1..]
-            .find(|c: char| char::is_ascii_alphabetic(&c))
-            .unwrap_or(s.len() - 1)
-        {
+        let (arg, argstr_len) = match Self::numeric_len(s) {
             0 => (vec![], 0),
             i => (
-                s[1..1 + i]
+                s[..i]
                     .split(',')
                     .map(|s| s.parse::<f64>().map_err(|e| anyhow!("{e}")))
                     .collect::<Result<Vec<f64>, anyhow::Error>>()?,
@@ -38,7 +61,224 @@ impl Op {
             ),
         };
 
-        Ok((Self { op, arg }, 1 + argstr_len))
+        Ok((Self { op, name, arg }, head_len + argstr_len))
+    }
+
+    // length of the leading numbers and commas of `s`, exponents included
+    // (`1e5` or `1.5e-3`), so that an `e` is only the next operator when it
+    // does not start one
+    fn numeric_len(s: &str) -> usize {
+        let bytes = s.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'0'..=b'9' | b'.' | b'+' | b'-' | b',' => i += 1,
+                b'e' | b'E'
+                    if i > 0 && matches!(bytes[i - 1], b'0'..=b'9' | b'.') =>
+                {
+                    let digits = match bytes.get(i + 1) {
+                        Some(b'+' | b'-') => i + 2,
+                        _ => i + 1,
+                    };
+                    if !bytes.get(digits).is_some_and(u8::is_ascii_digit) {
+                        break;
+                    }
+                    i = digits;
+                }
+                _ => break,
+            }
+        }
+        i
+    }
+
+    // comma-separated arguments in parentheses, positional ones (numbers)
+    // followed by named ones (`name=value`), the latter being placed at the
+    // position of their names; `label` is the operator in messages
+    fn parse_args(op: char, label: &str, s: &str) -> Result<Vec<f64>> {
+        let mut arg: Vec<Option<f64>> = Vec::new();
+        let mut named = false;
+        for item in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let Some((name, value)) = item.split_once('=') else {
+                if named {
+                    bail!("Positional argument '{item}' after named ones");
+                }
+                arg.push(Some(item.parse().map_err(|e| anyhow!("{e}"))?));
+                continue;
+            };
+            named = true;
+            let params = Params::of(op).ok_or_else(|| {
+                anyhow!("Operator '{label}' has no named arguments")
+            })?;
+            let (name, value) = (name.trim(), value.trim());
+            let positions = params.positions(name).ok_or_else(|| {
+                anyhow!(
+                    "Unknown argument '{name}' of operator '{label}', expecting one of {}",
+                    params.names().join(", ")
+                )
+            })?;
+            let value = params.value(name, value)?;
+            for i in positions {
+                if arg.len() <= i {
+                    arg.resize(i + 1, None);
+                }
+                if arg[i].replace(value).is_some() {
+                    bail!(
+                        "Argument '{name}' of operator '{label}' given twice"
+                    );
+                }
+            }
+        }
+        Ok(arg
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| {
+                v.unwrap_or_else(|| {
+                    Params::of(op).map_or(0.0, |params| params.args[i].1)
+                })
+            })
+            .collect())
+    }
+}
+
+// Names of the arguments of the built-in operators, for named arguments
+struct Params {
+    // name and default value of each positional argument
+    args: &'static [(&'static str, f64)],
+    // names setting several arguments at once
+    aliases: &'static [(&'static str, &'static [usize])],
+    // values written as words, with the name of their argument
+    words: &'static [(&'static str, &'static str, f64)],
+}
+
+impl Params {
+    const WINDOW: Params = Params {
+        args: &[("left", 0.0), ("right", 0.0)],
+        aliases: &[("window", &[0, 1])],
+        words: &[],
+    };
+    const LOG_SCALE: Params = Params {
+        args: &[("base", 10.0), ("axis", 0.0)],
+        aliases: &[],
+        words: &[("axis", "y", 0.0), ("axis", "x", 1.0)],
+    };
+    const AFFINE: Params = Params {
+        args: &[("scale", 1.0), ("offset", 0.0)],
+        aliases: &[],
+        words: &[],
+    };
+
+    fn of(op: char) -> Option<&'static Params> {
+        Some(match op {
+            'a' | 'b' | 'd' | 'M' | 't' => &Self::WINDOW,
+            'e' | 'l' => &Self::LOG_SCALE,
+            'x' | 'y' => &Self::AFFINE,
+            'D' => &Params {
+                args: &[("n", 0.0)],
+                aliases: &[],
+                words: &[],
+            },
+            'g' => &Params {
+                args: &[("mode", 0.0)],
+                aliases: &[],
+                words: &[("mode", "keep", 0.0), ("mode", "index", 1.0)],
+            },
+            'k' => &Params {
+                args: &[("k", 0.0), ("order", 0.0)],
+                aliases: &[],
+                words: &[("order", "largest", 0.0), ("order", "smallest", 1.0)],
+            },
+            'o' => &Params {
+                args: &[("by", 0.0), ("order", 0.0), ("stable", 0.0)],
+                aliases: &[],
+                words: &[
+                    ("by", "x", 0.0),
+                    ("by", "y", 1.0),
+                    ("order", "ascending", 0.0),
+                    ("order", "descending", 1.0),
+                    ("stable", "no", 0.0),
+                    ("stable", "yes", 1.0),
+                ],
+            },
+            'n' => &Params {
+                args: &[("mode", 0.0)],
+                aliases: &[],
+                words: &[("mode", "minmax", 0.0), ("mode", "zscore", 1.0)],
+            },
+            'S' | 'L' => &Params {
+                args: &[("id", 0.0)],
+                aliases: &[],
+                words: &[],
+            },
+            'r' => &Params {
+                args: &[("start", 0.0), ("step", 0.0)],
+                aliases: &[],
+                words: &[],
+            },
+            'R' => &Params {
+                args: &[("mode", 0.0)],
+                aliases: &[],
+                words: &[
+                    ("mode", "fit", 0.0),
+                    ("mode", "residuals", 1.0),
+                    ("mode", "parameters", 2.0),
+                ],
+            },
+            'u' => &Params {
+                args: &[("mode", 0.0)],
+                aliases: &[],
+                words: &[
+                    ("mode", "first", 0.0),
+                    ("mode", "last", 1.0),
+                    ("mode", "mean", 2.0),
+                ],
+            },
+            _ => return None,
+        })
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        self.args
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(self.aliases.iter().map(|(name, _)| *name))
+            .collect()
+    }
+
+    // positions of the arguments set by `name`
+    fn positions(&self, name: &str) -> Option<Vec<usize>> {
+        if let Some(i) = self.args.iter().position(|(n, _)| *n == name) {
+            return Some(vec![i]);
+        }
+        self.aliases
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, positions)| positions.to_vec())
+    }
+
+    fn value(&self, name: &str, value: &str) -> Result<f64> {
+        if let Some((_, _, v)) = self
+            .words
+            .iter()
+            .find(|(n, w, _)| *n == name && *w == value)
+        {
+            return Ok(*v);
+        }
+        value.parse().map_err(|_| {
+            let words = self
+                .words
+                .iter()
+                .filter(|(n, _, _)| *n == name)
+                .map(|(_, w, _)| *w)
+                .collect::<Vec<_>>();
+            if words.is_empty() {
+                anyhow!("Invalid value '{value}' of argument '{name}'")
+            } else {
+                anyhow!(
+                    "Invalid value '{value}' of argument '{name}', expecting a number or one of {}",
+                    words.join(", ")
+                )
+            }
+        })
     }
 }
 
@@ -57,6 +297,13 @@ pub struct OperateResult {
 
 pub trait Operator: std::fmt::Debug + Clone + Display + TryFrom<Op> {
     fn to_sql(&self, info: &OperateInfo) -> OperateResult;
+
+    /// Apply the operator to (x, y) points in Rust, producing the same table
+    /// as the SQL
+    fn eval(&self, _points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        bail!("Operator {self} has no native implementation")
+    }
+
     fn append_column_name(&self, name: &str) -> String {
         if !name.contains('-') {
             format!("{name}-{self}")
@@ -66,22 +313,64 @@ pub trait Operator: std::fmt::Debug + Clone + Display + TryFrom<Op> {
     }
 }
 
+// Sorting and mapping points are parallel with the `parallel` feature, as
+// they dominate the cost of the operators on large datasheets
+
+pub(crate) fn sort_points_by(
+    points: &mut [(f64, f64)],
+    key: impl Fn(&(f64, f64)) -> f64 + Sync,
+) {
+    let compare = |a: &(f64, f64), b: &(f64, f64)| key(a).total_cmp(&key(b));
+    #[cfg(feature = "parallel")]
+    points.par_sort_by(compare);
+    #[cfg(not(feature = "parallel"))]
+    points.sort_by(compare);
+}
+
+fn sort_by_x(points: &mut [(f64, f64)]) {
+    sort_points_by(points, |p| p.0);
+}
+
+// `f(i)` for each index of `points`
+fn map_points(
+    points: &[(f64, f64)],
+    f: impl Fn(usize) -> (f64, f64) + Sync + Send,
+) -> Vec<(f64, f64)> {
+    #[cfg(feature = "parallel")]
+    let indexes = (0..points.len()).into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let indexes = 0..points.len();
+    indexes.map(f).collect()
+}
+
+// y values of `points` except NaN, which stands for a missing value (NULL in
+// SQL) and is skipped like by the aggregate functions of duckdb
+fn present_values(points: &[(f64, f64)]) -> impl Iterator<Item = f64> + '_ {
+    points.iter().map(|(_, y)| *y).filter(|y| !y.is_nan())
+}
+
+// mean of the y values of `points` except NaN, NaN if there is none (like
+// `avg` of duckdb giving NULL)
+fn mean_of_present(points: &[(f64, f64)]) -> f64 {
+    let (sum, n) = present_values(points)
+        .fold((0.0, 0usize), |(sum, n), y| (sum + y, n + 1));
+    sum / n as f64
+}
+
 macro_rules! declare_operator_no_param {
     ($op:ident) => {
+        declare_operator_no_param!(
+            $op,
+            stringify!($op).chars().next().unwrap().to_ascii_lowercase()
+        );
+    };
+    ($op:ident, $op_char:expr) => {
         #[derive(Debug, Clone)]
         pub struct $op {}
 
         impl Display for $op {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                write!(
-                    f,
-                    "{}",
-                    stringify!($op)
-                        .chars()
-                        .next()
-                        .unwrap()
-                        .to_ascii_lowercase()
-                )
+                write!(f, "{}", $op_char)
             }
         }
 
@@ -89,11 +378,7 @@ macro_rules! declare_operator_no_param {
             type Error = anyhow::Error;
 
             fn try_from(op: Op) -> Result<Self> {
-                let op_char = stringify!($op)
-                    .chars()
-                    .next()
-                    .unwrap()
-                    .to_ascii_lowercase();
+                let op_char = $op_char;
                 if op.op != op_char {
                     bail!(
                         "{} only accepts '{}' as operator",
@@ -142,31 +427,181 @@ impl RelativeRange {
         })
     }
 
+    // points of sorted `points` with x in the window around `x`, including
+    // the point at `x` itself
+    fn window<'a>(&self, points: &'a [(f64, f64)], x: f64) -> &'a [(f64, f64)] {
+        let lo = points.partition_point(|p| p.0 < x - self.left_window);
+        let hi = points.partition_point(|p| p.0 <= x + self.right_window);
+        &points[lo..hi]
+    }
+
     fn generate_window_clause(&self) -> String {
         format!(
             "RANGE BETWEEN {} PRECEDING AND {} FOLLOWING",
             self.left_window, self.right_window
         )
     }
+
+    // `aggregate` of y over the window of each record
+    fn to_window_sql(
+        &self,
+        op: &impl Operator,
+        info: &OperateInfo,
+        aggregate: &str,
+    ) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = op.append_column_name(&info.y_name);
+
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT \"{}\", {}(\"{}\") over w as \"{}\" FROM {} WINDOW w AS (ORDER BY \"{}\" {}))",
+                info.tmp_table_num,
+                x_name,
+                aggregate,
+                info.y_name,
+                y_name,
+                info.src_table,
+                info.x_name,
+                self.generate_window_clause(),
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+// Base of a logarithm (10 by default) and the axis it applies to, y unless
+// the second argument is 1
+#[derive(Debug, Clone)]
+struct LogScale {
+    base: f64,
+    x_axis: bool,
+}
+
+impl Display for LogScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.x_axis {
+            write!(f, "{},1", self.base)
+        } else if self.base == 10.0 {
+            write!(f, "")
+        } else {
+            write!(f, "{}", self.base)
+        }
+    }
+}
+
+impl LogScale {
+    fn from_args(args: &[f64]) -> anyhow::Result<Self> {
+        let base = *args.first().unwrap_or(&10.0);
+        if !base.is_finite() || base <= 0.0 || base == 1.0 {
+            bail!("LogScale only accepts a positive finite base other than 1");
+        }
+        let x_axis = match args.get(1) {
+            None | Some(0.0) => false,
+            Some(1.0) => true,
+            Some(_) => bail!("LogScale only accepts axis 0 (y) or 1 (x)"),
+        };
+        if args.len() > 2 {
+            bail!("LogScale accepts at most a base and an axis");
+        }
+        Ok(Self { base, x_axis })
+    }
+}
+
+// Scale and offset (1 and 0 by default) of an affine map, v * scale + offset
+#[derive(Debug, Clone)]
+struct Affine {
+    scale: f64,
+    offset: f64,
+}
+
+impl Display for Affine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.offset != 0.0 {
+            write!(f, "{},{}", self.scale, self.offset)
+        } else if self.scale == 1.0 {
+            write!(f, "")
+        } else {
+            write!(f, "{}", self.scale)
+        }
+    }
+}
+
+impl Affine {
+    fn from_args(args: &[f64]) -> anyhow::Result<Self> {
+        let scale = *args.first().unwrap_or(&1.0);
+        let offset = *args.get(1).unwrap_or(&0.0);
+        if !scale.is_finite() || !offset.is_finite() || args.len() > 2 {
+            bail!("Affine only accepts a finite scale and a finite offset");
+        }
+        Ok(Self { scale, offset })
+    }
+
+    fn apply(&self, v: f64) -> f64 {
+        v * self.scale + self.offset
+    }
+
+    fn to_sql(&self, name: &str) -> String {
+        format!("\"{}\" * {} + {}", name, self.scale, self.offset)
+    }
+}
+
+// `f` applied to the x column if `x_axis`, the y column otherwise
+fn map_axis(
+    points: Vec<(f64, f64)>,
+    x_axis: bool,
+    f: impl Fn(f64) -> f64,
+) -> Vec<(f64, f64)> {
+    points
+        .into_iter()
+        .map(|(x, y)| if x_axis { (f(x), y) } else { (x, f(y)) })
+        .collect()
+}
+
+// `expr` (of the column name) replacing the x column if `x_axis`, the y
+// column otherwise
+fn map_axis_sql(
+    op: &impl Operator,
+    info: &OperateInfo,
+    x_axis: bool,
+    expr: impl Fn(&str) -> String,
+) -> OperateResult {
+    let (x_name, y_name) = if x_axis {
+        (op.append_column_name(&info.x_name), info.y_name.to_string())
+    } else {
+        (info.x_name.to_string(), op.append_column_name(&info.y_name))
+    };
+    let (x, y) = if x_axis {
+        (expr(&info.x_name), format!("\"{}\"", info.y_name))
+    } else {
+        (format!("\"{}\"", info.x_name), expr(&info.y_name))
+    };
+
+    OperateResult {
+        subquery: format!(
+            "t{} AS (SELECT {} AS \"{}\", {} AS \"{}\" FROM {})",
+            info.tmp_table_num, x, x_name, y, y_name, info.src_table,
+        ),
+        x_name,
+        y_name,
+    }
 }
 
 macro_rules! declare_operator_with_single_arg {
     ($op:ident, $arg_name:ident) => {
+        declare_operator_with_single_arg!(
+            $op,
+            $arg_name,
+            stringify!($op).chars().next().unwrap().to_ascii_lowercase()
+        );
+    };
+    ($op:ident, $arg_name:ident, $op_char:expr) => {
         #[derive(Debug, Clone)]
         pub struct $op($arg_name);
 
         impl Display for $op {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                write!(
-                    f,
-                    "{}{}",
-                    stringify!($op)
-                        .chars()
-                        .next()
-                        .unwrap()
-                        .to_ascii_lowercase(),
-                    self.0
-                )
+                write!(f, "{}{}", $op_char, self.0)
             }
         }
 
@@ -174,11 +609,7 @@ macro_rules! declare_operator_with_single_arg {
             type Error = anyhow::Error;
 
             fn try_from(op: Op) -> Result<Self> {
-                let op_char = stringify!($op)
-                    .chars()
-                    .next()
-                    .unwrap()
-                    .to_ascii_lowercase();
+                let op_char = $op_char;
                 if op.op != op_char {
                     bail!(
                         "{} only accepts '{}' as operator",
@@ -186,8 +617,7 @@ macro_rules! declare_operator_with_single_arg {
                         op_char
                     );
                 }
-                let range = RelativeRange::from_args(&op.arg)?;
-                Ok(Self(range))
+                Ok(Self($arg_name::from_args(&op.arg)?))
             }
         }
     };
@@ -195,30 +625,57 @@ macro_rules! declare_operator_with_single_arg {
 declare_operator_with_single_arg!(AverageOperator, RelativeRange);
 
 impl Operator for AverageOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_by_x(&mut points);
+        Ok(map_points(&points, |i| {
+            let x = points[i].0;
+            (x, mean_of_present(self.0.window(&points, x)))
+        }))
+    }
+
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
-        let x_name = info.x_name.to_string();
-        let y_name = self.append_column_name(&info.y_name);
+        self.0.to_window_sql(self, info, "avg")
+    }
+}
 
-        OperateResult {
-            subquery: format!(
-                "t{} AS (SELECT \"{}\", avg(\"{}\") over w as \"{}\" FROM {} WINDOW w AS (ORDER BY \"{}\" {})",
-                info.tmp_table_num,
-                x_name,
-                info.y_name,
-                y_name,
-                info.src_table,
-                info.x_name,
-                self.0.generate_window_clause(),
-            ),
-            x_name,
-            y_name,
-        }
+// rolling minimum
+declare_operator_with_single_arg!(BottomOperator, RelativeRange);
+
+impl Operator for BottomOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_by_x(&mut points);
+        Ok(map_points(&points, |i| {
+            let x = points[i].0;
+            let window = self.0.window(&points, x);
+            // missing values are skipped like by `min` of duckdb
+            (
+                x,
+                present_values(window)
+                    .min_by(f64::total_cmp)
+                    .unwrap_or(f64::NAN),
+            )
+        }))
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        self.0.to_window_sql(self, info, "min")
     }
 }
 
 declare_operator_no_param!(CDFOperator);
 
 impl Operator for CDFOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_points_by(&mut points, |p| p.1);
+        let n = points.len() as f64;
+        // peers (equal y values) share the fraction of the last one
+        Ok(map_points(&points, |i| {
+            let y = points[i].1;
+            let rank = points.partition_point(|p| p.1.total_cmp(&y).is_le());
+            (y, rank as f64 / n)
+        }))
+    }
+
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
         let x_name = info.y_name.to_string();
         let y_name = self.append_column_name(&info.y_name);
@@ -242,6 +699,23 @@ impl Operator for CDFOperator {
 declare_operator_with_single_arg!(DerivativeOperator, RelativeRange);
 
 impl Operator for DerivativeOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_by_x(&mut points);
+        let slope = |first: (f64, f64), last: (f64, f64)| {
+            (last.1 - first.1) / (last.0 - first.0)
+        };
+        let consecutive = self.0.to_string().is_empty();
+        Ok(map_points(&points, |i| {
+            let (x, y) = points[i];
+            if consecutive {
+                (x, slope(points[i.saturating_sub(1)], (x, y)))
+            } else {
+                let window = self.0.window(&points, x);
+                (x, slope(window[0], window[window.len() - 1]))
+            }
+        }))
+    }
+
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
         let x_name = info.x_name.to_string();
         let y_name = self.append_column_name(&info.y_name);
@@ -274,23 +748,56 @@ impl Operator for DerivativeOperator {
     }
 }
 
-declare_operator_no_param!(FilterFiniteOperator);
+/// Stride-based decimation to at most the given number of points, keeping
+/// every k-th point (from the first one) in their original order
+#[derive(Debug, Clone)]
+pub struct DownsampleOperator(usize);
+
+impl Display for DownsampleOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "D{}", self.0)
+    }
+}
+
+impl TryFrom<Op> for DownsampleOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        if op.op != 'D' {
+            bail!("DownsampleOperator only accepts 'D' as operator");
+        }
+        match op.arg.as_slice() {
+            &[n] if n >= 1.0 && n.fract() == 0.0 && n <= usize::MAX as f64 => {
+                Ok(Self(n as usize))
+            }
+            _ => bail!(
+                "DownsampleOperator only accepts a positive integer number of points"
+            ),
+        }
+    }
+}
+
+impl Operator for DownsampleOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        let stride = points.len().div_ceil(self.0).max(1);
+        Ok(points.into_iter().step_by(stride).collect())
+    }
 
-impl Operator for FilterFiniteOperator {
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
         let x_name = info.x_name.to_string();
         let y_name = self.append_column_name(&info.y_name);
 
         OperateResult {
             subquery: format!(
-                "t{} AS (SELECT \"{}\", \"{}\" as \"{}\" FROM {} WHERE \"{}\" IS NOT NULL AND \"{}\" NOT IN ('-nan', 'nan', 'inf', '-inf'))",
+                "t{} AS (SELECT \"{}\", \"{}\" AS \"{}\" FROM (SELECT \"{}\", \"{}\", row_number() over () - 1 AS i, count(*) over () AS len FROM {}) WHERE i % ceil(len / {}::DOUBLE)::BIGINT = 0 ORDER BY i)",
                 info.tmp_table_num,
                 info.x_name,
                 info.y_name,
                 y_name,
-                info.src_table,
-                info.y_name,
+                info.x_name,
                 info.y_name,
+                info.src_table,
+                self.0,
             ),
             x_name,
             y_name,
@@ -298,22 +805,64 @@ impl Operator for FilterFiniteOperator {
     }
 }
 
-declare_operator_no_param!(IntegralOperator);
+declare_operator_with_single_arg!(ExpOperator, LogScale);
+
+impl Operator for ExpOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        Ok(map_axis(points, self.0.x_axis, |v| self.0.base.powf(v)))
+    }
 
-impl Operator for IntegralOperator {
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
-        let x_name = info.x_name.to_string();
+        map_axis_sql(self, info, self.0.x_axis, |name| {
+            format!("pow({}, \"{}\")", self.0.base, name)
+        })
+    }
+}
+
+declare_operator_no_param!(FFTOperator, 'F');
+
+impl Operator for FFTOperator {
+    // y is assumed to be sampled at a uniform interval of x, the interval
+    // being derived from the range of x; missing values count as 0, as they
+    // are left out of the sums of duckdb
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        let n = points.len();
+        if n < 2 {
+            return Ok(vec![]);
+        }
+        let (min, max) = points
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &(x, _)| {
+                (min.min(x), max.max(x))
+            });
+        let interval = (max - min) / (n - 1) as f64;
+        let values = points
+            .iter()
+            .map(|(_, y)| if y.is_nan() { 0.0 } else { *y })
+            .collect::<Vec<_>>();
+        Ok(crate::spectrum::amplitude_spectrum(&values)
+            .into_iter()
+            .enumerate()
+            .map(|(k, amplitude)| (k as f64 / (n as f64 * interval), amplitude))
+            .collect())
+    }
+
+    // direct DFT, whose cost is quadratic in the number of records
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = "frequency".to_string();
         let y_name = self.append_column_name(&info.y_name);
 
         OperateResult {
             subquery: format!(
-                "t{} AS (SELECT \"{}\", sum(\"{}\") over w as \"{}\" FROM {} WINDOW w AS (ORDER BY \"{}\"))",
+                "t{} AS (SELECT k / (n * dt) AS \"{}\", CASE WHEN k = 0 OR 2 * k = n THEN 1 ELSE 2 END * sqrt(pow(sum(y * cos(2 * pi() * j * k / n)), 2) + pow(sum(y * sin(2 * pi() * j * k / n)), 2)) / n AS \"{}\" FROM (SELECT \"{}\" AS y, row_number() over () - 1 AS j, count(*) over () AS n, (max(\"{}\") over () - min(\"{}\") over ()) / (count(*) over () - 1) AS dt FROM {}), (SELECT unnest(range(0, floor(count(*) / 2)::BIGINT + 1)) AS k FROM {} HAVING count(*) > 1) GROUP BY k, n, dt ORDER BY k)",
                 info.tmp_table_num,
-                info.x_name,
-                info.y_name,
+                x_name,
                 y_name,
-                info.src_table,
+                info.y_name,
+                info.x_name,
                 info.x_name,
+                info.src_table,
+                info.src_table,
             ),
             x_name,
             y_name,
@@ -321,22 +870,28 @@ impl Operator for IntegralOperator {
     }
 }
 
-declare_operator_no_param!(MergeOperator);
+declare_operator_no_param!(FilterFiniteOperator);
+
+impl Operator for FilterFiniteOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        points.retain(|(_, y)| y.is_finite());
+        Ok(points)
+    }
 
-impl Operator for MergeOperator {
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
         let x_name = info.x_name.to_string();
         let y_name = self.append_column_name(&info.y_name);
 
         OperateResult {
             subquery: format!(
-                "t{} AS (SELECT \"{}\", sum(\"{}\") as \"{}\" FROM {} GROUP BY \"{}\")",
+                "t{} AS (SELECT \"{}\", \"{}\" as \"{}\" FROM {} WHERE \"{}\" IS NOT NULL AND \"{}\" NOT IN ('-nan', 'nan', 'inf', '-inf'))",
                 info.tmp_table_num,
                 info.x_name,
                 info.y_name,
                 y_name,
                 info.src_table,
-                info.x_name,
+                info.y_name,
+                info.y_name,
             ),
             x_name,
             y_name,
@@ -344,21 +899,89 @@ impl Operator for MergeOperator {
     }
 }
 
-declare_operator_no_param!(OrderOperator);
+/// Difference of the consecutive x values (_e.g._ inter-arrival times of
+/// event timestamps) as y, x being unchanged (`g` or `g0`) or replaced with
+/// the index of the record (`g1`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GapOperator {
+    Keep,
+    Index,
+}
+
+impl Display for GapOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GapOperator::Keep => write!(f, "g"),
+            GapOperator::Index => write!(f, "g1"),
+        }
+    }
+}
+
+impl TryFrom<Op> for GapOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        if op.op != 'g' {
+            bail!("GapOperator only accepts 'g' as operator");
+        }
+        match op.arg.as_slice() {
+            [] | [0.0] => Ok(GapOperator::Keep),
+            [1.0] => Ok(GapOperator::Index),
+            _ => bail!(
+                "GapOperator only accepts mode 0 (keep x) or 1 (index as x)"
+            ),
+        }
+    }
+}
+
+impl GapOperator {
+    // `eval` of the points following `last`, only meaningful if x is kept
+    fn eval_after(
+        &self,
+        points: Vec<(f64, f64)>,
+        last: Option<(f64, f64)>,
+    ) -> Vec<(f64, f64)> {
+        let mut prev_x = last.map_or(f64::NAN, |(x, _)| x);
+        points
+            .into_iter()
+            .enumerate()
+            .map(|(i, (x, _))| {
+                let gap = x - std::mem::replace(&mut prev_x, x);
+                match self {
+                    GapOperator::Keep => (x, gap),
+                    GapOperator::Index => (i as f64, gap),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Operator for GapOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        Ok(self.eval_after(points, None))
+    }
 
-impl Operator for OrderOperator {
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
-        let x_name = info.x_name.to_string();
+        let x_name = match self {
+            GapOperator::Keep => info.x_name.to_string(),
+            GapOperator::Index => "index".to_string(),
+        };
         let y_name = self.append_column_name(&info.y_name);
+        let x = match self {
+            GapOperator::Keep => format!("\"{}\"", info.x_name),
+            GapOperator::Index => "row_number() over () - 1".to_string(),
+        };
 
         OperateResult {
             subquery: format!(
-                "t{} AS (SELECT \"{}\", \"{}\" FROM {} ORDER BY \"{}\")",
+                "t{} AS (SELECT {} AS \"{}\", \"{}\" - lag(\"{}\") over () AS \"{}\" FROM {})",
                 info.tmp_table_num,
+                x,
+                x_name,
                 info.x_name,
-                info.y_name,
-                info.src_table,
                 info.x_name,
+                y_name,
+                info.src_table,
             ),
             x_name,
             y_name,
@@ -366,21 +989,947 @@ impl Operator for OrderOperator {
     }
 }
 
-declare_operator_no_param!(StepOperator);
+declare_operator_no_param!(IntegralOperator);
+
+impl Operator for IntegralOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_by_x(&mut points);
+        let mut sums = points
+            .iter()
+            .scan(0.0, |sum, (_, y)| {
+                *sum += y;
+                Some(*sum)
+            })
+            .collect::<Vec<_>>();
+        // the default window frame includes all peers (equal x values)
+        for i in (1..points.len()).rev() {
+            if points[i - 1].0 == points[i].0 {
+                sums[i - 1] = sums[i];
+            }
+        }
+        Ok(points.iter().map(|(x, _)| *x).zip(sums).collect())
+    }
 
-impl Operator for StepOperator {
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
         let x_name = info.x_name.to_string();
         let y_name = self.append_column_name(&info.y_name);
 
         OperateResult {
             subquery: format!(
-                "t{} AS (SELECT \"{}\", \"{}\" - lag(\"{}\") over () FROM {})",
+                "t{} AS (SELECT \"{}\", sum(\"{}\") over w as \"{}\" FROM {} WINDOW w AS (ORDER BY \"{}\"))",
                 info.tmp_table_num,
                 info.x_name,
                 info.y_name,
-                info.y_name,
+                y_name,
+                info.src_table,
+                info.x_name,
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+/// The k records with the largest y values (`k<k>` or `k<k>,0`), or with
+/// the smallest ones (`k<k>,1`), in their original order; ties are broken in
+/// favor of the earlier records, and missing values come last in either order
+#[derive(Debug, Clone)]
+pub struct TopKOperator {
+    pub(crate) k: usize,
+    smallest: bool,
+}
+
+impl Display for TopKOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.smallest {
+            write!(f, "k{},1", self.k)
+        } else {
+            write!(f, "k{}", self.k)
+        }
+    }
+}
+
+impl TryFrom<Op> for TopKOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        if op.op != 'k' {
+            bail!("TopKOperator only accepts 'k' as operator");
+        }
+        let smallest = match op.arg.get(1) {
+            None | Some(0.0) => false,
+            Some(1.0) => true,
+            Some(_) => bail!(
+                "TopKOperator only accepts order 0 (largest) or 1 (smallest)"
+            ),
+        };
+        match op.arg.first() {
+            Some(&k)
+                if k >= 0.0
+                    && k.fract() == 0.0
+                    && k <= usize::MAX as f64
+                    && op.arg.len() <= 2 =>
+            {
+                Ok(Self {
+                    k: k as usize,
+                    smallest,
+                })
+            }
+            _ => bail!(
+                "TopKOperator only accepts a non-negative integer number of records and an order"
+            ),
+        }
+    }
+}
+
+impl Operator for TopKOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        let mut indexes = (0..points.len()).collect::<Vec<_>>();
+        indexes.sort_by(|&a, &b| {
+            let (ya, yb) = (points[a].1, points[b].1);
+            let order = ya.total_cmp(&yb);
+            ya.is_nan()
+                .cmp(&yb.is_nan())
+                .then(if self.smallest {
+                    order
+                } else {
+                    order.reverse()
+                })
+                .then(a.cmp(&b))
+        });
+        indexes.truncate(self.k);
+        indexes.sort();
+        Ok(indexes.into_iter().map(|i| points[i]).collect())
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = self.append_column_name(&info.y_name);
+
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT \"{}\", \"{}\" AS \"{}\" FROM (SELECT \"{}\", \"{}\", row_number() over () AS i FROM {}) QUALIFY row_number() over (ORDER BY \"{}\" {} NULLS LAST, i) <= {} ORDER BY i)",
+                info.tmp_table_num,
+                info.x_name,
+                info.y_name,
+                y_name,
+                info.x_name,
+                info.y_name,
+                info.src_table,
+                info.y_name,
+                if self.smallest { "ASC" } else { "DESC" },
+                self.k,
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+declare_operator_with_single_arg!(LogOperator, LogScale);
+
+impl Operator for LogOperator {
+    // non-positive values have no logarithm and become NaN
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        Ok(map_axis(points, self.0.x_axis, |v| {
+            if v > 0.0 {
+                v.log(self.0.base)
+            } else {
+                f64::NAN
+            }
+        }))
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        map_axis_sql(self, info, self.0.x_axis, |name| {
+            format!(
+                "CASE WHEN \"{}\" <= 0 THEN 'nan'::DOUBLE ELSE ln(\"{}\") / ln({}) END",
+                name, name, self.0.base
+            )
+        })
+    }
+}
+
+declare_operator_with_single_arg!(MedianOperator, RelativeRange, 'M');
+
+impl Operator for MedianOperator {
+    // the window is clipped at the edges, and the median of an even number
+    // of points is the mean of the middle two, like `median` of duckdb,
+    // which also skips missing values
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_by_x(&mut points);
+        Ok(map_points(&points, |i| {
+            let x = points[i].0;
+            let mut window =
+                present_values(self.0.window(&points, x)).collect::<Vec<_>>();
+            window.sort_by(f64::total_cmp);
+            let mid = window.len() / 2;
+            let median = if window.is_empty() {
+                f64::NAN
+            } else if window.len().is_multiple_of(2) {
+                (window[mid - 1] + window[mid]) / 2.0
+            } else {
+                window[mid]
+            };
+            (x, median)
+        }))
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        self.0.to_window_sql(self, info, "median")
+    }
+}
+
+declare_operator_no_param!(MergeOperator);
+
+impl Operator for MergeOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_by_x(&mut points);
+        let mut merged: Vec<(f64, f64)> = Vec::new();
+        for (x, y) in points {
+            match merged.last_mut() {
+                Some(last) if last.0 == x => last.1 += y,
+                _ => merged.push((x, y)),
+            }
+        }
+        Ok(merged)
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = self.append_column_name(&info.y_name);
+
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT \"{}\", sum(\"{}\") as \"{}\" FROM {} GROUP BY \"{}\")",
+                info.tmp_table_num,
+                info.x_name,
+                info.y_name,
+                y_name,
+                info.src_table,
+                info.x_name,
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+/// Rescaling of y to [0, 1] (`n` or `n0`) or to zero mean and unit
+/// (population) standard deviation (`n1`), constant y becoming 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalizeOperator {
+    MinMax,
+    ZScore,
+}
+
+impl Display for NormalizeOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NormalizeOperator::MinMax => write!(f, "n"),
+            NormalizeOperator::ZScore => write!(f, "n1"),
+        }
+    }
+}
+
+impl TryFrom<Op> for NormalizeOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        if op.op != 'n' {
+            bail!("NormalizeOperator only accepts 'n' as operator");
+        }
+        match op.arg.as_slice() {
+            [] | [0.0] => Ok(NormalizeOperator::MinMax),
+            [1.0] => Ok(NormalizeOperator::ZScore),
+            _ => bail!(
+                "NormalizeOperator only accepts mode 0 (min-max) or 1 (z-score)"
+            ),
+        }
+    }
+}
+
+impl Operator for NormalizeOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        let (offset, scale) = match self {
+            NormalizeOperator::MinMax => {
+                let (min, max) = points.iter().fold(
+                    (f64::INFINITY, f64::NEG_INFINITY),
+                    |(min, max), &(_, y)| (min.min(y), max.max(y)),
+                );
+                (min, max - min)
+            }
+            NormalizeOperator::ZScore => {
+                let mean = mean_of_present(&points);
+                let (sum, n) = present_values(&points)
+                    .fold((0.0, 0usize), |(sum, n), y| {
+                        (sum + (y - mean).powi(2), n + 1)
+                    });
+                (mean, (sum / n as f64).sqrt())
+            }
+        };
+        Ok(points
+            .into_iter()
+            .map(|(x, y)| {
+                (
+                    x,
+                    if scale == 0.0 {
+                        0.0
+                    } else {
+                        (y - offset) / scale
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = self.append_column_name(&info.y_name);
+        let (offset, scale) = match self {
+            NormalizeOperator::MinMax => (
+                format!("min(\"{}\") over ()", info.y_name),
+                format!(
+                    "max(\"{}\") over () - min(\"{}\") over ()",
+                    info.y_name, info.y_name
+                ),
+            ),
+            NormalizeOperator::ZScore => (
+                format!("avg(\"{}\") over ()", info.y_name),
+                format!("stddev_pop(\"{}\") over ()", info.y_name),
+            ),
+        };
+
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT \"{}\", CASE WHEN {} = 0 THEN 0 ELSE (\"{}\" - {}) / ({}) END AS \"{}\" FROM {})",
+                info.tmp_table_num,
+                info.x_name,
+                scale,
+                info.y_name,
+                offset,
+                scale,
+                y_name,
+                info.src_table,
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+/// Records sorted by x (`o` or `o0`) or y (`o1`), in ascending or
+/// descending (second argument 1) order. Records of equal keys keep their
+/// order natively, and in SQL only if stable (third argument 1), which
+/// costs an extra column of row numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OrderOperator {
+    pub(crate) by_y: bool,
+    pub(crate) descending: bool,
+    stable: bool,
+}
+
+impl Display for OrderOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flag = |b: bool| if b { 1 } else { 0 };
+        if self.stable {
+            write!(f, "o{},{},1", flag(self.by_y), flag(self.descending))
+        } else if self.descending {
+            write!(f, "o{},1", flag(self.by_y))
+        } else if self.by_y {
+            write!(f, "o1")
+        } else {
+            write!(f, "o")
+        }
+    }
+}
+
+impl TryFrom<Op> for OrderOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        if op.op != 'o' {
+            bail!("OrderOperator only accepts 'o' as operator");
+        }
+        let flag = |i: usize, what: &str| match op.arg.get(i) {
+            None | Some(0.0) => Ok(false),
+            Some(1.0) => Ok(true),
+            Some(_) => Err(anyhow!("OrderOperator only accepts {what}")),
+        };
+        if op.arg.len() > 3 {
+            bail!(
+                "OrderOperator accepts at most a key, an order and a stability"
+            );
+        }
+        Ok(Self {
+            by_y: flag(0, "key 0 (x) or 1 (y)")?,
+            descending: flag(1, "order 0 (ascending) or 1 (descending)")?,
+            stable: flag(2, "stability 0 or 1")?,
+        })
+    }
+}
+
+impl OrderOperator {
+    /// Whether the records are sorted by x in ascending order afterwards
+    pub(crate) fn sorts_by_x(&self) -> bool {
+        !self.by_y && !self.descending
+    }
+
+    /// Key of a point, negated for descending order
+    pub(crate) fn key(&self) -> fn(&(f64, f64)) -> f64 {
+        match (self.by_y, self.descending) {
+            (false, false) => |p| p.0,
+            (false, true) => |p| -p.0,
+            (true, false) => |p| p.1,
+            (true, true) => |p| -p.1,
+        }
+    }
+}
+
+impl Operator for OrderOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_points_by(&mut points, self.key());
+        Ok(points)
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = self.append_column_name(&info.y_name);
+        let key = format!(
+            "\"{}\"{}",
+            if self.by_y {
+                &info.y_name
+            } else {
+                &info.x_name
+            },
+            if self.descending { " DESC" } else { "" }
+        );
+
+        OperateResult {
+            subquery: if self.stable {
+                format!(
+                    "t{} AS (SELECT \"{}\", \"{}\" AS \"{}\" FROM (SELECT *, row_number() over () AS i FROM {}) ORDER BY {}, i)",
+                    info.tmp_table_num,
+                    info.x_name,
+                    info.y_name,
+                    y_name,
+                    info.src_table,
+                    key,
+                )
+            } else {
+                format!(
+                    "t{} AS (SELECT \"{}\", \"{}\" AS \"{}\" FROM {} ORDER BY {})",
+                    info.tmp_table_num,
+                    info.x_name,
+                    info.y_name,
+                    y_name,
+                    info.src_table,
+                    key,
+                )
+            },
+            x_name,
+            y_name,
+        }
+    }
+}
+
+/// Percentiles (between 0 and 100) of y, interpolated linearly between the
+/// closest ranks like `quantile_cont` of duckdb
+#[derive(Debug, Clone)]
+pub struct QuantileOperator(Vec<f64>);
+
+impl Display for QuantileOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "q{}",
+            self.0
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+impl TryFrom<Op> for QuantileOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        if op.op != 'q' {
+            bail!("QuantileOperator only accepts 'q' as operator");
+        }
+        if op.arg.is_empty() {
+            bail!("QuantileOperator needs at least one percentile");
+        }
+        if op.arg.iter().any(|p| !(0.0..=100.0).contains(p)) {
+            bail!(
+                "QuantileOperator only accepts percentiles between 0 and 100"
+            );
+        }
+        Ok(Self(op.arg))
+    }
+}
+
+impl QuantileOperator {
+    // ranks between which the percentile `p` of `len` sorted values lies,
+    // with the fraction of the way from the first one to the second one
+    fn rank(len: usize, p: f64) -> (usize, usize, f64) {
+        let rank = (len - 1) as f64 * p / 100.0;
+        (rank.floor() as usize, rank.ceil() as usize, rank.fract())
+    }
+
+    /// Ranks of the values needed among `len` values sorted by y
+    #[cfg(feature = "cli")]
+    pub(crate) fn ranks(&self, len: usize) -> Vec<usize> {
+        if len == 0 {
+            return vec![];
+        }
+        self.0
+            .iter()
+            .flat_map(|&p| {
+                let (lo, hi, _) = Self::rank(len, p);
+                [lo, hi]
+            })
+            .collect()
+    }
+
+    /// Percentiles of `len` values sorted by y, `value` giving the y of
+    /// each rank of `ranks`
+    pub(crate) fn eval_sorted(
+        &self,
+        len: usize,
+        value: impl Fn(usize) -> f64,
+    ) -> Vec<(f64, f64)> {
+        if len == 0 {
+            return vec![];
+        }
+        self.0
+            .iter()
+            .map(|&p| {
+                let (lo, hi, fraction) = Self::rank(len, p);
+                let (lo, hi) = (value(lo), value(hi));
+                (
+                    p,
+                    if fraction == 0.0 {
+                        lo
+                    } else {
+                        lo + fraction * (hi - lo)
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+impl Operator for QuantileOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        // missing values are no values, as in quantile_cont
+        points.retain(|p| !p.1.is_nan());
+        sort_points_by(&mut points, |p| p.1);
+        Ok(self.eval_sorted(points.len(), |i| points[i].1))
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = "percentile".to_string();
+        let y_name = self.append_column_name(&info.y_name);
+        let list = |values: Vec<String>| format!("[{}]", values.join(", "));
+
+        // a table without values has no quantiles rather than NULL ones
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT unnest({}) AS \"{}\", unnest(q) AS \"{}\" FROM (SELECT quantile_cont(\"{}\", {}) AS q FROM {} HAVING count(\"{}\") > 0))",
+                info.tmp_table_num,
+                list(self.0.iter().map(|p| p.to_string()).collect()),
+                x_name,
+                y_name,
+                info.y_name,
+                list(self.0.iter().map(|p| (p / 100.0).to_string()).collect()),
+                info.src_table,
+                info.y_name,
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+// Regular grid of x values `start + k * step` (integer k)
+#[derive(Debug, Clone)]
+struct Grid {
+    start: f64,
+    step: f64,
+}
+
+impl Display for Grid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.start, self.step)
+    }
+}
+
+impl Grid {
+    fn from_args(args: &[f64]) -> anyhow::Result<Self> {
+        match *args {
+            [start, step]
+                if start.is_finite() && step.is_finite() && step > 0.0 =>
+            {
+                Ok(Self { start, step })
+            }
+            _ => bail!(
+                "Grid only accepts a finite start and a positive finite step"
+            ),
+        }
+    }
+}
+
+declare_operator_with_single_arg!(ResampleOperator, Grid);
+
+impl Operator for ResampleOperator {
+    // the grid points between the smallest and the largest x, y being
+    // interpolated between the last record at or before and the first record
+    // after them
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_by_x(&mut points);
+        let (Some(first), Some(last)) = (points.first(), points.last()) else {
+            return Ok(vec![]);
+        };
+        let Grid { start, step } = self.0;
+        let k_first = ((first.0 - start) / step).ceil().max(0.0) as i64;
+        let k_last = ((last.0 - start) / step).floor() as i64;
+        Ok((k_first..=k_last)
+            .map(|k| {
+                let x = start + k as f64 * step;
+                // rounding might put the first grid point right before the
+                // first record
+                let hi = points.partition_point(|p| p.0 <= x).max(1);
+                let lo = points[hi - 1];
+                match points.get(hi) {
+                    Some(hi) => {
+                        (x, lo.1 + (hi.1 - lo.1) * (x - lo.0) / (hi.0 - lo.0))
+                    }
+                    None => (x, lo.1),
+                }
+            })
+            .collect())
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = self.append_column_name(&info.y_name);
+        let Grid { start, step } = self.0;
+        let (x, y) = (&info.x_name, &info.y_name);
+
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT g AS \"{x}\", CASE WHEN hi.\"{x}\" IS NULL THEN lo.\"{y}\" ELSE lo.\"{y}\" + (hi.\"{y}\" - lo.\"{y}\") * (g - lo.\"{x}\") / (hi.\"{x}\" - lo.\"{x}\") END AS \"{}\" FROM (SELECT ({start} + k * {step})::DOUBLE AS g FROM (SELECT unnest(range(greatest(ceil((min(\"{x}\") - {start}) / {step}), 0)::BIGINT, floor((max(\"{x}\") - {start}) / {step})::BIGINT + 1)) AS k FROM {})) ASOF JOIN {} lo ON g >= lo.\"{x}\" ASOF LEFT JOIN {} hi ON g < hi.\"{x}\" ORDER BY g)",
+                info.tmp_table_num,
+                y_name,
+                info.src_table,
+                info.src_table,
+                info.src_table,
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+/// Least-squares fit of y = a * x + b, replacing y with the fitted line
+/// (`R` or `R0`) or the residuals (`R1`), or the table with the parameters
+/// (`R2`): x = 0, 1 and 2 for a, b and R^2 respectively
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegressionOperator {
+    Fit,
+    Residuals,
+    Parameters,
+}
+
+impl Display for RegressionOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegressionOperator::Fit => write!(f, "R"),
+            RegressionOperator::Residuals => write!(f, "R1"),
+            RegressionOperator::Parameters => write!(f, "R2"),
+        }
+    }
+}
+
+impl TryFrom<Op> for RegressionOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        if op.op != 'R' {
+            bail!("RegressionOperator only accepts 'R' as operator");
+        }
+        match op.arg.as_slice() {
+            [] | [0.0] => Ok(RegressionOperator::Fit),
+            [1.0] => Ok(RegressionOperator::Residuals),
+            [2.0] => Ok(RegressionOperator::Parameters),
+            _ => bail!(
+                "RegressionOperator only accepts mode 0 (fit), 1 (residuals) or 2 (parameters)"
+            ),
+        }
+    }
+}
+
+impl RegressionOperator {
+    // slope, intercept and R^2 like `regr_slope`, `regr_intercept` and
+    // `regr_r2` of duckdb, NaN where they are NULL; like them, points with a
+    // missing x or y are left out
+    fn fit(points: &[(f64, f64)]) -> (f64, f64, f64) {
+        let points = points
+            .iter()
+            .filter(|(x, y)| !x.is_nan() && !y.is_nan())
+            .collect::<Vec<_>>();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let (sxx, sxy, syy) =
+            points
+                .iter()
+                .fold((0.0, 0.0, 0.0), |(sxx, sxy, syy), (x, y)| {
+                    let (dx, dy) = (x - mean_x, y - mean_y);
+                    (sxx + dx * dx, sxy + dx * dy, syy + dy * dy)
+                });
+        if sxx == 0.0 {
+            return (f64::NAN, f64::NAN, f64::NAN);
+        }
+        let slope = sxy / sxx;
+        let r2 = if syy == 0.0 {
+            1.0
+        } else {
+            sxy * sxy / (sxx * syy)
+        };
+        (slope, mean_y - slope * mean_x, r2)
+    }
+}
+
+impl Operator for RegressionOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        if points.is_empty() {
+            return Ok(vec![]);
+        }
+        let (a, b, r2) = Self::fit(&points);
+        tracing::info!(a, b, r2, "Linear fit y = a * x + b");
+        Ok(match self {
+            RegressionOperator::Fit => {
+                points.into_iter().map(|(x, _)| (x, a * x + b)).collect()
+            }
+            RegressionOperator::Residuals => points
+                .into_iter()
+                .map(|(x, y)| (x, y - (a * x + b)))
+                .collect(),
+            RegressionOperator::Parameters => {
+                vec![(0.0, a), (1.0, b), (2.0, r2)]
+            }
+        })
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let y_name = self.append_column_name(&info.y_name);
+        let regr = |f: &str| {
+            format!("regr_{}(\"{}\", \"{}\")", f, info.y_name, info.x_name)
+        };
+        let line = format!(
+            "{} over () * \"{}\" + {} over ()",
+            regr("slope"),
+            info.x_name,
+            regr("intercept")
+        );
+        let (x_name, subquery) = match self {
+            RegressionOperator::Fit => (
+                info.x_name.to_string(),
+                format!(
+                    "SELECT \"{}\", {} AS \"{}\" FROM {}",
+                    info.x_name, line, y_name, info.src_table
+                ),
+            ),
+            RegressionOperator::Residuals => (
+                info.x_name.to_string(),
+                format!(
+                    "SELECT \"{}\", \"{}\" - ({}) AS \"{}\" FROM {}",
+                    info.x_name, info.y_name, line, y_name, info.src_table
+                ),
+            ),
+            RegressionOperator::Parameters => (
+                "parameter".to_string(),
+                format!(
+                    "SELECT unnest([0, 1, 2]) AS \"parameter\", unnest([{}, {}, {}]) AS \"{}\" FROM {} HAVING count(*) > 0",
+                    regr("slope"),
+                    regr("intercept"),
+                    regr("r2"),
+                    y_name,
+                    info.src_table
+                ),
+            ),
+        };
+
+        OperateResult {
+            subquery: format!("t{} AS ({})", info.tmp_table_num, subquery),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+declare_operator_no_param!(StepOperator);
+
+impl StepOperator {
+    // `eval` of the points following `last`
+    fn eval_after(
+        &self,
+        points: Vec<(f64, f64)>,
+        last: Option<(f64, f64)>,
+    ) -> Vec<(f64, f64)> {
+        let mut prev_y = last.map_or(f64::NAN, |(_, y)| y);
+        points
+            .into_iter()
+            .map(|(x, y)| (x, y - std::mem::replace(&mut prev_y, y)))
+            .collect()
+    }
+}
+
+impl Operator for StepOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        Ok(self.eval_after(points, None))
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = self.append_column_name(&info.y_name);
+
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT \"{}\", \"{}\" - lag(\"{}\") over () FROM {})",
+                info.tmp_table_num,
+                info.x_name,
+                info.y_name,
+                info.y_name,
+                info.src_table,
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+// rolling maximum
+declare_operator_with_single_arg!(TopOperator, RelativeRange);
+
+impl Operator for TopOperator {
+    fn eval(&self, mut points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        sort_by_x(&mut points);
+        Ok(map_points(&points, |i| {
+            let x = points[i].0;
+            let window = self.0.window(&points, x);
+            // missing values are skipped like by `max` of duckdb
+            (
+                x,
+                present_values(window)
+                    .max_by(f64::total_cmp)
+                    .unwrap_or(f64::NAN),
+            )
+        }))
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        self.0.to_window_sql(self, info, "max")
+    }
+}
+
+/// One record for each distinct x value, in the order of their first
+/// occurrences, keeping the first y (`u` or `u0`), the last y (`u1`) or the
+/// mean of the y values (`u2`) of the x value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniqueOperator {
+    First,
+    Last,
+    Mean,
+}
+
+impl Display for UniqueOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UniqueOperator::First => write!(f, "u"),
+            UniqueOperator::Last => write!(f, "u1"),
+            UniqueOperator::Mean => write!(f, "u2"),
+        }
+    }
+}
+
+impl TryFrom<Op> for UniqueOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        if op.op != 'u' {
+            bail!("UniqueOperator only accepts 'u' as operator");
+        }
+        match op.arg.as_slice() {
+            [] | [0.0] => Ok(UniqueOperator::First),
+            [1.0] => Ok(UniqueOperator::Last),
+            [2.0] => Ok(UniqueOperator::Mean),
+            _ => bail!(
+                "UniqueOperator only accepts mode 0 (first), 1 (last) or 2 (mean)"
+            ),
+        }
+    }
+}
+
+impl Operator for UniqueOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        // index in `unique` of each x value, with the number of its records
+        let mut index = HashMap::new();
+        let mut unique: Vec<(f64, f64, usize)> = Vec::new();
+        for (x, y) in points {
+            match index.get(&x.to_bits()) {
+                Some(&i) => {
+                    let (_, ref mut acc, ref mut count) = unique[i];
+                    *count += 1;
+                    match self {
+                        UniqueOperator::First => {}
+                        UniqueOperator::Last => *acc = y,
+                        UniqueOperator::Mean => *acc += y,
+                    }
+                }
+                None => {
+                    index.insert(x.to_bits(), unique.len());
+                    unique.push((x, y, 1));
+                }
+            }
+        }
+        Ok(unique
+            .into_iter()
+            .map(|(x, y, count)| match self {
+                UniqueOperator::Mean => (x, y / count as f64),
+                _ => (x, y),
+            })
+            .collect())
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = self.append_column_name(&info.y_name);
+        let aggregate = match self {
+            UniqueOperator::First => {
+                format!("first(\"{}\" ORDER BY i)", info.y_name)
+            }
+            UniqueOperator::Last => {
+                format!("last(\"{}\" ORDER BY i)", info.y_name)
+            }
+            UniqueOperator::Mean => format!("avg(\"{}\")", info.y_name),
+        };
+
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT \"{}\", {} AS \"{}\" FROM (SELECT \"{}\", \"{}\", row_number() over () AS i FROM {}) GROUP BY \"{}\" ORDER BY min(i))",
+                info.tmp_table_num,
+                info.x_name,
+                aggregate,
+                y_name,
+                info.x_name,
+                info.y_name,
                 info.src_table,
+                info.x_name,
             ),
             x_name,
             y_name,
@@ -388,24 +1937,104 @@ impl Operator for StepOperator {
     }
 }
 
-declare_operator_no_param!(UniqueOperator);
+declare_operator_with_single_arg!(XAffineOperator, Affine, 'x');
+
+impl Operator for XAffineOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        Ok(map_axis(points, true, |v| self.0.apply(v)))
+    }
 
-impl Operator for UniqueOperator {
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
-        let x_name = info.x_name.to_string();
-        let y_name = self.append_column_name(&info.y_name);
+        map_axis_sql(self, info, true, |name| self.0.to_sql(name))
+    }
+}
+
+declare_operator_with_single_arg!(YAffineOperator, Affine, 'y');
+
+impl Operator for YAffineOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        Ok(map_axis(points, false, |v| self.0.apply(v)))
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        map_axis_sql(self, info, false, |name| self.0.to_sql(name))
+    }
+}
+
+// Identifier of a checkpoint, 0 by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CheckpointId(usize);
+
+impl Display for CheckpointId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl CheckpointId {
+    fn from_args(args: &[f64]) -> anyhow::Result<Self> {
+        match *args {
+            [] => Ok(Self(0)),
+            [id] if id >= 0.0 && id.fract() == 0.0 && id <= u32::MAX as f64 => {
+                Ok(Self(id as usize))
+            }
+            _ => bail!("CheckpointId only accepts a non-negative integer"),
+        }
+    }
+
+    // CTE holding the records saved at the checkpoint
+    fn table_name(&self) -> String {
+        format!("checkpoint{}", self.0)
+    }
+}
+
+/// Records saved by the checkpoint operators while an operation sequence is
+/// evaluated
+pub(crate) type Checkpoints = HashMap<CheckpointId, Vec<(f64, f64)>>;
 
+declare_operator_with_single_arg!(CheckpointOperator, CheckpointId, 'S');
+
+impl Operator for CheckpointOperator {
+    // the records pass through unchanged, they are saved by `eval_in_seq`
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        Ok(points)
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
         OperateResult {
             subquery: format!(
-                "t{} AS (SELECT first(\"{}\"), first(\"{}\") FROM {} GROUP BY \"{}\")",
+                "t{} AS (SELECT \"{}\", \"{}\" FROM {}),\n{} AS (SELECT \"{}\" AS x, \"{}\" AS y FROM {})",
                 info.tmp_table_num,
                 info.x_name,
                 info.y_name,
                 info.src_table,
+                self.0.table_name(),
                 info.x_name,
+                info.y_name,
+                info.src_table,
             ),
-            x_name,
-            y_name,
+            x_name: info.x_name.to_string(),
+            y_name: info.y_name.to_string(),
+        }
+    }
+}
+
+declare_operator_with_single_arg!(RestoreOperator, CheckpointId, 'L');
+
+impl Operator for RestoreOperator {
+    fn eval(&self, _points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        bail!("Operator {self} only restores records in an operation sequence")
+    }
+
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT x, y FROM {})",
+                info.tmp_table_num,
+                self.0.table_name(),
+            ),
+            x_name: "x".to_string(),
+            y_name: "y".to_string(),
         }
     }
 }
@@ -413,6 +2042,10 @@ impl Operator for UniqueOperator {
 declare_operator_no_param!(FinalizeOperator);
 
 impl Operator for FinalizeOperator {
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        Ok(points)
+    }
+
     fn to_sql(&self, info: &OperateInfo) -> OperateResult {
         let x_name = "x".to_string();
         let y_name = "y".to_string();
@@ -433,57 +2066,601 @@ pub enum GenericOperator {
     #[strum(to_string = "{0}")]
     Average(AverageOperator),
     #[strum(to_string = "{0}")]
+    Bottom(BottomOperator),
+    #[strum(to_string = "{0}")]
     Cdf(CDFOperator),
     #[strum(to_string = "{0}")]
     Derivative(DerivativeOperator),
     #[strum(to_string = "{0}")]
+    Downsample(DownsampleOperator),
+    #[strum(to_string = "{0}")]
+    Exp(ExpOperator),
+    #[strum(to_string = "{0}")]
+    Fft(FFTOperator),
+    #[strum(to_string = "{0}")]
     FilterFinite(FilterFiniteOperator),
     #[strum(to_string = "{0}")]
+    Gap(GapOperator),
+    #[strum(to_string = "{0}")]
     Integral(IntegralOperator),
     #[strum(to_string = "{0}")]
+    TopK(TopKOperator),
+    #[strum(to_string = "{0}")]
+    Log(LogOperator),
+    #[strum(to_string = "{0}")]
+    Median(MedianOperator),
+    #[strum(to_string = "{0}")]
     Merge(MergeOperator),
     #[strum(to_string = "{0}")]
+    Normalize(NormalizeOperator),
+    #[strum(to_string = "{0}")]
     Order(OrderOperator),
     #[strum(to_string = "{0}")]
+    Quantile(QuantileOperator),
+    #[strum(to_string = "{0}")]
+    Resample(ResampleOperator),
+    #[strum(to_string = "{0}")]
+    Restore(RestoreOperator),
+    #[strum(to_string = "{0}")]
+    Regression(RegressionOperator),
+    #[strum(to_string = "{0}")]
+    Checkpoint(CheckpointOperator),
+    #[strum(to_string = "{0}")]
     Step(StepOperator),
     #[strum(to_string = "{0}")]
+    Top(TopOperator),
+    #[strum(to_string = "{0}")]
     Unique(UniqueOperator),
+    #[strum(to_string = "{0}")]
+    XAffine(XAffineOperator),
+    #[strum(to_string = "{0}")]
+    YAffine(YAffineOperator),
+    #[strum(to_string = "{0}")]
+    Registered(RegisteredOperator),
     Finalize(FinalizeOperator),
 }
 
-impl TryFrom<Op> for GenericOperator {
-    type Error = anyhow::Error;
-    fn try_from(op: Op) -> Result<Self, Self::Error> {
-        match op.op {
-            'a' => Ok(GenericOperator::Average(op.try_into()?)),
-            'c' => Ok(GenericOperator::Cdf(op.try_into()?)),
-            'd' => Ok(GenericOperator::Derivative(op.try_into()?)),
-            'f' => Ok(GenericOperator::FilterFinite(op.try_into()?)),
-            'i' => Ok(GenericOperator::Integral(op.try_into()?)),
-            'm' => Ok(GenericOperator::Merge(op.try_into()?)),
-            'o' => Ok(GenericOperator::Order(op.try_into()?)),
-            's' => Ok(GenericOperator::Step(op.try_into()?)),
-            'u' => Ok(GenericOperator::Unique(op.try_into()?)),
-            _ => Err(anyhow!("Invalid operator: {}", op.op)),
+impl TryFrom<Op> for GenericOperator {
+    type Error = anyhow::Error;
+    fn try_from(op: Op) -> Result<Self, Self::Error> {
+        if op.name.is_some() {
+            return Ok(GenericOperator::Registered(op.try_into()?));
+        }
+        match op.op {
+            'a' => Ok(GenericOperator::Average(op.try_into()?)),
+            'b' => Ok(GenericOperator::Bottom(op.try_into()?)),
+            'c' => Ok(GenericOperator::Cdf(op.try_into()?)),
+            'd' => Ok(GenericOperator::Derivative(op.try_into()?)),
+            'D' => Ok(GenericOperator::Downsample(op.try_into()?)),
+            'e' => Ok(GenericOperator::Exp(op.try_into()?)),
+            'F' => Ok(GenericOperator::Fft(op.try_into()?)),
+            'f' => Ok(GenericOperator::FilterFinite(op.try_into()?)),
+            'g' => Ok(GenericOperator::Gap(op.try_into()?)),
+            'i' => Ok(GenericOperator::Integral(op.try_into()?)),
+            'L' => Ok(GenericOperator::Restore(op.try_into()?)),
+            'k' => Ok(GenericOperator::TopK(op.try_into()?)),
+            'l' => Ok(GenericOperator::Log(op.try_into()?)),
+            'M' => Ok(GenericOperator::Median(op.try_into()?)),
+            'm' => Ok(GenericOperator::Merge(op.try_into()?)),
+            'n' => Ok(GenericOperator::Normalize(op.try_into()?)),
+            'o' => Ok(GenericOperator::Order(op.try_into()?)),
+            'q' => Ok(GenericOperator::Quantile(op.try_into()?)),
+            'r' => Ok(GenericOperator::Resample(op.try_into()?)),
+            'R' => Ok(GenericOperator::Regression(op.try_into()?)),
+            'S' => Ok(GenericOperator::Checkpoint(op.try_into()?)),
+            's' => Ok(GenericOperator::Step(op.try_into()?)),
+            't' => Ok(GenericOperator::Top(op.try_into()?)),
+            'u' => Ok(GenericOperator::Unique(op.try_into()?)),
+            'x' => Ok(GenericOperator::XAffine(op.try_into()?)),
+            'y' => Ok(GenericOperator::YAffine(op.try_into()?)),
+            _ => Ok(GenericOperator::Registered(op.try_into()?)),
+        }
+    }
+}
+
+impl Operator for GenericOperator {
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        match self {
+            GenericOperator::Average(average) => average.to_sql(info),
+            GenericOperator::Bottom(bottom) => bottom.to_sql(info),
+            GenericOperator::Cdf(cdf) => cdf.to_sql(info),
+            GenericOperator::Derivative(derivative) => derivative.to_sql(info),
+            GenericOperator::Downsample(downsample) => downsample.to_sql(info),
+            GenericOperator::Exp(exp) => exp.to_sql(info),
+            GenericOperator::Fft(fft) => fft.to_sql(info),
+            GenericOperator::FilterFinite(filter_finite) => {
+                filter_finite.to_sql(info)
+            }
+            GenericOperator::Gap(gap) => gap.to_sql(info),
+            GenericOperator::Integral(integral) => integral.to_sql(info),
+            GenericOperator::TopK(top_k) => top_k.to_sql(info),
+            GenericOperator::Log(log) => log.to_sql(info),
+            GenericOperator::Median(median) => median.to_sql(info),
+            GenericOperator::Merge(merge) => merge.to_sql(info),
+            GenericOperator::Normalize(normalize) => normalize.to_sql(info),
+            GenericOperator::Order(order) => order.to_sql(info),
+            GenericOperator::Quantile(quantile) => quantile.to_sql(info),
+            GenericOperator::Resample(resample) => resample.to_sql(info),
+            GenericOperator::Restore(restore) => restore.to_sql(info),
+            GenericOperator::Regression(regression) => regression.to_sql(info),
+            GenericOperator::Checkpoint(checkpoint) => checkpoint.to_sql(info),
+            GenericOperator::Step(step) => step.to_sql(info),
+            GenericOperator::Top(top) => top.to_sql(info),
+            GenericOperator::Unique(unique) => unique.to_sql(info),
+            GenericOperator::XAffine(affine) => affine.to_sql(info),
+            GenericOperator::YAffine(affine) => affine.to_sql(info),
+            GenericOperator::Registered(registered) => registered.to_sql(info),
+            GenericOperator::Finalize(finalize) => finalize.to_sql(info),
+        }
+    }
+
+    fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        match self {
+            GenericOperator::Average(average) => average.eval(points),
+            GenericOperator::Bottom(bottom) => bottom.eval(points),
+            GenericOperator::Cdf(cdf) => cdf.eval(points),
+            GenericOperator::Derivative(derivative) => derivative.eval(points),
+            GenericOperator::Downsample(downsample) => downsample.eval(points),
+            GenericOperator::Exp(exp) => exp.eval(points),
+            GenericOperator::Fft(fft) => fft.eval(points),
+            GenericOperator::FilterFinite(filter_finite) => {
+                filter_finite.eval(points)
+            }
+            GenericOperator::Gap(gap) => gap.eval(points),
+            GenericOperator::Integral(integral) => integral.eval(points),
+            GenericOperator::TopK(top_k) => top_k.eval(points),
+            GenericOperator::Log(log) => log.eval(points),
+            GenericOperator::Median(median) => median.eval(points),
+            GenericOperator::Merge(merge) => merge.eval(points),
+            GenericOperator::Normalize(normalize) => normalize.eval(points),
+            GenericOperator::Order(order) => order.eval(points),
+            GenericOperator::Quantile(quantile) => quantile.eval(points),
+            GenericOperator::Resample(resample) => resample.eval(points),
+            GenericOperator::Restore(restore) => restore.eval(points),
+            GenericOperator::Regression(regression) => regression.eval(points),
+            GenericOperator::Checkpoint(checkpoint) => checkpoint.eval(points),
+            GenericOperator::Step(step) => step.eval(points),
+            GenericOperator::Top(top) => top.eval(points),
+            GenericOperator::Unique(unique) => unique.eval(points),
+            GenericOperator::XAffine(affine) => affine.eval(points),
+            GenericOperator::YAffine(affine) => affine.eval(points),
+            GenericOperator::Registered(registered) => registered.eval(points),
+            GenericOperator::Finalize(finalize) => finalize.eval(points),
+        }
+    }
+}
+
+impl GenericOperator {
+    /// Whether the operator keeps the order of the points and only looks
+    /// back at the previous one, so that a stream of points could be
+    /// evaluated chunk by chunk with `eval_chunk`
+    pub(crate) fn is_streaming(&self) -> bool {
+        matches!(
+            self,
+            GenericOperator::Exp(_)
+                | GenericOperator::FilterFinite(_)
+                | GenericOperator::Gap(GapOperator::Keep)
+                | GenericOperator::Log(_)
+                | GenericOperator::Step(_)
+                | GenericOperator::XAffine(_)
+                | GenericOperator::YAffine(_)
+                | GenericOperator::Finalize(_)
+        )
+    }
+
+    // `eval` of a chunk of a stream, `last` being the last point before the
+    // chunk
+    pub(crate) fn eval_chunk(
+        &self,
+        chunk: Vec<(f64, f64)>,
+        last: Option<(f64, f64)>,
+    ) -> Result<Vec<(f64, f64)>> {
+        match self {
+            GenericOperator::Gap(gap) => Ok(gap.eval_after(chunk, last)),
+            GenericOperator::Step(step) => Ok(step.eval_after(chunk, last)),
+            op => op.eval(chunk),
+        }
+    }
+
+    /// What the operator computes, in a few words
+    pub fn description(&self) -> &'static str {
+        match self {
+            GenericOperator::Average(_) => "moving average of y",
+            GenericOperator::Bottom(_) => "moving minimum of y",
+            GenericOperator::Cdf(_) => "cumulative distribution of y",
+            GenericOperator::Derivative(_) => "derivative dy/dx",
+            GenericOperator::Downsample(_) => "every k-th record",
+            GenericOperator::Exp(_) => "exponential",
+            GenericOperator::Fft(_) => "amplitude spectrum of y",
+            GenericOperator::FilterFinite(_) => "records with finite y",
+            GenericOperator::Gap(_) => "difference of consecutive x values",
+            GenericOperator::Integral(_) => "integral of y over x",
+            GenericOperator::TopK(_) => "records with the top k y values",
+            GenericOperator::Log(_) => "logarithm",
+            GenericOperator::Median(_) => "moving median of y",
+            GenericOperator::Merge(_) => "sum of y for each x",
+            GenericOperator::Normalize(_) => "normalized y",
+            GenericOperator::Order(order) => {
+                match (order.by_y, order.descending) {
+                    (false, false) => "records ordered by x",
+                    (false, true) => "records ordered by x, descending",
+                    (true, false) => "records ordered by y",
+                    (true, true) => "records ordered by y, descending",
+                }
+            }
+            GenericOperator::Quantile(_) => "percentiles of y",
+            GenericOperator::Resample(_) => "y interpolated onto a grid",
+            GenericOperator::Regression(_) => "linear regression",
+            GenericOperator::Restore(_) => "records of a checkpoint",
+            GenericOperator::Checkpoint(_) => "checkpoint of the records",
+            GenericOperator::Step(_) => "difference of consecutive y values",
+            GenericOperator::Top(_) => "moving maximum of y",
+            GenericOperator::Unique(_) => "one record for each x",
+            GenericOperator::XAffine(_) => "x scaled and offset",
+            GenericOperator::YAffine(_) => "y scaled and offset",
+            GenericOperator::Registered(_) => "registered operator",
+            GenericOperator::Finalize(_) => "columns renamed to x and y",
+        }
+    }
+
+    /// Columns of the result of the operator applied to columns `x` and `y`,
+    /// the units being composed (e.g. `ms/s` for the derivative of `ms` over
+    /// `s`). Checkpoints are not followed, see `OpSeq::columns`.
+    pub fn columns(&self, x: Column, y: Column) -> (Column, Column) {
+        let unitless = |name: &str| Column::new(name, None::<&str>);
+        match self {
+            GenericOperator::Cdf(_) => (y, unitless("CDF")),
+            GenericOperator::Derivative(_) => {
+                let unit = compose_units(&y.unit, &x.unit, '/');
+                (x, y.with_unit(unit))
+            }
+            GenericOperator::Integral(_) => {
+                let unit = compose_units(&y.unit, &x.unit, '·');
+                (x, y.with_unit(unit))
+            }
+            GenericOperator::Exp(ExpOperator(scale))
+            | GenericOperator::Log(LogOperator(scale)) => {
+                if scale.x_axis {
+                    (x.with_unit(None), y)
+                } else {
+                    (x, y.with_unit(None))
+                }
+            }
+            GenericOperator::Fft(_) => {
+                let unit = compose_units(&None, &x.unit, '/');
+                (
+                    Column::new("frequency", unit),
+                    Column::new("amplitude", y.unit),
+                )
+            }
+            GenericOperator::Gap(mode) => {
+                let gap =
+                    Column::new(format!("gap of {}", x.name), x.unit.clone());
+                match mode {
+                    GapOperator::Keep => (x, gap),
+                    GapOperator::Index => (unitless("index"), gap),
+                }
+            }
+            GenericOperator::Normalize(_) => (x, y.with_unit(None)),
+            GenericOperator::Quantile(_) => {
+                (Column::new("percentile", Some("%")), y)
+            }
+            GenericOperator::Regression(RegressionOperator::Parameters) => {
+                (unitless("parameter"), unitless("value"))
+            }
+            _ => (x, y),
+        }
+    }
+
+    // `eval` of an operator of a sequence, `S` saving the points into
+    // `checkpoints` and `L` bringing them back
+    pub(crate) fn eval_in_seq(
+        &self,
+        points: Vec<(f64, f64)>,
+        checkpoints: &mut Checkpoints,
+    ) -> Result<Vec<(f64, f64)>> {
+        match self {
+            GenericOperator::Checkpoint(checkpoint) => {
+                checkpoints.insert(checkpoint.0, points.clone());
+                Ok(points)
+            }
+            GenericOperator::Restore(restore) => {
+                checkpoints.get(&restore.0).cloned().ok_or_else(|| {
+                    anyhow!(
+                        "Checkpoint {} is restored before it is saved",
+                        restore.0
+                    )
+                })
+            }
+            op => op.eval(points),
+        }
+    }
+}
+
+/// Operator provided by library users, registered under an operator letter
+/// or a longer name with `RegisteredOperator::register`
+pub trait CustomOperator: Send + Sync {
+    /// Reject invalid arguments when the operation sequence is parsed
+    fn check_args(&self, _args: &[f64]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Query on `src_table` (with columns `x_name` and `y_name`), whose
+    /// result has the columns `x` and `y`
+    fn to_query(
+        &self,
+        args: &[f64],
+        src_table: &str,
+        x_name: &str,
+        y_name: &str,
+    ) -> String;
+}
+
+static OPERATOR_REGISTRY: LazyLock<
+    RwLock<HashMap<String, Arc<dyn CustomOperator>>>,
+> = LazyLock::new(Default::default);
+
+/// A registered custom operator with its arguments
+#[derive(Clone)]
+pub struct RegisteredOperator {
+    name: String,
+    args: Vec<f64>,
+    operator: Arc<dyn CustomOperator>,
+}
+
+impl RegisteredOperator {
+    const BUILTIN_OPERATORS: &str = "abcdDefFgiklLMmnoqrRSstuxy";
+
+    /// Make `operator` available in operation sequences parsed afterwards,
+    /// as a letter (e.g. `w5`) or as a longer name in brackets (e.g.
+    /// `[ema]0.2`), made of alphanumeric characters, `-` and `_`. Built-in
+    /// letters and names already registered are rejected.
+    pub fn register(
+        name: impl ToString,
+        operator: impl CustomOperator + 'static,
+    ) -> Result<()> {
+        let name = name.to_string();
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => bail!("Empty operator name"),
+            (Some(op), None) if !op.is_ascii_alphabetic() => {
+                bail!("Non-alphabetic operator '{op}'")
+            }
+            (Some(op), None) if Self::BUILTIN_OPERATORS.contains(op) => {
+                bail!("Operator '{op}' is built-in")
+            }
+            _ if !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+            {
+                bail!("Invalid operator name '{name}'")
+            }
+            _ => {}
+        }
+        let mut registry = OPERATOR_REGISTRY
+            .write()
+            .map_err(|e| anyhow!("Failed to lock operator registry: {e}"))?;
+        if registry.contains_key(&name) {
+            bail!("Operator '{name}' is already registered");
+        }
+        registry.insert(name, Arc::new(operator));
+        Ok(())
+    }
+
+    /// Letters and names of all registered operators
+    pub fn registered() -> Vec<String> {
+        let mut ops = OPERATOR_REGISTRY
+            .read()
+            .map(|registry| registry.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        ops.sort();
+        ops
+    }
+}
+
+impl std::fmt::Debug for RegisteredOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegisteredOperator")
+            .field("name", &self.name)
+            .field("args", &self.args)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for RegisteredOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.name.len() == 1 {
+            write!(f, "{}", self.name)?;
+        } else {
+            write!(f, "[{}]", self.name)?;
+        }
+        write!(
+            f,
+            "{}",
+            self.args
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+impl TryFrom<Op> for RegisteredOperator {
+    type Error = anyhow::Error;
+
+    fn try_from(op: Op) -> Result<Self> {
+        let name = op.name.unwrap_or_else(|| op.op.to_string());
+        let operator = OPERATOR_REGISTRY
+            .read()
+            .map_err(|e| anyhow!("Failed to lock operator registry: {e}"))?
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Invalid operator: {name}"))?;
+        operator.check_args(&op.arg)?;
+        Ok(Self {
+            name,
+            args: op.arg,
+            operator,
+        })
+    }
+}
+
+impl Operator for RegisteredOperator {
+    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
+        let x_name = info.x_name.to_string();
+        let y_name = self.append_column_name(&info.y_name);
+
+        OperateResult {
+            subquery: format!(
+                "t{} AS (SELECT x AS \"{}\", y AS \"{}\" FROM ({}))",
+                info.tmp_table_num,
+                x_name,
+                y_name,
+                self.operator.to_query(
+                    &self.args,
+                    &info.src_table,
+                    &info.x_name,
+                    &info.y_name
+                ),
+            ),
+            x_name,
+            y_name,
+        }
+    }
+}
+
+/// Operator implemented by an executable `sp-op-<name>` on PATH, written as
+/// `{name:args}` in the operation sequence. The executable receives the
+/// (x, y) table as CSV on stdin and writes the result as CSV to stdout.
+#[derive(Debug, Clone)]
+pub struct ExternalOperator {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl ExternalOperator {
+    pub const PREFIX: &str = "sp-op-";
+
+    pub fn program(&self) -> String {
+        format!("{}{}", Self::PREFIX, self.name)
+    }
+
+    /// Names of all external operators found on PATH
+    pub fn discover() -> Vec<String> {
+        let mut names = std::env::var_os("PATH")
+            .map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                Some(name.strip_prefix(Self::PREFIX)?.to_string())
+            })
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+impl FromStr for ExternalOperator {
+    type Err = anyhow::Error;
+
+    /// `name:args` or `name`, args are comma-separated
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, args) = s.split_once(':').unwrap_or((s, ""));
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("Invalid external operator name '{name}'");
+        }
+        Ok(Self {
+            name: name.to_string(),
+            args: if args.is_empty() {
+                vec![]
+            } else {
+                args.split(',').map(|s| s.to_string()).collect()
+            },
+        })
+    }
+}
+
+impl Display for ExternalOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.args.is_empty() {
+            write!(f, "{{{}}}", self.name)
+        } else {
+            write!(f, "{{{}:{}}}", self.name, self.args.join(","))
+        }
+    }
+}
+
+/// Operator of an operation sequence walked by `OpSeq::explain`
+#[derive(Debug, Clone)]
+pub struct ExplainedStep {
+    /// The operator with its arguments, as written in operation sequences
+    pub operator: String,
+    pub description: &'static str,
+    pub x_name: String,
+    pub y_name: String,
+    pub warning: Option<String>,
+}
+
+/// Name and unit of a column, e.g. `Latency (ms)` as an axis label
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    /// `None` if unknown or dimensionless
+    pub unit: Option<String>,
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.name.as_str(), &self.unit) {
+            (name, None) => write!(f, "{name}"),
+            ("", Some(unit)) => write!(f, "{unit}"),
+            (name, Some(unit)) => write!(f, "{name} ({unit})"),
+        }
+    }
+}
+
+impl Column {
+    pub fn new(name: impl AsRef<str>, unit: Option<impl AsRef<str>>) -> Self {
+        Self {
+            name: name.as_ref().to_string(),
+            unit: unit.map(|s| s.as_ref().to_string()),
         }
     }
+
+    fn with_unit(self, unit: Option<String>) -> Self {
+        Self { unit, ..self }
+    }
 }
 
-impl Operator for GenericOperator {
-    fn to_sql(&self, info: &OperateInfo) -> OperateResult {
-        match self {
-            GenericOperator::Average(average) => average.to_sql(info),
-            GenericOperator::Cdf(cdf) => cdf.to_sql(info),
-            GenericOperator::Derivative(derivative) => derivative.to_sql(info),
-            GenericOperator::FilterFinite(filter_finite) => {
-                filter_finite.to_sql(info)
-            }
-            GenericOperator::Integral(integral) => integral.to_sql(info),
-            GenericOperator::Merge(merge) => merge.to_sql(info),
-            GenericOperator::Order(order) => order.to_sql(info),
-            GenericOperator::Step(step) => step.to_sql(info),
-            GenericOperator::Unique(unique) => unique.to_sql(info),
-            GenericOperator::Finalize(finalize) => finalize.to_sql(info),
+// unit of `a` * `b` or `a` / `b`, compound units being parenthesized
+fn compose_units(
+    a: &Option<String>,
+    b: &Option<String>,
+    op: char,
+) -> Option<String> {
+    let operand = |unit: &str| {
+        if unit.contains(['/', '·', ' ']) {
+            format!("({unit})")
+        } else {
+            unit.to_string()
+        }
+    };
+    match (a, b, op) {
+        (a, None, _) => a.clone(),
+        (None, Some(b), '/') => Some(format!("1/{}", operand(b))),
+        (None, Some(b), _) => Some(b.clone()),
+        (Some(a), Some(b), op) => {
+            Some(format!("{}{op}{}", operand(a), operand(b)))
         }
     }
 }
@@ -502,7 +2679,7 @@ impl FromStr for OpSeq {
             .into_iter()
             .map(GenericOperator::try_from)
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(Self { ops })
+        Self::new(ops, HashSet::new()).map(|(opseq, _)| opseq)
     }
 }
 
@@ -520,7 +2697,312 @@ impl Display for OpSeq {
     }
 }
 
+/// Builder of an `OpSeq` from typed operators, e.g.
+/// `OpSeq::builder().order().cdf().build()` is the same as `"oc".parse()`.
+/// Invalid arguments are reported by `build`.
+#[derive(Debug, Default)]
+pub struct OpSeqBuilder {
+    ops: Vec<Op>,
+}
+
+impl OpSeqBuilder {
+    pub(crate) fn push(mut self, op: char, arg: Vec<f64>) -> Self {
+        self.ops.push(Op {
+            op,
+            name: None,
+            arg,
+        });
+        self
+    }
+
+    /// `a`: average of y over x in `[x - left_window, x + right_window]`
+    pub fn average(self, left_window: f64, right_window: f64) -> Self {
+        self.push('a', vec![left_window, right_window])
+    }
+
+    /// `b`: minimum of y over x in `[x - left_window, x + right_window]`
+    pub fn bottom(self, left_window: f64, right_window: f64) -> Self {
+        self.push('b', vec![left_window, right_window])
+    }
+
+    /// `c`: cumulative distribution of y
+    pub fn cdf(self) -> Self {
+        self.push('c', vec![])
+    }
+
+    /// `d`: dy/dx over x in `[x - left_window, x + right_window]`, or between
+    /// consecutive records if both windows are 0
+    pub fn derivative(self, left_window: f64, right_window: f64) -> Self {
+        self.push('d', vec![left_window, right_window])
+    }
+
+    /// `D`: every k-th record, k being chosen so that at most `max_points`
+    /// records remain
+    pub fn downsample(self, max_points: usize) -> Self {
+        self.push('D', vec![max_points as f64])
+    }
+
+    /// `e`: `base` raised to the power of y, or of x if `x_axis` (inverse of
+    /// `l`)
+    pub fn exp(self, base: f64, x_axis: bool) -> Self {
+        self.push('e', vec![base, if x_axis { 1.0 } else { 0.0 }])
+    }
+
+    /// `F`: amplitude spectrum of y (frequency in cycles per x unit)
+    pub fn fft(self) -> Self {
+        self.push('F', vec![])
+    }
+
+    /// `f`: records with finite y only
+    pub fn filter_finite(self) -> Self {
+        self.push('f', vec![])
+    }
+
+    /// `g`: difference of the consecutive x values as y, x being replaced
+    /// with the index of the record if `index`
+    pub fn gap(self, index: bool) -> Self {
+        self.push('g', vec![if index { 1.0 } else { 0.0 }])
+    }
+
+    /// `i`: integral of y with respect to x
+    pub fn integral(self) -> Self {
+        self.push('i', vec![])
+    }
+
+    /// `k`: the `k` records with the largest y, or the smallest y if
+    /// `smallest`
+    pub fn top_k(self, k: usize, smallest: bool) -> Self {
+        self.push('k', vec![k as f64, if smallest { 1.0 } else { 0.0 }])
+    }
+
+    /// `l`: logarithm in `base` of y, or of x if `x_axis`, non-positive
+    /// values becoming NaN
+    pub fn log(self, base: f64, x_axis: bool) -> Self {
+        self.push('l', vec![base, if x_axis { 1.0 } else { 0.0 }])
+    }
+
+    /// `L`: the records saved by `checkpoint(id)` in place of the current
+    /// ones
+    pub fn restore(self, id: usize) -> Self {
+        self.push('L', vec![id as f64])
+    }
+
+    /// `M`: median of y over x in `[x - left_window, x + right_window]`
+    pub fn median(self, left_window: f64, right_window: f64) -> Self {
+        self.push('M', vec![left_window, right_window])
+    }
+
+    /// `m`: sum of y of each distinct x
+    pub fn merge(self) -> Self {
+        self.push('m', vec![])
+    }
+
+    /// `n`: y rescaled to [0, 1], or to z-scores if `z_score`
+    pub fn normalize(self, z_score: bool) -> Self {
+        self.push('n', vec![if z_score { 1.0 } else { 0.0 }])
+    }
+
+    /// `o`: records ordered by x
+    pub fn order(self) -> Self {
+        self.push('o', vec![])
+    }
+
+    /// `o`: records ordered by y if `by_y` (x otherwise), in descending
+    /// order if `descending`, records of equal keys keeping their order in
+    /// SQL as well if `stable`
+    pub fn order_by(self, by_y: bool, descending: bool, stable: bool) -> Self {
+        let flag = |b: bool| if b { 1.0 } else { 0.0 };
+        self.push('o', vec![flag(by_y), flag(descending), flag(stable)])
+    }
+
+    /// `q`: the given percentiles (between 0 and 100) of y
+    pub fn quantiles(self, percentiles: &[f64]) -> Self {
+        self.push('q', percentiles.to_vec())
+    }
+
+    /// `r`: y interpolated linearly at x = `start + k * step` (integer k)
+    /// between the smallest and the largest x
+    pub fn resample(self, start: f64, step: f64) -> Self {
+        self.push('r', vec![start, step])
+    }
+
+    /// `R`: least-squares line y = a * x + b
+    pub fn regression(self) -> Self {
+        self.push('R', vec![])
+    }
+
+    /// `R1`: residuals of the least-squares line
+    pub fn regression_residuals(self) -> Self {
+        self.push('R', vec![1.0])
+    }
+
+    /// `R2`: parameters a, b and R^2 of the least-squares line
+    pub fn regression_parameters(self) -> Self {
+        self.push('R', vec![2.0])
+    }
+
+    /// `s`: difference of consecutive y values
+    pub fn step(self) -> Self {
+        self.push('s', vec![])
+    }
+
+    /// `S`: the records unchanged, saved under `id` for `restore(id)`
+    pub fn checkpoint(self, id: usize) -> Self {
+        self.push('S', vec![id as f64])
+    }
+
+    /// `t`: maximum of y over x in `[x - left_window, x + right_window]`
+    pub fn top(self, left_window: f64, right_window: f64) -> Self {
+        self.push('t', vec![left_window, right_window])
+    }
+
+    /// `u`: the first record of each distinct x
+    pub fn unique(self) -> Self {
+        self.push('u', vec![])
+    }
+
+    /// `u1`: the last record of each x value
+    pub fn unique_last(self) -> Self {
+        self.push('u', vec![1.0])
+    }
+
+    /// `u2`: the mean of y for each x value
+    pub fn unique_mean(self) -> Self {
+        self.push('u', vec![2.0])
+    }
+
+    /// `x`: x * scale + offset
+    pub fn scale_x(self, scale: f64, offset: f64) -> Self {
+        self.push('x', vec![scale, offset])
+    }
+
+    /// `y`: y * scale + offset
+    pub fn scale_y(self, scale: f64, offset: f64) -> Self {
+        self.push('y', vec![scale, offset])
+    }
+
+    /// Operator registered with `RegisteredOperator::register`, under a
+    /// letter or a longer name
+    pub fn registered(mut self, name: impl ToString, args: &[f64]) -> Self {
+        let name = name.to_string();
+        let mut chars = name.chars();
+        self.ops.push(match (chars.next(), chars.next()) {
+            (Some(op), None) => Op {
+                op,
+                name: None,
+                arg: args.to_vec(),
+            },
+            _ => Op {
+                op: '[',
+                name: Some(name),
+                arg: args.to_vec(),
+            },
+        });
+        self
+    }
+
+    pub fn build(self) -> Result<OpSeq> {
+        let ops = self
+            .ops
+            .into_iter()
+            .map(GenericOperator::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        OpSeq::new(ops, HashSet::new()).map(|(opseq, _)| opseq)
+    }
+}
+
 impl OpSeq {
+    pub fn builder() -> OpSeqBuilder {
+        OpSeqBuilder::default()
+    }
+
+    // each checkpoint is saved once, before it is restored, `saved` being
+    // the checkpoints saved by the leading operators of a branch; returns
+    // them with the ones saved by `ops`
+    fn new(
+        ops: Vec<GenericOperator>,
+        mut saved: HashSet<CheckpointId>,
+    ) -> Result<(Self, HashSet<CheckpointId>)> {
+        for op in &ops {
+            match op {
+                GenericOperator::Checkpoint(checkpoint)
+                    if !saved.insert(checkpoint.0) =>
+                {
+                    bail!("Checkpoint {} is saved twice", checkpoint.0);
+                }
+                GenericOperator::Restore(restore)
+                    if !saved.contains(&restore.0) =>
+                {
+                    bail!(
+                        "Checkpoint {} is restored before it is saved",
+                        restore.0
+                    );
+                }
+                _ => {}
+            }
+        }
+        Ok((Self { ops }, saved))
+    }
+
+    /// Parse an operation sequence that may contain external operators,
+    /// returns the leading built-in operators and each external operator
+    /// with the built-in operators following it
+    pub fn parse_with_external(
+        s: &str,
+    ) -> Result<(Self, Vec<(ExternalOperator, Self)>)> {
+        let (head, mut rest) = s.split_once('{').unwrap_or((s, ""));
+        let head = head.parse()?;
+        let mut stages = Vec::new();
+        while !rest.is_empty() {
+            let (ext, tail) = rest
+                .split_once('}')
+                .ok_or_else(|| anyhow!("Unclosed '{{' in '{s}'"))?;
+            let (ops, next) = tail.split_once('{').unwrap_or((tail, ""));
+            stages.push((ext.parse()?, ops.parse()?));
+            rest = next;
+        }
+        Ok((head, stages))
+    }
+
+    /// Parse an operation sequence that may fork at `/`, returns the leading
+    /// operators shared by all branches and the operators of each branch,
+    /// e.g. `o/c/d0.1` sorts the records once, then computes both the CDF
+    /// and the derivative of the sorted records. A branch may restore the
+    /// checkpoints saved by the leading operators.
+    pub fn parse_with_branches(s: &str) -> Result<(Self, Vec<Self>)> {
+        let parse = |s: &str, saved| {
+            let ops = Self::str_to_ops(s)?
+                .into_iter()
+                .map(GenericOperator::try_from)
+                .collect::<Result<Vec<_>, _>>()?;
+            Self::new(ops, saved)
+        };
+        let mut parts = s.split('/');
+        let (head, saved) =
+            parse(parts.next().unwrap_or_default(), HashSet::new())?;
+        let branches = parts
+            .map(|s| parse(s, saved.clone()).map(|(branch, _)| branch))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((head, branches))
+    }
+
+    /// Same as `eval`, then applies each of `branches` separately to the
+    /// result, returns the points of each branch
+    pub fn eval_branches(
+        &self,
+        branches: &[OpSeq],
+        points: Vec<(f64, f64)>,
+    ) -> Result<Vec<Vec<(f64, f64)>>> {
+        let mut checkpoints = Checkpoints::new();
+        let points = self.eval_with(points, &mut checkpoints)?;
+        branches
+            .iter()
+            .map(|branch| {
+                branch.eval_with(points.clone(), &mut checkpoints.clone())
+            })
+            .collect()
+    }
+
     fn str_to_ops(s: &str) -> Result<Vec<Op>> {
         let mut ops = Vec::new();
         let len = s.len();
@@ -534,6 +3016,102 @@ impl OpSeq {
         Ok(ops)
     }
 
+    /// Whether all operators could be applied by `eval`
+    pub fn is_native(&self) -> bool {
+        !self
+            .ops
+            .iter()
+            .any(|op| matches!(op, GenericOperator::Registered(_)))
+    }
+
+    /// Apply the operators to (x, y) points in Rust, without duckdb
+    pub fn eval(&self, points: Vec<(f64, f64)>) -> Result<Vec<(f64, f64)>> {
+        self.eval_with(points, &mut Checkpoints::new())
+    }
+
+    fn eval_with(
+        &self,
+        points: Vec<(f64, f64)>,
+        checkpoints: &mut Checkpoints,
+    ) -> Result<Vec<(f64, f64)>> {
+        self.ops
+            .iter()
+            .try_fold(points, |points, op| op.eval_in_seq(points, checkpoints))
+    }
+
+    /// Same as `eval`, but the points come in chunks and are passed to `sink`
+    /// chunk by chunk as long as the operators are streaming (`e`, `f`, `g`
+    /// but not `g1`, `l`, `s`, `x` and `y`, see
+    /// `GenericOperator::is_streaming`), so that memory is bounded by the
+    /// size of a chunk. They are only collected
+    /// from the first blocking operator (e.g. `o` or `c`) on.
+    pub fn eval_chunks(
+        &self,
+        chunks: impl IntoIterator<Item = Result<Vec<(f64, f64)>>>,
+        mut sink: impl FnMut(Vec<(f64, f64)>) -> Result<()>,
+    ) -> Result<()> {
+        let streaming = self
+            .ops
+            .iter()
+            .position(|op| !op.is_streaming())
+            .unwrap_or(self.ops.len());
+        let (head, tail) = self.ops.split_at(streaming);
+        let mut lasts = vec![];
+        let mut collected = Vec::new();
+        for chunk in chunks {
+            let chunk = Self::eval_streaming(head, chunk?, &mut lasts)?;
+            if tail.is_empty() {
+                sink(chunk)?;
+            } else {
+                collected.extend(chunk);
+            }
+        }
+        if !tail.is_empty() {
+            let mut checkpoints = Checkpoints::new();
+            sink(tail.iter().try_fold(collected, |points, op| {
+                op.eval_in_seq(points, &mut checkpoints)
+            })?)?;
+        }
+        Ok(())
+    }
+
+    /// Whether all operators are streaming (see `eval_chunks`), so that the
+    /// points of a growing stream could be evaluated as they come with
+    /// `eval_chunk`
+    pub fn is_streaming(&self) -> bool {
+        self.ops.iter().all(GenericOperator::is_streaming)
+    }
+
+    /// `eval` of the next chunk of a stream of points, the operators being
+    /// streaming (see `is_streaming`), where `lasts` keeps the last input
+    /// point of each operator from one chunk to the next (empty before the
+    /// first one)
+    pub fn eval_chunk(
+        &self,
+        chunk: Vec<(f64, f64)>,
+        lasts: &mut Vec<Option<(f64, f64)>>,
+    ) -> Result<Vec<(f64, f64)>> {
+        if !self.is_streaming() {
+            bail!("Operators {self} are not all streaming");
+        }
+        Self::eval_streaming(&self.ops, chunk, lasts)
+    }
+
+    // `eval` of a chunk by streaming operators `ops`, see `eval_chunk`
+    fn eval_streaming(
+        ops: &[GenericOperator],
+        mut chunk: Vec<(f64, f64)>,
+        lasts: &mut Vec<Option<(f64, f64)>>,
+    ) -> Result<Vec<(f64, f64)>> {
+        lasts.resize(ops.len(), None);
+        for (op, last) in ops.iter().zip(lasts.iter_mut()) {
+            let next_last = chunk.last().copied().or(*last);
+            chunk = op.eval_chunk(chunk, *last)?;
+            *last = next_last;
+        }
+        Ok(chunk)
+    }
+
     pub fn get_tmp_table_name(&self) -> String {
         format!(
             "t{}",
@@ -556,33 +3134,758 @@ impl OpSeq {
         }
         format!(
             "WITH \n{}\n",
-            self.ops
-                .iter()
-                .chain(std::iter::once(&GenericOperator::Finalize(
-                    FinalizeOperator {}
-                )))
-                .scan(
-                    OperateInfo {
-                        src_table: src_table.to_string(),
-                        tmp_table_num: 1,
-                        x_name: x_name.to_string(),
-                        y_name: y_name.to_string(),
-                    },
-                    |info, op| {
-                        let OperateResult {
-                            subquery,
-                            x_name,
-                            y_name,
-                        } = op.to_sql(info);
-                        info.src_table = format!("t{}", info.tmp_table_num);
-                        info.tmp_table_num += 1;
-                        info.x_name = x_name;
-                        info.y_name = y_name;
-                        Some(subquery)
-                    },
-                )
+            self.translate(src_table, x_name, y_name, true)
+                .into_iter()
+                .map(|result| result.subquery)
                 .collect::<Vec<_>>()
                 .join(",\n")
         )
     }
+
+    /// SQL statements creating the tables `t1`, `t2`, ... (up to
+    /// `get_tmp_table_name`) of the CTEs of `to_sql` one after another, each
+    /// operator being preceded by the statements of `before(op, table)`, e.g.
+    /// to time the operators one by one
+    pub fn to_sql_steps(
+        &self,
+        src_table: &str,
+        x_name: &str,
+        y_name: &str,
+        mut before: impl FnMut(&GenericOperator, &str) -> String,
+    ) -> String {
+        let mut sql = String::new();
+        let results = self.translate(src_table, x_name, y_name, true);
+        for (i, result) in results.into_iter().enumerate() {
+            let table = format!("t{}", i + 1);
+            let op = self.ops.get(i);
+            if let Some(op) = op {
+                sql.push_str(&before(op, &table));
+            }
+            // restored by a later operator by its name
+            let checkpoint = match op {
+                Some(GenericOperator::Checkpoint(checkpoint)) => {
+                    Some(checkpoint.0.table_name())
+                }
+                _ => None,
+            };
+            for table in std::iter::once(table).chain(checkpoint) {
+                sql.push_str(&format!(
+                    "CREATE TABLE {table} AS WITH {} SELECT * FROM {table};\n",
+                    result.subquery
+                ));
+            }
+        }
+        sql
+    }
+
+    // result of each operator (followed by `FinalizeOperator` if `finalize`)
+    // on the table produced by the previous one
+    fn translate(
+        &self,
+        src_table: &str,
+        x_name: &str,
+        y_name: &str,
+        finalize: bool,
+    ) -> Vec<OperateResult> {
+        let finalizer = GenericOperator::Finalize(FinalizeOperator {});
+        self.ops
+            .iter()
+            .chain(finalize.then_some(&finalizer))
+            .scan(
+                OperateInfo {
+                    src_table: src_table.to_string(),
+                    tmp_table_num: 1,
+                    x_name: x_name.to_string(),
+                    y_name: y_name.to_string(),
+                },
+                |info, op| {
+                    tracing::debug!(
+                        operator = %op,
+                        src_table = %info.src_table,
+                        "Translating operator"
+                    );
+                    let result = op.to_sql(info);
+                    info.src_table = format!("t{}", info.tmp_table_num);
+                    info.tmp_table_num += 1;
+                    info.x_name = result.x_name.clone();
+                    info.y_name = result.y_name.clone();
+                    Some(result)
+                },
+            )
+            .collect()
+    }
+
+    /// Walk the operators without data, returns for each of them its
+    /// arguments and the column names of its result, starting from columns
+    /// `x_name` and `y_name`, along with possible mistakes such as an
+    /// operator depending on the order of the records before any `o`
+    pub fn explain(&self, x_name: &str, y_name: &str) -> Vec<ExplainedStep> {
+        let mut sorted = false;
+        let mut sorted_at_checkpoints = HashMap::new();
+        self.ops
+            .iter()
+            .zip(self.translate("t0", x_name, y_name, false))
+            .map(|(op, result)| {
+                let warning = match op {
+                    GenericOperator::Order(order) => {
+                        sorted = order.sorts_by_x();
+                        None
+                    }
+                    GenericOperator::Checkpoint(checkpoint) => {
+                        sorted_at_checkpoints.insert(checkpoint.0, sorted);
+                        None
+                    }
+                    GenericOperator::Restore(restore) => {
+                        sorted = sorted_at_checkpoints
+                            .get(&restore.0)
+                            .copied()
+                            .unwrap_or_default();
+                        None
+                    }
+                    GenericOperator::Downsample(_)
+                    | GenericOperator::Gap(_)
+                    | GenericOperator::Step(_)
+                    | GenericOperator::Unique(
+                        UniqueOperator::First | UniqueOperator::Last,
+                    ) if !sorted => Some(format!(
+                        "{op} depends on the order of the records, which are not sorted by x (with o) before"
+                    )),
+                    _ => None,
+                };
+                ExplainedStep {
+                    operator: op.to_string(),
+                    description: op.description(),
+                    x_name: result.x_name,
+                    y_name: result.y_name,
+                    warning,
+                }
+            })
+            .collect()
+    }
+
+    /// Columns of the result of the operators applied to columns `x` and
+    /// `y`, see `GenericOperator::columns`
+    pub fn columns(&self, x: Column, y: Column) -> (Column, Column) {
+        let mut checkpoints = HashMap::new();
+        self.ops.iter().fold((x, y), |(x, y), op| match op {
+            GenericOperator::Checkpoint(checkpoint) => {
+                checkpoints.insert(checkpoint.0, (x.clone(), y.clone()));
+                (x, y)
+            }
+            GenericOperator::Restore(restore) => {
+                checkpoints.get(&restore.0).cloned().unwrap_or((x, y))
+            }
+            op => op.columns(x, y),
+        })
+    }
+
+    /// SQL statements creating table `dst_table` (with columns `x` and `y`)
+    /// from `src_table`, where each checkpoint is created as a table as
+    /// well, so that the operators run by later statements (e.g. branches)
+    /// could restore it
+    pub fn to_sql_tables(&self, src_table: &str, dst_table: &str) -> String {
+        let mut sql = String::new();
+        let mut src_table = src_table.to_string();
+        let mut segment = OpSeq { ops: vec![] };
+        let mut create = |segment: &OpSeq, src_table: &str, table: &str| {
+            sql.push_str(&format!(
+                "CREATE TABLE {table} AS {}SELECT x, y FROM {};\n",
+                segment.to_sql(src_table, "x", "y"),
+                if segment.ops.is_empty() {
+                    src_table.to_string()
+                } else {
+                    segment.get_tmp_table_name()
+                }
+            ));
+        };
+        for op in &self.ops {
+            if let GenericOperator::Checkpoint(checkpoint) = op {
+                let table = checkpoint.0.table_name();
+                create(&segment, &src_table, &table);
+                segment.ops.clear();
+                src_table = table;
+            } else {
+                segment.ops.push(op.clone());
+            }
+        }
+        create(&segment, &src_table, dst_table);
+        sql
+    }
+}
+
+#[test]
+fn test_numeric_args_with_exponents() {
+    let args = |s: &str| {
+        OpSeq::str_to_ops(s)
+            .unwrap()
+            .into_iter()
+            .map(|op| (op.op, op.arg))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(args("a1e5"), vec![('a', vec![1e5])]);
+    assert_eq!(args("a1.5e-3"), vec![('a', vec![1.5e-3])]);
+    assert_eq!(args("x2E+1,1"), vec![('x', vec![20.0, 1.0])]);
+    // an `e` starting no exponent is the next operator
+    assert_eq!(args("a1e"), vec![('a', vec![1.0]), ('e', vec![])]);
+    assert_eq!(args("la5e2,1"), vec![('l', vec![]), ('a', vec![5e2, 1.0])]);
+    assert_eq!(
+        args("a5el"),
+        vec![('a', vec![5.0]), ('e', vec![]), ('l', vec![])]
+    );
+}
+
+#[test]
+fn test_quantiles_skip_missing_values() {
+    let points = vec![(0.0, 4.0), (1.0, f64::NAN), (2.0, 1.0), (3.0, 2.0)];
+    let quantiles = "q0,50,90,100".parse::<OpSeq>().unwrap().eval(points);
+    let expected = [(0.0, 1.0), (50.0, 2.0), (90.0, 3.6), (100.0, 4.0)];
+    let quantiles = quantiles.unwrap();
+    assert_eq!(quantiles.len(), expected.len());
+    for (q, e) in quantiles.into_iter().zip(expected) {
+        assert!(q.0 == e.0 && (q.1 - e.1).abs() < 1e-9, "{q:?} != {e:?}");
+    }
+    let empty = "q50".parse::<OpSeq>().unwrap().eval(vec![(0.0, f64::NAN)]);
+    assert!(empty.unwrap().is_empty());
+}
+
+#[test]
+fn test_named_args() {
+    let args = |s: &str| OpSeq::str_to_ops(s).map(|ops| ops[0].arg.clone());
+    // named arguments are placed by name, whatever their order
+    assert_eq!(args("a(right=2, left=1)").unwrap(), [1.0, 2.0]);
+    assert_eq!(args("a(left=1,right=2)").unwrap(), [1.0, 2.0]);
+    assert_eq!(args("a(window=3)").unwrap(), [3.0, 3.0]);
+    assert_eq!(args("a(right=2)").unwrap(), [0.0, 2.0]);
+    assert_eq!(args("o(order=descending,by=y)").unwrap(), [1.0, 1.0]);
+    assert_eq!(args("l(axis=x)").unwrap(), [10.0, 1.0]);
+    // positional ones come first, named ones fill the others
+    assert_eq!(args("a(1, right=3)").unwrap(), [1.0, 3.0]);
+    assert_eq!(args("o(1,stable=yes)").unwrap(), [1.0, 0.0, 1.0]);
+    assert_eq!(args("a(1,2)").unwrap(), [1.0, 2.0]);
+
+    assert!(args("a(right=3, 1)").is_err());
+    assert!(args("a(1, left=3)").is_err());
+    assert!(args("a(window=3, left=1)").is_err());
+    assert!(args("a(middle=1)").is_err());
+    assert!(args("o(by=z)").is_err());
+    assert!(args("c(x=1)").is_err());
+    assert!(args("a(left=1").is_err());
+}
+
+#[test]
+fn test_branches() {
+    let (head, branches) = OpSeq::parse_with_branches("oS1m/c/L1y2/").unwrap();
+    assert_eq!(head.to_string(), "oS1m");
+    let branches = branches.iter().map(OpSeq::to_string).collect::<Vec<_>>();
+    assert_eq!(branches, ["c", "L1y2", ""]);
+    let (head, branches) = OpSeq::parse_with_branches("o").unwrap();
+    assert_eq!((head.to_string(), branches.len()), ("o".to_string(), 0));
+    // a branch only restores the checkpoints of the shared operators
+    assert!(OpSeq::parse_with_branches("o/S1/L1").is_err());
+    assert!(OpSeq::parse_with_branches("o/c(").is_err());
+
+    // each branch starts from the output of the shared operators, whose
+    // outputs come in the order of the branches
+    let points = vec![(3.0, 1.0), (1.0, 2.0), (3.0, 3.0), (2.0, 4.0)];
+    let (head, branches) = OpSeq::parse_with_branches("om/x2/y0.5/").unwrap();
+    let shared = head.eval(points.clone()).unwrap();
+    assert_eq!(shared, [(1.0, 2.0), (2.0, 4.0), (3.0, 4.0)]);
+    let outputs = head.eval_branches(&branches, points).unwrap();
+    assert_eq!(
+        outputs,
+        [
+            vec![(2.0, 2.0), (4.0, 4.0), (6.0, 4.0)],
+            vec![(1.0, 1.0), (2.0, 2.0), (3.0, 2.0)],
+            shared,
+        ]
+    );
+}
+
+#[test]
+fn test_checkpoints() {
+    let points = vec![(2.0, 1.0), (1.0, 2.0), (2.0, 3.0)];
+    let eval = |s: &str| s.parse::<OpSeq>().unwrap().eval(points.clone());
+    // the records saved at the checkpoint are restored by later operators
+    assert_eq!(eval("oS1m").unwrap(), [(1.0, 2.0), (2.0, 4.0)]);
+    assert_eq!(
+        eval("oS1mL1").unwrap(),
+        [(1.0, 2.0), (2.0, 1.0), (2.0, 3.0)]
+    );
+    assert_eq!(eval("oSmy0L").unwrap(), eval("o").unwrap());
+    assert_eq!(
+        eval("S1x2S2L1y3").unwrap(),
+        [(2.0, 3.0), (1.0, 6.0), (2.0, 9.0)]
+    );
+    assert!("L1".parse::<OpSeq>().is_err());
+    assert!("S1S1".parse::<OpSeq>().is_err());
+
+    // in SQL, the checkpoint is a table of its own that the following
+    // operators (here those of a branch) continue from
+    let sql = "oS1m"
+        .parse::<OpSeq>()
+        .unwrap()
+        .to_sql_tables("t0", "shared");
+    let statements = sql
+        .split(";\n")
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    assert_eq!(statements.len(), 2);
+    assert!(statements[0].starts_with("CREATE TABLE checkpoint1 AS "));
+    assert!(statements[0].contains("FROM t0"));
+    assert!(statements[1].starts_with("CREATE TABLE shared AS "));
+    assert!(statements[1].contains("FROM checkpoint1"));
+    assert!(!statements[1].contains("t0 "));
+
+    // one table per operator, the checkpoint being restored by its name
+    let opseq = "oS1mL1".parse::<OpSeq>().unwrap();
+    let mut before = vec![];
+    let sql = opseq.to_sql_steps("t0", "x", "y", |op, table| {
+        before.push(format!("{op} {table}"));
+        format!("-- {table}\n")
+    });
+    assert_eq!(before, ["o t1", "S1 t2", "m t3", "L1 t4"]);
+    let created = sql
+        .lines()
+        .filter_map(|line| line.strip_prefix("CREATE TABLE "))
+        .map(|line| line.split_once(" AS WITH ").unwrap().0)
+        .collect::<Vec<_>>();
+    assert_eq!(created, ["t1", "t2", "checkpoint1", "t3", "t4", "t5"]);
+    assert_eq!(opseq.get_tmp_table_name(), "t5");
+    assert!(sql.starts_with("-- t1\nCREATE TABLE t1 AS WITH t1 AS ("));
+}
+
+// `actual` matches `expected` up to rounding, NaN matching NaN
+#[cfg(test)]
+fn assert_points(actual: &[(f64, f64)], expected: &[(f64, f64)]) {
+    let close =
+        |a: f64, b: f64| a.is_nan() && b.is_nan() || (a - b).abs() < 1e-9;
+    assert_eq!(actual.len(), expected.len(), "{actual:?} != {expected:?}");
+    for (a, e) in actual.iter().zip(expected) {
+        assert!(
+            close(a.0, e.0) && close(a.1, e.1),
+            "{actual:?} != {expected:?}"
+        );
+    }
+}
+
+#[test]
+fn test_average_skips_missing_values() {
+    let opseq = "a1".parse::<OpSeq>().unwrap();
+    let points = vec![(2.0, 3.0), (0.0, 1.0), (1.0, f64::NAN), (5.0, f64::NAN)];
+    // NaN is skipped like NULL by avg, a window of NaN only gives NaN
+    assert_points(
+        &opseq.eval(points).unwrap(),
+        &[(0.0, 1.0), (1.0, 2.0), (2.0, 3.0), (5.0, f64::NAN)],
+    );
+    assert!(opseq.eval(vec![]).unwrap().is_empty());
+    assert!(
+        opseq
+            .to_sql("t0", "x", "y")
+            .contains("avg(\"y\") over w as \"y-a1\" FROM t0 WINDOW w AS (ORDER BY \"x\" RANGE BETWEEN 1 PRECEDING AND 1 FOLLOWING)),")
+    );
+}
+
+#[test]
+fn test_normalize() {
+    let eval = |s: &str, points: &[(f64, f64)]| {
+        s.parse::<OpSeq>().unwrap().eval(points.to_vec()).unwrap()
+    };
+    let points = [(0.0, 2.0), (1.0, f64::NAN), (2.0, 4.0), (3.0, 6.0)];
+    assert_points(
+        &eval("n", &points),
+        &[(0.0, 0.0), (1.0, f64::NAN), (2.0, 0.5), (3.0, 1.0)],
+    );
+    // the mean and the standard deviation skip the missing value
+    let sd = (8.0f64 / 3.0).sqrt();
+    assert_points(
+        &eval("n1", &points),
+        &[
+            (0.0, -2.0 / sd),
+            (1.0, f64::NAN),
+            (2.0, 0.0),
+            (3.0, 2.0 / sd),
+        ],
+    );
+    // constant y becomes 0
+    assert_points(
+        &eval("n1", &[(0.0, 5.0), (1.0, 5.0)]),
+        &[(0.0, 0.0), (1.0, 0.0)],
+    );
+    assert!(eval("n", &[]).is_empty());
+
+    assert_eq!("n0".parse::<OpSeq>().unwrap().to_string(), "n");
+    assert!("n2".parse::<OpSeq>().is_err());
+    let sql = "n1".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains(
+        "(\"y\" - avg(\"y\") over ()) / (stddev_pop(\"y\") over ())"
+    ));
+    let sql = "n".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("max(\"y\") over () - min(\"y\") over ()"));
+}
+#[test]
+fn tmp_print_sql() {
+    for s in ["a1", "n1", "n"] {
+        println!(
+            "{s}: {}",
+            s.parse::<OpSeq>().unwrap().to_sql("t0", "x", "y")
+        );
+    }
+}
+
+#[test]
+fn test_downsample() {
+    let eval = |s: &str, points: Vec<(f64, f64)>| {
+        s.parse::<OpSeq>().unwrap().eval(points).unwrap()
+    };
+    let points = (0..7)
+        .map(|i| (i as f64, if i == 3 { f64::NAN } else { i as f64 }))
+        .collect::<Vec<_>>();
+    // every 3rd point (7 / 3 rounded up) from the first one, missing values
+    // included
+    assert_points(
+        &eval("D3", points.clone()),
+        &[(0.0, 0.0), (3.0, f64::NAN), (6.0, 6.0)],
+    );
+    assert_points(&eval("D10", points.clone()), &points);
+    assert_points(&eval("D1", points), &[(0.0, 0.0)]);
+    assert!(eval("D3", vec![]).is_empty());
+
+    assert!("D0".parse::<OpSeq>().is_err());
+    assert!("D1.5".parse::<OpSeq>().is_err());
+    assert!("D".parse::<OpSeq>().is_err());
+    let sql = "D3".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("WHERE i % ceil(len / 3::DOUBLE)::BIGINT = 0"));
+}
+
+#[test]
+fn test_median() {
+    let opseq = "M1".parse::<OpSeq>().unwrap();
+    let points = vec![
+        (4.0, 5.0),
+        (0.0, 1.0),
+        (1.0, 100.0),
+        (2.0, 3.0),
+        (3.0, f64::NAN),
+        (10.0, f64::NAN),
+    ];
+    // the outlier at x = 1 does not skew its neighbors, the window is
+    // clipped at the edges and missing values are skipped
+    assert_points(
+        &opseq.eval(points).unwrap(),
+        &[
+            (0.0, 50.5),
+            (1.0, 3.0),
+            (2.0, 51.5),
+            (3.0, 4.0),
+            (4.0, 5.0),
+            (10.0, f64::NAN),
+        ],
+    );
+    assert!(opseq.eval(vec![]).unwrap().is_empty());
+    assert_eq!(
+        "M(left=1, right=1)".parse::<OpSeq>().unwrap().to_string(),
+        "M1"
+    );
+    assert!("M-1".parse::<OpSeq>().is_err());
+    assert!(
+        opseq
+            .to_sql("t0", "x", "y")
+            .contains("median(\"y\") over w as \"y-M1\" FROM t0 WINDOW w AS (ORDER BY \"x\" RANGE BETWEEN 1 PRECEDING AND 1 FOLLOWING))")
+    );
+}
+
+#[test]
+fn test_affine() {
+    let eval = |s: &str, points: Vec<(f64, f64)>| {
+        s.parse::<OpSeq>().unwrap().eval(points).unwrap()
+    };
+    let points = vec![(1.0, 2e6), (2.0, f64::NAN), (-3.0, 0.0)];
+    // e.g. ns to ms, missing values stay missing
+    assert_points(
+        &eval("y1e-6", points.clone()),
+        &[(1.0, 2.0), (2.0, f64::NAN), (-3.0, 0.0)],
+    );
+    assert_points(
+        &eval("x2,1", points.clone()),
+        &[(3.0, 2e6), (5.0, f64::NAN), (-5.0, 0.0)],
+    );
+    assert_points(&eval("yx", points.clone()), &points);
+    assert!(eval("y2,1", vec![]).is_empty());
+
+    let to_string = |s: &str| s.parse::<OpSeq>().unwrap().to_string();
+    assert_eq!(to_string("y1,0"), "y");
+    assert_eq!(to_string("x2,0"), "x2");
+    assert_eq!(to_string("y(scale=0.5,offset=-1)"), "y0.5,-1");
+    assert!("y1,2,3".parse::<OpSeq>().is_err());
+    let sql = "y0.5,-1".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("SELECT \"x\" AS \"x\", \"y\" * 0.5 + -1 AS"));
+    let sql = "x1e-3".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("SELECT \"x\" * 0.001 + 0 AS"));
+}
+
+#[test]
+fn test_resample() {
+    let eval = |s: &str, points: Vec<(f64, f64)>| {
+        s.parse::<OpSeq>().unwrap().eval(points).unwrap()
+    };
+    let points = vec![(2.0, 4.0), (0.0, 0.0), (5.0, 10.0)];
+    // grid points between the smallest and the largest x only
+    assert_points(
+        &eval("r1,1.5", points.clone()),
+        &[(1.0, 2.0), (2.5, 5.0), (4.0, 8.0)],
+    );
+    assert_points(&eval("r0,5", points), &[(0.0, 0.0), (5.0, 10.0)]);
+    // a missing value spoils the interpolation on both of its sides
+    assert_points(
+        &eval("r0.5,1", vec![(0.0, 0.0), (1.0, f64::NAN), (2.0, 2.0)]),
+        &[(0.5, f64::NAN), (1.5, f64::NAN)],
+    );
+    assert!(eval("r0,1", vec![]).is_empty());
+
+    assert!("r0,0".parse::<OpSeq>().is_err());
+    assert!("r0,-1".parse::<OpSeq>().is_err());
+    assert!("r1".parse::<OpSeq>().is_err());
+    let sql = "r1,1.5".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("SELECT (1 + k * 1.5)::DOUBLE AS g"));
+    assert!(sql.contains(
+        "ASOF JOIN t0 lo ON g >= lo.\"x\" ASOF LEFT JOIN t0 hi ON g < hi.\"x\""
+    ));
+}
+
+#[test]
+fn test_gap() {
+    let parse = |s: &str| s.parse::<OpSeq>().unwrap();
+    let points = vec![(1.0, 5.0), (3.0, 6.0), (4.0, f64::NAN), (6.5, 7.0)];
+    assert_points(
+        &parse("g").eval(points.clone()).unwrap(),
+        &[(1.0, f64::NAN), (3.0, 2.0), (4.0, 1.0), (6.5, 2.5)],
+    );
+    assert_points(
+        &parse("g1").eval(points.clone()).unwrap(),
+        &[(0.0, f64::NAN), (1.0, 2.0), (2.0, 1.0), (3.0, 2.5)],
+    );
+    assert!(parse("g").eval(vec![]).unwrap().is_empty());
+    // the gap to the last point of the previous chunk is kept
+    let mut chunked = Vec::new();
+    parse("g")
+        .eval_chunks(
+            [
+                Ok(points[..2].to_vec()),
+                Ok(vec![]),
+                Ok(points[2..].to_vec()),
+            ],
+            |chunk| {
+                chunked.extend(chunk);
+                Ok(())
+            },
+        )
+        .unwrap();
+    assert_points(&chunked, &parse("g").eval(points).unwrap());
+
+    assert_eq!(parse("g0").to_string(), "g");
+    assert!("g2".parse::<OpSeq>().is_err());
+    let sql = parse("g").to_sql("t0", "x", "y");
+    assert!(
+        sql.contains("SELECT \"x\" AS \"x\", \"x\" - lag(\"x\") over () AS")
+    );
+    let sql = parse("g1").to_sql("t0", "x", "y");
+    assert!(sql.contains("SELECT row_number() over () - 1 AS \"index\""));
+}
+
+#[test]
+fn test_moving_min_max() {
+    let eval = |s: &str, points: Vec<(f64, f64)>| {
+        s.parse::<OpSeq>().unwrap().eval(points).unwrap()
+    };
+    let points = vec![
+        (3.0, 5.0),
+        (0.0, 3.0),
+        (1.0, f64::NAN),
+        (2.0, 1.0),
+        (10.0, f64::NAN),
+    ];
+    // missing values are skipped, a window of missing values only gives a
+    // missing value
+    assert_points(
+        &eval("b1", points.clone()),
+        &[
+            (0.0, 3.0),
+            (1.0, 1.0),
+            (2.0, 1.0),
+            (3.0, 1.0),
+            (10.0, f64::NAN),
+        ],
+    );
+    assert_points(
+        &eval("t1", points.clone()),
+        &[
+            (0.0, 3.0),
+            (1.0, 3.0),
+            (2.0, 5.0),
+            (3.0, 5.0),
+            (10.0, f64::NAN),
+        ],
+    );
+    // the window is measured in x, and may be asymmetric
+    assert_points(
+        &eval("t0,1", points),
+        &[
+            (0.0, 3.0),
+            (1.0, 1.0),
+            (2.0, 5.0),
+            (3.0, 5.0),
+            (10.0, f64::NAN),
+        ],
+    );
+    assert!(eval("b1", vec![]).is_empty());
+    assert!(eval("t1", vec![]).is_empty());
+
+    assert!("b-1".parse::<OpSeq>().is_err());
+    let sql = "b1".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("min(\"y\") over w as \"y-b1\" FROM t0 WINDOW w AS (ORDER BY \"x\" RANGE BETWEEN 1 PRECEDING AND 1 FOLLOWING))"));
+    let sql = "t0,1".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("max(\"y\") over w as \"y-t0,1\" FROM t0 WINDOW w AS (ORDER BY \"x\" RANGE BETWEEN 0 PRECEDING AND 1 FOLLOWING))"));
+}
+
+#[test]
+fn test_fft() {
+    let opseq = "F".parse::<OpSeq>().unwrap();
+    // 3 + cos(2 pi 0.5 x) sampled every 0.5 over 8 points
+    let points = (0..8)
+        .map(|j| {
+            let x = j as f64 * 0.5;
+            (x, 3.0 + (2.0 * std::f64::consts::PI * 0.5 * x).cos())
+        })
+        .collect::<Vec<_>>();
+    let spectrum = opseq.eval(points.clone()).unwrap();
+    // frequencies k / (n * interval) up to the Nyquist frequency
+    assert_points(
+        &spectrum,
+        &[(0.0, 3.0), (0.25, 0.0), (0.5, 1.0), (0.75, 0.0), (1.0, 0.0)],
+    );
+    // a missing value counts as 0
+    let mut missing = points.clone();
+    missing[3].1 = f64::NAN;
+    let mut zero = points;
+    zero[3].1 = 0.0;
+    assert_points(&opseq.eval(missing).unwrap(), &opseq.eval(zero).unwrap());
+    // at least two points are needed for the interval
+    assert!(opseq.eval(vec![]).unwrap().is_empty());
+    assert!(opseq.eval(vec![(0.0, 1.0)]).unwrap().is_empty());
+
+    let sql = opseq.to_sql("t0", "x", "y");
+    assert!(sql.contains("SELECT k / (n * dt) AS \"frequency\""));
+    assert!(sql.contains("FROM t0 HAVING count(*) > 1"));
+}
+
+#[test]
+fn test_regression() {
+    let points = vec![(0.0, 1.0), (1.0, 2.0), (2.0, 5.0), (3.0, f64::NAN)];
+    // y = 2 * x + 2/3, the missing value being left out
+    let eval = |opseq: &str| {
+        opseq
+            .parse::<OpSeq>()
+            .unwrap()
+            .eval(points.clone())
+            .unwrap()
+    };
+    assert_points(
+        &eval("R"),
+        &[
+            (0.0, 2.0 / 3.0),
+            (1.0, 8.0 / 3.0),
+            (2.0, 14.0 / 3.0),
+            (3.0, 20.0 / 3.0),
+        ],
+    );
+    assert_points(
+        &eval("R1"),
+        &[
+            (0.0, 1.0 / 3.0),
+            (1.0, -2.0 / 3.0),
+            (2.0, 1.0 / 3.0),
+            (3.0, f64::NAN),
+        ],
+    );
+    assert_points(
+        &eval("R2"),
+        &[(0.0, 2.0), (1.0, 2.0 / 3.0), (2.0, 12.0 / 13.0)],
+    );
+    // a single x leaves the line undefined
+    let opseq = "R2".parse::<OpSeq>().unwrap();
+    assert_points(
+        &opseq.eval(vec![(1.0, 1.0), (1.0, 2.0)]).unwrap(),
+        &[(0.0, f64::NAN), (1.0, f64::NAN), (2.0, f64::NAN)],
+    );
+    assert!(opseq.eval(vec![]).unwrap().is_empty());
+
+    assert!("R3".parse::<OpSeq>().is_err());
+    assert_eq!("R0".parse::<OpSeq>().unwrap().to_string(), "R");
+    let sql = "R1".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("regr_slope(\"y\", \"x\") over ()"));
+    assert!(opseq.to_sql("t0", "x", "y").contains("HAVING count(*) > 0"));
+}
+
+#[test]
+fn test_top_k() {
+    let points = vec![
+        (0.0, 3.0),
+        (1.0, f64::NAN),
+        (2.0, 5.0),
+        (3.0, 3.0),
+        (4.0, 1.0),
+    ];
+    let eval = |opseq: &str| {
+        opseq
+            .parse::<OpSeq>()
+            .unwrap()
+            .eval(points.clone())
+            .unwrap()
+    };
+    // ties keep the earlier records, in their original order
+    assert_points(&eval("k2"), &[(0.0, 3.0), (2.0, 5.0)]);
+    assert_points(&eval("k2,1"), &[(0.0, 3.0), (4.0, 1.0)]);
+    // missing values come last in either order
+    assert_points(
+        &eval("k4"),
+        &[(0.0, 3.0), (2.0, 5.0), (3.0, 3.0), (4.0, 1.0)],
+    );
+    assert_points(
+        &eval("k4,1"),
+        &[(0.0, 3.0), (2.0, 5.0), (3.0, 3.0), (4.0, 1.0)],
+    );
+    assert_points(&eval("k9"), &points);
+    assert!(eval("k0").is_empty());
+    assert!(
+        "k2".parse::<OpSeq>()
+            .unwrap()
+            .eval(vec![])
+            .unwrap()
+            .is_empty()
+    );
+
+    assert!("k-1".parse::<OpSeq>().is_err());
+    assert!("k1.5".parse::<OpSeq>().is_err());
+    assert!("k1,2".parse::<OpSeq>().is_err());
+    assert_eq!("k3,0".parse::<OpSeq>().unwrap().to_string(), "k3");
+    let sql = "k3,1".parse::<OpSeq>().unwrap().to_sql("t0", "x", "y");
+    assert!(sql.contains("ORDER BY \"y\" ASC NULLS LAST, i) <= 3 ORDER BY i)"));
+}
+
+#[test]
+fn test_eval_chunk() {
+    let points = vec![(1.0, 3.0), (2.0, 5.0), (4.0, f64::NAN), (7.0, 6.0)];
+    let opseq = "sgx2,1f".parse::<OpSeq>().unwrap();
+    assert!(opseq.is_streaming());
+    // the points of a growing stream, evaluated as they come
+    let mut lasts = vec![];
+    let mut chunked = vec![];
+    for chunk in [&points[..1], &[], &points[1..3], &points[3..]] {
+        chunked.extend(opseq.eval_chunk(chunk.to_vec(), &mut lasts).unwrap());
+    }
+    assert_points(&chunked, &opseq.eval(points).unwrap());
+
+    let opseq = "so".parse::<OpSeq>().unwrap();
+    assert!(!opseq.is_streaming());
+    assert!(opseq.eval_chunk(vec![(1.0, 1.0)], &mut vec![]).is_err());
 }
@@ -0,0 +1,374 @@
+// Serializable definition of a single-series pipeline, shared by the library
+// (PlotterBuilder), sp (--pipeline) and msp (pipeline key of the series)
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CsvDialect, DataFormat, DataInput, DataSeriesOptions, GnuplotTemplate,
+    OpSeq, PlotType, PlotterBuilder,
+};
+
+/// Pipeline read from TOML or JSON, e.g.
+///
+/// ```toml
+/// x = "$1"
+/// y = "$3"
+/// input_filter = "$3 > 0"
+///
+/// [input]
+/// path = "data.csv"
+///
+/// [[operators]]
+/// op = "a"
+/// args = [5, 5]
+///
+/// [output]
+/// script = "plot.gp"
+///
+/// [plot]
+/// type = "lines"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub input: InputConfig,
+    /// X axis expression
+    pub x: String,
+    /// Y axis expression
+    pub y: String,
+    pub input_filter: Option<String>,
+    pub output_filter: Option<String>,
+    /// Character marking column indexes in expressions
+    pub index_mark: char,
+    /// Built-in and registered operators, applied in order
+    pub operators: Vec<OperatorConfig>,
+    pub output: OutputConfig,
+    pub plot: PlotConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    pub path: String,
+    /// Input format, inferred by duckdb if unset
+    pub format: Option<String>,
+    /// Whether the input has a header row, detected if unset
+    pub header: Option<bool>,
+    /// Delimiter of CSV input, detected if unset
+    pub delimiter: Option<char>,
+    /// Character quoting the values of CSV input
+    pub quote: Option<char>,
+    /// Character escaping the quote within quoted values of CSV input
+    pub escape: Option<char>,
+    /// Prefix of the comment lines of CSV input
+    pub comment: Option<char>,
+    /// Pad rows of CSV input with missing columns and read malformed quoting
+    /// as is
+    pub flexible: bool,
+    /// Sheet of spreadsheet input, by name or position from 1
+    pub sheet: Option<String>,
+    /// Samples of Prometheus input, by metric name and/or label matchers
+    pub metric: Option<String>,
+    /// Offsets of the columns of fixed-width input, inferred if unset
+    pub column_offsets: Option<Vec<usize>>,
+    /// Number of lines skipped at the top of the file, before its header
+    pub skip_rows: usize,
+    /// Number of trailing data rows skipped, before the row limits
+    pub skip_footer: usize,
+    /// Number of leading data rows skipped
+    pub skip: usize,
+    /// Keep the first N rows after skip
+    pub head: Option<usize>,
+    /// Keep the last N rows after skip and head
+    pub tail: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperatorConfig {
+    /// Letter of the operator, e.g. `a`, or the name of a registered one
+    pub op: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Where the datasheet is written, a temporary file if unset
+    pub datasheet: Option<PathBuf>,
+    /// Where the gnuplot script is written
+    pub script: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlotConfig {
+    /// Run gnuplot on the script
+    pub enabled: bool,
+    /// `points`, `lines` or `linespoints`
+    #[serde(rename = "type")]
+    pub plot_type: String,
+    /// Title of the data series in the key
+    pub label: Option<String>,
+    /// Output file of gnuplot
+    pub output: Option<String>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            input: InputConfig::default(),
+            x: "$1".to_string(),
+            y: "$2".to_string(),
+            input_filter: None,
+            output_filter: None,
+            index_mark: '$',
+            operators: vec![],
+            output: OutputConfig::default(),
+            plot: PlotConfig::default(),
+        }
+    }
+}
+
+impl Default for PlotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            plot_type: "points".to_string(),
+            label: None,
+            output: None,
+        }
+    }
+}
+
+impl PipelineConfig {
+    pub fn from_toml_str(s: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn from_json_str(s: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    /// Read a JSON file if its extension is `.json`, a TOML file otherwise
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).context(format!(
+            "Failed to read pipeline file '{}'",
+            path.display()
+        ))?;
+        let is_json = path.extension().is_some_and(|ext| ext == "json");
+        if is_json {
+            Self::from_json_str(&content)
+        } else {
+            Self::from_toml_str(&content)
+        }
+        .context(format!(
+            "Failed to parse pipeline file '{}'",
+            path.display()
+        ))
+    }
+
+    pub fn to_toml_string(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn to_json_string(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Operation sequence of `operators`, `None` if there is none
+    pub fn opseq(&self) -> anyhow::Result<Option<OpSeq>> {
+        if self.operators.is_empty() {
+            return Ok(None);
+        }
+        self.operators
+            .iter()
+            .fold(OpSeq::builder(), |builder, op| {
+                let mut chars = op.op.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => builder.push(c, op.args.clone()),
+                    _ => builder.registered(&op.op, &op.args),
+                }
+            })
+            .build()
+            .map(Some)
+    }
+
+    pub fn plot_type(&self) -> anyhow::Result<PlotType> {
+        Ok(match self.plot.plot_type.as_str() {
+            "points" => PlotType::Points(None),
+            "lines" => PlotType::Lines(None),
+            "linespoints" => PlotType::Linespoints(None, None),
+            other => bail!("Invalid plot type '{other}'"),
+        })
+    }
+
+    pub fn to_builder(&self) -> anyhow::Result<PlotterBuilder> {
+        let input = &self.input;
+        if input.path.is_empty() {
+            bail!("Missing input path of the pipeline");
+        }
+        let format = match &input.format {
+            Some(format) => format.parse()?,
+            None => DataFormat::Auto,
+        };
+        let data_input =
+            DataInput::new(format, input.path.clone(), input.header)?
+                .with_delimiter(input.delimiter)?
+                .with_dialect(
+                    CsvDialect::default()
+                        .with_quote(input.quote)
+                        .with_escape(input.escape)
+                        .with_comment(input.comment)
+                        .with_flexible(input.flexible),
+                )?
+                .with_sheet(input.sheet.clone())?
+                .with_metric(input.metric.clone())?
+                .with_column_offsets(input.column_offsets.clone())?
+                .with_skipped_lines(input.skip_rows, input.skip_footer)?
+                .with_row_limits(input.skip, input.head, input.tail);
+        let mut builder = PlotterBuilder::from_input(data_input)
+            .with_exprs(&self.x, &self.y)
+            .with_filters(
+                self.input_filter.as_ref(),
+                self.output_filter.as_ref(),
+            )
+            .with_index_mark(self.index_mark)
+            .with_series_options(
+                DataSeriesOptions::default()
+                    .with_plot_type(self.plot_type()?)
+                    .with_label(self.plot.label.as_ref()),
+            )
+            .with_plot(self.plot.enabled);
+        if let Some(opseq) = self.opseq()? {
+            builder = builder.with_ops(opseq);
+        }
+        if let Some(path) = &self.output.datasheet {
+            builder = builder.with_datasheet_path(path);
+        }
+        if let Some(path) = &self.output.script {
+            builder = builder.with_script_path(path);
+        }
+        if let Some(output) = &self.plot.output {
+            builder = builder.with_template(
+                GnuplotTemplate::default().with_output(Some(output)),
+            );
+        }
+        Ok(builder)
+    }
+
+    /// Values of the sp options (as in a recipe) running this pipeline,
+    /// which dumps the datasheet to `output.datasheet` if the plot is
+    /// disabled
+    pub fn to_recipe(&self) -> anyhow::Result<toml::Table> {
+        let input = &self.input;
+        let mut table = toml::Table::new();
+        let mut set = |key: &str, value: toml::Value| {
+            table.insert(key.to_string(), value);
+        };
+        if !input.path.is_empty() {
+            set("i", input.path.clone().into());
+        }
+        if let Some(format) = &input.format {
+            set("f", format.clone().into());
+        }
+        if let Some(header) = input.header {
+            set("header", header.to_string().into());
+        }
+        if let Some(delimiter) = input.delimiter {
+            set("delimiter", delimiter.to_string().into());
+        }
+        for (key, c) in [
+            ("quote", input.quote),
+            ("escape", input.escape),
+            ("comment", input.comment),
+        ] {
+            if let Some(c) = c {
+                set(key, c.to_string().into());
+            }
+        }
+        if input.flexible {
+            set("flexible", true.into());
+        }
+        if let Some(sheet) = &input.sheet {
+            set("sheet", sheet.clone().into());
+        }
+        if let Some(metric) = &input.metric {
+            set("metric", metric.clone().into());
+        }
+        if let Some(offsets) = &input.column_offsets {
+            let offsets = offsets.iter().map(usize::to_string);
+            set(
+                "column-offsets",
+                offsets.collect::<Vec<_>>().join(",").into(),
+            );
+        }
+        set("skip-rows", (input.skip_rows as i64).into());
+        set("skip-footer", (input.skip_footer as i64).into());
+        set("skip", (input.skip as i64).into());
+        if let Some(head) = input.head {
+            set("head", (head as i64).into());
+        }
+        if let Some(tail) = input.tail {
+            set("tail", (tail as i64).into());
+        }
+        set("x", self.x.clone().into());
+        set("y", self.y.clone().into());
+        if let Some(filter) = &self.input_filter {
+            set("if", filter.clone().into());
+        }
+        if let Some(filter) = &self.output_filter {
+            set("of", filter.clone().into());
+        }
+        set("index-mark", self.index_mark.to_string().into());
+        if let Some(opseq) = self.opseq()? {
+            set("e", opseq.to_string().into());
+        }
+        if let Some(path) = &self.output.script {
+            set("S", path.display().to_string().into());
+        }
+        if self.plot.enabled {
+            set("m", "plot".into());
+        } else {
+            set("m", "dump".into());
+            if let Some(path) = &self.output.datasheet {
+                set("o", path.display().to_string().into());
+            }
+        }
+        Ok(table)
+    }
+
+    /// Keys of an msp data series (as in a manifest) plotting this pipeline,
+    /// whose input is given as an escaped glob pattern
+    pub fn to_series_table(&self) -> anyhow::Result<toml::Table> {
+        let mut table = toml::Table::new();
+        let mut set = |key: &str, value: String| {
+            table.insert(key.to_string(), value.into());
+        };
+        set(
+            "file",
+            format!("glob:{}", glob::Pattern::escape(&self.input.path)),
+        );
+        set("xexpr", self.x.clone());
+        set("yexpr", self.y.clone());
+        if let Some(filter) = &self.input_filter {
+            set("ifilter", filter.clone());
+        }
+        if let Some(filter) = &self.output_filter {
+            set("ofilter", filter.clone());
+        }
+        if let Some(opseq) = self.opseq()? {
+            set("opseq", opseq.to_string());
+        }
+        set("plot", self.plot.plot_type.clone());
+        if let Some(label) = &self.plot.label {
+            set("title", label.clone());
+        }
+        Ok(table)
+    }
+}
@@ -0,0 +1,849 @@
+// The whole pipeline of sp (ingestion, expressions, filters, operators and
+// plotting) for programs embedding it without building command lines
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdout, Command, ExitStatus, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{
+    Column, DataInput, DataPoints, DataSeriesOptions, ErrorCategory, Expr,
+    GnuplotTemplate, MemorySize, MissingValues, OpSeq, PlainSelector, Plotter,
+    TempArtifacts, TimeFormat, WithCategory, check_gnuplot,
+    plotter::{parse_csv_point, temp_filename},
+};
+
+/// Flag for aborting `PlotterBuilder::execute` from another thread (e.g. a
+/// cancel button or a signal handler), the running duckdb or gnuplot is
+/// killed as soon as it is set
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            return Err(anyhow::anyhow!("Cancelled"))
+                .with_category(ErrorCategory::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+// Run `work` (e.g. reading the output of `child`) and wait for `child`,
+// which is killed once `cancel` is cancelled
+fn supervise<T>(
+    mut child: Child,
+    cancel: Option<&CancellationToken>,
+    work: impl FnOnce() -> T,
+) -> std::io::Result<(T, ExitStatus)> {
+    let Some(cancel) = cancel else {
+        let result = work();
+        return Ok((result, child.wait()?));
+    };
+    let child = Mutex::new(child);
+    let done = AtomicBool::new(false);
+    let poll_interval = Duration::from_millis(20);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(Ordering::Relaxed) {
+                if cancel.is_cancelled() {
+                    let _ = child.lock().unwrap().kill();
+                    return;
+                }
+                std::thread::sleep(poll_interval);
+            }
+        });
+        let result = work();
+        // polled so that the watcher could take the lock to kill
+        let status = loop {
+            match child.lock().unwrap().try_wait() {
+                Ok(None) => {}
+                status => break status,
+            }
+            std::thread::sleep(poll_interval);
+        };
+        done.store(true, Ordering::Relaxed);
+        Ok((result, status?.unwrap()))
+    })
+}
+
+/// Run `sql` with duckdb, `consume` is given the piped stdout (if any).
+/// Failures are categorized by the error type reported by duckdb.
+pub fn run_duckdb<T>(
+    sql: &str,
+    stdout: Stdio,
+    consume: impl FnOnce(Option<ChildStdout>) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    run_duckdb_cancellable(sql, stdout, None, consume)
+}
+
+fn run_duckdb_cancellable<T>(
+    sql: &str,
+    stdout: Stdio,
+    cancel: Option<&CancellationToken>,
+    consume: impl FnOnce(Option<ChildStdout>) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let _span = tracing::info_span!("duckdb").entered();
+    tracing::debug!(sql);
+    let mut child = Command::new("duckdb")
+        .arg("-csv")
+        .arg("-bail")
+        .arg("-c")
+        .arg(sql)
+        .stdout(stdout)
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(anyhow::Error::from)
+        .with_category(ErrorCategory::ExternalTool)?;
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr_handle = std::thread::spawn(move || {
+        let mut content = String::new();
+        let _ = stderr.read_to_string(&mut content);
+        content
+    });
+    let stdout = child.stdout.take();
+    let (result, status) = supervise(child, cancel, || consume(stdout))?;
+    let stderr = stderr_handle.join().unwrap_or_default();
+    if let Some(cancel) = cancel {
+        cancel.check()?;
+    }
+    if !status.success() {
+        let category = if ["Parser Error", "Binder Error", "Catalog Error"]
+            .iter()
+            .any(|t| stderr.contains(t))
+        {
+            ErrorCategory::Expression
+        } else if ["IO Error", "Invalid Input Error", "Conversion Error"]
+            .iter()
+            .any(|t| stderr.contains(t))
+        {
+            ErrorCategory::InputParse
+        } else {
+            ErrorCategory::ExternalTool
+        };
+        return Err(anyhow::anyhow!(
+            "duckdb failed with {status}: {}\nOriginal SQL:\n{sql}",
+            stderr.trim()
+        ))
+        .with_category(category);
+    }
+    if !stderr.trim().is_empty() {
+        tracing::debug!(stderr = stderr.trim(), "duckdb succeeded");
+    }
+    result
+}
+
+impl OpSeq {
+    /// Number of points read at once by `OpSeq::apply_csv`
+    pub const STREAM_CHUNK_POINTS: usize = 65536;
+
+    /// Apply the operators to the points without any file, natively unless
+    /// there are registered operators (which require duckdb). The points keep
+    /// their titles.
+    pub fn apply(&self, points: &DataPoints) -> anyhow::Result<DataPoints> {
+        if self.is_native() {
+            return Ok(DataPoints::new(
+                &points.xtitle,
+                &points.ytitle,
+                self.eval(points.points.clone())?,
+            ));
+        }
+        self.apply_sql(points, false)
+    }
+
+    // `apply` by duckdb, whatever the operators, as a single query or one
+    // table after another (`to_sql_steps`) if `steps`
+    fn apply_sql(
+        &self,
+        points: &DataPoints,
+        steps: bool,
+    ) -> anyhow::Result<DataPoints> {
+        // non-finite values are not numeric literals of SQL, and NaN stands
+        // for a missing value
+        let value = |v: f64| {
+            if v.is_nan() {
+                "NULL".to_string()
+            } else if v.is_finite() {
+                v.to_string()
+            } else {
+                format!("'{v}'::DOUBLE")
+            }
+        };
+        let mut sql = "CREATE TABLE t0 (x DOUBLE, y DOUBLE);\n".to_string();
+        if !points.points.is_empty() {
+            sql.push_str(&format!(
+                "INSERT INTO t0 VALUES {};\n",
+                points
+                    .points
+                    .iter()
+                    .map(|&(x, y)| format!("({}, {})", value(x), value(y)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        if steps {
+            sql.push_str(
+                &self.to_sql_steps("t0", "x", "y", |_, _| String::new()),
+            );
+        } else {
+            sql.push_str(&self.to_sql("t0", "x", "y"));
+        }
+        sql.push_str(&format!(
+            "SELECT * FROM {};\n",
+            self.get_tmp_table_name()
+        ));
+        run_duckdb(&sql, Stdio::piped(), |stdout| {
+            let result =
+                DataPoints::from_reader(BufReader::new(stdout.unwrap()))
+                    .context("Failed to read the result of duckdb")?;
+            Ok(DataPoints {
+                xtitle: points.xtitle.clone(),
+                ytitle: points.ytitle.clone(),
+                points: result.points,
+            })
+        })
+    }
+
+    /// Same as `eval`, but lazily over the points of an iterator (e.g. the
+    /// `PointStream` of `DataPoints::stream_csv`), `Self::STREAM_CHUNK_POINTS`
+    /// points at a time, so that inputs larger than memory are processed in
+    /// bounded memory. Only streaming operators (e.g. `f`, `s` and `x`) are
+    /// accepted, as the others need all the points (see `eval_chunks`). The
+    /// iteration ends after the first error.
+    pub fn eval_iter<'a, E: Into<anyhow::Error>>(
+        &'a self,
+        points: impl IntoIterator<Item = Result<(f64, f64), E>> + 'a,
+    ) -> anyhow::Result<impl Iterator<Item = anyhow::Result<(f64, f64)>> + 'a>
+    {
+        if let Some(op) = self.ops.iter().find(|op| !op.is_streaming()) {
+            anyhow::bail!(
+                "Operator {op} needs all the points, which an iterator does not hold"
+            );
+        }
+        let mut points = points.into_iter().map(|p| p.map_err(Into::into));
+        // last input point of each operator
+        let mut lasts = vec![None; self.ops.len()];
+        let mut failed = false;
+        let chunks = std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let chunk = points
+                .by_ref()
+                .take(Self::STREAM_CHUNK_POINTS)
+                .collect::<anyhow::Result<Vec<_>>>();
+            if chunk.as_ref().is_ok_and(Vec::is_empty) {
+                return None;
+            }
+            let chunk = chunk.and_then(|chunk| {
+                self.ops.iter().zip(lasts.iter_mut()).try_fold(
+                    chunk,
+                    |chunk, (op, last)| {
+                        let next_last = chunk.last().copied().or(*last);
+                        let chunk = op.eval_chunk(chunk, *last)?;
+                        *last = next_last;
+                        anyhow::Ok(chunk)
+                    },
+                )
+            });
+            failed = chunk.is_err();
+            Some(chunk)
+        });
+        Ok(chunks.flat_map(|chunk| match chunk {
+            Ok(chunk) => chunk.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        }))
+    }
+
+    /// Same as `apply`, but the points are read from a datasheet (CSV with a
+    /// header row) and written to another one, `Self::STREAM_CHUNK_POINTS`
+    /// points at a time, so that streaming operators run in bounded memory (see
+    /// `OpSeq::eval_chunks`). Returns the number of points written.
+    pub fn apply_csv(
+        &self,
+        reader: impl BufRead,
+        writer: impl Write,
+    ) -> anyhow::Result<usize> {
+        self.apply_csv_with(reader, writer, None)
+    }
+
+    /// Same as `apply_csv`, but the blocking operators are bounded by
+    /// `max_memory` as well (see `OpSeq::eval_chunks_bounded`)
+    pub fn apply_csv_bounded(
+        &self,
+        reader: impl BufRead,
+        writer: impl Write,
+        max_memory: MemorySize,
+    ) -> anyhow::Result<usize> {
+        self.apply_csv_with(reader, writer, Some(max_memory))
+    }
+
+    fn apply_csv_with(
+        &self,
+        mut reader: impl BufRead,
+        mut writer: impl Write,
+        max_memory: Option<MemorySize>,
+    ) -> anyhow::Result<usize> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        write!(writer, "{header}")?;
+        let mut rows = 0;
+        let mut write_points = |points: Vec<(f64, f64)>| {
+            for (x, y) in &points {
+                writeln!(writer, "{x},{y}")?;
+            }
+            rows += points.len();
+            anyhow::Ok(())
+        };
+        if self.is_native() {
+            let mut lines = reader
+                .lines()
+                .filter(|line| !line.as_ref().is_ok_and(String::is_empty));
+            let chunks = std::iter::from_fn(|| {
+                let chunk = lines
+                    .by_ref()
+                    .take(Self::STREAM_CHUNK_POINTS)
+                    .map(|line| Ok(parse_csv_point(&line?)?))
+                    .collect::<anyhow::Result<Vec<_>>>();
+                match chunk {
+                    Ok(chunk) if chunk.is_empty() => None,
+                    chunk => Some(chunk),
+                }
+            });
+            match max_memory {
+                Some(max_memory) => {
+                    self.eval_chunks_bounded(chunks, max_memory, write_points)?
+                }
+                None => self.eval_chunks(chunks, write_points)?,
+            }
+        } else {
+            // registered operators need all the points in duckdb anyway
+            let points =
+                DataPoints::from_reader(header.as_bytes().chain(reader))?;
+            write_points(self.apply(&points)?.points)?;
+        }
+        writer.flush()?;
+        Ok(rows)
+    }
+}
+
+/// Files produced by `PlotterBuilder::execute`
+#[derive(Debug, Clone)]
+pub struct Artifacts {
+    /// The (x, y) datasheet, as CSV with a header row
+    pub datasheet_path: PathBuf,
+
+    /// The gnuplot script, if it is written
+    pub script_path: Option<PathBuf>,
+
+    /// Number of rows in the datasheet
+    pub rows: usize,
+}
+
+/// Progress of `PlotterBuilder::execute`, reported to the observer set with
+/// `PlotterBuilder::with_observer`
+#[derive(Debug, Clone)]
+pub enum PipelineEvent {
+    /// The SQL of ingestion, expressions, filters and operators is sent to
+    /// duckdb, which runs them as a single query
+    QueryStarted {
+        sql: String,
+    },
+
+    /// Rows of the datasheet received from duckdb so far, reported every
+    /// `PipelineEvent::ROWS_PER_EVENT` rows
+    RowsRead {
+        rows: usize,
+    },
+
+    /// The datasheet is complete
+    DatasheetWritten {
+        path: PathBuf,
+        rows: usize,
+    },
+
+    ScriptWritten {
+        path: PathBuf,
+    },
+
+    PlotStarted,
+
+    PlotFinished,
+}
+
+impl PipelineEvent {
+    pub const ROWS_PER_EVENT: usize = 10000;
+}
+
+#[derive(Clone)]
+struct Observer(Arc<dyn Fn(&PipelineEvent) + Send + Sync>);
+
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Observer")
+    }
+}
+
+/// Builder of a single-series sp pipeline, e.g.
+///
+/// ```no_run
+/// # use spreadsheet_plotter::PlotterBuilder;
+/// let artifacts = PlotterBuilder::new("data.csv")?
+///     .with_exprs("$1", "$3")
+///     .with_opseq("c")?
+///     .with_script_path("plot.gp")
+///     .execute()?;
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct PlotterBuilder {
+    input: DataInput,
+    xexpr: String,
+    yexpr: String,
+    input_filter: Option<String>,
+    output_filter: Option<String>,
+    index_mark: char,
+    time_x: Option<TimeFormat>,
+    missing: MissingValues,
+    columns: Option<(Column, Column)>,
+    opseq: Option<OpSeq>,
+    template: GnuplotTemplate,
+    series_options: DataSeriesOptions,
+    datasheet_path: Option<PathBuf>,
+    script_path: Option<PathBuf>,
+    plot: bool,
+    max_memory: Option<MemorySize>,
+    observer: Option<Observer>,
+    cancel: Option<CancellationToken>,
+}
+
+impl PlotterBuilder {
+    /// Read the input file of format inferred by duckdb
+    pub fn new(input_path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        DataInput::new(
+            Default::default(),
+            input_path.as_ref().display().to_string(),
+            None,
+        )
+        .with_category(ErrorCategory::Usage)
+        .map(Self::from_input)
+    }
+
+    pub fn from_input(input: DataInput) -> Self {
+        Self {
+            input,
+            xexpr: "$1".to_string(),
+            yexpr: "$2".to_string(),
+            input_filter: None,
+            output_filter: None,
+            index_mark: '$',
+            time_x: None,
+            missing: MissingValues::default(),
+            columns: None,
+            opseq: None,
+            template: GnuplotTemplate::default(),
+            series_options: DataSeriesOptions::default(),
+            datasheet_path: None,
+            script_path: None,
+            plot: false,
+            max_memory: None,
+            observer: None,
+            cancel: None,
+        }
+    }
+
+    pub fn with_exprs(
+        mut self,
+        xexpr: impl AsRef<str>,
+        yexpr: impl AsRef<str>,
+    ) -> Self {
+        self.xexpr = xexpr.as_ref().to_string();
+        self.yexpr = yexpr.as_ref().to_string();
+        self
+    }
+
+    pub fn with_filters(
+        mut self,
+        input_filter: Option<impl AsRef<str>>,
+        output_filter: Option<impl AsRef<str>>,
+    ) -> Self {
+        self.input_filter = input_filter.map(|s| s.as_ref().to_string());
+        self.output_filter = output_filter.map(|s| s.as_ref().to_string());
+        self
+    }
+
+    /// Character marking column indexes in expressions, `$` by default
+    pub fn with_index_mark(mut self, index_mark: char) -> Self {
+        self.index_mark = index_mark;
+        self
+    }
+
+    /// x values are timestamps of the format, plotted on a time axis (see
+    /// `PlainSelector::with_time_x`)
+    pub fn with_time_x(mut self, time_x: Option<TimeFormat>) -> Self {
+        self.time_x = time_x;
+        self
+    }
+
+    /// Policy of the selected records with missing values, which are kept
+    /// (`MissingValues::Propagate`) by default
+    pub fn with_missing(mut self, missing: MissingValues) -> Self {
+        self.missing = missing;
+        self
+    }
+
+    /// Names and units of the selected x and y values, which label the axes
+    /// as composed by the operators (see `OpSeq::columns`)
+    pub fn with_columns(mut self, x: Column, y: Column) -> Self {
+        self.columns = Some((x, y));
+        self
+    }
+
+    /// Built-in and registered operators applied to the selected data,
+    /// external operators are not supported
+    pub fn with_opseq(
+        mut self,
+        opseq: impl AsRef<str>,
+    ) -> anyhow::Result<Self> {
+        self.opseq = Some(opseq.as_ref().parse()?);
+        Ok(self)
+    }
+
+    /// Same as `with_opseq`, but with operators built by `OpSeq::builder`
+    pub fn with_ops(mut self, opseq: OpSeq) -> Self {
+        self.opseq = Some(opseq);
+        self
+    }
+
+    /// Appearance of the plot, whose data series are replaced by the one of
+    /// the datasheet
+    pub fn with_template(mut self, template: GnuplotTemplate) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Appearance of the data series, whose datasheet path is replaced
+    pub fn with_series_options(mut self, options: DataSeriesOptions) -> Self {
+        self.series_options = options;
+        self
+    }
+
+    /// Where the datasheet is written, a temporary file by default
+    pub fn with_datasheet_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.datasheet_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Where the gnuplot script is written, not written by default
+    pub fn with_script_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.script_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Memory budget of duckdb, beyond which it spills to a temporary
+    /// directory (reported as a warning)
+    pub fn with_max_memory(mut self, max_memory: MemorySize) -> Self {
+        self.max_memory = Some(max_memory);
+        self
+    }
+
+    /// Also run gnuplot on the script
+    pub fn with_plot(mut self, plot: bool) -> Self {
+        self.plot = plot;
+        self
+    }
+
+    /// Call `observer` on each `PipelineEvent` of `execute`, e.g. to show
+    /// progress or forward the events through a channel
+    pub fn with_observer(
+        mut self,
+        observer: impl Fn(&PipelineEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.observer = Some(Observer(Arc::new(observer)));
+        self
+    }
+
+    /// Abort `execute` once `token` is cancelled, removing the partial
+    /// datasheet
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    fn check_cancelled(&self) -> anyhow::Result<()> {
+        self.cancel
+            .as_ref()
+            .map_or(Ok(()), CancellationToken::check)
+    }
+
+    fn notify(&self, event: PipelineEvent) {
+        if let Some(Observer(observer)) = &self.observer {
+            observer(&event);
+        }
+    }
+
+    /// SQL of the pipeline, printing the datasheet
+    pub fn to_sql(&self) -> anyhow::Result<String> {
+        let expr = |s: &String| Expr::new(s, self.index_mark);
+        let selector = PlainSelector::new(
+            expr(&self.xexpr),
+            expr(&self.yexpr),
+            self.input_filter.as_ref().map(expr),
+            self.output_filter.as_ref().map(expr),
+        )
+        .with_category(ErrorCategory::Expression)?
+        .with_time_x(self.time_x.clone())
+        .with_missing(self.missing);
+        // a memory budget is meant for inputs larger than the memory
+        let input =
+            self.input.clone().with_streaming(self.max_memory.is_some());
+        let mut sql = input.to_sql("src_tbl");
+        sql.push_str(&selector.to_preprocess_sql("src_tbl", "t0"));
+        sql.push_str(&input.drop_sql("src_tbl"));
+        let final_table = match &self.opseq {
+            Some(opseq) => {
+                sql.push_str(&opseq.to_sql("t0", "x", "y"));
+                opseq.get_tmp_table_name()
+            }
+            None => "t0".to_string(),
+        };
+        sql.push_str(&selector.to_postprocess_sql(&final_table));
+        Ok(sql)
+    }
+
+    /// Run the pipeline with duckdb (and gnuplot if requested)
+    pub fn execute(mut self) -> anyhow::Result<Artifacts> {
+        self.input = self.input.read_natively()?;
+        let datasheet_path = match &self.datasheet_path {
+            Some(path) => path.clone(),
+            None => temp_filename("sp-", "csv")?,
+        };
+        let mut sql = self.to_sql()?;
+        // duckdb creates the spill directory in a directory of our own
+        let spill_dir = match self.max_memory {
+            Some(max_memory) => {
+                let spill_dir =
+                    TempArtifacts::create("sp-", "spspill", |path| {
+                        std::fs::create_dir(path)
+                    })?
+                    .join("spill");
+                sql.insert_str(0, &max_memory.to_duckdb_sql(&spill_dir));
+                Some(spill_dir)
+            }
+            None => None,
+        };
+        self.check_cancelled()?;
+        self.notify(PipelineEvent::QueryStarted { sql: sql.clone() });
+        let cancel = self.cancel.as_ref();
+        let rows =
+            run_duckdb_cancellable(&sql, Stdio::piped(), cancel, |stdout| {
+                let mut stdout = stdout.unwrap();
+                let mut datasheet =
+                    File::create(&datasheet_path).context(format!(
+                        "Failed to create datasheet '{}'",
+                        datasheet_path.display()
+                    ))?;
+                // lines received, including the header row
+                let (mut lines, mut reported) = (0, 0);
+                let mut buf = vec![0; 64 * 1024];
+                loop {
+                    let n = stdout.read(&mut buf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    datasheet.write_all(&buf[..n])?;
+                    lines += buf[..n].iter().filter(|&&b| b == b'\n').count();
+                    if lines - reported >= PipelineEvent::ROWS_PER_EVENT {
+                        self.notify(PipelineEvent::RowsRead {
+                            rows: lines - 1,
+                        });
+                        reported = lines;
+                    }
+                }
+                Ok(lines.saturating_sub(1))
+            })
+            .inspect_err(|e| {
+                if ErrorCategory::of(e) == Some(ErrorCategory::Cancelled) {
+                    let _ = std::fs::remove_file(&datasheet_path);
+                }
+            });
+        // duckdb only creates the spill directory once it exceeds the budget
+        if let Some(spill_dir) = &spill_dir {
+            if spill_dir.exists() {
+                tracing::warn!(
+                    "duckdb exceeded the memory budget and spilled to disk"
+                );
+            }
+            let _ = std::fs::remove_dir_all(
+                spill_dir.parent().unwrap_or(spill_dir),
+            );
+        }
+        let rows = rows?;
+        self.notify(PipelineEvent::DatasheetWritten {
+            path: datasheet_path.clone(),
+            rows,
+        });
+
+        let mut template = self.template.clone();
+        if self.time_x.is_some() {
+            template = template.with_time_x();
+        }
+        if let Some((x, y)) = self.columns.clone() {
+            let (x, y) = match &self.opseq {
+                Some(opseq) => opseq.columns(x, y),
+                None => (x, y),
+            };
+            template = template.with_default_labels(
+                Some(x.to_string()).filter(|s| !s.is_empty()),
+                Some(y.to_string()).filter(|s| !s.is_empty()),
+            );
+        }
+        if self.series_options.is_categorical_x() {
+            template = template.with_x_categories(
+                DataPoints::read_categories(&datasheet_path, &[1]).context(
+                    format!(
+                        "Failed to read the categories of datasheet '{}'",
+                        datasheet_path.display()
+                    ),
+                )?,
+            );
+        }
+        let gpcmd = template
+            .with_data_series_options(vec![
+                self.series_options
+                    .clone()
+                    .with_datasheet_path(datasheet_path.display().to_string()),
+            ])
+            .to_string();
+        if let Some(path) = &self.script_path {
+            std::fs::write(path, gpcmd.clone() + "\n").context(format!(
+                "Failed to write gnuplot script '{}'",
+                path.display()
+            ))?;
+            self.notify(PipelineEvent::ScriptWritten { path: path.clone() });
+        }
+        if self.plot {
+            self.check_cancelled()?;
+            self.notify(PipelineEvent::PlotStarted);
+            let script_path = match &self.script_path {
+                Some(path) => path.clone(),
+                None => {
+                    let path = temp_filename("sp-", "gp")?;
+                    std::fs::write(&path, gpcmd + "\n").context(format!(
+                        "Failed to write gnuplot script '{}'",
+                        path.display()
+                    ))?;
+                    path
+                }
+            };
+            let status = Plotter::spawn_script(script_path)
+                .and_then(|child| Ok(supervise(child, cancel, || ())?.1));
+            self.check_cancelled()?;
+            check_gnuplot(status)?;
+            self.notify(PipelineEvent::PlotFinished);
+        }
+
+        Ok(Artifacts {
+            datasheet_path,
+            script_path: self.script_path,
+            rows,
+        })
+    }
+}
+
+#[test]
+fn test_native_matches_sql() {
+    // the SQL side needs duckdb, which the build does not
+    if which::which("duckdb").is_err() {
+        return;
+    }
+    let points = DataPoints::new(
+        "x",
+        "y",
+        [3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0, 6.0, 5.0, 3.0]
+            .iter()
+            .enumerate()
+            // distinct x in no order, so that sorting is deterministic
+            .map(|(i, &y)| ((i * 3 % 10) as f64, y))
+            .collect(),
+    );
+    let check = |opseq: &str, points: &DataPoints| {
+        let opseq = opseq.parse::<OpSeq>().unwrap();
+        let native = opseq.apply(points).unwrap().points;
+        let sql = opseq.apply_sql(points, false).unwrap().points;
+        // the same, with each operator materialized as by --timing of sp
+        let steps = opseq.apply_sql(points, true).unwrap().points;
+        let sorted = |mut points: Vec<(f64, f64)>| {
+            points
+                .sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+            points
+        };
+        // NaN (NULL in SQL) stands for a missing value on both sides
+        let close =
+            |a: f64, b: f64| a.is_nan() && b.is_nan() || (a - b).abs() < 1e-9;
+        let native = sorted(native);
+        for sql in [sql, steps] {
+            assert_eq!(native.len(), sql.len(), "{opseq}");
+            for (n, s) in native.iter().zip(sorted(sql)) {
+                assert!(
+                    close(n.0, s.0) && close(n.1, s.1),
+                    "{opseq}: {n:?} != {s:?}"
+                );
+            }
+        }
+    };
+    for opseq in [
+        "o", "oc", "m", "os", "oi", "f", "x2,1", "y0.5,-1", "u", "oS1mL1",
+    ] {
+        check(opseq, &points);
+    }
+    for opseq in [
+        "q0,25,50,90,100",
+        "a1",
+        "n",
+        "n1",
+        "D3",
+        "M2",
+        "r0.5,2",
+        "og",
+        "og1",
+        "b1",
+        "t0,2",
+        "oF",
+        "R1",
+        "R2",
+        "k2",
+        "k2,1",
+    ] {
+        check(opseq, &points);
+    }
+
+    // missing values (NULL in SQL) are skipped by the aggregate functions
+    let mut points = points;
+    points.points[2].1 = f64::NAN;
+    points.points[7].1 = f64::NAN;
+    for opseq in ["q0,25,50,90,100", "a1", "a0", "n", "n1"] {
+        check(opseq, &points);
+    }
+}
@@ -1,23 +1,42 @@
 use anyhow::anyhow;
 use regex::{Captures, Regex};
 
+use crate::{MissingValues, Table, TimeFormat, Value, sqlexpr};
+
 pub struct Expr {
     raw_expr: String,
+    index_mark: char,
     index_pattern: Regex,
 }
 
 impl Expr {
     pub fn new(raw_expr: &str, index_mark: char) -> Self {
-        let index_mark = match index_mark {
+        let escaped_mark = match index_mark {
             '-' | '\\' => format!("\\{index_mark}"),
             _ => index_mark.to_string(),
         };
         Self {
             raw_expr: raw_expr.to_string(),
-            index_pattern: Regex::new(&format!(r"[{index_mark}]\d+")).unwrap(),
+            index_mark,
+            index_pattern: Regex::new(&format!(r"[{escaped_mark}]\d+"))
+                .unwrap(),
         }
     }
 
+    /// The expression parsed for native evaluation on the rows of a table
+    /// of `columns`, an error if it is beyond the subset of SQL evaluated
+    /// natively
+    fn compile(&self, columns: &[String]) -> anyhow::Result<sqlexpr::Node> {
+        sqlexpr::compile(&self.raw_expr, self.index_mark, columns).map_err(
+            |e| {
+                anyhow!(
+                    "Expression '{}' is not evaluated natively: {e}",
+                    self.raw_expr
+                )
+            },
+        )
+    }
+
     /// Get a list of indexes referenced by this expression
     fn get_required_indexes(&self) -> anyhow::Result<IndexList> {
         self.index_pattern
@@ -115,10 +134,13 @@ impl IndexList {
 pub struct PlainSelector {
     xexpr: Expr,
     yexpr: Expr,
+    extra_yexprs: Vec<Expr>,
     pre_filter: Option<Expr>,
     pre_index_list: IndexList,
     post_filter: Option<Expr>,
     post_index_list: IndexList,
+    time_x: Option<TimeFormat>,
+    missing: MissingValues,
 }
 
 impl PlainSelector {
@@ -143,23 +165,75 @@ impl PlainSelector {
         Ok(Self {
             xexpr,
             yexpr,
+            extra_yexprs: Vec::new(),
             pre_filter,
             post_filter,
             pre_index_list,
             post_index_list,
+            time_x: None,
+            missing: MissingValues::default(),
         })
     }
 
+    /// Policy of the records with missing values (NULL), applied right
+    /// after the selection
+    pub fn with_missing(mut self, missing: MissingValues) -> Self {
+        self.missing = missing;
+        self
+    }
+
+    /// x values are timestamps of the format, which are converted to seconds
+    /// since the Unix epoch so that the operators could compute on them
+    pub fn with_time_x(mut self, time_x: Option<TimeFormat>) -> Self {
+        self.time_x = time_x;
+        self
+    }
+
+    /// Additional y expressions evaluated in the same pass over the input,
+    /// each of them produces another data series
+    pub fn with_extra_yexprs(
+        mut self,
+        extra_yexprs: Vec<Expr>,
+    ) -> anyhow::Result<Self> {
+        for yexpr in &extra_yexprs {
+            self.pre_index_list.merge(yexpr.get_required_indexes()?);
+        }
+        self.pre_index_list.simplify();
+        self.extra_yexprs = extra_yexprs;
+        Ok(self)
+    }
+
+    /// Names of the y columns in the preprocessed table, one for each series
+    pub fn y_columns(&self) -> Vec<String> {
+        std::iter::once("y".to_string())
+            .chain((0..self.extra_yexprs.len()).map(|i| format!("y_{}", i + 2)))
+            .collect()
+    }
+
+    /// SQL selecting `dst_table` from `src_table`, which is left to the
+    /// caller to drop (see `DataInput::drop_sql`)
     pub fn to_preprocess_sql(
         &self,
         src_table: &str,
         dst_table: &str,
     ) -> String {
+        let xexpr = self.xexpr.to_sql(&self.pre_index_list);
         let query = format!(
-            "CREATE TABLE {} AS SELECT {} AS x, {} AS y FROM {}{};\n",
+            "CREATE TABLE {} AS SELECT {} AS x, {} FROM {}{};\n",
             dst_table,
-            self.xexpr.to_sql(&self.pre_index_list),
-            self.yexpr.to_sql(&self.pre_index_list),
+            match &self.time_x {
+                Some(format) => format.to_sql(&xexpr),
+                None => xexpr,
+            },
+            std::iter::once(&self.yexpr)
+                .chain(&self.extra_yexprs)
+                .zip(self.y_columns())
+                .map(|(yexpr, column)| format!(
+                    "{} AS {column}",
+                    yexpr.to_sql(&self.pre_index_list)
+                ))
+                .collect::<Vec<_>>()
+                .join(", "),
             src_table,
             if let Some(ref filter) = self.pre_filter {
                 format!(" WHERE {}", filter.to_sql(&self.pre_index_list))
@@ -168,23 +242,20 @@ impl PlainSelector {
             }
         );
 
-        let cleanup = format!(
-            "DROP TABLE {};\n{}",
-            src_table,
-            self.pre_index_list.generate_clean()
-        );
+        let cleanup = self.pre_index_list.generate_clean();
 
         format!(
-            "{}{}{}",
+            "{}{}{}{}",
             self.pre_index_list.generate_preamble(src_table),
             query,
+            self.missing.to_sql(dst_table, &self.y_columns()),
             cleanup
         )
     }
 
-    pub fn to_postprocess_sql(&self, src_table: &str) -> String {
+    fn postprocess_query(&self, src_table: &str) -> String {
         format!(
-            "SELECT * FROM {}{};\n",
+            "SELECT * FROM {}{}",
             src_table,
             if let Some(ref filter) = self.post_filter {
                 format!(" WHERE {}", filter.to_sql(&self.post_index_list))
@@ -193,4 +264,157 @@ impl PlainSelector {
             }
         )
     }
+
+    /// `to_preprocess_sql` evaluated natively on the rows of `table`: a table
+    /// of columns `x` and `y_columns`. Expressions are evaluated with the
+    /// arithmetic, comparisons, logic, `CASE`, `CAST` and common math
+    /// functions of duckdb, others being errors.
+    pub fn select_table(&self, table: &Table) -> anyhow::Result<Table> {
+        let compile = |expr: &Expr| expr.compile(&table.columns);
+        let xexpr = compile(&self.xexpr)?;
+        let yexprs = std::iter::once(&self.yexpr)
+            .chain(&self.extra_yexprs)
+            .map(compile)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let filter = self.pre_filter.as_ref().map(compile).transpose()?;
+        let mut rows = Vec::new();
+        for row in &table.rows {
+            if let Some(filter) = &filter
+                && !filter.is_true(row)?
+            {
+                continue;
+            }
+            let x = match (&self.time_x, xexpr.eval(row)?) {
+                (_, Value::Null) => Value::Null,
+                (None, x) => x,
+                (Some(format), Value::Text(x))
+                    if format.parse(&x)?.is_some() =>
+                {
+                    Value::from_f64(format.parse(&x)?.unwrap())
+                }
+                (Some(format), x) => {
+                    return Err(anyhow!(
+                        "'{x}' is not a timestamp of format {format}"
+                    ));
+                }
+            };
+            let ys = yexprs.iter().map(|yexpr| yexpr.eval(row));
+            rows.push(
+                std::iter::once(Ok(x))
+                    .chain(ys)
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            );
+        }
+        Ok(Table {
+            columns: std::iter::once("x".to_string())
+                .chain(self.y_columns())
+                .collect(),
+            rows: self.missing.apply_rows(rows)?,
+        })
+    }
+
+    /// `to_postprocess_sql` evaluated natively on an `(x, y)` table, see
+    /// `select_table`
+    pub fn postprocess_table(&self, mut table: Table) -> anyhow::Result<Table> {
+        let Some(filter) = &self.post_filter else {
+            return Ok(table);
+        };
+        let filter = filter.compile(&table.columns)?;
+        let mut rows = Vec::with_capacity(table.rows.len());
+        for row in table.rows {
+            if filter.is_true(&row)? {
+                rows.push(row);
+            }
+        }
+        table.rows = rows;
+        Ok(table)
+    }
+
+    /// Whether the output rows are filtered (by `post_filter`)
+    pub fn filters_output(&self) -> bool {
+        self.post_filter.is_some()
+    }
+
+    pub fn to_postprocess_sql(&self, src_table: &str) -> String {
+        format!("{};\n", self.postprocess_query(src_table))
+    }
+
+    /// Same as `to_postprocess_sql`, but for multiple `(x, y)` tables, which
+    /// are placed side by side as columns `x_1, y_1, x_2, y_2, ...`
+    pub fn to_multi_postprocess_sql(&self, src_tables: &[String]) -> String {
+        format!(
+            "SELECT * FROM {};\n",
+            src_tables
+                .iter()
+                .enumerate()
+                .map(|(i, table)| format!(
+                    "({}) AS s{n}(x_{n}, y_{n})",
+                    self.postprocess_query(table),
+                    n = i + 1
+                ))
+                .collect::<Vec<_>>()
+                .join(" POSITIONAL JOIN ")
+        )
+    }
+
+    /// Same as `to_postprocess_sql`, but joins two `(x, y)` tables by x into
+    /// columns `x, old, new, abs_change, rel_change`
+    pub fn to_diff_sql(&self, old_table: &str, new_table: &str) -> String {
+        let old = "TRY_CAST(o.y AS DOUBLE)";
+        let new = "TRY_CAST(n.y AS DOUBLE)";
+        format!(
+            "SELECT x, o.y AS old, n.y AS new, {new} - {old} AS abs_change, ({new} - {old}) / NULLIF({old}, 0) AS rel_change FROM ({}) AS o FULL JOIN ({}) AS n USING (x) ORDER BY x;\n",
+            self.postprocess_query(old_table),
+            self.postprocess_query(new_table)
+        )
+    }
+
+    /// Same as `to_postprocess_sql`, but produces one row of summary
+    /// statistics (count, min, max, mean, stddev, percentiles) for each axis
+    /// of each table instead of the data
+    pub fn to_stats_sql(&self, src_tables: &[String]) -> String {
+        const PERCENTILES: [(&str, f64); 7] = [
+            ("p1", 0.01),
+            ("p5", 0.05),
+            ("p25", 0.25),
+            ("p50", 0.5),
+            ("p75", 0.75),
+            ("p95", 0.95),
+            ("p99", 0.99),
+        ];
+        let axis_stats = |axis: &str, label: String, query: &str| {
+            let v = format!("TRY_CAST({axis} AS DOUBLE)");
+            format!(
+                "SELECT '{label}' AS axis, count(*) AS count, min({v}) AS min, max({v}) AS max, avg({v}) AS mean, stddev_samp({v}) AS stddev, {} FROM ({query}) AS stats_src(x, y)",
+                PERCENTILES
+                    .iter()
+                    .map(|(name, q)| format!(
+                        "quantile_cont({v}, {q}) AS {name}"
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+        let suffix = |i: usize| {
+            if src_tables.len() == 1 {
+                "".to_string()
+            } else {
+                format!("_{}", i + 1)
+            }
+        };
+        format!(
+            "{};\n",
+            src_tables
+                .iter()
+                .enumerate()
+                .flat_map(|(i, table)| {
+                    let query = self.postprocess_query(table);
+                    ["x", "y"].map(|axis| {
+                        axis_stats(axis, format!("{axis}{}", suffix(i)), &query)
+                    })
+                })
+                .collect::<Vec<_>>()
+                .join(" UNION ALL ")
+        )
+    }
 }
@@ -1,5 +1,7 @@
 use std::{fmt::Display, str::FromStr};
 
+use crate::{DataPoints, NativePlot, NativeSeries, SeriesStyle};
+
 #[derive(Debug, Clone)]
 struct PlotSize {
     width: f64,
@@ -21,6 +23,54 @@ impl Display for PlotSize {
     }
 }
 
+/// Physical length used for the canvas size of terminals
+#[derive(Debug, Clone, Copy)]
+pub enum Length {
+    Inch(f64),
+    Centimeter(f64),
+}
+
+impl Length {
+    pub fn to_inches(self) -> f64 {
+        match self {
+            Length::Inch(v) => v,
+            Length::Centimeter(v) => v / 2.54,
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (value, ctor): (&str, fn(f64) -> Self) =
+            if let Some(v) = s.strip_suffix("in") {
+                (v, Self::Inch)
+            } else if let Some(v) = s.strip_suffix("cm") {
+                (v, Self::Centimeter)
+            } else {
+                anyhow::bail!("Length '{s}' must end with 'in' or 'cm'");
+            };
+        let value = value.trim().parse::<f64>().map_err(|e| {
+            anyhow::anyhow!("Failed to parse length '{s}': {e}")
+        })?;
+        if !value.is_finite() || value <= 0.0 {
+            anyhow::bail!("Length '{s}' must be positive");
+        }
+        Ok(ctor(value))
+    }
+}
+
+impl Display for Length {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Length::Inch(v) => write!(f, "{v}in"),
+            Length::Centimeter(v) => write!(f, "{v}cm"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Font {
     family: String,
@@ -79,6 +129,63 @@ impl Display for Terminal {
     }
 }
 
+/// Coordinated appearance defaults for common plotting scenarios
+#[derive(Debug, Clone)]
+pub enum Preset {
+    /// Compact postscript figures with colorblind-safe lines for papers
+    Paper,
+    /// Large fonts and thick lines for projected slides
+    Slides,
+    /// Light-on-dark x11 window
+    Dark,
+    /// Dense x11 window with small fonts for monitoring
+    Dashboard,
+}
+
+impl Preset {
+    fn palette(&self) -> &'static [(u8, u8, u8)] {
+        match self {
+            // Okabe-Ito palette, distinguishable under color blindness
+            Preset::Paper | Preset::Slides => &[
+                (0x00, 0x72, 0xb2),
+                (0xd5, 0x5e, 0x00),
+                (0x00, 0x9e, 0x73),
+                (0xcc, 0x79, 0xa7),
+                (0xe6, 0x9f, 0x00),
+                (0x56, 0xb4, 0xe9),
+                (0xf0, 0xe4, 0x42),
+                (0x00, 0x00, 0x00),
+            ],
+            Preset::Dark | Preset::Dashboard => &[
+                (0x8e, 0xc0, 0x7c),
+                (0xfa, 0xbd, 0x2f),
+                (0x83, 0xa5, 0x98),
+                (0xfb, 0x49, 0x34),
+                (0xd3, 0x86, 0x9b),
+                (0xfe, 0x80, 0x19),
+                (0xb8, 0xbb, 0x26),
+                (0xeb, 0xdb, 0xb2),
+            ],
+        }
+    }
+
+    pub fn terminal(&self) -> Terminal {
+        match self {
+            Preset::Paper | Preset::Slides => Terminal::Postscript,
+            Preset::Dark | Preset::Dashboard => Terminal::X11,
+        }
+    }
+
+    fn line_weight(&self) -> f64 {
+        match self {
+            Preset::Paper => 2.0,
+            Preset::Slides => 4.0,
+            Preset::Dark => 2.0,
+            Preset::Dashboard => 1.5,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 enum AxisId {
     X,
@@ -122,6 +229,10 @@ pub struct AxisOptions {
 
     /// Tics of axis (args: <pos, label>...)
     custom_tics: Option<Vec<(f64, String)>>,
+
+    /// Time axis of seconds since the Unix epoch, with the strftime format
+    /// of its tics (arg: format)
+    time_format: Option<String>,
 }
 
 impl Default for AxisOptions {
@@ -133,11 +244,15 @@ impl Default for AxisOptions {
             label: None,
             standard_tics: None,
             custom_tics: None,
+            time_format: None,
         }
     }
 }
 
 impl AxisOptions {
+    /// Tic format of time axes unless specified otherwise
+    pub const DEFAULT_TIME_FORMAT: &str = "%H:%M:%S";
+
     pub fn new_x() -> Self {
         Self {
             id: AxisId::X,
@@ -202,18 +317,32 @@ impl AxisOptions {
         self
     }
 
+    pub fn with_time_format(
+        mut self,
+        time_format: Option<impl AsRef<str>>,
+    ) -> Self {
+        self.time_format = time_format.map(|s| s.as_ref().to_string());
+        self
+    }
+
     fn need_configure(&self) -> bool {
         self.logscale.is_some()
             || self.range.is_some()
             || self.label.is_some()
             || self.standard_tics.is_some()
             || self.custom_tics.is_some()
+            || self.time_format.is_some()
     }
 }
 
 impl Display for AxisOptions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "## {} axis", self.id)?;
+        if let Some(format) = &self.time_format {
+            write!(f, "\nset {}data time", self.id)?;
+            write!(f, "\nset timefmt \"%s\"")?;
+            write!(f, "\nset format {} \"{}\"", self.id, format)?;
+        }
         if let Some(base) = self.logscale {
             let base = format!(" {base}");
             write!(f, "\nset logscale {}{}", self.id, base)?;
@@ -274,6 +403,27 @@ impl Display for Color {
     }
 }
 
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    /// `#rrggbb` or a color name known by gnuplot
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                anyhow::bail!("Color '{s}' must be in the form of #rrggbb");
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+            Ok(Self::RGB(channel(0)?, channel(2)?, channel(4)?))
+        } else if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            Ok(Self::Named(s.to_string()))
+        } else {
+            anyhow::bail!("Invalid color '{s}'");
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PointStyle {
     pub point_type: usize,
@@ -333,9 +483,12 @@ impl Display for PlotType {
 
 #[derive(Clone, Debug)]
 pub struct DataSeriesOptions {
-    /// Path to the 2-column temporary datasheet file
+    /// Path to the temporary datasheet file
     datasheet_path: String,
 
+    /// Columns (1-based) of x and y values in the datasheet
+    columns: (usize, usize),
+
     /// Use x2 axis for this data series
     use_x2: bool,
 
@@ -350,17 +503,45 @@ pub struct DataSeriesOptions {
 
     /// Additional options to be used for this data series
     additional_options: Option<String>,
+
+    /// Width of uniformly-distributed random offsets added to x and y values
+    jitter: Option<(f64, f64)>,
+
+    /// Panel (0-based, row by row) of the multiplot layout holding this data
+    /// series
+    panel: usize,
+
+    /// The datasheet failed to generate, only the label is shown in the key
+    failed: bool,
+
+    /// Statistics (label, value) drawn as dashed lines in the color of this
+    /// data series, labeled in the margin
+    stat_lines: Vec<(String, f64)>,
+
+    /// Statistics are of x values and drawn as vertical lines
+    vertical_stat_lines: bool,
+
+    /// x values are category labels, placed at the positions given by
+    /// `GnuplotTemplate::with_x_categories`
+    categorical_x: bool,
 }
 
 impl Default for DataSeriesOptions {
     fn default() -> Self {
         Self {
             datasheet_path: "".to_string(),
+            columns: (1, 2),
             use_x2: false,
             use_y2: false,
             plot_type: PlotType::Points(None),
             label: None,
             additional_options: None,
+            jitter: None,
+            panel: 0,
+            failed: false,
+            stat_lines: Vec::new(),
+            vertical_stat_lines: false,
+            categorical_x: false,
         }
     }
 }
@@ -381,6 +562,11 @@ impl DataSeriesOptions {
         self
     }
 
+    pub fn with_columns(mut self, x_column: usize, y_column: usize) -> Self {
+        self.columns = (x_column, y_column);
+        self
+    }
+
     pub fn with_plot_type(mut self, plot_type: PlotType) -> Self {
         self.plot_type = plot_type;
         self
@@ -408,14 +594,88 @@ impl DataSeriesOptions {
         self.use_y2 = use_y2;
         self
     }
+
+    pub fn with_jitter(mut self, jitter: Option<(f64, f64)>) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_panel(mut self, panel: usize) -> Self {
+        self.panel = panel;
+        self
+    }
+
+    pub fn with_failed(mut self, failed: bool) -> Self {
+        self.failed = failed;
+        self
+    }
+
+    pub fn with_stat_lines(
+        mut self,
+        stat_lines: Vec<(String, f64)>,
+        vertical: bool,
+    ) -> Self {
+        self.stat_lines = stat_lines;
+        self.vertical_stat_lines = vertical;
+        self
+    }
+
+    pub fn with_categorical_x(mut self, categorical_x: bool) -> Self {
+        self.categorical_x = categorical_x;
+        self
+    }
+
+    pub fn is_categorical_x(&self) -> bool {
+        self.categorical_x
+    }
+
+    fn using_clause(&self) -> String {
+        // expression in parentheses, as gnuplot takes a bare number as a
+        // column
+        fn jittered_expr(expr: String, width: f64) -> String {
+            if width == 0.0 {
+                format!("({expr})")
+            } else {
+                format!("({expr}+(rand(0)-0.5)*{width})")
+            }
+        }
+        fn jittered(column: usize, width: f64) -> String {
+            if width == 0.0 {
+                column.to_string()
+            } else {
+                jittered_expr(format!("${column}"), width)
+            }
+        }
+        let (x_column, y_column) = self.columns;
+        let (x_width, y_width) = self.jitter.unwrap_or((0.0, 0.0));
+        let x = if self.categorical_x {
+            jittered_expr(
+                format!("{CATEGORY_FUNCTION}(strcol({x_column}))"),
+                x_width,
+            )
+        } else {
+            jittered(x_column, x_width)
+        };
+        format!("{}:{}", x, jittered(y_column, y_width))
+    }
 }
 
 impl Display for DataSeriesOptions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.failed {
+            // a function without any defined value only shows up in the key
+            return write!(
+                f,
+                "NaN {} title \"{} (failed)\"",
+                self.plot_type,
+                self.label.as_deref().unwrap_or("?")
+            );
+        }
         write!(
             f,
-            "'{}' using 1:2 axis x{}y{} {}",
+            "'{}' using {} axis x{}y{} {}",
             self.datasheet_path,
+            self.using_clause(),
             if self.use_x2 { "2" } else { "1" },
             if self.use_y2 { "2" } else { "1" },
             self.plot_type,
@@ -438,6 +698,13 @@ pub struct GnuplotTemplate {
     /// Size of the plot (width, height)
     plot_size: PlotSize,
 
+    /// Physical size of the canvas (width, height) [default: terminal
+    /// default]
+    canvas_size: Option<(Length, Length)>,
+
+    /// Resolution used to convert physical sizes for pixel-based terminals
+    dpi: u32,
+
     /// Font to be used for all labels (family, size)
     font: Option<Font>,
 
@@ -453,6 +720,10 @@ pub struct GnuplotTemplate {
     /// Gnuplot output destination
     output: Option<String>,
 
+    /// Datasheet paths and the output are relative to the directory of the
+    /// script rather than to the working directory of gnuplot
+    script_relative_paths: bool,
+
     /// Options for x1 axis
     xopt: AxisOptions,
 
@@ -468,6 +739,36 @@ pub struct GnuplotTemplate {
     /// Display grid
     grid: bool,
 
+    /// Background color of the plot [default: terminal default]
+    background: Option<Color>,
+
+    /// Color of borders, tics and texts [default: terminal default]
+    foreground: Option<Color>,
+
+    /// Line types overriding the default palette of gnuplot
+    line_types: Vec<LineStyle>,
+
+    /// Seed of the random number generator used for jitter
+    seed: Option<u32>,
+
+    /// Multiplot layout (rows, columns) [default: single plot]
+    layout: Option<(usize, usize)>,
+
+    /// Share the x axis between the panels of a column, keeping tic labels
+    /// and label of the x axis on the bottom row only
+    shared_x: bool,
+
+    /// Titles of the panels of the multiplot layout
+    panel_titles: Vec<String>,
+
+    /// Show the key only in the first panel, for panels plotting the same
+    /// data series of different groups
+    shared_key: bool,
+
+    /// Labels of the x values of categorical data series, placed at 1, 2,
+    /// ... in this order
+    x_categories: Vec<String>,
+
     /// Data series options
     data_series_options: Vec<DataSeriesOptions>,
 }
@@ -477,16 +778,28 @@ impl Default for GnuplotTemplate {
         Self {
             additional_command: None,
             plot_size: PlotSize::default(),
+            canvas_size: None,
+            dpi: 96,
             font: None,
             key_position: "top right".to_string(),
             key_font: None,
             terminal: Terminal::Postscript,
             output: None,
+            script_relative_paths: false,
             xopt: AxisOptions::new_x(),
             x2opt: AxisOptions::new_x2(),
             yopt: AxisOptions::new_y(),
             y2opt: AxisOptions::new_y2(),
             grid: false,
+            background: None,
+            foreground: None,
+            line_types: Vec::new(),
+            seed: None,
+            layout: None,
+            shared_x: false,
+            panel_titles: Vec::new(),
+            shared_key: false,
+            x_categories: Vec::new(),
             data_series_options: Vec::new(),
         }
     }
@@ -509,6 +822,14 @@ impl GnuplotTemplate {
         self.data_series_options = data_series_options;
         self
     }
+
+    /// Category labels of the x values of data series with
+    /// `DataSeriesOptions::with_categorical_x`, used as tics of the x axis
+    pub fn with_x_categories(mut self, categories: Vec<String>) -> Self {
+        self.x_categories = categories;
+        self
+    }
+
     pub fn with_additional_command(
         mut self,
         additional_command: Option<impl AsRef<str>>,
@@ -521,6 +842,17 @@ impl GnuplotTemplate {
         self.plot_size = PlotSize { width, height };
         self
     }
+    pub fn with_canvas_size(
+        mut self,
+        canvas_size: Option<(Length, Length)>,
+    ) -> Self {
+        self.canvas_size = canvas_size;
+        self
+    }
+    pub fn with_dpi(mut self, dpi: u32) -> Self {
+        self.dpi = dpi;
+        self
+    }
     pub fn with_font(mut self, font: Option<(impl AsRef<str>, usize)>) -> Self {
         self.font = font.map(|(family, size)| Font {
             family: family.as_ref().to_string(),
@@ -550,14 +882,138 @@ impl GnuplotTemplate {
         self.output = output.map(|s| s.as_ref().to_string());
         self
     }
+    pub fn with_script_relative_paths(mut self, relative: bool) -> Self {
+        self.script_relative_paths = relative;
+        self
+    }
+
     pub fn with_grid(mut self, grid: bool) -> Self {
         self.grid = grid;
         self
     }
+    pub fn with_background(mut self, background: Option<Color>) -> Self {
+        self.background = background;
+        self
+    }
+    pub fn with_foreground(mut self, foreground: Option<Color>) -> Self {
+        self.foreground = foreground;
+        self
+    }
+    pub fn with_line_types(mut self, line_types: Vec<LineStyle>) -> Self {
+        self.line_types = line_types;
+        self
+    }
+    /// Replace colors of the line types, keeping the line weight of the
+    /// current line types (e.g. from a preset)
+    pub fn with_palette(mut self, colors: Vec<Color>) -> Self {
+        let weight = self.line_types.first().map(|lt| lt.weight).unwrap_or(1.0);
+        self.line_types = colors
+            .into_iter()
+            .enumerate()
+            .map(|(i, color)| LineStyle {
+                line_type: i + 1,
+                color,
+                weight,
+            })
+            .collect();
+        self
+    }
+    pub fn with_seed(mut self, seed: Option<u32>) -> Self {
+        self.seed = seed;
+        self
+    }
+    pub fn with_layout(mut self, layout: Option<(usize, usize)>) -> Self {
+        self.layout = layout;
+        self
+    }
+    pub fn with_shared_x(mut self, shared_x: bool) -> Self {
+        self.shared_x = shared_x;
+        self
+    }
+    pub fn with_panel_titles(mut self, panel_titles: Vec<String>) -> Self {
+        self.panel_titles = panel_titles;
+        self
+    }
+    pub fn with_shared_key(mut self, shared_key: bool) -> Self {
+        self.shared_key = shared_key;
+        self
+    }
+    /// Apply the terminal, fonts, size, grid and palette of a preset. Options
+    /// set afterwards override the preset.
+    pub fn with_preset(self, preset: Preset) -> Self {
+        let line_types = preset
+            .palette()
+            .iter()
+            .enumerate()
+            .map(|(i, &(r, g, b))| LineStyle {
+                line_type: i + 1,
+                color: Color::RGB(r, g, b),
+                weight: preset.line_weight(),
+            })
+            .collect();
+        let template = self
+            .with_line_types(line_types)
+            .with_terminal(preset.terminal());
+        match preset {
+            Preset::Paper => template
+                .with_font(Some(("Helvetica", 16)))
+                .with_key_font(Some(("Helvetica", 14)))
+                .with_plot_size(1.0, 0.6)
+                .with_grid(true)
+                .with_background(None)
+                .with_foreground(None),
+            Preset::Slides => template
+                .with_font(Some(("Helvetica", 24)))
+                .with_key_font(Some(("Helvetica", 22)))
+                .with_plot_size(1.0, 0.75)
+                .with_grid(true)
+                .with_background(None)
+                .with_foreground(None),
+            Preset::Dark => template
+                .with_font(Some(("Helvetica", 14)))
+                .with_key_font(Some(("Helvetica", 14)))
+                .with_plot_size(1.0, 1.0)
+                .with_grid(true)
+                .with_background(Some(Color::RGB(0x28, 0x28, 0x28)))
+                .with_foreground(Some(Color::RGB(0xeb, 0xdb, 0xb2))),
+            Preset::Dashboard => template
+                .with_font(Some(("Helvetica", 10)))
+                .with_key_font(Some(("Helvetica", 9)))
+                .with_plot_size(1.0, 1.0)
+                .with_grid(true)
+                .with_background(Some(Color::RGB(0x1d, 0x20, 0x21)))
+                .with_foreground(Some(Color::RGB(0xd5, 0xc4, 0xa1))),
+        }
+    }
     pub fn with_xopt(mut self, xopt: AxisOptions) -> Self {
         self.xopt = xopt;
         self
     }
+
+    /// Labels of the x and y axes unless they already have one, e.g. the
+    /// columns of `OpSeq::columns`
+    pub fn with_default_labels(
+        mut self,
+        xlabel: Option<impl AsRef<str>>,
+        ylabel: Option<impl AsRef<str>>,
+    ) -> Self {
+        if self.xopt.label.is_none() {
+            self.xopt.label = xlabel.map(|s| s.as_ref().to_string());
+        }
+        if self.yopt.label.is_none() {
+            self.yopt.label = ylabel.map(|s| s.as_ref().to_string());
+        }
+        self
+    }
+
+    /// Plot the x axis as a time axis (see `AxisOptions::with_time_format`)
+    /// unless it already is one
+    pub fn with_time_x(mut self) -> Self {
+        self.xopt.time_format.get_or_insert_with(|| {
+            AxisOptions::DEFAULT_TIME_FORMAT.to_string()
+        });
+        self
+    }
     pub fn with_x2opt(mut self, x2opt: AxisOptions) -> Self {
         self.x2opt = x2opt;
         self
@@ -570,6 +1026,87 @@ impl GnuplotTemplate {
         self.y2opt = y2opt;
         self
     }
+
+    /// The plot drawn without gnuplot, its data series being read from
+    /// their datasheets: the labels, ranges and log scales of the x and y
+    /// axes, the grid, and the type and label of each series are kept. The
+    /// options beyond them (e.g. time axes, second axes, panels or custom
+    /// commands) are ignored with a warning, and categorical x values are
+    /// an error.
+    pub fn to_native_plot(&self) -> anyhow::Result<NativePlot> {
+        let mut ignored = Vec::new();
+        let mut ignore = |option: &str, used: bool| {
+            if used {
+                ignored.push(option.to_string());
+            }
+        };
+        let series = &self.data_series_options;
+        ignore(
+            "the time format of the x axis",
+            self.xopt.time_format.is_some(),
+        );
+        ignore(
+            "the tics of the axes",
+            [&self.xopt, &self.yopt].iter().any(|axis| {
+                axis.standard_tics.is_some() || axis.custom_tics.is_some()
+            }),
+        );
+        ignore(
+            "the x2 and y2 axes",
+            series.iter().any(|s| s.use_x2 || s.use_y2),
+        );
+        ignore("the multiplot layout", self.layout.is_some());
+        ignore(
+            "the custom gnuplot commands",
+            self.additional_command.is_some(),
+        );
+        ignore("jitter", series.iter().any(|s| s.jitter.is_some()));
+        ignore(
+            "the statistics lines",
+            series.iter().any(|s| !s.stat_lines.is_empty()),
+        );
+        if !ignored.is_empty() {
+            tracing::warn!(
+                "Plotted without gnuplot, ignoring {}",
+                ignored.join(", ")
+            );
+        }
+        if series.iter().any(|s| s.categorical_x) {
+            anyhow::bail!("Categorical x values are only plotted by gnuplot");
+        }
+
+        let series = series
+            .iter()
+            .filter(|s| !s.failed)
+            .map(|s| {
+                let points =
+                    DataPoints::read_columns(&s.datasheet_path, s.columns)
+                        .map_err(|e| {
+                            anyhow::anyhow!(
+                                "Failed to read datasheet '{}': {e}",
+                                s.datasheet_path
+                            )
+                        })?;
+                Ok(NativeSeries {
+                    label: Some(s.label.clone().unwrap_or(points.ytitle)),
+                    points: points.points,
+                    style: match s.plot_type {
+                        PlotType::Points(_) => SeriesStyle::Points,
+                        PlotType::Lines(_) => SeriesStyle::Lines,
+                        PlotType::Linespoints(_, _) => SeriesStyle::LinesPoints,
+                    },
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(NativePlot::new(series)
+            .with_xlabel(self.xopt.label.as_ref())
+            .with_ylabel(self.yopt.label.as_ref())
+            .with_xrange(self.xopt.range.clone())
+            .with_yrange(self.yopt.range.clone())
+            .with_xlogscale(self.xopt.logscale)
+            .with_ylogscale(self.yopt.logscale)
+            .with_grid(self.grid))
+    }
 }
 
 impl Display for GnuplotTemplate {
@@ -580,13 +1117,43 @@ impl Display for GnuplotTemplate {
         writeln!(f, "set encoding utf8")?;
         writeln!(f, "set datafile separator ','")?;
         writeln!(f, "set key autotitle columnhead")?;
+        if let Some(seed) = self.seed {
+            // rand(x) with positive x seeds the generator, 0 is not allowed
+            writeln!(f, "seed = rand({})", seed.max(1))?;
+        }
+        if self.script_relative_paths {
+            // ARG0 is the path of the script, up to its last '/'
+            writeln!(f, "{SCRIPT_DIR_VARIABLE} = ''")?;
+            writeln!(
+                f,
+                "if (exists(\"ARG0\")) {{ do for [i = strlen(ARG0):1:-1] {{ if ({SCRIPT_DIR_VARIABLE} eq '' && ARG0[i:i] eq '/') {{ {SCRIPT_DIR_VARIABLE} = ARG0[1:i] }} }} }}"
+            )?;
+        }
         write!(
             f,
-            "set terminal {}{}\n\n",
+            "set terminal {}{}{}{}\n\n",
             self.terminal,
+            match (&self.canvas_size, &self.terminal) {
+                // vector terminals take physical sizes as-is, pixel-based
+                // terminals need conversion, and the dumb terminal sizes in
+                // characters on its own
+                (Some((w, h)), Terminal::Postscript) =>
+                    format!(" size {w},{h}"),
+                (Some((w, h)), Terminal::X11) => format!(
+                    " size {},{}",
+                    (w.to_inches() * self.dpi as f64).round() as u32,
+                    (h.to_inches() * self.dpi as f64).round() as u32
+                ),
+                (Some(_), Terminal::Dumb(_, _)) | (None, _) => "".to_string(),
+            },
             match &self.font {
                 Some(font) => format!(" font {font}"),
                 None => "".to_string(),
+            },
+            match (&self.background, &self.terminal) {
+                // the dumb terminal has no notion of colors
+                (_, Terminal::Dumb(_, _)) | (None, _) => "".to_string(),
+                (Some(bg), _) => format!(" background {bg}"),
             }
         )?;
 
@@ -607,6 +1174,9 @@ impl Display for GnuplotTemplate {
         {
             writeln!(f, "{}", self.y2opt)?;
         }
+        if !self.x_categories.is_empty() {
+            write_categories(f, &self.x_categories, self.xopt.range.is_none())?;
+        }
         writeln!(f)?;
 
         writeln!(f, "# Global appearance")?;
@@ -618,6 +1188,22 @@ impl Display for GnuplotTemplate {
         if self.grid {
             writeln!(f, "set grid")?;
         }
+        for lt in self.line_types.iter() {
+            writeln!(
+                f,
+                "set linetype {} lc {} lw {}",
+                lt.line_type, lt.color, lt.weight
+            )?;
+        }
+        if let Some(fg) = &self.foreground {
+            writeln!(f, "set border lc {fg}")?;
+            writeln!(f, "set tics textcolor {fg}")?;
+            writeln!(f, "set key textcolor {fg}")?;
+            writeln!(f, "set title textcolor {fg}")?;
+            for axis in ["x", "y", "x2", "y2"] {
+                writeln!(f, "set {axis}label textcolor {fg}")?;
+            }
+        }
         writeln!(f)?;
 
         if let Some(cmd) = &self.additional_command {
@@ -630,23 +1216,180 @@ impl Display for GnuplotTemplate {
         // postscript file into a pdf document.
         if let Some(output) = &self.output {
             if matches!(self.terminal, Terminal::Postscript) {
-                writeln!(f, "set output '|ps2pdf -dEPSCrop - {output}'")?;
+                if self.script_relative_paths {
+                    writeln!(
+                        f,
+                        "set output '|ps2pdf -dEPSCrop - \"'.{SCRIPT_DIR_VARIABLE}.'{output}\"'"
+                    )?;
+                } else {
+                    writeln!(f, "set output '|ps2pdf -dEPSCrop - {output}'")?;
+                }
             }
         }
-        write!(
-            f,
-            "plot\\\n\t{}\n",
-            self.data_series_options
+        let relative = self.script_relative_paths;
+        let Some((rows, cols)) = self.layout else {
+            return write_plot(
+                f,
+                self.data_series_options.iter(),
+                false,
+                relative,
+            );
+        };
+
+        writeln!(f, "set multiplot layout {rows},{cols}")?;
+        for panel in 0..rows * cols {
+            writeln!(f, "# Panel {}", panel + 1)?;
+            if self.shared_x {
+                // panels are laid out row by row, the first panel decides
+                // the x range unless it is given
+                if panel == 0 && self.xopt.range.is_none() {
+                    writeln!(f, "set xrange [*:*] writeback")?;
+                } else if panel == 1 && self.xopt.range.is_none() {
+                    writeln!(f, "set xrange restore")?;
+                }
+                if panel == 0 && rows > 1 {
+                    writeln!(f, "set format x \"\"")?;
+                    writeln!(f, "unset xlabel")?;
+                } else if panel == (rows - 1) * cols && rows > 1 {
+                    writeln!(f, "set format x \"% h\"")?;
+                    if self.xopt.need_configure() {
+                        writeln!(f, "{}", self.xopt)?;
+                    }
+                }
+            }
+            if let Some(title) = self.panel_titles.get(panel) {
+                writeln!(f, "set title \"{}\"", title.replace('"', "\\\""))?;
+            }
+            if self.shared_key && panel == 1 {
+                writeln!(f, "unset key")?;
+            }
+            let mut series = self
+                .data_series_options
                 .iter()
-                .map(|opt| format!("{opt}"))
-                .collect::<Vec<_>>()
-                .join(",\\\n\t")
-        )?;
+                .filter(|opt| opt.panel == panel)
+                .peekable();
+            if series.peek().is_none() {
+                writeln!(f, "set multiplot next")?;
+            } else {
+                write_plot(f, series, true, relative)?;
+            }
+        }
+        writeln!(f, "unset multiplot")?;
 
         Ok(())
     }
 }
 
+/// gnuplot function mapping a category label to its position on the x axis
+const CATEGORY_FUNCTION: &str = "sp_category";
+
+/// gnuplot variable holding the directory of the script (with a trailing
+/// '/'), see `GnuplotTemplate::with_script_relative_paths`
+const SCRIPT_DIR_VARIABLE: &str = "sp_dir";
+
+// `CATEGORY_FUNCTION` and the tics of `categories`, other labels having no
+// position; the x range leaves half a category of margin if `set_range`
+fn write_categories(
+    f: &mut std::fmt::Formatter<'_>,
+    categories: &[String],
+    set_range: bool,
+) -> std::fmt::Result {
+    let quoted = |label: &str| {
+        format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""))
+    };
+    writeln!(
+        f,
+        "{CATEGORY_FUNCTION}(s) = {}NaN",
+        categories
+            .iter()
+            .enumerate()
+            .map(|(i, label)| format!("s eq {} ? {} : ", quoted(label), i + 1))
+            .collect::<String>()
+    )?;
+    writeln!(
+        f,
+        "set xtics ({})",
+        categories
+            .iter()
+            .enumerate()
+            .map(|(i, label)| format!("{} {}", quoted(label), i + 1))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )?;
+    if set_range {
+        writeln!(f, "set xrange [0.5:{}.5]", categories.len())?;
+    }
+    Ok(())
+}
+
+/// Tag of the first arrow and label of statistics, above those likely used
+/// by custom commands
+const STAT_LINE_TAG: usize = 1000;
+
+fn write_plot<'a>(
+    f: &mut std::fmt::Formatter<'_>,
+    data_series_options: impl Iterator<Item = &'a DataSeriesOptions>,
+    multiplot: bool,
+    script_relative: bool,
+) -> std::fmt::Result {
+    let data_series_options = data_series_options.collect::<Vec<_>>();
+    let mut tag = STAT_LINE_TAG;
+    // data series take line types in order, so the statistics of the k-th
+    // one use line type k
+    for (lt, opt) in (1..).zip(data_series_options.iter()) {
+        for (label, value) in &opt.stat_lines {
+            let value = format_stat_value(*value);
+            if opt.vertical_stat_lines {
+                let axis = if opt.use_x2 { "second" } else { "first" };
+                writeln!(
+                    f,
+                    "set arrow {tag} from {axis} {value}, graph 0 to {axis} {value}, graph 1 nohead dt 2 lt {lt} front"
+                )?;
+                writeln!(
+                    f,
+                    "set label {tag} \"{label}={value}\" at {axis} {value}, graph 1 offset 0,char 0.5 center tc lt {lt} front"
+                )?;
+            } else {
+                let axis = if opt.use_y2 { "second" } else { "first" };
+                writeln!(
+                    f,
+                    "set arrow {tag} from graph 0, {axis} {value} to graph 1, {axis} {value} nohead dt 2 lt {lt} front"
+                )?;
+                writeln!(
+                    f,
+                    "set label {tag} \"{label}={value}\" at graph 1, {axis} {value} offset char 1,0 tc lt {lt} front"
+                )?;
+            }
+            tag += 1;
+        }
+    }
+    write!(
+        f,
+        "plot\\\n\t{}\n",
+        data_series_options
+            .iter()
+            .map(|opt| if script_relative && !opt.failed {
+                format!("{SCRIPT_DIR_VARIABLE}.{opt}")
+            } else {
+                format!("{opt}")
+            })
+            .collect::<Vec<_>>()
+            .join(",\\\n\t")
+    )?;
+    // later panels of a multiplot would keep arrows and labels
+    for tag in (STAT_LINE_TAG..tag).filter(|_| multiplot) {
+        writeln!(f, "unset arrow {tag}")?;
+        writeln!(f, "unset label {tag}")?;
+    }
+    Ok(())
+}
+
+/// Value of a statistic with at most 3 decimal places
+fn format_stat_value(value: f64) -> String {
+    let s = format!("{value:.3}");
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
 #[test]
 fn test_gnuplot_script_display() {
     let xopt = AxisOptions::new_x()
@@ -738,3 +1481,51 @@ fn test_gnuplot_script_display() {
 
     println!("{script}");
 }
+
+#[test]
+fn test_script_relative_paths() {
+    let script = GnuplotTemplate::from_data_series_options(vec![
+        DataSeriesOptions::from_datasheet_path("series-1.csv"),
+        DataSeriesOptions::default()
+            .with_label(Some("b"))
+            .with_failed(true),
+    ])
+    .with_output(Some("plot.pdf"))
+    .with_script_relative_paths(true)
+    .to_string();
+    assert!(script.contains("sp_dir = ARG0[1:i]"));
+    assert!(script.ends_with(
+        "set output '|ps2pdf -dEPSCrop - \"'.sp_dir.'plot.pdf\"'\n\
+         plot\\\n\
+         \tsp_dir.'series-1.csv' using 1:2 axis x1y1 with points,\\\n\
+         \tNaN with points title \"b (failed)\"\n"
+    ));
+}
+
+#[test]
+fn test_native_plot() {
+    let path = std::env::temp_dir()
+        .join(format!("sp-test-native-plot-{}.csv", std::process::id()));
+    std::fs::write(&path, "x_1,y_1,x_2,y_2\n0,0,0,9\n1,1,1,\n2,4,,\n").unwrap();
+    let series = |columns: (usize, usize)| {
+        DataSeriesOptions::from_datasheet_path(path.display().to_string())
+            .with_columns(columns.0, columns.1)
+            .with_plot_type(PlotType::Lines(None))
+    };
+    let template = GnuplotTemplate::from_data_series_options(vec![
+        series((1, 2)),
+        // untitled series are titled by the header of the datasheet
+        series((3, 4)).with_label(Some("squares")),
+    ])
+    .with_xopt(AxisOptions::new_x().with_label(Some("n")));
+    let text = template.to_native_plot().unwrap().to_text(40, 12);
+    assert!(text.contains("y_1 *****|"));
+    assert!(text.contains("squares #####|"));
+    assert!(text.lines().last().unwrap().trim() == "n");
+
+    let categorical = GnuplotTemplate::from_data_series_options(vec![
+        series((1, 2)).with_categorical_x(true),
+    ]);
+    assert!(categorical.to_native_plot().is_err());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,29 +1,30 @@
 use std::borrow::Cow;
 use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::{Command, ExitStatus};
-
-use rand::Rng;
-
-fn temp_filename(prefix: &str) -> PathBuf {
-    let tmp_dir = std::env::temp_dir();
-
-    let mut rng = rand::rng();
-    const CHARSET: &[u8] =
-        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-    let suffix: String = (0..16)
-        .map(|_| {
-            let idx = rng.random_range(0..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect();
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::Mutex;
+
+use crate::{TempArtifacts, TimeFormat};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-    // Combine components: /tmp/prefixXXXXXX
-    tmp_dir.join(format!("{prefix}{suffix}"))
+/// Generator of random names once seeded by `Plotter::set_seed`, names are
+/// drawn from the thread-local generator otherwise
+static SEEDED_RNG: Mutex<Option<StdRng>> = Mutex::new(None);
+
+/// New empty file /tmp/prefixXXXXXX.extension, tracked by `TempArtifacts`
+pub(crate) fn temp_filename(
+    prefix: &str,
+    extension: &str,
+) -> std::io::Result<PathBuf> {
+    TempArtifacts::create(prefix, extension, |path| {
+        File::create_new(path).map(drop)
+    })
 }
 
-fn to_rfc4180_csv_cell(input: &str) -> Cow<'_, str> {
+pub(crate) fn to_rfc4180_csv_cell(input: &str) -> Cow<'_, str> {
     let needs_quoting = input.contains(|c| {
         matches!(c, ',' | '"' | '\n' | '\r') || c.is_whitespace()
     });
@@ -46,12 +47,306 @@ fn to_rfc4180_csv_cell(input: &str) -> Cow<'_, str> {
     Cow::Owned(escaped)
 }
 
+/// Points of a CSV datasheet read row by row, see `DataPoints::stream_csv`
+pub struct PointStream<R> {
+    pub xtitle: String,
+    pub ytitle: String,
+    lines: std::io::Lines<R>,
+}
+
+impl<R: BufRead> Iterator for PointStream<R> {
+    type Item = std::io::Result<(f64, f64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.lines.next()? {
+                Ok(line) if line.is_empty() => continue,
+                Ok(line) => return Some(parse_csv_point(&line)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
 pub struct DataPoints {
     pub xtitle: String,
     pub ytitle: String,
     pub points: Vec<(f64, f64)>,
 }
 
+impl DataPoints {
+    pub fn new(
+        xtitle: impl AsRef<str>,
+        ytitle: impl AsRef<str>,
+        points: Vec<(f64, f64)>,
+    ) -> Self {
+        Self {
+            xtitle: xtitle.as_ref().to_string(),
+            ytitle: ytitle.as_ref().to_string(),
+            points,
+        }
+    }
+
+    /// Read a 2-column numeric CSV file with a header row, e.g. datasheets
+    /// produced by `sp`. ISO-8601 timestamps of x are read as seconds since
+    /// the Unix epoch (see `TimeFormat`), and empty cells as NaN (see
+    /// `MissingValues`).
+    pub fn from_csv(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Same as `from_csv`, but the CSV is read from `reader`
+    pub fn from_reader(reader: impl BufRead) -> std::io::Result<Self> {
+        let mut stream = Self::stream_reader(reader)?;
+        let points = stream.by_ref().collect::<std::io::Result<_>>()?;
+        Ok(Self {
+            xtitle: stream.xtitle,
+            ytitle: stream.ytitle,
+            points,
+        })
+    }
+
+    /// Same as `from_csv`, but the points are read one row at a time as
+    /// they are iterated, e.g. by `OpSeq::eval_iter`, so that files larger
+    /// than memory could be processed
+    pub fn stream_csv(
+        path: impl AsRef<Path>,
+    ) -> std::io::Result<PointStream<BufReader<File>>> {
+        Self::stream_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Same as `stream_csv`, but the CSV is read from `reader`
+    pub fn stream_reader<R: BufRead>(
+        reader: R,
+    ) -> std::io::Result<PointStream<R>> {
+        let mut lines = reader.lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let mut titles = header.splitn(2, ',').map(|s| s.trim_matches('"'));
+        Ok(PointStream {
+            xtitle: titles.next().unwrap_or("x").to_string(),
+            ytitle: titles.next().unwrap_or("y").to_string(),
+            lines,
+        })
+    }
+
+    /// Points of columns `x` and `y` (1-based) of a CSV datasheet with a
+    /// header row, titled by it, e.g. of a series of a multi-column
+    /// datasheet. Empty cells (of shorter series) and cells that are not
+    /// numbers are missing values (NaN).
+    pub fn read_columns(
+        path: impl AsRef<Path>,
+        (x, y): (usize, usize),
+    ) -> std::io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header =
+            split_csv_row(&lines.next().transpose()?.unwrap_or_default());
+        let title = |column: usize, default: &str| {
+            header
+                .get(column.saturating_sub(1))
+                .cloned()
+                .unwrap_or(default.to_string())
+        };
+        let (xtitle, ytitle) = (title(x, "x"), title(y, "y"));
+        let mut points = Vec::new();
+        for line in lines {
+            let cells = split_csv_row(&line?);
+            let value = |column: usize| {
+                cells
+                    .get(column.saturating_sub(1))
+                    .and_then(|cell| cell.trim().parse().ok())
+                    .unwrap_or(f64::NAN)
+            };
+            points.push((value(x), value(y)));
+        }
+        Ok(Self::new(xtitle, ytitle, points))
+    }
+
+    /// Distinct values in the columns (1-based) of a CSV datasheet with a
+    /// header row, in the order of their first appearance, e.g. the labels
+    /// of a categorical x axis (see `GnuplotTemplate::with_x_categories`).
+    /// Empty values (of shorter series) are skipped.
+    pub fn read_categories(
+        path: impl AsRef<Path>,
+        columns: &[usize],
+    ) -> std::io::Result<Vec<String>> {
+        let mut categories = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        for line in BufReader::new(File::open(path)?).lines().skip(1) {
+            let cells = split_csv_row(&line?);
+            for column in columns {
+                if let Some(cell) = cells.get(column.saturating_sub(1))
+                    && !cell.is_empty()
+                    && seen.insert(cell.clone())
+                {
+                    categories.push(cell.clone());
+                }
+            }
+        }
+        Ok(categories)
+    }
+
+    /// Downsample to at most `max_points` points with the
+    /// Largest-Triangle-Three-Buckets algorithm, which preserves the visual
+    /// shape of the series. Returns a note for the legend if points are
+    /// dropped, the note is also appended to `ytitle`.
+    pub fn decimate(&mut self, max_points: usize) -> Option<String> {
+        let max_points = max_points.max(3);
+        let total = self.points.len();
+        if total <= max_points {
+            return None;
+        }
+        self.points = lttb(&self.points, max_points);
+        let note = format!("(LTTB: {max_points} of {total} points)");
+        self.ytitle = format!("{} {note}", self.ytitle);
+        Some(note)
+    }
+}
+
+// cells of a CSV row, where quoted cells may contain commas and `""`
+fn split_csv_row(line: &str) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cells.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells
+}
+
+/// Convert CSV with a header row (e.g. a dump of duckdb) into JSON: an
+/// array of row objects, or an object of column arrays if `columnar`,
+/// cells that are JSON numbers being written as such and empty cells as
+/// null. Returns the number of rows.
+#[cfg(feature = "cli")]
+pub fn csv_to_json(
+    csv: impl BufRead,
+    writer: &mut impl Write,
+    columnar: bool,
+) -> anyhow::Result<usize> {
+    use serde_json::Value;
+
+    let mut lines = csv.lines();
+    let names = match lines.next().transpose()? {
+        Some(header) => split_csv_row(&header)
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![],
+    };
+    let value = |cell: String| match serde_json::from_str(&cell) {
+        _ if cell.is_empty() => Value::Null,
+        Ok(number @ Value::Number(_)) => number,
+        _ => Value::String(cell),
+    };
+
+    let mut rows = 0;
+    let mut columns = vec![vec![]; names.len()];
+    if !columnar {
+        write!(writer, "[")?;
+    }
+    for line in lines {
+        let cells = split_csv_row(&line?).into_iter().map(value);
+        if columnar {
+            for (column, cell) in columns.iter_mut().zip(cells) {
+                column.push(cell);
+            }
+        } else {
+            let fields = names
+                .iter()
+                .zip(cells)
+                .map(|(name, cell)| format!("{name}:{cell}"))
+                .collect::<Vec<_>>();
+            let sep = if rows == 0 { "" } else { "," };
+            write!(writer, "{sep}\n{{{}}}", fields.join(","))?;
+        }
+        rows += 1;
+    }
+    if columnar {
+        let fields = names
+            .iter()
+            .zip(columns)
+            .map(|(name, column)| format!("{name}:{}", Value::Array(column)))
+            .collect::<Vec<_>>();
+        writeln!(writer, "{{{}}}", fields.join(","))?;
+    } else {
+        writeln!(writer, "{}]", if rows == 0 { "" } else { "\n" })?;
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+/// (x, y) of a row of a 2-column numeric CSV file
+pub(crate) fn parse_csv_point(line: &str) -> std::io::Result<(f64, f64)> {
+    fn invalid(msg: String) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+    }
+    let (x, y) = line
+        .split_once(',')
+        .ok_or_else(|| invalid(format!("Not a 2-column row: {line}")))?;
+    // empty cells are missing values
+    let parse = |v: &str| match v {
+        "" => Ok(f64::NAN),
+        v => v
+            .parse::<f64>()
+            .map_err(|e| invalid(format!("Non-numeric value {v}: {e}"))),
+    };
+    // ISO-8601 timestamps of x are read as seconds since the Unix epoch
+    let x = match parse(x) {
+        Ok(x) => x,
+        Err(e) => match TimeFormat::Iso8601.parse(x.trim_matches('"')) {
+            Ok(Some(x)) => x,
+            _ => return Err(e),
+        },
+    };
+    Ok((x, parse(y)?))
+}
+
+fn lttb(points: &[(f64, f64)], threshold: usize) -> Vec<(f64, f64)> {
+    let len = points.len();
+    // the first and the last points are always kept, the rest are divided
+    // into (threshold - 2) buckets, each contributing one point
+    let every = (len - 2) as f64 / (threshold - 2) as f64;
+    let mut sampled = Vec::with_capacity(threshold);
+    sampled.push(points[0]);
+    let mut a = 0;
+    for i in 0..threshold - 2 {
+        // average point of the next bucket
+        let avg_start = ((i + 1) as f64 * every) as usize + 1;
+        let avg_end = (((i + 2) as f64 * every) as usize + 1).min(len);
+        let avg_len = (avg_end - avg_start) as f64;
+        let (avg_x, avg_y) = points[avg_start..avg_end]
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let (avg_x, avg_y) = (avg_x / avg_len, avg_y / avg_len);
+
+        // point of the current bucket forming the largest triangle with the
+        // previously selected point and the average of the next bucket
+        let range_start = (i as f64 * every) as usize + 1;
+        let range_end = ((i + 1) as f64 * every) as usize + 1;
+        let (ax, ay) = points[a];
+        let mut max_area = -1.0;
+        for (j, (x, y)) in points[range_start..range_end].iter().enumerate() {
+            let area =
+                ((ax - avg_x) * (y - ay) - (ax - x) * (avg_y - ay)).abs();
+            if area > max_area {
+                max_area = area;
+                a = range_start + j;
+            }
+        }
+        sampled.push(points[a]);
+    }
+    sampled.push(points[len - 1]);
+    sampled
+}
+
 pub enum DataSeriesSource {
     File(File),
     Stdin(std::io::Stdin),
@@ -61,8 +356,10 @@ pub enum DataSeriesSource {
 
 impl DataSeriesSource {
     pub fn dump(self, force_path: Option<PathBuf>) -> std::io::Result<PathBuf> {
-        let temp_ds_path = force_path
-            .unwrap_or_else(|| temp_filename("sp-").with_extension("csv"));
+        let temp_ds_path = match force_path {
+            Some(path) => path,
+            None => temp_filename("sp-", "csv")?,
+        };
         let mut temp_ds = File::create(temp_ds_path.clone())?;
         match self {
             DataSeriesSource::File(mut f) => {
@@ -75,10 +372,14 @@ impl DataSeriesSource {
                 std::io::copy(&mut c, &mut temp_ds)?;
             }
             DataSeriesSource::Points(p) => {
-                writeln!(temp_ds, "{},", to_rfc4180_csv_cell(&p.xtitle))?;
-                writeln!(temp_ds, "{}\n", to_rfc4180_csv_cell(&p.ytitle))?;
+                writeln!(
+                    temp_ds,
+                    "{},{}",
+                    to_rfc4180_csv_cell(&p.xtitle),
+                    to_rfc4180_csv_cell(&p.ytitle)
+                )?;
                 for (x, y) in p.points.iter() {
-                    writeln!(temp_ds, "{x},{y}\n")?;
+                    writeln!(temp_ds, "{x},{y}")?;
                 }
             }
         }
@@ -90,15 +391,75 @@ impl DataSeriesSource {
 pub struct Plotter {}
 
 impl Plotter {
+    /// Make the random names (e.g. of temporary files) deterministic
+    pub fn set_seed(seed: u64) {
+        *SEEDED_RNG.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some(StdRng::seed_from_u64(seed));
+    }
+
+    /// Random alphanumeric string of `len` characters
+    pub fn random_name(len: usize) -> String {
+        const CHARSET: &[u8] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+        let mut seeded = SEEDED_RNG.lock().unwrap_or_else(|e| e.into_inner());
+        let mut thread_rng = rand::rng();
+        let rng: &mut dyn rand::RngCore = match seeded.as_mut() {
+            Some(rng) => rng,
+            None => &mut thread_rng,
+        };
+        (0..len)
+            .map(|_| CHARSET[rng.random_range(0..CHARSET.len())] as char)
+            .collect()
+    }
+
     pub fn plot(gpcmd: &str) -> std::io::Result<ExitStatus> {
         // generate temporary gnuplot script file
-        let out_gp_name = temp_filename("sp-").with_extension("gp");
-        let mut out_gp = File::create(out_gp_name.clone())?;
+        let out_gp_name = temp_filename("sp-", "gp")?;
+        Self::plot_with_script_path(gpcmd, out_gp_name)
+    }
+
+    /// Store the gnuplot script at `script_path` and plot it
+    pub fn plot_with_script_path(
+        gpcmd: &str,
+        script_path: impl AsRef<Path>,
+    ) -> std::io::Result<ExitStatus> {
+        let out_gp_name = script_path.as_ref();
+        let mut out_gp = File::create(out_gp_name)?;
         writeln!(out_gp, "{gpcmd}")?;
         drop(out_gp);
 
-        log::info!("Temporary gnuplot script file: {}", out_gp_name.display());
+        Self::plot_script(out_gp_name)
+    }
+
+    /// Plot an existing gnuplot script file
+    pub fn plot_script(
+        script_path: impl AsRef<Path>,
+    ) -> std::io::Result<ExitStatus> {
+        Self::spawn_script(script_path)?.wait()
+    }
+
+    /// Same as `plot_script`, but returns without waiting for gnuplot
+    pub fn spawn_script(
+        script_path: impl AsRef<Path>,
+    ) -> std::io::Result<Child> {
+        let out_gp_name = script_path.as_ref();
+        tracing::info!("gnuplot script file: {}", out_gp_name.display());
         // call gnuplot
-        Command::new("gnuplot").arg("-p").arg(&out_gp_name).status()
+        Command::new("gnuplot").arg("-p").arg(out_gp_name).spawn()
     }
 }
+
+#[test]
+fn test_lttb_decimation() {
+    let mut dp = DataPoints {
+        xtitle: "x".to_string(),
+        ytitle: "y".to_string(),
+        points: (0..1000).map(|i| (i as f64, (i % 7) as f64)).collect(),
+    };
+    assert!(dp.decimate(2000).is_none());
+    assert!(dp.decimate(100).is_some());
+    assert_eq!(dp.points.len(), 100);
+    assert_eq!(dp.points.first(), Some(&(0.0, 0.0)));
+    assert_eq!(dp.points.last(), Some(&(999.0, (999 % 7) as f64)));
+    assert!(dp.points.windows(2).all(|w| w[0].0 < w[1].0));
+}
@@ -0,0 +1,313 @@
+// Prometheus text exposition format (e.g. a `curl host:9100/metrics`
+// snapshot) read natively and converted to CSV, one row per sample
+
+use anyhow::{Context, anyhow, bail};
+use regex::Regex;
+
+// comparison of a label matcher, as in PromQL
+#[derive(Debug, Clone)]
+enum Matcher {
+    Equal(String),
+    NotEqual(String),
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+impl Matcher {
+    // a missing label has an empty value
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Equal(v) => value == v,
+            Self::NotEqual(v) => value != v,
+            Self::Matches(re) => re.is_match(value),
+            Self::NotMatches(re) => !re.is_match(value),
+        }
+    }
+}
+
+/// Samples selected by a metric name and/or label matchers, e.g.
+/// `node_cpu_seconds_total{mode="idle",cpu=~"[0-3]"}` or `{job!="test"}`
+#[derive(Debug, Clone)]
+pub(crate) struct MetricSelector {
+    name: Option<String>,
+    labels: Vec<(String, Matcher)>,
+}
+
+impl MetricSelector {
+    pub(crate) fn parse(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let (name, rest) = match s.find('{') {
+            Some(i) => (&s[..i], &s[i..]),
+            None => (s, ""),
+        };
+        let mut labels = Vec::new();
+        if !rest.is_empty() {
+            let Some(matchers) =
+                rest.strip_prefix('{').and_then(|r| r.strip_suffix('}'))
+            else {
+                bail!("Invalid metric selector '{s}'");
+            };
+            for (label, op, value) in split_labels(matchers)
+                .context(format!("Invalid metric selector '{s}'"))?
+            {
+                let anchored = || Regex::new(&format!("^(?:{value})$"));
+                let matcher = match op {
+                    "=" => Matcher::Equal(value),
+                    "!=" => Matcher::NotEqual(value),
+                    "=~" => Matcher::Matches(anchored()?),
+                    "!~" => Matcher::NotMatches(anchored()?),
+                    _ => unreachable!(),
+                };
+                labels.push((label, matcher));
+            }
+        }
+        let name = name.trim();
+        if name.is_empty() && labels.is_empty() {
+            bail!("Empty metric selector");
+        }
+        Ok(Self {
+            name: (!name.is_empty()).then(|| name.to_string()),
+            labels,
+        })
+    }
+
+    fn matches(&self, sample: &Sample) -> bool {
+        let label = |name: &str| {
+            sample
+                .labels
+                .iter()
+                .find(|(label, _)| label == name)
+                .map_or("", |(_, value)| value.as_str())
+        };
+        self.name.as_ref().is_none_or(|name| *name == sample.name)
+            && self
+                .labels
+                .iter()
+                .all(|(name, matcher)| matcher.matches(label(name)))
+    }
+}
+
+struct Sample {
+    name: String,
+    labels: Vec<(String, String)>,
+    value: f64,
+    // milliseconds since the Unix epoch
+    timestamp: Option<i64>,
+}
+
+// `label="value"` pairs separated by commas (with a trailing one allowed)
+// as (label, operator, unescaped value), the operator being `=` in samples
+fn split_labels(
+    s: &str,
+) -> anyhow::Result<Vec<(String, &'static str, String)>> {
+    let mut labels = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let end = rest
+            .find(['=', '!'])
+            .ok_or_else(|| anyhow!("Missing value of label '{rest}'"))?;
+        let label = rest[..end].trim().to_string();
+        rest = &rest[end..];
+        let op = ["=~", "!~", "!=", "="]
+            .into_iter()
+            .find(|op| rest.starts_with(op))
+            .ok_or_else(|| anyhow!("Invalid matcher of label '{label}'"))?;
+        rest = rest[op.len()..].trim_start();
+        let Some(quoted) = rest.strip_prefix('"') else {
+            bail!("Unquoted value of label '{label}'");
+        };
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, c)) => value.push(c),
+                    None => break None,
+                },
+                Some((i, '"')) => break Some(i),
+                Some((_, c)) => value.push(c),
+                None => break None,
+            }
+        }
+        .ok_or_else(|| anyhow!("Unterminated value of label '{label}'"))?;
+        labels.push((label, op, value));
+        rest = quoted[end + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    Ok(labels)
+}
+
+// sample of a line, none for blank lines and comments (`# HELP`, `# TYPE`)
+fn parse_sample(line: &str) -> anyhow::Result<Option<Sample>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (name, labels, rest) = match line.find(['{', ' ', '\t']) {
+        Some(i) if line[i..].starts_with('{') => {
+            let end = line
+                .rfind('}')
+                .ok_or_else(|| anyhow!("Unterminated labels"))?;
+            let labels = split_labels(&line[i + 1..end])?
+                .into_iter()
+                .map(|(label, _, value)| (label, value))
+                .collect();
+            (&line[..i], labels, &line[end + 1..])
+        }
+        Some(i) => (&line[..i], vec![], &line[i..]),
+        None => bail!("Missing value"),
+    };
+    let mut fields = rest.split_whitespace();
+    let value = fields.next().ok_or_else(|| anyhow!("Missing value"))?;
+    let value = value
+        .parse::<f64>()
+        .map_err(|_| anyhow!("Invalid value '{value}'"))?;
+    let timestamp = fields
+        .next()
+        .map(|ts| {
+            ts.parse::<i64>()
+                .map_err(|_| anyhow!("Invalid timestamp '{ts}'"))
+        })
+        .transpose()?;
+    Ok(Some(Sample {
+        name: name.to_string(),
+        labels,
+        value,
+        timestamp,
+    }))
+}
+
+/// Write the samples of the exposition at `path` selected by `selector`
+/// (all of them if unset) to `dst` as CSV, with the columns `metric`, one
+/// per label name (in alphabetical order, empty where a sample lacks it),
+/// `value` (NaN being missing) and `timestamp` (in seconds since the Unix
+/// epoch, empty if the sample has none)
+pub(crate) fn samples_to_csv(
+    path: &str,
+    selector: Option<&MetricSelector>,
+    dst: &std::path::Path,
+) -> anyhow::Result<()> {
+    use std::collections::BTreeSet;
+    use std::io::{BufRead, Write};
+
+    use crate::plotter::to_rfc4180_csv_cell;
+
+    let file = std::fs::File::open(path)
+        .context(format!("Failed to open '{path}'"))?;
+    let mut samples = Vec::new();
+    for (i, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let sample = parse_sample(&line?)
+            .context(format!("Invalid sample at line {} of '{path}'", i + 1))?;
+        samples.extend(
+            sample.filter(|s| selector.is_none_or(|sel| sel.matches(s))),
+        );
+    }
+    if samples.is_empty() {
+        bail!("No sample of '{path}' matches the metric selector");
+    }
+    let label_names = samples
+        .iter()
+        .flat_map(|s| s.labels.iter().map(|(label, _)| label.as_str()))
+        .collect::<BTreeSet<_>>();
+    // labels named as the other columns are renamed as Prometheus does when
+    // scraping labels that it sets itself
+    let columns = label_names
+        .iter()
+        .map(|&label| match label {
+            "metric" | "value" | "timestamp" => format!("exported_{label}"),
+            label => label.to_string(),
+        })
+        .collect::<Vec<_>>();
+    if let Some(column) = columns
+        .iter()
+        .filter(|column| column.starts_with("exported_"))
+        .find(|column| label_names.contains(column.as_str()))
+    {
+        bail!("Label '{column}' of '{path}' collides with a renamed label");
+    }
+    tracing::debug!(
+        path,
+        samples = samples.len(),
+        ?label_names,
+        "Reading Prometheus samples"
+    );
+
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(dst)?);
+    let header = std::iter::once("metric")
+        .chain(columns.iter().map(String::as_str))
+        .chain(["value", "timestamp"])
+        .map(|name| to_rfc4180_csv_cell(name).to_string())
+        .collect::<Vec<_>>();
+    writeln!(writer, "{}", header.join(","))?;
+    for sample in &samples {
+        let mut cells = vec![to_rfc4180_csv_cell(&sample.name).to_string()];
+        for name in &label_names {
+            let value = sample.labels.iter().find(|(label, _)| label == name);
+            cells.push(value.map_or("".to_string(), |(_, value)| {
+                to_rfc4180_csv_cell(value).to_string()
+            }));
+        }
+        cells.push(match sample.value {
+            v if v.is_nan() => "".to_string(),
+            v => v.to_string(),
+        });
+        cells.push(match sample.timestamp {
+            Some(ms) => (ms as f64 / 1000.0).to_string(),
+            None => "".to_string(),
+        });
+        writeln!(writer, "{}", cells.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test_samples_to_csv() {
+    let dir = std::env::temp_dir();
+    let src = dir.join(format!("sp-test-{}.prom", std::process::id()));
+    let dst = dir.join(format!("sp-test-{}.csv", std::process::id()));
+    let convert = |exposition: &str, selector: Option<&str>| {
+        std::fs::write(&src, exposition).unwrap();
+        let selector = selector.map(|s| MetricSelector::parse(s).unwrap());
+        let result =
+            samples_to_csv(src.to_str().unwrap(), selector.as_ref(), &dst)
+                .map(|()| std::fs::read_to_string(&dst).unwrap());
+        let _ = std::fs::remove_file(&src);
+        let _ = std::fs::remove_file(&dst);
+        result
+    };
+
+    // comments are skipped, timestamps are optional
+    let csv = convert(
+        "# HELP http_requests_total Requests.\n\
+         # TYPE http_requests_total counter\n\
+         http_requests_total{path=\"/a,b\",quote=\"say \\\"hi\\\"\"} 3 1700000000000\n\
+         http_requests_total 4\n",
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        csv,
+        "metric,path,quote,value,timestamp\n\
+         http_requests_total,\"/a,b\",\"say \"\"hi\"\"\",3,1700000000\n\
+         http_requests_total,,,4,\n"
+    );
+
+    // labels named as the other columns are renamed
+    let csv = convert("up{value=\"x\",job=\"api\"} NaN\n", None).unwrap();
+    assert_eq!(
+        csv,
+        "metric,job,exported_value,value,timestamp\nup,api,x,,\n"
+    );
+    assert!(convert("up{value=\"x\",exported_value=\"y\"} 1\n", None).is_err());
+
+    // only the selected samples are kept
+    let exposition = "up{job=\"api\"} 1\nup{job=\"web\"} 0\nload 0.5\n";
+    let csv = convert(exposition, Some("up{job!~\"w.*\"}")).unwrap();
+    assert_eq!(csv, "metric,job,value,timestamp\nup,api,1,\n");
+    let csv = convert(exposition, Some("load")).unwrap();
+    assert_eq!(csv, "metric,value,timestamp\nload,0.5,\n");
+    assert!(convert(exposition, Some("missing")).is_err());
+    assert!(convert("# TYPE up gauge\n", None).is_err());
+}
@@ -0,0 +1,182 @@
+// Python bindings of the in-memory datasheet, operators and plot scripts,
+// built as an extension module with maturin (see pyproject.toml)
+
+use std::path::PathBuf;
+
+use numpy::{PyArray1, PyReadonlyArray1};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{
+    DataPoints, DataSeriesOptions, DataSeriesSource, GnuplotTemplate, OpSeq,
+    PlotType,
+};
+
+/// Python view of `DataPoints`
+#[pyclass(name = "DataPoints")]
+struct PyDataPoints {
+    #[pyo3(get)]
+    xtitle: String,
+    #[pyo3(get)]
+    ytitle: String,
+    points: Vec<(f64, f64)>,
+}
+
+impl PyDataPoints {
+    fn to_data_points(&self) -> DataPoints {
+        DataPoints::new(&self.xtitle, &self.ytitle, self.points.clone())
+    }
+}
+
+impl From<DataPoints> for PyDataPoints {
+    fn from(points: DataPoints) -> Self {
+        Self {
+            xtitle: points.xtitle,
+            ytitle: points.ytitle,
+            points: points.points,
+        }
+    }
+}
+
+#[pymethods]
+impl PyDataPoints {
+    #[new]
+    #[pyo3(signature = (x, y, xtitle = "x", ytitle = "y"))]
+    fn new(
+        x: PyReadonlyArray1<f64>,
+        y: PyReadonlyArray1<f64>,
+        xtitle: &str,
+        ytitle: &str,
+    ) -> PyResult<Self> {
+        let (x, y) = (x.as_array(), y.as_array());
+        if x.len() != y.len() {
+            return Err(PyValueError::new_err(format!(
+                "x and y differ in length ({} and {})",
+                x.len(),
+                y.len()
+            )));
+        }
+        Ok(DataPoints::new(
+            xtitle,
+            ytitle,
+            x.iter().copied().zip(y.iter().copied()).collect(),
+        )
+        .into())
+    }
+
+    /// Columns `x` and `y` of a pandas DataFrame, titled by the column names
+    #[staticmethod]
+    fn from_pandas(df: &Bound<'_, PyAny>, x: &str, y: &str) -> PyResult<Self> {
+        let column = |name: &str| -> PyResult<PyReadonlyArray1<f64>> {
+            Ok(df
+                .get_item(name)?
+                .call_method1("to_numpy", ("float64",))?
+                .extract()?)
+        };
+        Self::new(column(x)?, column(y)?, x, y)
+    }
+
+    /// Read a datasheet written by `sp`
+    #[staticmethod]
+    fn from_csv(path: PathBuf) -> PyResult<Self> {
+        Ok(DataPoints::from_csv(path)?.into())
+    }
+
+    #[getter]
+    fn x<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        PyArray1::from_iter(py, self.points.iter().map(|(x, _)| *x))
+    }
+
+    #[getter]
+    fn y<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        PyArray1::from_iter(py, self.points.iter().map(|(_, y)| *y))
+    }
+
+    fn __len__(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Apply an operation sequence (e.g. `"c"`)
+    fn apply(&self, py: Python<'_>, opseq: &str) -> anyhow::Result<Self> {
+        let opseq = opseq.parse::<OpSeq>()?;
+        let points = self.to_data_points();
+        Ok(py.detach(|| opseq.apply(&points))?.into())
+    }
+
+    /// Write the datasheet as CSV, to a temporary file unless `path` is
+    /// given, and return its path
+    #[pyo3(signature = (path = None))]
+    fn dump(&self, path: Option<PathBuf>) -> PyResult<PathBuf> {
+        Ok(DataSeriesSource::Points(self.to_data_points()).dump(path)?)
+    }
+}
+
+/// gnuplot script plotting the datasheets, `plot_type` is one of `points`,
+/// `lines` and `linespoints`
+#[pyfunction]
+#[pyo3(signature = (datasheets, labels = None, plot_type = "points", output = None))]
+fn plot_script(
+    datasheets: Vec<String>,
+    labels: Option<Vec<String>>,
+    plot_type: &str,
+    output: Option<String>,
+) -> PyResult<String> {
+    let plot_type = match plot_type {
+        "points" => PlotType::Points(None),
+        "lines" => PlotType::Lines(None),
+        "linespoints" => PlotType::Linespoints(None, None),
+        other => {
+            return Err(PyValueError::new_err(format!(
+                "Invalid plot type '{other}'"
+            )));
+        }
+    };
+    let labels = labels.unwrap_or_default();
+    let options = datasheets
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            DataSeriesOptions::from_datasheet_path(path)
+                .with_plot_type(plot_type.clone())
+                .with_label(labels.get(i))
+        })
+        .collect();
+    Ok(GnuplotTemplate::from_data_series_options(options)
+        .with_output(output)
+        .to_string())
+}
+
+#[pymodule]
+fn spreadsheet_plotter(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDataPoints>()?;
+    m.add_function(wrap_pyfunction!(plot_script, m)?)?;
+    Ok(())
+}
+
+#[test]
+fn test_plot_script() {
+    let script = plot_script(
+        vec!["a.csv".to_string(), "b.csv".to_string()],
+        Some(vec!["A".to_string()]),
+        "lines",
+        Some("out.pdf".to_string()),
+    )
+    .unwrap();
+    assert!(script.contains(
+        "\t'a.csv' using 1:2 axis x1y1 with lines title \"A\",\\\n\
+         \t'b.csv' using 1:2 axis x1y1 with lines\n"
+    ));
+    assert!(script.contains("out.pdf"));
+    assert!(plot_script(vec![], None, "bars", None).is_err());
+
+    let points =
+        PyDataPoints::from(DataPoints::new("n", "n^2", vec![(2.0, 4.0)]))
+            .to_data_points();
+    assert_eq!(
+        (
+            points.xtitle.as_str(),
+            points.ytitle.as_str(),
+            points.points
+        ),
+        ("n", "n^2", vec![(2.0, 4.0)])
+    );
+}
@@ -0,0 +1,151 @@
+// Discrete Fourier transform of the native spectrum operator, radix-2 for
+// lengths of powers of two and Bluestein's algorithm for the other lengths
+
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn from_angle(angle: f64) -> Self {
+        Self {
+            re: angle.cos(),
+            im: angle.sin(),
+        }
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+
+    fn conj(self) -> Self {
+        Self {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+
+    fn norm(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+}
+
+// in-place iterative radix-2 FFT, `buf.len()` being a power of two
+fn fft(buf: &mut [Complex], inverse: bool) {
+    let n = buf.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let step = Complex::from_angle(sign * 2.0 * PI / len as f64);
+        for chunk in buf.chunks_mut(len) {
+            let mut w = Complex { re: 1.0, im: 0.0 };
+            let (lo, hi) = chunk.split_at_mut(len / 2);
+            for (a, b) in lo.iter_mut().zip(hi.iter_mut()) {
+                let t = b.mul(w);
+                *b = a.sub(t);
+                *a = a.add(t);
+                w = w.mul(step);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// DFT of real values, X_k = sum of x_j * exp(-2 pi i j k / n)
+fn dft(values: &[f64]) -> Vec<Complex> {
+    let n = values.len();
+    let real = |v: &f64| Complex { re: *v, im: 0.0 };
+    if n.is_power_of_two() {
+        let mut buf = values.iter().map(real).collect::<Vec<_>>();
+        fft(&mut buf, false);
+        return buf;
+    }
+    // Bluestein: X_k = c_k * sum of (x_j * c_j) * conj(c_(k - j)) with the
+    // chirp c_m = exp(-pi i m^2 / n), the sum being a convolution of length
+    // m >= 2n - 1; m^2 is reduced modulo 2n to keep the angles accurate
+    let chirp = (0..n)
+        .map(|m| {
+            let m2 = (m as u128 * m as u128 % (2 * n as u128)) as f64;
+            Complex::from_angle(-PI * m2 / n as f64)
+        })
+        .collect::<Vec<_>>();
+    let m = (2 * n - 1).next_power_of_two();
+    let mut a = vec![Complex::default(); m];
+    for (j, v) in values.iter().enumerate() {
+        a[j] = real(v).mul(chirp[j]);
+    }
+    let mut b = vec![Complex::default(); m];
+    b[0] = chirp[0].conj();
+    for j in 1..n {
+        b[j] = chirp[j].conj();
+        b[m - j] = chirp[j].conj();
+    }
+    fft(&mut a, false);
+    fft(&mut b, false);
+    for (a, b) in a.iter_mut().zip(&b) {
+        *a = a.mul(*b);
+    }
+    fft(&mut a, true);
+    (0..n)
+        .map(|k| {
+            let conv = Complex {
+                re: a[k].re / m as f64,
+                im: a[k].im / m as f64,
+            };
+            conv.mul(chirp[k])
+        })
+        .collect()
+}
+
+/// Single-sided amplitude spectrum of `values` sampled at a uniform interval,
+/// i.e. the amplitude of the sinusoid at each of the frequencies k / n (in
+/// cycles per sample) for k from 0 to n / 2
+pub(crate) fn amplitude_spectrum(values: &[f64]) -> Vec<f64> {
+    let n = values.len();
+    if n == 0 {
+        return vec![];
+    }
+    dft(values)
+        .into_iter()
+        .take(n / 2 + 1)
+        .enumerate()
+        .map(|(k, x)| {
+            // the constant component and the Nyquist frequency have no
+            // mirrored negative frequency
+            let scale = if k == 0 || 2 * k == n { 1.0 } else { 2.0 };
+            scale * x.norm() / n as f64
+        })
+        .collect()
+}
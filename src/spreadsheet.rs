@@ -0,0 +1,90 @@
+// Spreadsheets (xlsx, xlsm, xlsb, xls and ods) read natively with calamine
+// and converted to CSV, which duckdb then reads like any other input
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, anyhow};
+use calamine::{Data, Reader, open_workbook_auto};
+
+use crate::plotter::to_rfc4180_csv_cell;
+
+// cell as written to CSV, errors (e.g. #DIV/0!) being missing values
+fn cell_to_csv(cell: &Data) -> String {
+    match cell {
+        Data::Int(v) => v.to_string(),
+        Data::Float(v) => v.to_string(),
+        Data::Bool(v) => v.to_string(),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => {
+            to_rfc4180_csv_cell(s).to_string()
+        }
+        // durations are written in seconds
+        Data::DateTime(dt) if dt.is_duration() => {
+            (dt.as_f64() * 86400.0).to_string()
+        }
+        Data::DateTime(dt) => {
+            let (year, month, day, hour, minute, second, milli) =
+                dt.to_ymd_hms_milli();
+            format!(
+                "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{milli:03}"
+            )
+        }
+        Data::Error(_) | Data::Empty => "".to_string(),
+    }
+}
+
+/// Names of the sheets of the workbook at `path`, in order
+pub(crate) fn sheet_names(path: &str) -> anyhow::Result<Vec<String>> {
+    let workbook = open_workbook_auto(path)
+        .context(format!("Failed to open spreadsheet '{path}'"))?;
+    Ok(workbook.sheet_names())
+}
+
+/// Write the sheet of the workbook at `path` to `dst` as CSV, the sheet
+/// being given by its name or by its position (from 1), the first one if
+/// `sheet` is unset, without its first `skip` rows
+pub(crate) fn sheet_to_csv(
+    path: &str,
+    sheet: Option<&str>,
+    skip: usize,
+    dst: &Path,
+) -> anyhow::Result<()> {
+    let mut workbook = open_workbook_auto(path)
+        .context(format!("Failed to open spreadsheet '{path}'"))?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        None => names.first(),
+        Some(sheet) => names.iter().find(|name| *name == sheet).or_else(|| {
+            sheet
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| names.get(i.checked_sub(1)?))
+        }),
+    }
+    .cloned()
+    .ok_or_else(|| {
+        anyhow!(
+            "No sheet '{}' in '{path}' (sheets: {})",
+            sheet.unwrap_or("1"),
+            names.join(", ")
+        )
+    })?;
+    let range = workbook
+        .worksheet_range(&name)
+        .context(format!("Failed to read sheet '{name}' of '{path}'"))?;
+    tracing::debug!(
+        path,
+        sheet = name,
+        rows = range.height(),
+        "Reading spreadsheet"
+    );
+
+    let mut writer = BufWriter::new(File::create(dst)?);
+    for row in range.rows().skip(skip) {
+        let cells = row.iter().map(cell_to_csv).collect::<Vec<_>>();
+        writeln!(writer, "{}", cells.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
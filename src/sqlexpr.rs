@@ -0,0 +1,785 @@
+// The subset of the SQL expressions of duckdb that the expressions and the
+// filters of `PlainSelector` are evaluated with natively, on the rows of a
+// `Table`: arithmetic, comparisons, logic, CASE, CAST and common functions
+
+use anyhow::{anyhow, bail};
+
+use crate::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Word(String),
+    Quoted(String),
+    Index(usize),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 20] = [
+    "**", "//", "||", "::", "<>", "!=", "<=", ">=", "==", "+", "-", "*", "/",
+    "%", "^", "=", "<", ">", "(", ")",
+];
+
+fn tokenize(expr: &str, index_mark: char) -> anyhow::Result<Vec<Token>> {
+    let chars = expr.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let digits_from =
+        |j: usize| chars[j..].iter().take_while(|c| c.is_ascii_digit()).count();
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == index_mark && digits_from(i + 1) > 0 {
+            let len = digits_from(i + 1);
+            let index = chars[i + 1..i + 1 + len].iter().collect::<String>();
+            tokens.push(Token::Index(index.parse()?));
+            i += 1 + len;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '.')
+            {
+                // the sign of an exponent
+                if matches!(chars[i], 'e' | 'E')
+                    && matches!(chars.get(i + 1), Some('+' | '-'))
+                {
+                    i += 1;
+                }
+                i += 1;
+            }
+            let number = chars[start..i].iter().collect::<String>();
+            tokens.push(Token::Number(number.parse().map_err(|_| {
+                anyhow!("Invalid number '{number}' at char {start}")
+            })?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else if c == '\'' || c == '"' {
+            // quotes within are doubled
+            let start = i;
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => bail!("Unterminated {c} at char {start}"),
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                        text.push(c);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => break,
+                    Some(&q) => {
+                        text.push(q);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push(match c {
+                '\'' => Token::Text(text),
+                _ => Token::Quoted(text),
+            });
+        } else if c == ',' {
+            tokens.push(Token::Symbol(","));
+            i += 1;
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(*s))
+                .ok_or_else(|| anyhow!("Unexpected '{c}' at char {i}"))?;
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Concat,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    IntDiv,
+    Mod,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CastType {
+    Double,
+    Integer,
+    Varchar,
+    Boolean,
+}
+
+/// Expression parsed against the columns of a table, see `compile`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node {
+    Literal(Value),
+    Column(usize),
+    Neg(Box<Node>),
+    Not(Box<Node>),
+    IsNull(Box<Node>, bool),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    Call(String, Vec<Node>),
+    Case(Option<Box<Node>>, Vec<(Node, Node)>, Option<Box<Node>>),
+    Cast(Box<Node>, CastType, bool),
+}
+
+/// Parse `expr`, whose columns are referred to by name (of `columns`) or
+/// by their index from 1 after `index_mark` (e.g. `$2`)
+pub(crate) fn compile(
+    expr: &str,
+    index_mark: char,
+    columns: &[String],
+) -> anyhow::Result<Node> {
+    let mut parser = Parser {
+        tokens: tokenize(expr, index_mark)?,
+        pos: 0,
+        columns,
+    };
+    let node = parser.expr()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(node),
+        Some(token) => bail!("Unexpected {token:?} in '{expr}'"),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    columns: &'a [String],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn eat_symbol(&mut self, symbols: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Symbol(s)) if symbols.contains(s) => {
+                let s = *s;
+                self.pos += 1;
+                Some(s)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> anyhow::Result<()> {
+        match self.eat_symbol(&[symbol]) {
+            Some(_) => Ok(()),
+            None => bail!("Expected '{symbol}', found {:?}", self.peek()),
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> anyhow::Result<()> {
+        match self.eat_keyword(keyword) {
+            true => Ok(()),
+            false => bail!("Expected {keyword}, found {:?}", self.peek()),
+        }
+    }
+
+    fn expr(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.and()?;
+        while self.eat_keyword("OR") {
+            node = Node::Binary(BinaryOp::Or, node.into(), self.and()?.into());
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.not()?;
+        while self.eat_keyword("AND") {
+            node = Node::Binary(BinaryOp::And, node.into(), self.not()?.into());
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> anyhow::Result<Node> {
+        match self.eat_keyword("NOT") {
+            true => Ok(Node::Not(self.not()?.into())),
+            false => self.is(),
+        }
+    }
+
+    fn is(&mut self) -> anyhow::Result<Node> {
+        let node = self.comparison()?;
+        if !self.eat_keyword("IS") {
+            return Ok(node);
+        }
+        let negated = self.eat_keyword("NOT");
+        self.expect_keyword("NULL")?;
+        Ok(Node::IsNull(node.into(), negated))
+    }
+
+    fn comparison(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.concat()?;
+        while let Some(symbol) =
+            self.eat_symbol(&["=", "==", "<>", "!=", "<", "<=", ">", ">="])
+        {
+            let op = match symbol {
+                "=" | "==" => BinaryOp::Eq,
+                "<>" | "!=" => BinaryOp::Ne,
+                "<" => BinaryOp::Lt,
+                "<=" => BinaryOp::Le,
+                ">" => BinaryOp::Gt,
+                _ => BinaryOp::Ge,
+            };
+            node = Node::Binary(op, node.into(), self.concat()?.into());
+        }
+        Ok(node)
+    }
+
+    fn concat(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.sum()?;
+        while self.eat_symbol(&["||"]).is_some() {
+            node =
+                Node::Binary(BinaryOp::Concat, node.into(), self.sum()?.into());
+        }
+        Ok(node)
+    }
+
+    fn sum(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.product()?;
+        while let Some(symbol) = self.eat_symbol(&["+", "-"]) {
+            let op = match symbol {
+                "+" => BinaryOp::Add,
+                _ => BinaryOp::Sub,
+            };
+            node = Node::Binary(op, node.into(), self.product()?.into());
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.power()?;
+        while let Some(symbol) = self.eat_symbol(&["*", "/", "//", "%"]) {
+            let op = match symbol {
+                "*" => BinaryOp::Mul,
+                "/" => BinaryOp::Div,
+                "//" => BinaryOp::IntDiv,
+                _ => BinaryOp::Mod,
+            };
+            node = Node::Binary(op, node.into(), self.power()?.into());
+        }
+        Ok(node)
+    }
+
+    fn power(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.unary()?;
+        while self.eat_symbol(&["^", "**"]).is_some() {
+            node =
+                Node::Binary(BinaryOp::Pow, node.into(), self.unary()?.into());
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Node> {
+        match self.eat_symbol(&["-", "+"]) {
+            Some("-") => Ok(Node::Neg(self.unary()?.into())),
+            Some(_) => self.unary(),
+            None => self.cast(),
+        }
+    }
+
+    fn cast(&mut self) -> anyhow::Result<Node> {
+        let mut node = self.primary()?;
+        while self.eat_symbol(&["::"]).is_some() {
+            node = Node::Cast(node.into(), self.cast_type()?, false);
+        }
+        Ok(node)
+    }
+
+    fn cast_type(&mut self) -> anyhow::Result<CastType> {
+        let Some(Token::Word(name)) = self.peek().cloned() else {
+            bail!("Expected a type, found {:?}", self.peek());
+        };
+        self.pos += 1;
+        Ok(match name.to_ascii_uppercase().as_str() {
+            "DOUBLE" | "FLOAT" | "REAL" | "DECIMAL" | "NUMERIC" => {
+                CastType::Double
+            }
+            "INTEGER" | "INT" | "BIGINT" | "SMALLINT" | "TINYINT"
+            | "HUGEINT" => CastType::Integer,
+            "VARCHAR" | "TEXT" | "STRING" => CastType::Varchar,
+            "BOOLEAN" | "BOOL" => CastType::Boolean,
+            _ => bail!("Type {name} is not supported natively"),
+        })
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Node> {
+        let Some(token) = self.peek().cloned() else {
+            bail!("Unexpected end of expression");
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(number) => Ok(Node::Literal(Value::Number(number))),
+            Token::Text(text) => Ok(Node::Literal(Value::Text(text))),
+            Token::Index(0) => bail!("Invalid index 0"),
+            Token::Index(index) if index > self.columns.len() => bail!(
+                "Index {index} is beyond the {} columns",
+                self.columns.len()
+            ),
+            Token::Index(index) => Ok(Node::Column(index - 1)),
+            Token::Quoted(name) => self.column(&name),
+            Token::Symbol("(") => {
+                let node = self.expr()?;
+                self.expect_symbol(")")?;
+                Ok(node)
+            }
+            Token::Symbol(symbol) => bail!("Unexpected '{symbol}'"),
+            Token::Word(word) => match word.to_ascii_uppercase().as_str() {
+                "NULL" => Ok(Node::Literal(Value::Null)),
+                "TRUE" => Ok(Node::Literal(Value::Bool(true))),
+                "FALSE" => Ok(Node::Literal(Value::Bool(false))),
+                "CASE" => self.case(),
+                "CAST" | "TRY_CAST" => {
+                    self.expect_symbol("(")?;
+                    let node = self.expr()?;
+                    self.expect_keyword("AS")?;
+                    let cast_type = self.cast_type()?;
+                    self.expect_symbol(")")?;
+                    let tried = word.eq_ignore_ascii_case("TRY_CAST");
+                    Ok(Node::Cast(node.into(), cast_type, tried))
+                }
+                _ if self.eat_symbol(&["("]).is_some() => {
+                    let mut args = Vec::new();
+                    if self.eat_symbol(&[")"]).is_none() {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat_symbol(&[")"]).is_some() {
+                                break;
+                            }
+                            self.expect_symbol(",")?;
+                        }
+                    }
+                    let name = word.to_ascii_lowercase();
+                    check_call(&name, args.len())?;
+                    Ok(Node::Call(name, args))
+                }
+                _ => self.column(&word),
+            },
+        }
+    }
+
+    fn case(&mut self) -> anyhow::Result<Node> {
+        let operand = match self.is_keyword("WHEN") {
+            true => None,
+            false => Some(Box::new(self.expr()?)),
+        };
+        let mut branches = Vec::new();
+        while self.eat_keyword("WHEN") {
+            let condition = self.expr()?;
+            self.expect_keyword("THEN")?;
+            branches.push((condition, self.expr()?));
+        }
+        if branches.is_empty() {
+            bail!("CASE without WHEN");
+        }
+        let otherwise = match self.eat_keyword("ELSE") {
+            true => Some(Box::new(self.expr()?)),
+            false => None,
+        };
+        self.expect_keyword("END")?;
+        Ok(Node::Case(operand, branches, otherwise))
+    }
+
+    fn column(&self, name: &str) -> anyhow::Result<Node> {
+        let exact = self.columns.iter().position(|c| c == name);
+        exact
+            .or_else(|| {
+                self.columns
+                    .iter()
+                    .position(|c| c.eq_ignore_ascii_case(name))
+            })
+            .map(Node::Column)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Column '{name}' not found (columns: {})",
+                    self.columns.join(", ")
+                )
+            })
+    }
+}
+
+// functions evaluated natively, with their numbers of arguments
+const FUNCTIONS: [(&str, usize, usize); 31] = [
+    ("abs", 1, 1),
+    ("sqrt", 1, 1),
+    ("cbrt", 1, 1),
+    ("exp", 1, 1),
+    ("ln", 1, 1),
+    ("log", 1, 2),
+    ("log10", 1, 1),
+    ("log2", 1, 1),
+    ("pow", 2, 2),
+    ("power", 2, 2),
+    ("floor", 1, 1),
+    ("ceil", 1, 1),
+    ("ceiling", 1, 1),
+    ("round", 1, 2),
+    ("trunc", 1, 1),
+    ("sign", 1, 1),
+    ("sin", 1, 1),
+    ("cos", 1, 1),
+    ("tan", 1, 1),
+    ("asin", 1, 1),
+    ("acos", 1, 1),
+    ("atan", 1, 1),
+    ("atan2", 2, 2),
+    ("pi", 0, 0),
+    ("greatest", 1, usize::MAX),
+    ("least", 1, usize::MAX),
+    ("coalesce", 1, usize::MAX),
+    ("nullif", 2, 2),
+    ("length", 1, 1),
+    ("lower", 1, 1),
+    ("upper", 1, 1),
+];
+
+fn check_call(name: &str, args: usize) -> anyhow::Result<()> {
+    match FUNCTIONS.iter().find(|(f, _, _)| *f == name) {
+        None => bail!("Function {name}() is not supported natively"),
+        Some((_, min, max)) if args < *min || args > *max => {
+            bail!("Function {name}() does not take {args} arguments")
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+fn number(value: &Value) -> anyhow::Result<Option<f64>> {
+    match value {
+        Value::Null => Ok(None),
+        value => value.to_f64().map(Some),
+    }
+}
+
+fn truth(value: &Value) -> anyhow::Result<Option<bool>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Bool(b) => Ok(Some(*b)),
+        Value::Number(n) => Ok(Some(*n != 0.0)),
+        Value::Text(text) => match text.to_ascii_lowercase().as_str() {
+            "true" | "t" => Ok(Some(true)),
+            "false" | "f" => Ok(Some(false)),
+            _ => bail!("'{text}' is not a boolean"),
+        },
+    }
+}
+
+// order of two values, numbers being compared to the numbers of text
+fn compare(a: &Value, b: &Value) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (a, b) => match (a.to_f64(), b.to_f64()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => Some(a.to_string().cmp(&b.to_string())),
+        },
+    }
+}
+
+impl Node {
+    /// Value of the expression on a row of the table it was compiled for,
+    /// missing values propagating as NULL does in SQL
+    pub(crate) fn eval(&self, row: &[Value]) -> anyhow::Result<Value> {
+        Ok(match self {
+            Node::Literal(value) => value.clone(),
+            Node::Column(i) => row.get(*i).cloned().unwrap_or(Value::Null),
+            Node::Neg(node) => match number(&node.eval(row)?)? {
+                Some(n) => Value::Number(-n),
+                None => Value::Null,
+            },
+            Node::Not(node) => match truth(&node.eval(row)?)? {
+                Some(b) => Value::Bool(!b),
+                None => Value::Null,
+            },
+            Node::IsNull(node, negated) => {
+                Value::Bool(node.eval(row)?.is_null() != *negated)
+            }
+            Node::Binary(op, a, b) => {
+                Self::binary(*op, a.eval(row)?, || b.eval(row))?
+            }
+            Node::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(row))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Self::call(name, args)?
+            }
+            Node::Case(operand, branches, otherwise) => {
+                let operand =
+                    operand.as_ref().map(|o| o.eval(row)).transpose()?;
+                for (condition, result) in branches {
+                    let condition = condition.eval(row)?;
+                    let matched = match &operand {
+                        Some(operand) => {
+                            compare(operand, &condition)
+                                == Some(std::cmp::Ordering::Equal)
+                        }
+                        None => truth(&condition)? == Some(true),
+                    };
+                    if matched {
+                        return result.eval(row);
+                    }
+                }
+                match otherwise {
+                    Some(otherwise) => otherwise.eval(row)?,
+                    None => Value::Null,
+                }
+            }
+            Node::Cast(node, cast_type, tried) => {
+                match Self::cast(node.eval(row)?, *cast_type) {
+                    Err(_) if *tried => Value::Null,
+                    result => result?,
+                }
+            }
+        })
+    }
+
+    /// Whether the expression is true on a row, e.g. a filter keeping it
+    pub(crate) fn is_true(&self, row: &[Value]) -> anyhow::Result<bool> {
+        Ok(truth(&self.eval(row)?)? == Some(true))
+    }
+
+    fn binary(
+        op: BinaryOp,
+        a: Value,
+        b: impl FnOnce() -> anyhow::Result<Value>,
+    ) -> anyhow::Result<Value> {
+        use std::cmp::Ordering;
+
+        // AND and OR only evaluate `b` when `a` does not decide
+        match op {
+            BinaryOp::And | BinaryOp::Or => {
+                let decisive = op == BinaryOp::Or;
+                let a = truth(&a)?;
+                if a == Some(decisive) {
+                    return Ok(Value::Bool(decisive));
+                }
+                return Ok(match (a, truth(&b()?)?) {
+                    (_, Some(b)) if b == decisive => Value::Bool(decisive),
+                    (Some(_), Some(b)) => Value::Bool(b),
+                    _ => Value::Null,
+                });
+            }
+            _ => {}
+        }
+        let b = b()?;
+        if a.is_null() || b.is_null() {
+            return Ok(Value::Null);
+        }
+        let order = || compare(&a, &b);
+        let comparison = |accepted: &[Ordering]| {
+            Ok(Value::Bool(order().is_some_and(|o| accepted.contains(&o))))
+        };
+        let (x, y) = match op {
+            BinaryOp::Eq => return comparison(&[Ordering::Equal]),
+            BinaryOp::Ne => {
+                return comparison(&[Ordering::Less, Ordering::Greater]);
+            }
+            BinaryOp::Lt => return comparison(&[Ordering::Less]),
+            BinaryOp::Le => {
+                return comparison(&[Ordering::Less, Ordering::Equal]);
+            }
+            BinaryOp::Gt => return comparison(&[Ordering::Greater]),
+            BinaryOp::Ge => {
+                return comparison(&[Ordering::Greater, Ordering::Equal]);
+            }
+            BinaryOp::Concat => return Ok(Value::Text(format!("{a}{b}"))),
+            _ => (a.to_f64()?, b.to_f64()?),
+        };
+        Ok(match op {
+            BinaryOp::Add => Value::Number(x + y),
+            BinaryOp::Sub => Value::Number(x - y),
+            BinaryOp::Mul => Value::Number(x * y),
+            // division by zero is NULL in duckdb
+            BinaryOp::Div | BinaryOp::IntDiv | BinaryOp::Mod if y == 0.0 => {
+                Value::Null
+            }
+            BinaryOp::Div => Value::Number(x / y),
+            BinaryOp::IntDiv => Value::Number((x / y).trunc()),
+            BinaryOp::Mod => Value::Number(x % y),
+            _ => Value::Number(x.powf(y)),
+        })
+    }
+
+    fn call(name: &str, args: Vec<Value>) -> anyhow::Result<Value> {
+        match name {
+            "coalesce" => {
+                return Ok(args
+                    .into_iter()
+                    .find(|arg| !arg.is_null())
+                    .unwrap_or(Value::Null));
+            }
+            "nullif" => {
+                let equal = compare(&args[0], &args[1])
+                    == Some(std::cmp::Ordering::Equal);
+                return Ok(if equal { Value::Null } else { args[0].clone() });
+            }
+            // NULL arguments are ignored, as in duckdb
+            "greatest" | "least" => {
+                let mut best: Option<Value> = None;
+                for arg in args.into_iter().filter(|arg| !arg.is_null()) {
+                    let better = best.as_ref().is_none_or(|best| {
+                        let order = compare(&arg, best);
+                        match name {
+                            "greatest" => {
+                                order == Some(std::cmp::Ordering::Greater)
+                            }
+                            _ => order == Some(std::cmp::Ordering::Less),
+                        }
+                    });
+                    if better {
+                        best = Some(arg);
+                    }
+                }
+                return Ok(best.unwrap_or(Value::Null));
+            }
+            _ => {}
+        }
+        if args.iter().any(Value::is_null) {
+            return Ok(Value::Null);
+        }
+        match name {
+            "length" => {
+                return Ok(Value::Number(
+                    args[0].to_string().chars().count() as f64
+                ));
+            }
+            "lower" => {
+                return Ok(Value::Text(args[0].to_string().to_lowercase()));
+            }
+            "upper" => {
+                return Ok(Value::Text(args[0].to_string().to_uppercase()));
+            }
+            _ => {}
+        }
+        let n = args
+            .iter()
+            .map(Value::to_f64)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let x = n.first().copied().unwrap_or(f64::NAN);
+        let result = match name {
+            "abs" => x.abs(),
+            "sqrt" => x.sqrt(),
+            "cbrt" => x.cbrt(),
+            "exp" => x.exp(),
+            "ln" => x.ln(),
+            "log" if n.len() == 2 => n[1].log(x),
+            "log" | "log10" => x.log10(),
+            "log2" => x.log2(),
+            "pow" | "power" => x.powf(n[1]),
+            "floor" => x.floor(),
+            "ceil" | "ceiling" => x.ceil(),
+            "round" => {
+                let scale = 10f64.powi(n.get(1).copied().unwrap_or(0.0) as i32);
+                (x * scale).round() / scale
+            }
+            "trunc" => x.trunc(),
+            "sign" => match x {
+                x if x > 0.0 => 1.0,
+                x if x < 0.0 => -1.0,
+                x => x,
+            },
+            "sin" => x.sin(),
+            "cos" => x.cos(),
+            "tan" => x.tan(),
+            "asin" => x.asin(),
+            "acos" => x.acos(),
+            "atan" => x.atan(),
+            "atan2" => x.atan2(n[1]),
+            "pi" => std::f64::consts::PI,
+            _ => bail!("Function {name}() is not supported natively"),
+        };
+        Ok(Value::Number(result))
+    }
+
+    fn cast(value: Value, cast_type: CastType) -> anyhow::Result<Value> {
+        if value.is_null() {
+            return Ok(Value::Null);
+        }
+        Ok(match cast_type {
+            CastType::Double => Value::Number(value.to_f64()?),
+            // rounded to the nearest, as by duckdb
+            CastType::Integer => Value::Number(value.to_f64()?.round()),
+            CastType::Varchar => Value::Text(value.to_string()),
+            CastType::Boolean => match truth(&value)? {
+                Some(b) => Value::Bool(b),
+                None => Value::Null,
+            },
+        })
+    }
+}
+
+#[test]
+fn test_eval() {
+    let columns = ["a", "B", "c d"].map(String::from);
+    let row = [Value::Number(3.0), Value::Text("x".into()), Value::Null];
+    let eval = |expr: &str| compile(expr, '$', &columns)?.eval(&row);
+    let number = |expr: &str| eval(expr).unwrap();
+    assert_eq!(number("-a ^ 2 + $1 * 2 - 1"), Value::Number(14.0));
+    assert_eq!(number("(a + 1) / 8 * 2"), Value::Number(1.0));
+    assert_eq!(number("7 // 2 + 7 % 4 + 1e1"), Value::Number(16.0));
+    assert_eq!(number("a / 0"), Value::Null);
+    assert_eq!(number("\"c d\" + 1"), Value::Null);
+    assert_eq!(number("b || '!' || a"), Value::Text("x!3".into()));
+    assert_eq!(number("a > 2 AND NOT b = 'y'"), Value::Bool(true));
+    assert_eq!(number("$3 > 1 OR a < 1"), Value::Null);
+    assert_eq!(number("$3 > 1 OR a > 1"), Value::Bool(true));
+    assert_eq!(number("$3 IS NULL AND a IS NOT NULL"), Value::Bool(true));
+    assert_eq!(number("coalesce($3, log(100), 0)"), Value::Number(2.0));
+    assert_eq!(number("greatest(1, $3, a)"), Value::Number(3.0));
+    assert_eq!(number("round(pi() * 100, 1)"), Value::Number(314.2));
+    assert_eq!(
+        number("CASE WHEN a < 3 THEN 'low' WHEN a < 5 THEN 'mid' END"),
+        Value::Text("mid".into())
+    );
+    assert_eq!(
+        number("CASE a WHEN 3 THEN 1 ELSE 0 END"),
+        Value::Number(1.0)
+    );
+    assert_eq!(
+        number("CAST('2.5' AS DOUBLE) + '1'::INTEGER"),
+        Value::Number(3.5)
+    );
+    assert_eq!(number("TRY_CAST(b AS DOUBLE)"), Value::Null);
+    assert!(eval("CAST(b AS DOUBLE)").is_err());
+    assert!(eval("b + 1").is_err());
+    assert!(eval("missing").is_err());
+    assert!(eval("$4").is_err());
+    assert!(eval("regexp_matches(b, 'x')").is_err());
+    assert!(eval("a +").is_err());
+}
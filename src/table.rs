@@ -0,0 +1,343 @@
+// Tables of text input read natively, for builds and targets that have no
+// duckdb to ingest them, e.g. wasm32
+
+use std::fmt::Display;
+
+use anyhow::bail;
+
+use crate::CsvDialect;
+
+/// Value of a cell of a `Table`, typed as duckdb would compute on it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Missing value, i.e. an empty cell
+    Null,
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+impl Value {
+    /// Value of a cell: empty cells are missing, and cells that are numbers
+    /// (after trimming) are read as such
+    pub fn parse(cell: &str) -> Self {
+        let trimmed = cell.trim();
+        if trimmed.is_empty() {
+            return Self::Null;
+        }
+        // words like `inf` or `nan` stay text, as in the columns of duckdb
+        let numeric = trimmed
+            .trim_start_matches(['+', '-'])
+            .starts_with(|c: char| c.is_ascii_digit() || c == '.');
+        match trimmed.parse() {
+            Ok(number) if numeric => Self::Number(number),
+            _ => Self::Text(cell.to_string()),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// Number of the value, NaN if missing, and an error if it is text that
+    /// is not a number
+    pub fn to_f64(&self) -> anyhow::Result<f64> {
+        match self {
+            Self::Null => Ok(f64::NAN),
+            Self::Number(number) => Ok(*number),
+            Self::Bool(b) => Ok(f64::from(u8::from(*b))),
+            Self::Text(text) => match Self::parse(text) {
+                Self::Number(number) => Ok(number),
+                _ => bail!("'{text}' is not a number"),
+            },
+        }
+    }
+
+    /// Missing values are NaN, see `to_f64`
+    pub fn from_f64(number: f64) -> Self {
+        match number {
+            n if n.is_nan() => Self::Null,
+            n => Self::Number(n),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Null => Ok(()),
+            Self::Number(number) => write!(f, "{number}"),
+            Self::Text(text) => write!(f, "{text}"),
+            Self::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+/// Rows of named columns, e.g. of a CSV file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    /// Read CSV text of `dialect` delimited by `delimiter`, whose first row
+    /// names the columns if `header`. Columns are otherwise named `column0`,
+    /// `column1`, ..., as by duckdb. Blank lines are skipped, and so are
+    /// comment lines.
+    pub fn from_csv(
+        text: &str,
+        delimiter: char,
+        header: bool,
+        dialect: &CsvDialect,
+    ) -> anyhow::Result<Self> {
+        let mut records = split_records(text, delimiter, dialect)?.into_iter();
+        let columns = match header {
+            true => dedup_names(records.next().unwrap_or_default()),
+            false => vec![],
+        };
+        let mut table = Self {
+            columns,
+            rows: vec![],
+        };
+        for (i, record) in records.enumerate() {
+            if !header && i == 0 {
+                table.columns =
+                    (0..record.len()).map(|c| format!("column{c}")).collect();
+            }
+            let width = table.columns.len();
+            if record.len() > width
+                || (record.len() < width && !dialect.flexible)
+            {
+                bail!(
+                    "Row {} has {} columns instead of {width} (see --flexible)",
+                    i + 1 + usize::from(header),
+                    record.len()
+                );
+            }
+            let mut row = record
+                .iter()
+                .map(|cell| Value::parse(cell))
+                .collect::<Vec<_>>();
+            row.resize(width, Value::Null);
+            table.rows.push(row);
+        }
+        Ok(table)
+    }
+
+    /// Position of column `name`, compared case-insensitively like the
+    /// identifiers of SQL unless one matches exactly
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == name).or_else(|| {
+            self.columns
+                .iter()
+                .position(|c| c.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Append the rows of `other`, whose columns are matched by name, the
+    /// columns missing in either table being filled with missing values
+    pub fn union_by_name(&mut self, other: Table) {
+        let positions = other
+            .columns
+            .iter()
+            .map(|name| match self.column(name) {
+                Some(i) => i,
+                None => {
+                    self.columns.push(name.clone());
+                    for row in &mut self.rows {
+                        row.push(Value::Null);
+                    }
+                    self.columns.len() - 1
+                }
+            })
+            .collect::<Vec<_>>();
+        for row in other.rows {
+            let mut merged = vec![Value::Null; self.columns.len()];
+            for (value, &i) in row.into_iter().zip(&positions) {
+                merged[i] = value;
+            }
+            self.rows.push(merged);
+        }
+    }
+
+    /// Write the table as CSV with a header row, missing values being empty
+    /// cells
+    pub fn write_csv(
+        &self,
+        writer: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        let line = |cells: Vec<String>| {
+            cells
+                .iter()
+                .map(|cell| csv_cell(cell))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        writeln!(writer, "{}", line(self.columns.clone()))?;
+        for row in &self.rows {
+            writeln!(
+                writer,
+                "{}",
+                line(row.iter().map(Value::to_string).collect())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// cells quoted as in RFC 4180 when they have to be
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+// names of the header row, duplicates being renamed `name_1`, `name_2`, ...
+// and empty ones `column<i>`, as by duckdb
+fn dedup_names(names: Vec<String>) -> Vec<String> {
+    let mut deduped: Vec<String> = Vec::with_capacity(names.len());
+    for (i, name) in names.into_iter().enumerate() {
+        let name = match name.trim() {
+            "" => format!("column{i}"),
+            name => name.to_string(),
+        };
+        let mut unique = name.clone();
+        let mut n = 0;
+        while deduped.contains(&unique) {
+            n += 1;
+            unique = format!("{name}_{n}");
+        }
+        deduped.push(unique);
+    }
+    deduped
+}
+
+// cells of the records of CSV text, where quoted cells may contain the
+// delimiter, line breaks and escaped quotes
+fn split_records(
+    text: &str,
+    delimiter: char,
+    dialect: &CsvDialect,
+) -> anyhow::Result<Vec<Vec<String>>> {
+    let quote = dialect.quote.unwrap_or('"');
+    let escape = dialect.escape.unwrap_or(quote);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut cell = String::new();
+    // whether the current record has any character, blank lines being none
+    let mut started = false;
+    let mut quoted = false;
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                c if c == escape && chars.peek() == Some(&quote) => {
+                    chars.next();
+                    cell.push(quote);
+                }
+                c if c == quote => quoted = false,
+                c => {
+                    line += usize::from(c == '\n');
+                    cell.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            c if !started && Some(c) == dialect.comment => {
+                // the comment runs until the end of the line
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                line += 1;
+                if started {
+                    record.push(std::mem::take(&mut cell));
+                    records.push(std::mem::take(&mut record));
+                    started = false;
+                }
+            }
+            c if c == delimiter => {
+                record.push(std::mem::take(&mut cell));
+                started = true;
+            }
+            c if c == quote => {
+                quoted = true;
+                started = true;
+            }
+            c => {
+                cell.push(c);
+                started = true;
+            }
+        }
+    }
+    if quoted && !dialect.flexible {
+        bail!("Unterminated quoted value starting before line {line}");
+    }
+    if started {
+        record.push(cell);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[test]
+fn test_table_from_csv() {
+    let dialect = CsvDialect::default();
+    let table = Table::from_csv(
+        "a,b,a\n1,\"x, \"\"y\"\"\",\n\n-2.5e1,\"two\nlines\",nan\n",
+        ',',
+        true,
+        &dialect,
+    )
+    .unwrap();
+    assert_eq!(table.columns, ["a", "b", "a_1"]);
+    assert_eq!(
+        table.rows,
+        [
+            vec![
+                Value::Number(1.0),
+                Value::Text("x, \"y\"".into()),
+                Value::Null
+            ],
+            vec![
+                Value::Number(-25.0),
+                Value::Text("two\nlines".into()),
+                Value::Text("nan".into())
+            ],
+        ]
+    );
+    let mut csv = Vec::new();
+    table.write_csv(&mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "a,b,a_1\n1,\"x, \"\"y\"\"\",\n-25,\"two\nlines\",nan\n"
+    );
+
+    // no header, comments and short rows of flexible input
+    let dialect = dialect.with_comment(Some('#')).with_flexible(true);
+    let table =
+        Table::from_csv("# c\n1;2\r\n3\n", ';', false, &dialect).unwrap();
+    assert_eq!(table.columns, ["column0", "column1"]);
+    assert_eq!(table.rows[1], [Value::Number(3.0), Value::Null]);
+    assert!(Table::from_csv("a\n1,2\n", ',', true, &dialect).is_err());
+
+    let mut more = Table::from_csv("b,c\n4,5\n", ',', true, &dialect).unwrap();
+    more.union_by_name(
+        Table::from_csv("C,d\n6,7\n", ',', true, &dialect).unwrap(),
+    );
+    assert_eq!(more.columns, ["b", "c", "d"]);
+    assert_eq!(
+        more.rows[1],
+        [Value::Null, Value::Number(6.0), Value::Number(7.0)]
+    );
+}
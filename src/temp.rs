@@ -0,0 +1,139 @@
+// Temporary files of a run (datasheets, scripts, logs, ...), removed once
+// the run is over or interrupted
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::Plotter;
+
+static TRACKED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Registry of the temporary files created by this process, e.g. the
+/// scripts of `Plotter::plot` and the datasheets of `DataSeriesSource::dump`
+/// without a path. They are only removed by `TempArtifacts::cleanup`, which
+/// sp and msp call when they exit.
+pub struct TempArtifacts;
+
+impl TempArtifacts {
+    /// Create a new path `<prefix><random>.<extension>` in the temporary
+    /// directory with `create`, which must fail with `AlreadyExists` rather
+    /// than reuse an existing path (e.g. `File::create_new`), another name
+    /// being tried then. The names follow `Plotter::set_seed` if it is
+    /// called, which makes them predictable but not hijackable by files
+    /// planted in their place. The path is tracked.
+    pub fn create(
+        prefix: &str,
+        extension: &str,
+        mut create: impl FnMut(&Path) -> std::io::Result<()>,
+    ) -> std::io::Result<PathBuf> {
+        let tmp_dir = std::env::temp_dir();
+        loop {
+            let mut name = format!("{prefix}{}", Plotter::random_name(16));
+            if !extension.is_empty() {
+                name = format!("{name}.{extension}");
+            }
+            let path = tmp_dir.join(name);
+            match create(&path) {
+                Ok(()) => {
+                    Self::track(&path);
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Remove `path` (a file or a directory) at cleanup
+    pub fn track(path: impl Into<PathBuf>) {
+        let path = path.into();
+        let mut tracked = TRACKED.lock().unwrap_or_else(|e| e.into_inner());
+        if !tracked.contains(&path) {
+            tracked.push(path);
+        }
+    }
+
+    /// Keep `path` at cleanup, e.g. once it is handed over to the user
+    pub fn untrack(path: impl AsRef<Path>) {
+        TRACKED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|p| p != path.as_ref());
+    }
+
+    /// Forget the tracked paths and remove them unless `preserve`, returns
+    /// the paths left on disk
+    pub fn cleanup(preserve: bool) -> Vec<PathBuf> {
+        let tracked = std::mem::take(
+            &mut *TRACKED.lock().unwrap_or_else(|e| e.into_inner()),
+        );
+        tracked
+            .into_iter()
+            .filter(|path| path.exists())
+            .filter(|path| {
+                preserve
+                    || if path.is_dir() {
+                        std::fs::remove_dir_all(path).is_err()
+                    } else {
+                        std::fs::remove_file(path).is_err()
+                    }
+            })
+            .collect()
+    }
+}
+
+/// Clean up the temporary files, reporting the ones left on stderr
+#[cfg(feature = "cli")]
+pub fn finish_temp_artifacts(preserve: bool) {
+    for path in TempArtifacts::cleanup(preserve) {
+        eprintln!("Kept temporary file: {}", path.display());
+    }
+}
+
+/// Clean up the temporary files when the process is interrupted by SIGINT,
+/// SIGTERM or SIGHUP, and exit with the conventional code of the signal
+#[cfg(feature = "cli")]
+pub fn finish_temp_artifacts_on_signal(preserve: bool) {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+    let Ok(mut signals) =
+        signal_hook::iterator::Signals::new([SIGINT, SIGTERM, SIGHUP])
+    else {
+        tracing::warn!("Failed to handle signals, temporary files may leak");
+        return;
+    };
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            finish_temp_artifacts(preserve);
+            std::process::exit(128 + signal);
+        }
+    });
+}
+
+#[test]
+fn test_create_skips_taken_names() {
+    let create = || {
+        TempArtifacts::create("sp-test-", "csv", |path| {
+            std::fs::File::create_new(path).map(drop)
+        })
+        .unwrap()
+    };
+    Plotter::set_seed(4707);
+    let first = create();
+    // the name drawn first is taken, e.g. by a planted file
+    Plotter::set_seed(4707);
+    let second = create();
+    assert_ne!(first, second);
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+    TempArtifacts::untrack(&second);
+    // the names are reproducible
+    Plotter::set_seed(4707);
+    let again = create();
+    assert_eq!(again, first);
+    std::fs::remove_file(&again).unwrap();
+    TempArtifacts::untrack(&again);
+}
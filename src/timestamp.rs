@@ -0,0 +1,301 @@
+// Timestamps of time axes, read as seconds since the Unix epoch (UTC) both
+// natively and by duckdb, so that the operators compute on plain numbers
+
+use std::fmt::Display;
+
+/// Format of the timestamps of a time axis
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// ISO-8601 / RFC-3339, e.g. `2024-05-01T12:00:00.5+02:00`, the date
+    /// and the time being separated by `T` or a space. Timestamps without
+    /// offset are in UTC.
+    #[default]
+    Iso8601,
+    /// strptime format, e.g. `%d/%b/%Y:%H:%M:%S %z` (of web server logs).
+    /// Natively, only `%Y %m %d %b %B %H %M %S %f %z %%` are supported.
+    Strptime(String),
+}
+
+impl TimeFormat {
+    /// strptime format, or ISO-8601 if `format` is empty
+    pub fn new(format: impl AsRef<str>) -> Self {
+        match format.as_ref() {
+            "" => Self::Iso8601,
+            format => Self::Strptime(format.to_string()),
+        }
+    }
+
+    /// Seconds since the Unix epoch, `None` if `s` is not a timestamp of this
+    /// format, and an error if the format has directives not supported
+    /// natively (e.g. `%y` or `%j`, which only duckdb reads)
+    pub fn parse(&self, s: &str) -> anyhow::Result<Option<f64>> {
+        match self {
+            Self::Iso8601 => Ok(parse_iso8601(s.trim())),
+            Self::Strptime(format) => {
+                check_directives(format)?;
+                Ok(parse_strptime(s, format))
+            }
+        }
+    }
+
+    /// SQL of duckdb converting the timestamps of `expr` to seconds since the
+    /// Unix epoch, `expr` being either a string or already a timestamp
+    pub fn to_sql(&self, expr: &str) -> String {
+        match self {
+            Self::Iso8601 => format!("epoch(CAST(({expr}) AS TIMESTAMP))"),
+            Self::Strptime(format) => format!(
+                "epoch(strptime(CAST(({expr}) AS VARCHAR), '{}'))",
+                format.replace('\'', "''")
+            ),
+        }
+    }
+}
+
+impl Display for TimeFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Iso8601 => write!(f, "ISO-8601"),
+            Self::Strptime(format) => write!(f, "{format}"),
+        }
+    }
+}
+
+// days since 1970-01-01 of a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+    let days_in_month = match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if day == 0 || day > days_in_month {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5
+        + day as i64
+        - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+fn epoch_seconds(
+    (year, month, day): (i64, u32, u32),
+    (hour, minute, second): (u32, u32, f64),
+    offset: i64,
+) -> Option<f64> {
+    if hour > 23 || minute > 59 || !(0.0..61.0).contains(&second) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day)?;
+    let seconds = days * 86400 + (hour * 3600 + minute * 60) as i64 - offset;
+    Some(seconds as f64 + second)
+}
+
+// leading digits of `s` (at most `max`), with the rest of `s`
+fn take_number(s: &str, max: usize) -> Option<(u32, &str)> {
+    let len = s
+        .bytes()
+        .take(max)
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    Some((s[..len].parse().ok()?, &s[len..]))
+}
+
+// fraction of a second after the decimal point
+fn take_fraction(s: &str) -> (f64, &str) {
+    let len = s.bytes().take_while(|b| b.is_ascii_digit()).count();
+    let fraction = format!("0.{}", &s[..len]).parse().unwrap_or(0.0);
+    (fraction, &s[len..])
+}
+
+// `Z`, `+HH`, `+HHMM` or `+HH:MM` in seconds, with the rest of `s`
+fn take_offset(s: &str) -> Option<(i64, &str)> {
+    let sign = match s.chars().next()? {
+        'Z' | 'z' => return Some((0, &s[1..])),
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let (hours, rest) = take_number(&s[1..], 2)?;
+    let rest = rest.strip_prefix(':').unwrap_or(rest);
+    let (minutes, rest) = take_number(rest, 2).unwrap_or((0, rest));
+    Some((sign * (hours * 3600 + minutes * 60) as i64, rest))
+}
+
+fn parse_iso8601(s: &str) -> Option<f64> {
+    let (year, rest) = take_number(s, 4)?;
+    let (month, rest) = take_number(rest.strip_prefix('-')?, 2)?;
+    let (day, rest) = take_number(rest.strip_prefix('-')?, 2)?;
+    let date = (year as i64, month, day);
+    let Some(rest) = rest.strip_prefix(['T', 't', ' ']) else {
+        if !rest.is_empty() {
+            return None;
+        }
+        return epoch_seconds(date, (0, 0, 0.0), 0);
+    };
+    let (hour, rest) = take_number(rest, 2)?;
+    let (minute, rest) = take_number(rest.strip_prefix(':')?, 2)?;
+    let (second, rest) = match rest.strip_prefix(':') {
+        Some(rest) => {
+            let (second, rest) = take_number(rest, 2)?;
+            let (fraction, rest) = match rest.strip_prefix(['.', ',']) {
+                Some(rest) => take_fraction(rest),
+                None => (0.0, rest),
+            };
+            (second as f64 + fraction, rest)
+        }
+        None => (0.0, rest),
+    };
+    let (offset, rest) = match rest {
+        "" => (0, rest),
+        _ => take_offset(rest.trim_start())?,
+    };
+    if !rest.is_empty() {
+        return None;
+    }
+    epoch_seconds(date, (hour, minute, second), offset)
+}
+
+// directives of `format` that parse_strptime reads
+fn check_directives(format: &str) -> anyhow::Result<()> {
+    let mut spec = format.chars();
+    while let Some(c) = spec.next() {
+        if c != '%' {
+            continue;
+        }
+        let directive = match spec.next() {
+            Some('-') => spec.next(),
+            directive => directive,
+        };
+        match directive {
+            Some('Y' | 'm' | 'd' | 'b' | 'B' | 'H' | 'M' | 'S' | 'f' | 'z')
+            | Some('%') => {}
+            Some(d) => anyhow::bail!(
+                "Directive '%{d}' of time format '{format}' is not supported natively"
+            ),
+            None => anyhow::bail!("Time format '{format}' ends with '%'"),
+        }
+    }
+    Ok(())
+}
+
+fn parse_strptime(s: &str, format: &str) -> Option<f64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct",
+        "nov", "dec",
+    ];
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0.0);
+    let mut offset = 0;
+    let mut rest = s;
+    let mut spec = format.chars();
+    while let Some(c) = spec.next() {
+        if c != '%' {
+            rest = rest.strip_prefix(c)?;
+            continue;
+        }
+        let mut directive = spec.next()?;
+        // `%-d` etc. (without padding) are read like `%d`
+        if directive == '-' {
+            directive = spec.next()?;
+        }
+        rest = match directive {
+            'Y' => {
+                let (v, rest) = take_number(rest.trim_start(), 4)?;
+                year = v as i64;
+                rest
+            }
+            'm' => {
+                let (v, rest) = take_number(rest.trim_start(), 2)?;
+                month = v;
+                rest
+            }
+            'd' => {
+                let (v, rest) = take_number(rest.trim_start(), 2)?;
+                day = v;
+                rest
+            }
+            'H' => {
+                let (v, rest) = take_number(rest.trim_start(), 2)?;
+                hour = v;
+                rest
+            }
+            'M' => {
+                let (v, rest) = take_number(rest.trim_start(), 2)?;
+                minute = v;
+                rest
+            }
+            'S' => {
+                let (v, rest) = take_number(rest.trim_start(), 2)?;
+                second += v as f64;
+                rest
+            }
+            'f' => {
+                let (fraction, rest) = take_fraction(rest);
+                second += fraction;
+                rest
+            }
+            'b' | 'B' => {
+                let prefix = rest.get(..3)?.to_ascii_lowercase();
+                month = MONTHS.iter().position(|m| *m == prefix)? as u32 + 1;
+                rest[3..].trim_start_matches(|c: char| c.is_alphabetic())
+            }
+            'z' => {
+                let (v, rest) = take_offset(rest)?;
+                offset = v;
+                rest
+            }
+            '%' => rest.strip_prefix('%')?,
+            _ => return None,
+        };
+    }
+    if !rest.trim().is_empty() {
+        return None;
+    }
+    epoch_seconds((year, month, day), (hour, minute, second), offset)
+}
+
+#[test]
+fn test_parse_timestamps() {
+    let iso = TimeFormat::Iso8601;
+    assert_eq!(iso.parse("1970-01-02").unwrap(), Some(86400.0));
+    assert_eq!(
+        iso.parse("2024-05-01T12:00:00Z").unwrap(),
+        Some(1714564800.0)
+    );
+    assert_eq!(iso.parse("2024-05-01 12:00").unwrap(), Some(1714564800.0));
+    assert_eq!(
+        iso.parse("2024-05-01T14:00:00.25+02:00").unwrap(),
+        Some(1714564800.25)
+    );
+    assert_eq!(
+        iso.parse("2024-05-01T11:30:00,5-0030").unwrap(),
+        Some(1714564800.5)
+    );
+    assert_eq!(iso.parse("2024-02-30").unwrap(), None);
+    assert_eq!(iso.parse("12:00:00").unwrap(), None);
+
+    let clf = TimeFormat::new("%d/%b/%Y:%H:%M:%S %z");
+    assert_eq!(
+        clf.parse("01/May/2024:14:00:00 +0200").unwrap(),
+        Some(1714564800.0)
+    );
+    let full = TimeFormat::new("%B %-d, %Y %H:%M:%S.%f%%");
+    assert_eq!(
+        full.parse("May 1, 2024 12:00:00.125%").unwrap(),
+        Some(1714564800.125)
+    );
+    let ymd = TimeFormat::new("%Y%m%d");
+    assert_eq!(ymd.parse("20240501").unwrap(), Some(1714521600.0));
+    assert_eq!(ymd.parse("2024-05-01").unwrap(), None);
+
+    for format in ["%y-%m-%d", "%Y %j", "%s", "%Y%"] {
+        assert!(TimeFormat::new(format).parse("24-05-01").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+// JavaScript bindings of the native pipeline, built for wasm32 without
+// duckdb or gnuplot: CSV text is selected and transformed in the browser,
+// then dumped as CSV or plotted as SVG
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    CsvDialect, Expr, NativePlot, NativeSeries, OpSeq, PlainSelector,
+    SeriesStyle, Table,
+};
+
+/// Index mark of the expressions, as in `sp`
+const INDEX_MARK: char = '$';
+
+// the (x, y) points of `csv` selected by `x` and `y`, filtered by `filter`
+// and transformed by `ops`
+fn transform(
+    csv: &str,
+    x: &str,
+    y: &str,
+    ops: &str,
+    filter: Option<String>,
+    delimiter: Option<char>,
+) -> anyhow::Result<Vec<(f64, f64)>> {
+    let table = Table::from_csv(
+        csv,
+        delimiter.unwrap_or(','),
+        true,
+        &CsvDialect::default(),
+    )?;
+    let selector = PlainSelector::new(
+        Expr::new(x, INDEX_MARK),
+        Expr::new(y, INDEX_MARK),
+        filter.map(|filter| Expr::new(&filter, INDEX_MARK)),
+        None,
+    )?;
+    let points = selector
+        .select_table(&table)?
+        .rows
+        .iter()
+        .map(|row| Ok((row[0].to_f64()?, row[1].to_f64()?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    ops.parse::<OpSeq>()?.eval(points)
+}
+
+fn js_error(e: anyhow::Error) -> JsError {
+    JsError::new(&format!("{e:#}"))
+}
+
+/// CSV of the points `x,y` of `csv` (with a header row) selected by the
+/// SQL expressions `x` and `y` (e.g. `$1` or `price * 2`), kept by `filter`
+/// and transformed by the operators `ops` of `sp` (e.g. `od0.1`)
+#[wasm_bindgen(js_name = transformCsv)]
+pub fn transform_csv(
+    csv: &str,
+    x: &str,
+    y: &str,
+    ops: &str,
+    filter: Option<String>,
+    delimiter: Option<char>,
+) -> Result<String, JsError> {
+    let points =
+        transform(csv, x, y, ops, filter, delimiter).map_err(js_error)?;
+    Ok(std::iter::once("x,y".to_string())
+        .chain(points.iter().map(|(x, y)| match y.is_nan() {
+            true => format!("{x},"),
+            false => format!("{x},{y}"),
+        }))
+        .map(|line| line + "\n")
+        .collect())
+}
+
+/// SVG of `width` by `height` pixels plotting the points of
+/// `transformCsv` with `style` (`points`, `lines` or `linespoints`)
+#[wasm_bindgen(js_name = plotCsv)]
+#[allow(clippy::too_many_arguments)]
+pub fn plot_csv(
+    csv: &str,
+    x: &str,
+    y: &str,
+    ops: &str,
+    filter: Option<String>,
+    delimiter: Option<char>,
+    style: Option<String>,
+    width: u32,
+    height: u32,
+) -> Result<String, JsError> {
+    let style = match style.as_deref() {
+        None | Some("points") => SeriesStyle::Points,
+        Some("lines") => SeriesStyle::Lines,
+        Some("linespoints") => SeriesStyle::LinesPoints,
+        Some(style) => {
+            return Err(JsError::new(&format!("Unknown style '{style}'")));
+        }
+    };
+    let points =
+        transform(csv, x, y, ops, filter, delimiter).map_err(js_error)?;
+    let plot = NativePlot::new(vec![NativeSeries {
+        label: None,
+        points,
+        style,
+    }])
+    .with_xlabel(Some(x))
+    .with_ylabel(Some(y));
+    Ok(plot.to_svg(width, height))
+}
+
+#[test]
+fn test_transform_csv() {
+    let csv = "t,price\n1,10\n2,\n3,30\n4,40\n";
+    let dump = transform_csv(csv, "t", "price / 10", "y2,0", None, None)
+        .map_err(|_| ())
+        .unwrap();
+    assert_eq!(dump, "x,y\n1,2\n2,\n3,6\n4,8\n");
+    let dump = transform_csv(csv, "$1", "$2", "", Some("t > 2".into()), None)
+        .map_err(|_| ())
+        .unwrap();
+    assert_eq!(dump, "x,y\n3,30\n4,40\n");
+
+    let svg = plot_csv(csv, "t", "price", "", None, None, None, 320, 240)
+        .map_err(|_| ())
+        .unwrap();
+    assert!(svg.contains(">price</text>"));
+}